@@ -0,0 +1,61 @@
+//! Verification cost by game length. Every move signs everything before it,
+//! so rebuilding each signed prefix separately grows quadratically; verify()
+//! builds the signed bytes once and should scale linearly.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lineage::block::{ChallengeBlock, GameChain};
+use lineage::crypto;
+use lineage::key::PublicKey;
+use lineage::notation;
+
+use chess::Action;
+
+/// A game of `plies` moves shuffling the knights back and forth.
+fn knight_shuffle(plies: usize) -> GameChain {
+    let rng = crypto::new_rng();
+    let white = crypto::generate_key(&rng);
+    let black = crypto::generate_key(&rng);
+    let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+    let mut chain = GameChain::new(challenge);
+    chain.accept(&white).unwrap();
+    chain.accept(&black).unwrap();
+    let moves = ["g1f3", "g8f6", "f3g1", "f6g8"];
+    for ply in 0..plies {
+        let key_pair = if ply % 2 == 0 { &white } else { &black };
+        let mv = notation::parse_uci(moves[ply % moves.len()]).unwrap();
+        chain
+            .make_move_block(key_pair, Action::MakeMove(mv))
+            .unwrap();
+    }
+    chain
+}
+
+fn verify(c: &mut Criterion) {
+    let mut group = c.benchmark_group("verify");
+    for &plies in &[50, 100, 200] {
+        let chain = knight_shuffle(plies);
+        group.bench_with_input(BenchmarkId::new("chain", plies), &chain, |b, chain| {
+            b.iter(|| assert!(chain.verify()))
+        });
+        let bytes = chain.as_bytes();
+        group.bench_with_input(BenchmarkId::new("from_bytes", plies), &bytes, |b, bytes| {
+            b.iter(|| GameChain::from_bytes(bytes).unwrap())
+        });
+        // the old approach: serialize the prefix again for every move
+        group.bench_with_input(
+            BenchmarkId::new("prefix_per_move", plies),
+            &chain,
+            |b, chain| {
+                b.iter(|| {
+                    (0..chain.move_count())
+                        .map(|ply| chain.truncated(ply).as_bytes().len())
+                        .sum::<usize>()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, verify);
+criterion_main!(benches);
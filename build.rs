@@ -0,0 +1,5 @@
+fn main() {
+    // Only the grpc feature needs generated code.
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/lineage.proto").unwrap();
+}
@@ -0,0 +1,55 @@
+/* Generated with cbindgen from src/ffi.rs; regenerate with
+ *   cbindgen --config cbindgen.toml --crate lineage --output include/lineage.h
+ */
+
+#ifndef LINEAGE_H
+#define LINEAGE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+typedef enum LineageStatus {
+  LINEAGE_STATUS_OK = 0,
+  LINEAGE_STATUS_NULL_POINTER = 1,
+  LINEAGE_STATUS_INVALID_KEY = 2,
+  LINEAGE_STATUS_KEY_NOT_IN_CHALLENGE = 3,
+  LINEAGE_STATUS_ALREADY_ACCEPTED = 4,
+  LINEAGE_STATUS_NOT_YOUR_TURN = 5,
+  LINEAGE_STATUS_ILLEGAL_MOVE = 6,
+  LINEAGE_STATUS_VERIFICATION_FAILED = 7,
+  LINEAGE_STATUS_MALFORMED = 8,
+  LINEAGE_STATUS_OTHER = 255,
+} LineageStatus;
+
+typedef struct GameChain GameChain;
+
+typedef struct LineageBuffer {
+  uint8_t *data;
+  size_t len;
+} LineageBuffer;
+
+GameChain *lineage_chain_new(const uint8_t *white_public_key, const uint8_t *black_public_key);
+
+GameChain *lineage_chain_from_bytes(const uint8_t *bytes, size_t len);
+
+void lineage_chain_free(GameChain *chain);
+
+LineageBuffer lineage_chain_to_bytes(const GameChain *chain);
+
+void lineage_buffer_free(LineageBuffer buffer);
+
+LineageBuffer lineage_generate_key(void);
+
+LineageStatus lineage_chain_accept(GameChain *chain, const uint8_t *pkcs8, size_t pkcs8_len);
+
+LineageStatus lineage_chain_make_move(GameChain *chain,
+                                      const uint8_t *pkcs8,
+                                      size_t pkcs8_len,
+                                      const char *uci);
+
+bool lineage_chain_verify(const GameChain *chain);
+
+size_t lineage_chain_move_count(const GameChain *chain);
+
+#endif /* LINEAGE_H */
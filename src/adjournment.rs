@@ -0,0 +1,230 @@
+//! Adjournments. The player to move can sign a pause, stopping the game until
+//! they sign a resume, as a player seals their move in over-the-board
+//! correspondence play. Both blocks carry a timestamp, so clock calculations
+//! can leave the adjourned time out.
+
+use crate::block::GameChain;
+use crate::error::Error;
+use crate::extension::{ExtensionBlock, ExtensionKind};
+
+use alloc::vec::Vec;
+use ring::signature::Ed25519KeyPair;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Adjournment {
+    /// The number of moves played before the game was paused.
+    pub move_count: usize,
+    /// When the game was paused, in seconds since the Unix epoch.
+    pub paused_at: u64,
+    /// When the game was resumed, or None if it is still adjourned.
+    pub resumed_at: Option<u64>,
+}
+
+impl GameChain {
+    /// Adjourns the game at `timestamp`.
+    pub fn adjourn(&mut self, key_pair: &Ed25519KeyPair, timestamp: u64) -> Result<(), Error> {
+        self.sign_extension(
+            key_pair,
+            ExtensionKind::Pause,
+            timestamp.to_be_bytes().to_vec(),
+        )
+    }
+
+    /// Resumes an adjourned game at `timestamp`.
+    pub fn resume(&mut self, key_pair: &Ed25519KeyPair, timestamp: u64) -> Result<(), Error> {
+        self.sign_extension(
+            key_pair,
+            ExtensionKind::Resume,
+            timestamp.to_be_bytes().to_vec(),
+        )
+    }
+
+    /// The game's adjournments, in order.
+    pub fn adjournments(&self) -> Vec<Adjournment> {
+        let mut adjournments: Vec<Adjournment> = Vec::new();
+        for (at, block) in self.extensions() {
+            match (block.kind(), timestamp(block)) {
+                (ExtensionKind::Pause, Some(paused_at)) => adjournments.push(Adjournment {
+                    move_count: *at,
+                    paused_at,
+                    resumed_at: None,
+                }),
+                (ExtensionKind::Resume, Some(resumed_at)) => {
+                    if let Some(last) = adjournments.last_mut() {
+                        last.resumed_at = Some(resumed_at);
+                    }
+                }
+                _ => {}
+            }
+        }
+        adjournments
+    }
+
+    pub fn is_adjourned(&self) -> bool {
+        self.adjournments()
+            .last()
+            .is_some_and(|adjournment| adjournment.resumed_at.is_none())
+    }
+
+    /// The seconds between `from` and `to` that the game wasn't adjourned,
+    /// which is the time that counts against a player's clock.
+    pub fn active_seconds(&self, from: u64, to: u64) -> u64 {
+        let paused: u64 = self
+            .adjournments()
+            .iter()
+            .map(|adjournment| {
+                let start = adjournment.paused_at.max(from);
+                let end = adjournment.resumed_at.unwrap_or(u64::MAX).min(to);
+                end.saturating_sub(start)
+            })
+            .sum();
+        to.saturating_sub(from).saturating_sub(paused)
+    }
+}
+
+fn timestamp(block: &ExtensionBlock) -> Option<u64> {
+    if block.body().len() != 8 {
+        return None;
+    }
+    let mut timestamp = [0; 8];
+    timestamp.copy_from_slice(block.body());
+    Some(u64::from_be_bytes(timestamp))
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "adjournment",
+        reason,
+    }
+}
+
+/// Checks a pause or resume against the adjournment before it: pauses and
+/// resumes alternate, and timestamps never go backwards.
+fn check_next(
+    previous: Option<&Adjournment>,
+    challenge_timestamp: u64,
+    block: &ExtensionBlock,
+) -> Result<(), Error> {
+    let timestamp = timestamp(block).ok_or_else(|| malformed("wrong body length"))?;
+    let earliest = match (block.kind(), previous) {
+        (ExtensionKind::Pause, Some(previous)) => match previous.resumed_at {
+            Some(resumed_at) => resumed_at,
+            None => return Err(Error::GameAdjourned),
+        },
+        (ExtensionKind::Pause, None) => challenge_timestamp,
+        (_, Some(previous)) if previous.resumed_at.is_none() => previous.paused_at,
+        _ => return Err(malformed("game not adjourned")),
+    };
+    if timestamp < earliest {
+        return Err(malformed("timestamp goes backwards"));
+    }
+    Ok(())
+}
+
+pub(crate) fn check_extension(chain: &GameChain, block: &ExtensionBlock) -> Result<(), Error> {
+    check_next(
+        chain.adjournments().last(),
+        chain.challenge().timestamp(),
+        block,
+    )
+}
+
+/// Checks that a move may be appended, which it can't while the game is
+/// adjourned.
+pub(crate) fn check_move(chain: &GameChain) -> Result<(), Error> {
+    if chain.is_adjourned() {
+        return Err(Error::GameAdjourned);
+    }
+    Ok(())
+}
+
+/// Checks every pause and resume in the chain, and that no move was played
+/// while the game was adjourned.
+pub(crate) fn check_chain(chain: &GameChain) -> Result<(), Error> {
+    let mut adjournments: Vec<Adjournment> = Vec::new();
+    for (at, block) in chain.extensions() {
+        if block.kind() != ExtensionKind::Pause && block.kind() != ExtensionKind::Resume {
+            continue;
+        }
+        check_next(adjournments.last(), chain.challenge().timestamp(), block)?;
+        match adjournments.last_mut() {
+            Some(last) if block.kind() == ExtensionKind::Resume => {
+                if last.move_count != *at {
+                    return Err(Error::GameAdjourned);
+                }
+                last.resumed_at = timestamp(block);
+            }
+            _ => adjournments.push(Adjournment {
+                move_count: *at,
+                paused_at: timestamp(block).unwrap_or(0),
+                resumed_at: None,
+            }),
+        }
+    }
+    match adjournments.last() {
+        Some(last) if last.resumed_at.is_none() && last.move_count != chain.move_count() => {
+            Err(Error::GameAdjourned)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlockBuilder, GameStatus};
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use chess::{Action, ChessMove, Square};
+
+    fn uci(mv: &str) -> Action {
+        Action::MakeMove(ChessMove::new(
+            mv[..2].parse::<Square>().unwrap(),
+            mv[2..4].parse::<Square>().unwrap(),
+            None,
+        ))
+    }
+
+    #[test]
+    fn adjourn_and_resume() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .timestamp(1_000)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        chain.make_move_block(&white, uci("e2e4")).unwrap();
+
+        assert!(chain.resume(&black, 1_100).is_err());
+        assert_eq!(chain.adjourn(&white, 1_100), Err(Error::NotYourTurn));
+        chain.adjourn(&black, 1_100).unwrap();
+        assert_eq!(chain.status(), GameStatus::Adjourned);
+        assert_eq!(chain.adjourn(&black, 1_200), Err(Error::GameAdjourned));
+        assert_eq!(
+            chain.make_move_block(&black, uci("e7e5")),
+            Err(Error::GameAdjourned)
+        );
+        assert!(chain.resume(&black, 1_050).is_err());
+        chain.resume(&black, 5_000).unwrap();
+        assert_eq!(chain.status(), GameStatus::InProgress);
+        chain.make_move_block(&black, uci("e7e5")).unwrap();
+
+        assert_eq!(
+            chain.adjournments(),
+            vec![Adjournment {
+                move_count: 1,
+                paused_at: 1_100,
+                resumed_at: Some(5_000),
+            }]
+        );
+        assert_eq!(chain.active_seconds(1_000, 6_000), 1_100);
+        assert_eq!(chain.active_seconds(2_000, 3_000), 0);
+
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain));
+    }
+}
@@ -0,0 +1,194 @@
+//! Adjudications. When a tournament's adjournment deadline passes with a
+//! game unfinished, the arbiter named in the challenge can sign an assessed
+//! result, optionally with a reference to the engine evaluation behind it,
+//! like a hash or a URL. The adjudicated result ends the game like a
+//! checkmate, so only a dispute and its resolution may follow.
+
+use crate::block::{ChainEntry, GameChain, GameResult};
+use crate::commitment;
+use crate::dispute;
+use crate::error::Error;
+use crate::extension::ExtensionKind;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use chess::{BoardStatus, Color};
+use ring::signature::Ed25519KeyPair;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Adjudication {
+    /// The number of moves played before the game was adjudicated.
+    pub move_count: usize,
+    pub result: GameResult,
+    /// What the arbiter based the result on, if they said.
+    pub evaluation: Option<String>,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "adjudication",
+        reason,
+    }
+}
+
+/// The body is the result (0 for a white win, 1 for a black win, 2 for a
+/// draw), then the evaluation reference as UTF-8, empty if there is none.
+fn parse_body(body: &[u8]) -> Result<(GameResult, Option<String>), Error> {
+    let result = match body.first() {
+        Some(0) => GameResult::Win(Color::White),
+        Some(1) => GameResult::Win(Color::Black),
+        Some(2) => GameResult::Draw,
+        _ => return Err(malformed("unknown result")),
+    };
+    let evaluation =
+        String::from_utf8(body[1..].to_vec()).map_err(|_| malformed("evaluation is not UTF-8"))?;
+    Ok((result, Some(evaluation).filter(|text| !text.is_empty())))
+}
+
+pub(crate) fn check_extension(chain: &GameChain, body: &[u8]) -> Result<(), Error> {
+    parse_body(body)?;
+    if chain.claimed_result().is_some() {
+        return Err(Error::GameOver);
+    }
+    Ok(())
+}
+
+/// Checks that no move follows an adjudication.
+pub(crate) fn check_move(chain: &GameChain) -> Result<(), Error> {
+    if chain.adjudication().is_some() {
+        return Err(Error::GameOver);
+    }
+    Ok(())
+}
+
+/// Checks that only a dispute and its resolution follow an adjudication,
+/// and that the game hadn't already ended.
+pub(crate) fn check_chain(chain: &GameChain) -> Result<(), Error> {
+    let entries = chain.entries();
+    let at = match entries.iter().position(|entry| match entry {
+        ChainEntry::Extension(block) => block.kind() == ExtensionKind::Adjudication,
+        _ => false,
+    }) {
+        Some(at) => at,
+        None => return Ok(()),
+    };
+    for entry in &entries[at + 1..] {
+        match entry {
+            ChainEntry::Extension(block) if dispute::follows_result(block.kind()) => {}
+            _ => return Err(Error::GameOver),
+        }
+    }
+    // no move follows, so the game ended before it if it's over without it
+    if chain.time_forfeit().is_some()
+        || commitment::illegal_reply(chain).is_some()
+        || chain.current_position()?.status() != BoardStatus::Ongoing
+    {
+        return Err(Error::GameOver);
+    }
+    Ok(())
+}
+
+impl GameChain {
+    /// Records `result` for the unfinished game, signed by its arbiter,
+    /// with a reference to the evaluation it rests on.
+    pub fn adjudicate(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        result: GameResult,
+        evaluation: Option<&str>,
+    ) -> Result<(), Error> {
+        if self.challenge().arbiter_public_key().is_none() {
+            return Err(malformed("the game has no arbiter"));
+        }
+        let mut body = Vec::with_capacity(1 + evaluation.map_or(0, str::len));
+        body.push(match result {
+            GameResult::Win(Color::White) => 0,
+            GameResult::Win(Color::Black) => 1,
+            GameResult::Draw => 2,
+        });
+        body.extend(evaluation.unwrap_or_default().as_bytes());
+        self.sign_extension(key_pair, ExtensionKind::Adjudication, body)
+    }
+
+    /// The arbiter's adjudication, if the game was adjudicated.
+    pub fn adjudication(&self) -> Option<Adjudication> {
+        self.extensions()
+            .iter()
+            .find(|(_, block)| block.kind() == ExtensionKind::Adjudication)
+            .and_then(|(at, block)| {
+                let (result, evaluation) = parse_body(block.body()).ok()?;
+                Some(Adjudication {
+                    move_count: *at,
+                    result,
+                    evaluation,
+                })
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlockBuilder, GameStatus};
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn arbiter_adjudicates() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let arbiter = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .arbiter(&PublicKey::of(&arbiter))
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let mv = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(mv)).unwrap();
+        chain.adjourn(&black, 1_000).unwrap();
+        assert_eq!(chain.status(), GameStatus::Adjourned);
+
+        assert_eq!(
+            chain.adjudicate(&white, GameResult::Win(Color::White), None),
+            Err(Error::NotYourTurn)
+        );
+        chain
+            .adjudicate(&arbiter, GameResult::Draw, Some("sf16 depth 40: 0.00"))
+            .unwrap();
+        assert_eq!(
+            chain.adjudication(),
+            Some(Adjudication {
+                move_count: 1,
+                result: GameResult::Draw,
+                evaluation: Some("sf16 depth 40: 0.00".into()),
+            })
+        );
+        assert_eq!(chain.status(), GameStatus::Finished(GameResult::Draw));
+        assert_eq!(
+            chain.adjudicate(&arbiter, GameResult::Draw, None),
+            Err(Error::GameOver)
+        );
+        assert_eq!(chain.resume(&black, 2_000), Err(Error::GameOver));
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain.clone()));
+
+        // the adjudicated result can still be disputed
+        chain.dispute_result(&black, "I was winning").unwrap();
+        assert_eq!(chain.status(), GameStatus::Disputed);
+
+        let mut unrefereed = GameChain::new(crate::block::ChallengeBlock::new(
+            &PublicKey::of(&white),
+            &PublicKey::of(&black),
+        ));
+        unrefereed.accept(&white).unwrap();
+        unrefereed.accept(&black).unwrap();
+        assert!(unrefereed
+            .adjudicate(&arbiter, GameResult::Draw, None)
+            .is_err());
+    }
+}
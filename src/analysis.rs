@@ -0,0 +1,216 @@
+//! Engine analysis. An annotator signs an evaluation of a move with their own
+//! key, naming the engine that produced it. Like commentary, analysis blocks
+//! are carried after the game's blocks, no game block signs them, and game
+//! verification ignores them.
+
+use crate::block::GameChain;
+use crate::error::Error;
+use crate::extension::{ExtensionBlock, ExtensionKind};
+use crate::notation;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use chess::ChessMove;
+use core::str;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Evaluation {
+    /// The engine's name and version, as reported by it.
+    pub engine: String,
+    /// The score in centipawns, from white's point of view.
+    pub score_cp: i32,
+    pub depth: u8,
+    /// The line the engine expects, starting with the reply to the move.
+    pub best_line: Vec<ChessMove>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Analysis {
+    pub annotator_public_key: [u8; 32],
+    /// The index of the move the evaluation is of the position after.
+    pub move_index: usize,
+    pub evaluation: Evaluation,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "analysis",
+        reason,
+    }
+}
+
+/// Reads an analysis body: the annotator's public key, the move index as a
+/// four-byte big-endian integer, the score as a four-byte big-endian signed
+/// integer, the depth, the engine name's length and the name, and the best
+/// line as space-separated UCI moves.
+fn parse(block: &ExtensionBlock) -> Result<Analysis, Error> {
+    let body = block.body();
+    if block.kind() != ExtensionKind::Analysis || body.len() < 42 {
+        return Err(malformed("wrong body"));
+    }
+    let mut annotator_public_key = [0; 32];
+    annotator_public_key.copy_from_slice(&body[..32]);
+    let mut move_index = [0; 4];
+    move_index.copy_from_slice(&body[32..36]);
+    let mut score_cp = [0; 4];
+    score_cp.copy_from_slice(&body[36..40]);
+    let depth = body[40];
+    let engine_end = 42 + body[41] as usize;
+    let engine = body
+        .get(42..engine_end)
+        .ok_or_else(|| malformed("engine name is cut off"))?;
+    let engine = str::from_utf8(engine).map_err(|_| malformed("engine name is not UTF-8"))?;
+    let best_line =
+        str::from_utf8(&body[engine_end..]).map_err(|_| malformed("best line is not UTF-8"))?;
+    let best_line = best_line
+        .split_whitespace()
+        .map(notation::parse_uci)
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| malformed("best line is not UCI"))?;
+    Ok(Analysis {
+        annotator_public_key,
+        move_index: u32::from_be_bytes(move_index) as usize,
+        evaluation: Evaluation {
+            engine: engine.into(),
+            score_cp: i32::from_be_bytes(score_cp),
+            depth,
+            best_line,
+        },
+    })
+}
+
+impl GameChain {
+    /// Signs an evaluation of the move at `move_index` with the annotator's
+    /// key.
+    pub fn add_analysis(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        move_index: usize,
+        evaluation: &Evaluation,
+    ) -> Result<(), Error> {
+        if move_index >= self.move_count() {
+            return Err(malformed("no such move"));
+        }
+        if evaluation.engine.len() > u8::MAX as usize {
+            return Err(malformed("engine name is too long"));
+        }
+        let mut body = Vec::with_capacity(42 + evaluation.engine.len());
+        body.extend(key_pair.public_key().as_ref());
+        body.extend(&(move_index as u32).to_be_bytes());
+        body.extend(&evaluation.score_cp.to_be_bytes());
+        body.push(evaluation.depth);
+        body.push(evaluation.engine.len() as u8);
+        body.extend(evaluation.engine.as_bytes());
+        let best_line: Vec<String> = evaluation
+            .best_line
+            .iter()
+            .map(|mv| format!("{}", mv))
+            .collect();
+        body.extend(best_line.join(" ").as_bytes());
+        let block = ExtensionBlock::new(
+            &self.canonical_id(),
+            ExtensionKind::Analysis,
+            body,
+            key_pair,
+        );
+        self.append_analysis_block(block)
+    }
+
+    /// Appends an analysis block signed elsewhere, after checking its
+    /// signature.
+    pub fn append_analysis_block(&mut self, block: ExtensionBlock) -> Result<(), Error> {
+        if self.accepts().iter().any(Option::is_none) {
+            return Err(Error::VerificationFailed);
+        }
+        let analysis = parse(&block)?;
+        if !block.verify(&self.canonical_id(), &analysis.annotator_public_key) {
+            return Err(Error::VerificationFailed);
+        }
+        self.push_commentary(block)
+    }
+
+    /// The evaluations whose signatures verify, in order. Those that don't
+    /// verify are left out rather than failing the game.
+    pub fn analysis(&self) -> Vec<Analysis> {
+        let canonical_id = self.canonical_id();
+        self.commentary()
+            .iter()
+            .filter_map(|block| {
+                let analysis = parse(block).ok()?;
+                if block.verify(&canonical_id, &analysis.annotator_public_key) {
+                    Some(analysis)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// The verified evaluations of the move at `move_index`.
+    pub fn analysis_of(&self, move_index: usize) -> Vec<Analysis> {
+        self.analysis()
+            .into_iter()
+            .filter(|analysis| analysis.move_index == move_index)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use chess::Action;
+
+    #[test]
+    fn analyse_moves() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let annotator = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let e4 = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(e4)).unwrap();
+
+        let evaluation = Evaluation {
+            engine: "Stockfish 10".into(),
+            score_cp: -25,
+            depth: 22,
+            best_line: ["c7c5", "g1f3"]
+                .iter()
+                .map(|uci| notation::parse_uci(uci).unwrap())
+                .collect(),
+        };
+        assert!(chain.add_analysis(&annotator, 1, &evaluation).is_err());
+        chain.add_analysis(&annotator, 0, &evaluation).unwrap();
+        chain.annotate(&annotator, 0, "Best by test").unwrap();
+        assert_eq!(chain.comments().len(), 1);
+        let analysis = chain.analysis_of(0);
+        assert_eq!(analysis.len(), 1);
+        assert_eq!(analysis[0].evaluation, evaluation);
+        assert_eq!(
+            &analysis[0].annotator_public_key[..],
+            annotator.public_key().as_ref()
+        );
+
+        // analysis survives encoding but isn't part of the game
+        let restored = GameChain::from_bytes(&chain.as_bytes()).unwrap();
+        assert!(restored.verify());
+        assert_eq!(restored.analysis(), chain.analysis());
+        assert_eq!(restored.truncated(1).analysis(), Vec::new());
+
+        let mut forged = chain.commentary()[0].as_bytes();
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+        assert_eq!(
+            chain.append_extension_block(ExtensionBlock::from_bytes(&forged).unwrap()),
+            Err(Error::VerificationFailed)
+        );
+    }
+}
@@ -0,0 +1,488 @@
+//! Animated replays of verified games, for sharing them as images. Each
+//! frame is a position on the game line with the move that reached it
+//! highlighted, stamped with the ply, the move, and the fingerprint of the
+//! key that signed it. The first frame shows the starting position and the
+//! chain id.
+//!
+//! GIFs are drawn with a built-in 3x5 pixel font and a fixed eight-color
+//! palette; SVGs use the Unicode chess glyphs and loop with CSS animation.
+
+use crate::block::GameChain;
+use crate::error::Error;
+use crate::render::{self, BoardStyle};
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use chess::{Board, ChessMove, Color, File, Rank, Square};
+
+const SQUARE: usize = 24;
+const BOARD: usize = 8 * SQUARE;
+const CAPTION: usize = 14;
+const TEXT_SCALE: usize = 2;
+const PIECE_SCALE: usize = 4;
+
+const LIGHT: u8 = 0;
+const DARK: u8 = 1;
+const WHITE: u8 = 2;
+const BLACK: u8 = 3;
+const CAPTION_BACKGROUND: u8 = 4;
+const HIGHLIGHT: u8 = 5;
+
+const PALETTE: [[u8; 3]; 8] = [
+    [240, 217, 181],
+    [181, 136, 99],
+    [255, 255, 255],
+    [0, 0, 0],
+    [48, 48, 48],
+    [205, 210, 106],
+    [0, 0, 0],
+    [0, 0, 0],
+];
+
+fn hex(color: u8) -> String {
+    let [r, g, b] = PALETTE[color as usize];
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// How replays are drawn. By default a frame is shown for a second, from
+/// white's side.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationOptions {
+    frame_ms: u32,
+    orientation: Color,
+}
+
+impl Default for AnimationOptions {
+    fn default() -> AnimationOptions {
+        AnimationOptions {
+            frame_ms: 1000,
+            orientation: Color::White,
+        }
+    }
+}
+
+impl AnimationOptions {
+    pub fn new() -> AnimationOptions {
+        AnimationOptions::default()
+    }
+
+    pub fn frame_ms(mut self, frame_ms: u32) -> AnimationOptions {
+        self.frame_ms = frame_ms;
+        self
+    }
+
+    /// The side shown at the bottom of the board.
+    pub fn orientation(mut self, orientation: Color) -> AnimationOptions {
+        self.orientation = orientation;
+        self
+    }
+}
+
+struct Frame {
+    board: Board,
+    last_move: Option<ChessMove>,
+    caption: String,
+}
+
+/// The top-left corner of `square` in pixels.
+fn square_origin(square: Square, orientation: Color) -> (usize, usize) {
+    let (file, rank) = (square.get_file().to_index(), square.get_rank().to_index());
+    match orientation {
+        Color::White => (file * SQUARE, (7 - rank) * SQUARE),
+        Color::Black => ((7 - file) * SQUARE, rank * SQUARE),
+    }
+}
+
+fn squares() -> impl Iterator<Item = Square> {
+    (0..64).map(|i| Square::make_square(Rank::from_index(i / 8), File::from_index(i % 8)))
+}
+
+fn is_light(square: Square) -> bool {
+    (square.get_file().to_index() + square.get_rank().to_index()) % 2 == 1
+}
+
+impl GameChain {
+    fn frames(&self) -> Result<Vec<Frame>, Error> {
+        if !self.verify() {
+            return Err(Error::VerificationFailed);
+        }
+        let mut frames = vec![Frame {
+            board: self.challenge().starting_board(),
+            last_move: None,
+            caption: format!("{:08x}", self.id()),
+        }];
+        for (index, mv, board, signer) in self.iter_positions() {
+            frames.push(Frame {
+                board,
+                last_move: Some(mv),
+                caption: format!("{}. {} {}", index + 1, mv, signer),
+            });
+        }
+        Ok(frames)
+    }
+
+    /// The game line as a looping animated GIF.
+    pub fn to_animated_gif(&self, options: &AnimationOptions) -> Result<Vec<u8>, Error> {
+        let frames = self.frames()?;
+        let (width, height) = (BOARD, BOARD + CAPTION);
+        let mut gif = Vec::new();
+        gif.extend(b"GIF89a");
+        gif.extend(&(width as u16).to_le_bytes());
+        gif.extend(&(height as u16).to_le_bytes());
+        // a global table of 2^3 colors
+        gif.extend(&[0xa2, 0, 0]);
+        for color in &PALETTE {
+            gif.extend(color);
+        }
+        // loop forever
+        gif.extend(&[0x21, 0xff, 0x0b]);
+        gif.extend(b"NETSCAPE2.0");
+        gif.extend(&[0x03, 0x01, 0, 0, 0]);
+
+        let delay = (options.frame_ms / 10).min(u16::MAX as u32) as u16;
+        for frame in &frames {
+            gif.extend(&[0x21, 0xf9, 0x04, 0x04]);
+            gif.extend(&delay.to_le_bytes());
+            gif.extend(&[0, 0]);
+            gif.push(0x2c);
+            gif.extend(&[0, 0, 0, 0]);
+            gif.extend(&(width as u16).to_le_bytes());
+            gif.extend(&(height as u16).to_le_bytes());
+            gif.push(0);
+            gif.push(3);
+            let pixels = draw(frame, options.orientation);
+            for block in lzw(&pixels).chunks(255) {
+                gif.push(block.len() as u8);
+                gif.extend(block);
+            }
+            gif.push(0);
+        }
+        gif.push(0x3b);
+        Ok(gif)
+    }
+
+    /// The game line as a looping animated SVG.
+    pub fn to_animated_svg(&self, options: &AnimationOptions) -> Result<String, Error> {
+        let frames = self.frames()?;
+        let seconds = options.frame_ms as f64 / 1000.0;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\" \
+             font-family=\"monospace\" text-anchor=\"middle\">\n",
+            BOARD,
+            BOARD + CAPTION
+        );
+        svg.push_str(&format!(
+            "<style>.f{{opacity:0;animation:f {}s step-end infinite}}\
+             @keyframes f{{0%{{opacity:1}}{}%{{opacity:0}}}}</style>\n",
+            seconds * frames.len() as f64,
+            100.0 / frames.len() as f64
+        ));
+        for square in squares() {
+            let (x, y) = square_origin(square, options.orientation);
+            let color = if is_light(square) { LIGHT } else { DARK };
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                x,
+                y,
+                SQUARE,
+                SQUARE,
+                hex(color)
+            ));
+        }
+        svg.push_str(&format!(
+            "<rect y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+            BOARD,
+            BOARD,
+            CAPTION,
+            hex(CAPTION_BACKGROUND)
+        ));
+        for (i, frame) in frames.iter().enumerate() {
+            svg.push_str(&format!(
+                "<g class=\"f\" style=\"animation-delay:{}s\">\n",
+                seconds * i as f64
+            ));
+            if let Some(mv) = frame.last_move {
+                for &square in &[mv.get_source(), mv.get_dest()] {
+                    let (x, y) = square_origin(square, options.orientation);
+                    svg.push_str(&format!(
+                        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                        x,
+                        y,
+                        SQUARE,
+                        SQUARE,
+                        hex(HIGHLIGHT)
+                    ));
+                }
+            }
+            for square in squares() {
+                if let (Some(piece), Some(color)) =
+                    (frame.board.piece_on(square), frame.board.color_on(square))
+                {
+                    let (x, y) = square_origin(square, options.orientation);
+                    svg.push_str(&format!(
+                        "<text x=\"{}\" y=\"{}\" font-size=\"20\">{}</text>\n",
+                        x + SQUARE / 2,
+                        y + SQUARE - 5,
+                        render::piece_char(piece, color, BoardStyle::Unicode)
+                    ));
+                }
+            }
+            svg.push_str(&format!(
+                "<text x=\"{}\" y=\"{}\" font-size=\"10\" fill=\"{}\">{}</text>\n</g>\n",
+                BOARD / 2,
+                BOARD + CAPTION - 4,
+                hex(WHITE),
+                frame.caption
+            ));
+        }
+        svg.push_str("</svg>\n");
+        Ok(svg)
+    }
+}
+
+/// Draws a frame as palette indices, row by row.
+fn draw(frame: &Frame, orientation: Color) -> Vec<u8> {
+    let mut canvas = Canvas {
+        pixels: vec![CAPTION_BACKGROUND; BOARD * (BOARD + CAPTION)],
+    };
+    let highlighted = |square: Square| {
+        frame
+            .last_move
+            .is_some_and(|mv| mv.get_source() == square || mv.get_dest() == square)
+    };
+    for square in squares() {
+        let (x, y) = square_origin(square, orientation);
+        let color = if highlighted(square) {
+            HIGHLIGHT
+        } else if is_light(square) {
+            LIGHT
+        } else {
+            DARK
+        };
+        canvas.fill(x, y, SQUARE, SQUARE, color);
+        if let (Some(piece), Some(side)) =
+            (frame.board.piece_on(square), frame.board.color_on(square))
+        {
+            let letter = render::piece_char(piece, Color::White, BoardStyle::Ascii);
+            let (fill, outline) = match side {
+                Color::White => (WHITE, BLACK),
+                Color::Black => (BLACK, WHITE),
+            };
+            let (x, y) = (
+                x + (SQUARE - 3 * PIECE_SCALE) / 2,
+                y + (SQUARE - 5 * PIECE_SCALE) / 2,
+            );
+            canvas.glyph(letter, x, y, PIECE_SCALE, 1, outline);
+            canvas.glyph(letter, x, y, PIECE_SCALE, 0, fill);
+        }
+    }
+    let advance = 4 * TEXT_SCALE;
+    let fits = (BOARD - 2 * TEXT_SCALE) / advance;
+    for (i, c) in frame.caption.chars().take(fits).enumerate() {
+        canvas.glyph(
+            c,
+            2 * TEXT_SCALE + i * advance,
+            BOARD + TEXT_SCALE,
+            TEXT_SCALE,
+            0,
+            WHITE,
+        );
+    }
+    canvas.pixels
+}
+
+struct Canvas {
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn fill(&mut self, x: usize, y: usize, width: usize, height: usize, color: u8) {
+        for row in y..y + height {
+            let start = row * BOARD + x;
+            for pixel in &mut self.pixels[start..start + width] {
+                *pixel = color;
+            }
+        }
+    }
+
+    /// Draws `c` at `scale` with its top-left corner at (x, y), widening
+    /// each of its pixels by `spread` on every side.
+    fn glyph(&mut self, c: char, x: usize, y: usize, scale: usize, spread: usize, color: u8) {
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) != 0 {
+                    self.fill(
+                        x + column * scale - spread,
+                        y + row * scale - spread,
+                        scale + 2 * spread,
+                        scale + 2 * spread,
+                        color,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Compresses palette indices into GIF image data with a minimum code size
+/// of 3. Only literal codes are written, with a clear code before every six
+/// so that codes stay four bits wide: valid LZW, if not much smaller.
+fn lzw(pixels: &[u8]) -> Vec<u8> {
+    const CLEAR: u32 = 8;
+    const END: u32 = 9;
+    let mut data = Vec::with_capacity(pixels.len() * 7 / 12 + 1);
+    let (mut bits, mut count) = (0u32, 0);
+    let mut push = |code: u32, data: &mut Vec<u8>| {
+        bits |= code << count;
+        count += 4;
+        while count >= 8 {
+            data.push(bits as u8);
+            bits >>= 8;
+            count -= 8;
+        }
+    };
+    for chunk in pixels.chunks(6) {
+        push(CLEAR, &mut data);
+        for &pixel in chunk {
+            push(pixel as u32, &mut data);
+        }
+    }
+    push(END, &mut data);
+    if count > 0 {
+        data.push(bits as u8);
+    }
+    data
+}
+
+/// Rows of a 3x5 glyph, most significant bit leftmost. Characters without
+/// a glyph are drawn as a question mark.
+fn glyph(c: char) -> [u8; 5] {
+    match c {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b110, 0b001, 0b010, 0b100, 0b111],
+        '3' => [0b110, 0b001, 0b010, 0b001, 0b110],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b110, 0b001, 0b110],
+        '6' => [0b011, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b110],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        'a' => [0b000, 0b011, 0b101, 0b101, 0b011],
+        'b' => [0b100, 0b110, 0b101, 0b101, 0b110],
+        'c' => [0b000, 0b011, 0b100, 0b100, 0b011],
+        'd' => [0b001, 0b011, 0b101, 0b101, 0b011],
+        'e' => [0b000, 0b010, 0b111, 0b100, 0b011],
+        'f' => [0b001, 0b010, 0b111, 0b010, 0b010],
+        'g' => [0b000, 0b011, 0b101, 0b011, 0b110],
+        'h' => [0b100, 0b110, 0b101, 0b101, 0b101],
+        'i' => [0b010, 0b000, 0b010, 0b010, 0b010],
+        'j' => [0b001, 0b000, 0b001, 0b101, 0b010],
+        'k' => [0b100, 0b101, 0b110, 0b110, 0b101],
+        'l' => [0b110, 0b010, 0b010, 0b010, 0b111],
+        'm' => [0b000, 0b110, 0b111, 0b111, 0b101],
+        'n' => [0b000, 0b110, 0b101, 0b101, 0b101],
+        'o' => [0b000, 0b010, 0b101, 0b101, 0b010],
+        'p' => [0b000, 0b110, 0b101, 0b110, 0b100],
+        'q' => [0b000, 0b011, 0b101, 0b011, 0b001],
+        'r' => [0b000, 0b011, 0b100, 0b100, 0b100],
+        's' => [0b000, 0b011, 0b110, 0b011, 0b110],
+        't' => [0b010, 0b111, 0b010, 0b010, 0b001],
+        'u' => [0b000, 0b101, 0b101, 0b101, 0b011],
+        'v' => [0b000, 0b101, 0b101, 0b101, 0b010],
+        'w' => [0b000, 0b101, 0b101, 0b111, 0b111],
+        'x' => [0b000, 0b101, 0b010, 0b010, 0b101],
+        'y' => [0b000, 0b101, 0b101, 0b011, 0b110],
+        'z' => [0b000, 0b111, 0b001, 0b100, 0b111],
+        ' ' => [0b000; 5],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        _ => [0b110, 0b001, 0b010, 0b000, 0b010],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+    use ring::signature::KeyPair;
+
+    #[test]
+    fn animate_game() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        assert_eq!(
+            chain.to_animated_svg(&AnimationOptions::new()),
+            Err(Error::VerificationFailed)
+        );
+        chain.accept(&black).unwrap();
+        for (i, uci) in ["e2e4", "e7e5", "g1f3"].iter().enumerate() {
+            let key_pair = if i % 2 == 0 { &white } else { &black };
+            let mv = notation::parse_uci(uci).unwrap();
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        }
+
+        let options = AnimationOptions::new().frame_ms(500);
+        let svg = chain.to_animated_svg(&options).unwrap();
+        assert_eq!(svg.matches("class=\"f\"").count(), 4);
+        assert!(svg.contains(&format!(
+            "3. g1f3 {}",
+            crypto::fingerprint(white.public_key().as_ref())
+        )));
+        assert!(svg.contains("animation-delay:1.5s"));
+
+        let gif = chain.to_animated_gif(&options).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+        assert_eq!(&gif[6..10], &[192, 0, 206, 0]);
+        assert_eq!(gif.last(), Some(&0x3b));
+        let black_side = chain
+            .to_animated_gif(&options.orientation(Color::Black))
+            .unwrap();
+        assert_ne!(gif, black_side);
+    }
+
+    #[test]
+    fn literal_lzw() {
+        // a clear code, six literals, a clear, the seventh, and the end
+        let data = lzw(&[1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(data, [0x18, 0x32, 0x54, 0x86, 0x97]);
+    }
+}
@@ -0,0 +1,231 @@
+//! Structured audit reports for arbiters and third-party verifiers.
+
+use crate::block::{GameChain, GameResult};
+use crate::forfeit;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use chess::Color;
+
+/// How far in the future a timestamp may be before it's implausible, to allow
+/// for clock skew between machines.
+pub const MAX_CLOCK_SKEW_SECONDS: u64 = 5 * 60;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Check {
+    Passed,
+    Failed(String),
+    NotApplicable(String),
+}
+
+impl Check {
+    fn to_json(&self) -> String {
+        match self {
+            Check::Passed => "{\"status\":\"passed\"}".to_string(),
+            Check::Failed(reason) => format!(
+                "{{\"status\":\"failed\",\"reason\":{}}}",
+                json_string(reason)
+            ),
+            Check::NotApplicable(reason) => format!(
+                "{{\"status\":\"not_applicable\",\"reason\":{}}}",
+                json_string(reason)
+            ),
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditReport {
+    pub chain_id: u32,
+    pub move_count: usize,
+    pub signatures: Check,
+    pub move_legality: Check,
+    pub timestamps: Check,
+    pub clock: Check,
+    pub result: Check,
+    pub outcome: Option<GameResult>,
+}
+
+impl AuditReport {
+    /// Audits a chain. `now` is the current Unix time in seconds, used to
+    /// judge whether timestamps are plausible.
+    pub fn for_chain(chain: &GameChain, now: u64) -> AuditReport {
+        let accepted = chain.accepts().iter().all(Option::is_some);
+        let signatures = if !accepted {
+            Check::Failed("the challenge has not been accepted by both players".to_string())
+        } else if chain.verify() {
+            Check::Passed
+        } else {
+            Check::Failed("a signature does not verify".to_string())
+        };
+
+        let game = chain.get_game();
+        let move_legality = match &game {
+            Ok(_) => Check::Passed,
+            Err(error) => Check::Failed(error.to_string()),
+        };
+
+        let timestamp = chain.challenge().timestamp();
+        let timestamps = if timestamp == 0 {
+            Check::NotApplicable("the challenge has no timestamp".to_string())
+        } else if timestamp > now + MAX_CLOCK_SKEW_SECONDS {
+            Check::Failed(format!(
+                "the challenge timestamp {} is in the future",
+                timestamp
+            ))
+        } else {
+            Check::Passed
+        };
+
+        let clock = match chain.challenge().time_control() {
+            None => Check::NotApplicable("the game is untimed".to_string()),
+            Some(_) if chain.time_forfeit().is_some() => match forfeit::check_chain(chain) {
+                Ok(_) => Check::Passed,
+                Err(error) => Check::Failed(error.to_string()),
+            },
+            Some(_) => Check::NotApplicable("no time forfeit was claimed".to_string()),
+        };
+
+        // a finished game can't have moves after the end, and replaying it
+        // has to reach the same result the chain reports
+        let outcome = chain.result();
+        let result = match &game {
+            Err(_) => Check::NotApplicable("moves are not legal".to_string()),
+            Ok(game) => {
+                let board = game.current_position();
+                let consistent = match outcome {
+                    Some(GameResult::Win(color)) => {
+                        board.status() == chess::BoardStatus::Checkmate
+                            && board.side_to_move() == !color
+                    }
+                    Some(GameResult::Draw) => board.status() != chess::BoardStatus::Ongoing,
+                    None => board.status() == chess::BoardStatus::Ongoing,
+                };
+                if consistent {
+                    Check::Passed
+                } else {
+                    Check::Failed("the recorded result does not match the position".to_string())
+                }
+            }
+        };
+
+        AuditReport {
+            chain_id: chain.id(),
+            move_count: chain.move_count(),
+            signatures,
+            move_legality,
+            timestamps,
+            clock,
+            result,
+            outcome,
+        }
+    }
+
+    /// True if nothing failed. Checks that didn't apply don't count against
+    /// the chain.
+    pub fn passed(&self) -> bool {
+        [
+            &self.signatures,
+            &self.move_legality,
+            &self.timestamps,
+            &self.clock,
+            &self.result,
+        ]
+        .iter()
+        .all(|check| !matches!(check, Check::Failed(_)))
+    }
+
+    pub fn to_json(&self) -> String {
+        let outcome = match self.outcome {
+            Some(GameResult::Win(Color::White)) => "\"white\"",
+            Some(GameResult::Win(Color::Black)) => "\"black\"",
+            Some(GameResult::Draw) => "\"draw\"",
+            None => "null",
+        };
+        format!(
+            "{{\"chain_id\":{},\"move_count\":{},\"passed\":{},\"signatures\":{},\"move_legality\":{},\"timestamps\":{},\"clock\":{},\"result\":{},\"outcome\":{}}}",
+            self.chain_id,
+            self.move_count,
+            self.passed(),
+            self.signatures.to_json(),
+            self.move_legality.to_json(),
+            self.timestamps.to_json(),
+            self.clock.to_json(),
+            self.result.to_json(),
+            outcome
+        )
+    }
+}
+
+/// Audits every chain in a bundle.
+pub fn audit_bundle(chains: &[GameChain], now: u64) -> Vec<AuditReport> {
+    chains
+        .iter()
+        .map(|chain| AuditReport::for_chain(chain, now))
+        .collect()
+}
+
+/// Serializes a bundle's reports as a JSON array.
+pub fn bundle_to_json(reports: &[AuditReport]) -> String {
+    let reports: Vec<String> = reports.iter().map(AuditReport::to_json).collect();
+    format!("[{}]", reports.join(","))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlock, ChallengeBlockBuilder};
+    use crate::crypto;
+    use crate::key::PublicKey;
+
+    #[test]
+    fn audit_chains() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .timestamp(2_000)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        assert!(chain.accept(&white).is_ok());
+        assert!(chain.accept(&black).is_ok());
+
+        let report = AuditReport::for_chain(&chain, 1_000_000);
+        assert!(report.passed());
+        assert_eq!(report.outcome, None);
+        assert!(report
+            .to_json()
+            .starts_with("{\"chain_id\":0,\"move_count\":0,\"passed\":true,"));
+
+        let report = AuditReport::for_chain(&chain, 1_000);
+        assert!(!report.passed());
+        assert_eq!(report.signatures, Check::Passed);
+        assert!(matches!(report.timestamps, Check::Failed(_)));
+
+        let unaccepted = GameChain::new(ChallengeBlock::new(
+            &PublicKey::of(&white),
+            &PublicKey::of(&black),
+        ));
+        let reports = audit_bundle(&[chain, unaccepted], 1_000_000);
+        assert!(!reports[1].passed());
+        assert!(bundle_to_json(&reports).starts_with("[{"));
+    }
+}
@@ -0,0 +1,414 @@
+//! Acting on a player's behalf in correspondence games they've left running,
+//! e.g. in the daemon. Everything the autopilot does is signed into the
+//! chain as an ordinary block, so its decisions verify like the player's own:
+//! it signs receipts for the opponent's moves as they arrive, and once an
+//! opponent has been out of time for a grace period, claims the forfeit. An
+//! opponent whose heartbeats have stopped can be claimed without the grace.
+//! It can also accept incoming challenges, within limits set by an
+//! AcceptPolicy.
+
+use crate::block::{GameChain, GameStatus, TimeControl};
+use crate::contacts::AddressBook;
+use crate::heartbeat::{self, Heartbeat};
+use crate::manager::GameManager;
+use crate::revocation;
+
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+/// What the autopilot does. By default it does nothing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AutoPolicy {
+    acknowledge_moves: bool,
+    claim_after: Option<u64>,
+    claim_abandoned_after: Option<u64>,
+    accept: Option<AcceptPolicy>,
+}
+
+impl AutoPolicy {
+    pub fn new() -> AutoPolicy {
+        AutoPolicy::default()
+    }
+
+    /// Signs a receipt for each opponent move, starting the opponent's
+    /// clock only once the move has actually arrived.
+    pub fn acknowledge_moves(mut self, acknowledge_moves: bool) -> AutoPolicy {
+        self.acknowledge_moves = acknowledge_moves;
+        self
+    }
+
+    /// Claims a time forfeit once the opponent has been out of time for
+    /// `seconds`, not counting their vacations.
+    pub fn claim_after(mut self, seconds: u64) -> AutoPolicy {
+        self.claim_after = Some(seconds);
+        self
+    }
+
+    /// Claims a time forfeit as soon as the opponent is out of time if they
+    /// haven't sent a heartbeat for `seconds`, or ever, instead of waiting
+    /// out the grace period meant for opponents still thinking.
+    pub fn claim_abandoned_after(mut self, seconds: u64) -> AutoPolicy {
+        self.claim_abandoned_after = Some(seconds);
+        self
+    }
+
+    /// Accepts the challenges `accept` allows.
+    pub fn accept_challenges(mut self, accept: AcceptPolicy) -> AutoPolicy {
+        self.accept = Some(accept);
+        self
+    }
+}
+
+/// Why a challenge wasn't accepted automatically.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Refusal {
+    NotAContact,
+    TimeControl,
+    TooManyGames,
+}
+
+/// Which challenges the autopilot accepts. By default, any challenge at all.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AcceptPolicy {
+    opponents: Option<Vec<[u8; 32]>>,
+    time_controls: Option<Vec<Option<TimeControl>>>,
+    max_games: Option<usize>,
+}
+
+impl AcceptPolicy {
+    pub fn new() -> AcceptPolicy {
+        AcceptPolicy::default()
+    }
+
+    /// Only accepts challenges from the keys in `contacts`.
+    pub fn contacts_only(mut self, contacts: &AddressBook) -> AcceptPolicy {
+        self.opponents = Some(contacts.iter().map(|(_, key)| *key).collect());
+        self
+    }
+
+    /// Accepts challenges with `time_control`, None meaning untimed. Once
+    /// any time control is given, challenges with others are refused.
+    pub fn time_control(mut self, time_control: Option<TimeControl>) -> AcceptPolicy {
+        self.time_controls
+            .get_or_insert_with(Vec::new)
+            .push(time_control);
+        self
+    }
+
+    /// Refuses challenges while `games` games are already in progress or
+    /// adjourned.
+    pub fn max_games(mut self, games: usize) -> AcceptPolicy {
+        self.max_games = Some(games);
+        self
+    }
+
+    /// Whether `public_key` may accept the challenge in `chain` while it has
+    /// `active_games` other games underway.
+    pub fn check(
+        &self,
+        chain: &GameChain,
+        public_key: &[u8],
+        active_games: usize,
+    ) -> Result<(), Refusal> {
+        let challenge = chain.challenge();
+        let opponent = if challenge.white_public_key() == *public_key {
+            challenge.black_public_key()
+        } else {
+            challenge.white_public_key()
+        };
+        if let Some(opponents) = &self.opponents {
+            if !opponents.iter().any(|contact| opponent == *contact) {
+                return Err(Refusal::NotAContact);
+            }
+        }
+        if let Some(time_controls) = &self.time_controls {
+            if !time_controls.contains(&challenge.time_control()) {
+                return Err(Refusal::TimeControl);
+            }
+        }
+        if self.max_games.is_some_and(|max| active_games >= max) {
+            return Err(Refusal::TooManyGames);
+        }
+        Ok(())
+    }
+}
+
+/// Something the autopilot signed into a game.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AutoAction {
+    Accepted { id: u32 },
+    Acknowledged { id: u32, move_index: usize },
+    ClaimedForfeit { id: u32 },
+}
+
+/// Accepts the challenge in `chain` at `now` if it's waiting on `key_pair`
+/// and `policy` allows it, given how many games are already underway.
+pub fn accept_challenge(
+    chain: &mut GameChain,
+    key_pair: &Ed25519KeyPair,
+    policy: &AcceptPolicy,
+    active_games: usize,
+    now: u64,
+) -> Option<AutoAction> {
+    if chain.status() != GameStatus::AwaitingAcceptance {
+        return None;
+    }
+    let public_key = key_pair.public_key().as_ref();
+    chain.side_of(public_key)?;
+    policy.check(chain, public_key, active_games).ok()?;
+    chain.accept_at(key_pair, now).ok()?;
+    Some(AutoAction::Accepted { id: chain.id() })
+}
+
+/// Applies `policy` to one game at `now`, given the heartbeats received for
+/// it, returning what was signed. Challenges are left to accept_challenge.
+pub fn apply(
+    chain: &mut GameChain,
+    key_pair: &Ed25519KeyPair,
+    policy: &AutoPolicy,
+    heartbeats: &[Heartbeat],
+    now: u64,
+) -> Option<AutoAction> {
+    if chain.status() != GameStatus::InProgress {
+        return None;
+    }
+    let public_key = key_pair.public_key().as_ref();
+    chain.side_of(public_key)?;
+    let id = chain.id();
+    let move_count = chain.move_count();
+    if chain.is_my_turn(public_key) {
+        let acknowledged = chain
+            .receipts()
+            .last()
+            .is_some_and(|receipt| receipt.move_index + 1 == move_count);
+        if policy.acknowledge_moves
+            && move_count > 0
+            && !acknowledged
+            && chain.acknowledge_move(key_pair, now).is_ok()
+        {
+            return Some(AutoAction::Acknowledged {
+                id,
+                move_index: move_count - 1,
+            });
+        }
+        return None;
+    }
+    let reading = chain.clock_at(now).ok()?;
+    let abandoned = policy.claim_abandoned_after.is_some_and(|silence| {
+        heartbeat::last_seen(chain, heartbeats, reading.color)
+            .is_none_or(|seen| now.saturating_sub(seen) >= silence)
+    });
+    let claim_after = if abandoned { 0 } else { policy.claim_after? };
+    if reading.flagged()
+        && reading.used_seconds - reading.allowed_seconds >= claim_after
+        && chain.claim_time_forfeit(key_pair, now).is_ok()
+    {
+        return Some(AutoAction::ClaimedForfeit { id });
+    }
+    None
+}
+
+impl GameManager {
+    /// Applies `policy` to every game `key_pair` plays in, returning what was
+    /// signed, by game. Challenges the manager holds revocations for are
+    /// left alone.
+    pub fn apply_policy(
+        &self,
+        key_pair: &Ed25519KeyPair,
+        policy: &AutoPolicy,
+        now: u64,
+    ) -> Vec<AutoAction> {
+        let public_key = key_pair.public_key().as_ref();
+        let mut ids = self.ids();
+        ids.sort_unstable();
+        let mut active_games = ids
+            .iter()
+            .filter(|&&id| {
+                self.with_chain(id, |chain| {
+                    chain.side_of(public_key).is_some()
+                        && matches!(
+                            chain.status(),
+                            GameStatus::InProgress | GameStatus::Adjourned
+                        )
+                })
+                .unwrap_or(false)
+            })
+            .count();
+        let revocations = self.revocations();
+        ids.into_iter()
+            .filter_map(|id| {
+                let heartbeats = self.heartbeats(id);
+                self.with_chain(id, |chain| {
+                    let revoked = revocation::check(chain, &revocations).is_err();
+                    if let (Some(accept), false) = (&policy.accept, revoked) {
+                        let accepted = accept_challenge(chain, key_pair, accept, active_games, now);
+                        if accepted.is_some() {
+                            active_games += 1;
+                            return accepted;
+                        }
+                    }
+                    apply(chain, key_pair, policy, &heartbeats, now)
+                })
+                .ok()?
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlockBuilder, GameResult};
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::{Action, Color};
+
+    #[test]
+    fn claims_after_grace() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let day = 24 * 60 * 60;
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .timestamp(0)
+            .time_control(3 * day as u32, 0)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let e4 = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(e4)).unwrap();
+        let manager = GameManager::new();
+        let id = manager.insert(chain).unwrap();
+
+        // black's node acknowledges the move once, starting black's clock
+        let receipts = AutoPolicy::new().acknowledge_moves(true);
+        assert_eq!(
+            manager.apply_policy(&black, &receipts, 100),
+            vec![AutoAction::Acknowledged { id, move_index: 0 }]
+        );
+        assert_eq!(manager.apply_policy(&black, &receipts, 200), Vec::new());
+
+        // white's node waits a day past black's flag before claiming
+        let claims = AutoPolicy::new().claim_after(day);
+        assert_eq!(
+            manager.apply_policy(&white, &claims, 99 + 4 * day),
+            Vec::new()
+        );
+        assert_eq!(
+            manager.apply_policy(&white, &claims, 100 + 4 * day),
+            vec![AutoAction::ClaimedForfeit { id }]
+        );
+        let chain = manager.get(id).unwrap();
+        assert_eq!(
+            chain.status(),
+            GameStatus::Finished(GameResult::Win(Color::White))
+        );
+        assert!(chain.verify());
+    }
+
+    #[test]
+    fn claims_abandoned_games() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let day = 24 * 60 * 60;
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .timestamp(0)
+            .time_control(3 * day as u32, 0)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let e4 = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(e4)).unwrap();
+        chain.acknowledge_move(&black, 0).unwrap();
+        let heartbeat = chain.heartbeat(&black, 3 * day).unwrap();
+        let manager = GameManager::new();
+        let id = manager.insert(chain).unwrap();
+        manager.record_heartbeat(id, heartbeat).unwrap();
+
+        // black ran out of time at 3 days, but was still there then
+        let claims = AutoPolicy::new()
+            .claim_after(7 * day)
+            .claim_abandoned_after(day);
+        assert_eq!(
+            manager.apply_policy(&white, &claims, 3 * day + 1),
+            Vec::new()
+        );
+        assert_eq!(
+            manager.apply_policy(&white, &claims, 4 * day),
+            vec![AutoAction::ClaimedForfeit { id }]
+        );
+    }
+
+    #[test]
+    fn accepts_allowed_challenges() {
+        let rng = crypto::new_rng();
+        let me = crypto::generate_key(&rng);
+        let friend = crypto::generate_key(&rng);
+        let stranger = crypto::generate_key(&rng);
+        let day = 24 * 60 * 60;
+        let correspondence = TimeControl {
+            base_seconds: 3 * day,
+            increment_seconds: 0,
+        };
+        let manager = GameManager::new();
+        let challenge = |id, from: &Ed25519KeyPair, base_seconds| {
+            let challenge = ChallengeBlockBuilder::new(&PublicKey::of(from), &PublicKey::of(&me))
+                .id(id)
+                .timestamp(0)
+                .time_control(base_seconds, 0)
+                .build()
+                .unwrap();
+            let mut chain = GameChain::new(challenge);
+            chain.accept_at(from, 0).unwrap();
+            manager.insert(chain).unwrap();
+        };
+        challenge(1, &stranger, 3 * day);
+        challenge(2, &friend, 60);
+        challenge(3, &friend, 3 * day);
+        challenge(4, &friend, 3 * day);
+
+        let mut contacts = AddressBook::new();
+        contacts
+            .add("friend", friend.public_key().as_ref())
+            .unwrap();
+        let accept = AcceptPolicy::new()
+            .contacts_only(&contacts)
+            .time_control(Some(correspondence))
+            .max_games(1);
+        let chain = manager.get(1).unwrap();
+        let public_key = me.public_key().as_ref();
+        assert_eq!(
+            accept.check(&chain, public_key, 0),
+            Err(Refusal::NotAContact)
+        );
+        let chain = manager.get(2).unwrap();
+        assert_eq!(
+            accept.check(&chain, public_key, 0),
+            Err(Refusal::TimeControl)
+        );
+        let chain = manager.get(3).unwrap();
+        assert_eq!(accept.check(&chain, public_key, 0), Ok(()));
+        assert_eq!(
+            accept.check(&chain, public_key, 1),
+            Err(Refusal::TooManyGames)
+        );
+
+        // only the third fits, and then there's no room for the fourth
+        let policy = AutoPolicy::new().accept_challenges(accept);
+        assert_eq!(
+            manager.apply_policy(&me, &policy, 100),
+            vec![AutoAction::Accepted { id: 3 }]
+        );
+        assert_eq!(manager.apply_policy(&me, &policy, 200), Vec::new());
+        assert_eq!(manager.get(3).unwrap().status(), GameStatus::InProgress);
+        assert_eq!(
+            manager.get(4).unwrap().status(),
+            GameStatus::AwaitingAcceptance
+        );
+    }
+}
@@ -0,0 +1,484 @@
+//! Encrypted backups of everything a player needs to move to a new device:
+//! key files, the config directory (contacts and the like), and the game
+//! store. The archive is sealed with ChaCha20-Poly1305 under a key derived
+//! from a passphrase with PBKDF2, and every chain is checked before anything
+//! is restored.
+//!
+//! An archive is encoded as: the magic bytes, a version byte, the PBKDF2
+//! iteration count (4 bytes), a salt (16 bytes), a nonce (12 bytes), and the
+//! sealed entries. Each entry is its section (1 byte), name length (2 bytes),
+//! name, contents length (4 bytes), and contents.
+
+use crate::block::{GameChain, GameStatus};
+use crate::crypto::{self, Rng};
+use crate::error::Error;
+use crate::store::GameStore;
+
+use core::num::NonZeroU32;
+use ring::{aead, pbkdf2};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const MAGIC: &[u8] = b"LNGB";
+const VERSION: u8 = 1;
+const ITERATIONS: u32 = 100_000;
+/// The most iterations an archive may ask for, so that opening one can't be
+/// made to take arbitrarily long.
+const MAX_ITERATIONS: u32 = 10 * ITERATIONS;
+const HEADER_LEN: usize = 4 + 1 + 4 + 16 + 12;
+
+/// Where an entry is restored to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Section {
+    Key,
+    Config,
+    Store,
+}
+
+impl Section {
+    fn as_byte(self) -> u8 {
+        match self {
+            Section::Key => 0,
+            Section::Config => 1,
+            Section::Store => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Section> {
+        match byte {
+            0 => Some(Section::Key),
+            1 => Some(Section::Config),
+            2 => Some(Section::Store),
+            _ => None,
+        }
+    }
+}
+
+/// One backed up file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub section: Section,
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+impl Entry {
+    fn is_chain(&self) -> bool {
+        self.section == Section::Store && self.name.ends_with(".chain")
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Archive {
+    entries: Vec<Entry>,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "backup",
+        reason,
+    }
+}
+
+fn invalid_data(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Names are plain file names, so restoring can't write outside its
+/// directory.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', '\0'])
+}
+
+fn file_name(path: &Path) -> io::Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(String::from)
+        .ok_or_else(|| invalid_data(format!("{} has no usable file name", path.display())))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: NonZeroU32) -> [u8; 32] {
+    let mut key = [0; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    key
+}
+
+/// The ChaCha20-Poly1305 key an archive is sealed and opened with.
+fn archive_key(key: &[u8; 32]) -> aead::LessSafeKey {
+    aead::LessSafeKey::new(aead::UnboundKey::new(&aead::CHACHA20_POLY1305, key).unwrap())
+}
+
+impl Archive {
+    pub fn new() -> Archive {
+        Archive::default()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Adds a file with the given contents. Fails if the name isn't a plain
+    /// file name or is already in the section.
+    pub fn add(&mut self, section: Section, name: &str, contents: Vec<u8>) -> Result<(), Error> {
+        if !valid_name(name) {
+            return Err(malformed("bad file name"));
+        }
+        if self
+            .entries
+            .iter()
+            .any(|entry| entry.section == section && entry.name == name)
+        {
+            return Err(malformed("duplicate file name"));
+        }
+        self.entries.push(Entry {
+            section,
+            name: name.into(),
+            contents,
+        });
+        Ok(())
+    }
+
+    /// Adds the file at `path`, a key file or config file.
+    pub fn add_file<P: AsRef<Path>>(&mut self, section: Section, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        self.add(section, &file_name(path)?, fs::read(path)?)
+            .map_err(|error| invalid_data(error.to_string()))
+    }
+
+    /// Adds every file directly inside `dir`. A missing directory adds
+    /// nothing.
+    pub fn add_dir<P: AsRef<Path>>(&mut self, section: Section, dir: P) -> io::Result<()> {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => return Err(error),
+        };
+        let mut paths = Vec::new();
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+        for path in paths {
+            self.add_file(section, path)?;
+        }
+        Ok(())
+    }
+
+    /// Adds the store's chains and profiles while it may still be in use. A
+    /// chain caught halfway through being rewritten is read again; chains
+    /// that still don't verify are left out, and their paths returned.
+    pub fn add_store(&mut self, store: &GameStore) -> io::Result<Vec<PathBuf>> {
+        let mut skipped = Vec::new();
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(store.dir())? {
+            let path = entry?.path();
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some("chain") | Some("heartbeats") | Some("profile") => paths.push(path),
+                _ => {}
+            }
+        }
+        paths.sort();
+        for path in paths {
+            let mut contents = fs::read(&path)?;
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "chain")
+            {
+                let mut attempts = 1;
+                while GameChain::from_bytes(&contents).is_err() && attempts < 3 {
+                    thread::sleep(Duration::from_millis(50));
+                    contents = fs::read(&path)?;
+                    attempts += 1;
+                }
+                if GameChain::from_bytes(&contents).is_err() {
+                    skipped.push(path);
+                    continue;
+                }
+            }
+            self.add(Section::Store, &file_name(&path)?, contents)
+                .map_err(|error| invalid_data(error.to_string()))?;
+        }
+        Ok(skipped)
+    }
+
+    fn encode_entries(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for entry in &self.entries {
+            bytes.push(entry.section.as_byte());
+            bytes.extend(&(entry.name.len() as u16).to_be_bytes());
+            bytes.extend(entry.name.as_bytes());
+            bytes.extend(&(entry.contents.len() as u32).to_be_bytes());
+            bytes.extend(&entry.contents);
+        }
+        bytes
+    }
+
+    fn decode_entries(mut bytes: &[u8]) -> Result<Archive, Error> {
+        let mut archive = Archive::new();
+        while !bytes.is_empty() {
+            if bytes.len() < 3 {
+                return Err(malformed("not enough bytes"));
+            }
+            let section =
+                Section::from_byte(bytes[0]).ok_or_else(|| malformed("unknown section"))?;
+            let name_len = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+            if bytes.len() < 3 + name_len + 4 {
+                return Err(malformed("not enough bytes"));
+            }
+            let name = core::str::from_utf8(&bytes[3..3 + name_len])
+                .map_err(|_| malformed("file name is not UTF-8"))?;
+            let mut len = [0; 4];
+            len.copy_from_slice(&bytes[3 + name_len..7 + name_len]);
+            let len = u32::from_be_bytes(len) as usize;
+            let rest = &bytes[7 + name_len..];
+            if rest.len() < len {
+                return Err(malformed("not enough bytes"));
+            }
+            archive.add(section, name, rest[..len].to_vec())?;
+            bytes = &rest[len..];
+        }
+        Ok(archive)
+    }
+
+    /// Encrypts the archive under `passphrase`, with a salt and nonce drawn
+    /// from `rng`.
+    pub fn seal(&self, passphrase: &str, rng: &dyn Rng) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend(MAGIC);
+        header.push(VERSION);
+        header.extend(&ITERATIONS.to_be_bytes());
+        let mut salt_and_nonce = [0; 16 + 12];
+        rng.fill_bytes(&mut salt_and_nonce);
+        header.extend(&salt_and_nonce);
+
+        let iterations = NonZeroU32::new(ITERATIONS).unwrap();
+        let key = derive_key(passphrase, &salt_and_nonce[..16], iterations);
+        let key = archive_key(&key);
+        let mut nonce = [0; 12];
+        nonce.copy_from_slice(&salt_and_nonce[16..]);
+        let mut in_out = self.encode_entries();
+        key.seal_in_place_append_tag(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::from(&header),
+            &mut in_out,
+        )
+        .expect("backups are far smaller than the per-nonce limit");
+
+        let mut bytes = header;
+        bytes.extend(in_out);
+        bytes
+    }
+
+    /// Decrypts an archive made by seal. A wrong passphrase and a damaged
+    /// archive can't be told apart.
+    pub fn open(bytes: &[u8], passphrase: &str) -> Result<Archive, Error> {
+        if bytes.len() < HEADER_LEN || !bytes.starts_with(MAGIC) {
+            return Err(malformed("not a backup"));
+        }
+        if bytes[4] != VERSION {
+            return Err(malformed("unknown version"));
+        }
+        let mut iterations = [0; 4];
+        iterations.copy_from_slice(&bytes[5..9]);
+        let iterations = u32::from_be_bytes(iterations);
+        if iterations > MAX_ITERATIONS {
+            return Err(malformed("too many iterations"));
+        }
+        let iterations = NonZeroU32::new(iterations).ok_or_else(|| malformed("zero iterations"))?;
+        let (header, sealed) = bytes.split_at(HEADER_LEN);
+        let key = derive_key(passphrase, &header[9..25], iterations);
+        let key = archive_key(&key);
+        let mut nonce = [0; 12];
+        nonce.copy_from_slice(&header[25..]);
+        let mut in_out = sealed.to_vec();
+        let plaintext = key
+            .open_in_place(
+                aead::Nonce::assume_unique_for_key(nonce),
+                aead::Aad::from(header),
+                &mut in_out,
+            )
+            .map_err(|_| malformed("wrong passphrase or damaged archive"))?;
+        Archive::decode_entries(plaintext)
+    }
+
+    /// Checks that every chain decodes and verifies, or for challenges not
+    /// yet accepted by both players that the accepts so far check out, and
+    /// that every key file holds a key. Returns the number of chains.
+    pub fn verify(&self) -> io::Result<usize> {
+        let mut chains = 0;
+        for entry in &self.entries {
+            let result = match entry.section {
+                Section::Key => crypto::key_from_pkcs8(&entry.contents).map(|_| ()),
+                Section::Store if entry.is_chain() => {
+                    chains += 1;
+                    GameChain::from_bytes(&entry.contents).and_then(|chain| {
+                        if chain.status() == GameStatus::AwaitingAcceptance {
+                            chain.check_accepts()
+                        } else if chain.verify() {
+                            Ok(())
+                        } else {
+                            Err(Error::VerificationFailed)
+                        }
+                    })
+                }
+                _ => Ok(()),
+            };
+            if let Err(error) = result {
+                return Err(invalid_data(format!("{}: {}", entry.name, error)));
+            }
+        }
+        Ok(chains)
+    }
+
+    /// Writes the section's files into `dir` after verifying the whole
+    /// archive. Existing files are left alone. Returns the paths written.
+    pub fn restore_files<P: AsRef<Path>>(
+        &self,
+        section: Section,
+        dir: P,
+    ) -> io::Result<Vec<PathBuf>> {
+        self.verify()?;
+        fs::create_dir_all(&dir)?;
+        let mut written = Vec::new();
+        for entry in self.entries.iter().filter(|entry| entry.section == section) {
+            let path = dir.as_ref().join(&entry.name);
+            if path.exists() {
+                continue;
+            }
+            fs::write(&path, &entry.contents)?;
+            written.push(path);
+        }
+        Ok(written)
+    }
+
+    /// Merges the archived chains into `store` and writes any profiles it
+    /// lacks, after verifying the whole archive. Returns how many chains
+    /// changed the store.
+    pub fn restore_store(&self, store: &GameStore) -> io::Result<usize> {
+        self.verify()?;
+        let mut changed = 0;
+        for entry in self
+            .entries
+            .iter()
+            .filter(|entry| entry.section == Section::Store)
+        {
+            if entry.is_chain() {
+                let chain = GameChain::from_bytes(&entry.contents)
+                    .map_err(|error| invalid_data(error.to_string()))?;
+                if store.merge(&chain)? {
+                    changed += 1;
+                }
+            } else {
+                let path = store.dir().join(&entry.name);
+                if !path.exists() {
+                    fs::write(path, &entry.contents)?;
+                }
+            }
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto::SeededRng;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn backup_and_restore() {
+        let rng = crypto::new_rng();
+        let pkcs8 = crypto::generate_pkcs8(&rng);
+        let white = crypto::key_from_pkcs8(&pkcs8).unwrap();
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let mv = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(mv)).unwrap();
+        // a challenge still waiting on black, which can't verify yet
+        let mut pending = GameChain::new(ChallengeBlock::new(
+            &PublicKey::of(&white),
+            &PublicKey::of(&black),
+        ));
+        pending.accept(&white).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("lineage-backup-{}", crypto::random_id(&rng)));
+        let store = GameStore::open(dir.join("games")).unwrap();
+        store.save(&chain).unwrap();
+        store.save(&pending).unwrap();
+        let mut archive = Archive::new();
+        archive
+            .add(Section::Key, "white.pk8", pkcs8.clone())
+            .unwrap();
+        archive
+            .add(Section::Config, "contacts", b"alice 1111\n".to_vec())
+            .unwrap();
+        assert!(archive.add_store(&store).unwrap().is_empty());
+        assert!(archive.add(Section::Key, "../escape", Vec::new()).is_err());
+
+        let sealed = archive.seal("correct horse", &SeededRng::new(7));
+        assert!(Archive::open(&sealed, "wrong horse").is_err());
+        let mut damaged = sealed.clone();
+        *damaged.last_mut().unwrap() ^= 1;
+        assert!(Archive::open(&damaged, "correct horse").is_err());
+        let mut costly = sealed.clone();
+        costly[5..9].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert_eq!(
+            Archive::open(&costly, "correct horse"),
+            Err(malformed("too many iterations"))
+        );
+        let opened = Archive::open(&sealed, "correct horse").unwrap();
+        assert_eq!(opened, archive);
+        assert_eq!(opened.verify().unwrap(), 2);
+
+        // restoring onto a fresh device
+        let restored = GameStore::open(dir.join("restored")).unwrap();
+        assert_eq!(opened.restore_store(&restored).unwrap(), 2);
+        assert_eq!(restored.load(chain.id()).unwrap(), chain);
+        assert_eq!(restored.load(pending.id()).unwrap(), pending);
+        let keys = opened
+            .restore_files(Section::Key, dir.join("keys"))
+            .unwrap();
+        assert_eq!(fs::read(&keys[0]).unwrap(), pkcs8);
+        assert!(opened
+            .restore_files(Section::Key, dir.join("keys"))
+            .unwrap()
+            .is_empty());
+
+        // a chain that doesn't verify stops the restore before anything is
+        // written
+        let mut forged = Archive::new();
+        let mut bytes = chain.as_bytes();
+        *bytes.last_mut().unwrap() ^= 1;
+        forged.add(Section::Store, "00000000.chain", bytes).unwrap();
+        forged.add(Section::Key, "black.pk8", pkcs8).unwrap();
+        assert!(forged
+            .restore_files(Section::Key, dir.join("forged"))
+            .is_err());
+        assert!(!dir.join("forged").exists());
+        let mut forged = Archive::new();
+        let mut bytes = pending.as_bytes();
+        *bytes.last_mut().unwrap() ^= 1;
+        forged.add(Section::Store, "00000000.chain", bytes).unwrap();
+        assert!(forged.verify().is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
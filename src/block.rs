@@ -1,38 +1,188 @@
+use crate::adjournment;
+use crate::adjudication;
+use crate::checkpoint;
+use crate::clock::Clock;
+#[cfg(feature = "std")]
+use crate::clock::SystemClock;
+use crate::commitment;
+use crate::conditional;
 use crate::crypto;
+use crate::dispute;
+use crate::error::Error;
+use crate::event::ChainEvent;
+use crate::extension::{ExtensionBlock, ExtensionKind};
+use crate::forfeit;
+use crate::key::PublicKey;
+use crate::metrics;
+use crate::network::Network;
+use crate::receipt;
+use crate::rotation;
+use crate::seeding;
+use crate::takeback::Line;
+use crate::vacation;
+use crate::view::GameChainView;
 
-use chess::{Action, Color, Game, MoveGen};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use chess::{Action, Board, BoardStatus, ChessMove, Color, Game, MoveGen, ALL_SQUARES};
+use core::cell::Cell;
+use core::fmt;
+use ring::digest;
 use ring::signature::{Ed25519KeyPair, KeyPair};
+#[cfg(feature = "std")]
+use std::sync::mpsc::{self, Receiver, Sender};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variant {
+    Standard,
+    FromPosition,
+    Chess960,
+}
+
+impl Variant {
+    fn from_byte(byte: u8) -> Option<Variant> {
+        match byte {
+            0 => Some(Variant::Standard),
+            1 => Some(Variant::FromPosition),
+            2 => Some(Variant::Chess960),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Variant::Standard => 0,
+            Variant::FromPosition => 1,
+            Variant::Chess960 => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeControl {
+    pub base_seconds: u32,
+    pub increment_seconds: u32,
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct ChallengeBlock {
     version: u8,
     network_id: u8,
     id: u32,
-    white_public_key: [u8; 32],
-    black_public_key: [u8; 32],
+    white_public_key: PublicKey,
+    black_public_key: PublicKey,
     paired_game_id: u32,
     timestamp: u64,
+    // Only encoded from version 1. Version 0 is the original layout, which
+    // ends with the timestamp: a standard game with no time control.
+    variant: Variant,
+    time_control: Option<TimeControl>,
+    starting_fen: Option<String>,
+    // Only encoded from version 1, which also adds timestamps to accepts.
+    valid_until: Option<u64>,
+    // Only encoded from version 2, which puts a flags byte after the starting
+    // FEN saying which of valid_until and the arbiter's key follow. Version 3
+    // encodes the challenge the same way, but its accepts name their signer
+    // and are always timestamped.
+    arbiter_public_key: Option<PublicKey>,
+    // Also only encoded from version 2, after the arbiter's key when the
+    // flags byte's third bit is set: a count, then each name and value with a
+    // length byte before it.
+    tags: Vec<(String, String)>,
+    // Set by the flags byte's fourth bit, from version 2, with nothing
+    // encoded after it.
+    simultaneous: bool,
+    // Milliseconds of each think not counted against the clock, for network
+    // lag. From version 2, two bytes after the tags when the flags byte's
+    // fifth bit is set; 0 when there's none.
+    lag_allowance_ms: u16,
 }
 
-impl ChallengeBlock {
-    pub fn new(white_public_key: &[u8], black_public_key: &[u8]) -> ChallengeBlock {
-        let mut white_bytes: [u8; 32] = [0; 32];
-        white_bytes.copy_from_slice(&white_public_key);
-        let mut black_bytes: [u8; 32] = [0; 32];
-        black_bytes.copy_from_slice(&black_public_key);
+/// The most bytes a challenge's tags may take up, encoded.
+pub const MAX_TAGS_LEN: usize = 512;
+
+/// The most move blocks a chain may hold, well beyond the longest legal game
+/// even with takebacks.
+pub const MAX_MOVES: usize = 1 << 15;
+
+/// The most extension blocks a chain may hold, counting commentary.
+pub const MAX_EXTENSIONS: usize = 1 << 15;
+
+fn tags_len(tags: &[(String, String)]) -> usize {
+    if tags.is_empty() {
+        return 0;
+    }
+    1 + tags
+        .iter()
+        .map(|(name, value)| 2 + name.len() + value.len())
+        .sum::<usize>()
+}
+
+fn read_tags(bytes: &[u8]) -> Result<Vec<(String, String)>, Error> {
+    let malformed = |reason| Error::Malformed {
+        block: "challenge",
+        reason,
+    };
+    let count = *bytes.first().ok_or_else(|| malformed("not enough bytes"))?;
+    if count == 0 {
+        return Err(malformed("empty tags"));
+    }
+    let mut offset = 1;
+    let read_string = |offset: &mut usize| {
+        let len = *bytes
+            .get(*offset)
+            .ok_or_else(|| malformed("not enough bytes"))? as usize;
+        let string = bytes
+            .get(*offset + 1..*offset + 1 + len)
+            .ok_or_else(|| malformed("not enough bytes"))?;
+        *offset += 1 + len;
+        String::from_utf8(string.to_vec()).map_err(|_| malformed("tag is not UTF-8"))
+    };
+    let mut tags = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name = read_string(&mut offset)?;
+        let value = read_string(&mut offset)?;
+        if offset > MAX_TAGS_LEN {
+            return Err(Error::TooLarge {
+                what: "challenge tag bytes",
+                limit: MAX_TAGS_LEN,
+            });
+        }
+        tags.push((name, value));
+    }
+    Ok(tags)
+}
 
+impl ChallengeBlock {
+    /// A standard challenge on the main network, with a random id.
+    pub fn new(white_public_key: &PublicKey, black_public_key: &PublicKey) -> ChallengeBlock {
         ChallengeBlock {
             version: 0,
             network_id: 0,
-            id: 0, //TODO make random,
-            white_public_key: white_bytes,
-            black_public_key: black_bytes,
+            id: crypto::random_id(&crypto::new_rng()),
+            white_public_key: *white_public_key,
+            black_public_key: *black_public_key,
             paired_game_id: 0,
             timestamp: 0, // TODO make timestamp
+            variant: Variant::Standard,
+            time_control: None,
+            starting_fen: None,
+            valid_until: None,
+            arbiter_public_key: None,
+            tags: Vec::new(),
+            simultaneous: false,
+            lag_allowance_ms: 0,
         }
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> ChallengeBlock {
+    pub fn from_bytes(bytes: &[u8]) -> Result<ChallengeBlock, Error> {
+        if bytes.len() < 82 {
+            return Err(Error::Malformed {
+                block: "challenge",
+                reason: "not enough bytes",
+            });
+        }
         let mut id_bytes = [0; 4];
         id_bytes.copy_from_slice(&bytes[2..6]);
         let mut white_public_key = [0; 32];
@@ -43,55 +193,777 @@ impl ChallengeBlock {
         paired_game_id_bytes.copy_from_slice(&bytes[70..74]);
         let mut timestamp_bytes = [0; 8];
         timestamp_bytes.copy_from_slice(&bytes[74..82]);
+        let (variant, time_control, starting_fen, fen_end) = if bytes[0] == 0 {
+            (Variant::Standard, None, None, 82)
+        } else {
+            if bytes.len() < 92 {
+                return Err(Error::Malformed {
+                    block: "challenge",
+                    reason: "not enough bytes",
+                });
+            }
+            let variant = match Variant::from_byte(bytes[82]) {
+                Some(variant) => variant,
+                None => {
+                    return Err(Error::Malformed {
+                        block: "challenge",
+                        reason: "unknown variant",
+                    });
+                }
+            };
+            let mut base_bytes = [0; 4];
+            base_bytes.copy_from_slice(&bytes[83..87]);
+            let mut increment_bytes = [0; 4];
+            increment_bytes.copy_from_slice(&bytes[87..91]);
+            let time_control = match (
+                u32::from_be_bytes(base_bytes),
+                u32::from_be_bytes(increment_bytes),
+            ) {
+                (0, 0) => None,
+                (base_seconds, increment_seconds) => Some(TimeControl {
+                    base_seconds,
+                    increment_seconds,
+                }),
+            };
+            let fen_len = bytes[91] as usize;
+            if bytes.len() < 92 + fen_len {
+                return Err(Error::Malformed {
+                    block: "challenge",
+                    reason: "not enough bytes",
+                });
+            }
+            let starting_fen = if fen_len == 0 {
+                None
+            } else {
+                match String::from_utf8(bytes[92..92 + fen_len].to_vec()) {
+                    Ok(fen) => Some(fen),
+                    Err(_) => {
+                        return Err(Error::Malformed {
+                            block: "challenge",
+                            reason: "starting FEN is not UTF-8",
+                        });
+                    }
+                }
+            };
+            (variant, time_control, starting_fen, 92 + fen_len)
+        };
 
-        ChallengeBlock {
+        if Network::from_id(bytes[1]).is_none() {
+            return Err(Error::Malformed {
+                block: "challenge",
+                reason: "unknown network",
+            });
+        }
+        let not_enough_bytes = Error::Malformed {
+            block: "challenge",
+            reason: "not enough bytes",
+        };
+        let flags = match bytes[0] {
+            0 => 0,
+            1 => 1,
+            2 | 3 => *bytes.get(fen_end).ok_or_else(|| not_enough_bytes.clone())?,
+            _ => {
+                return Err(Error::Malformed {
+                    block: "challenge",
+                    reason: "unsupported version",
+                });
+            }
+        };
+        if flags & !31 != 0 {
+            return Err(Error::Malformed {
+                block: "challenge",
+                reason: "unknown flags",
+            });
+        }
+        let mut offset = fen_end + if bytes[0] >= 2 { 1 } else { 0 };
+        let valid_until = if flags & 1 != 0 {
+            if bytes.len() < offset + 8 {
+                return Err(not_enough_bytes);
+            }
+            let mut valid_until_bytes = [0; 8];
+            valid_until_bytes.copy_from_slice(&bytes[offset..offset + 8]);
+            offset += 8;
+            Some(u64::from_be_bytes(valid_until_bytes))
+        } else {
+            None
+        };
+        let arbiter_public_key = if flags & 2 != 0 {
+            if bytes.len() < offset + 32 {
+                return Err(not_enough_bytes);
+            }
+            let mut arbiter_public_key = [0; 32];
+            arbiter_public_key.copy_from_slice(&bytes[offset..offset + 32]);
+            offset += 32;
+            Some(PublicKey::from(arbiter_public_key))
+        } else {
+            None
+        };
+        let tags = if flags & 4 != 0 {
+            read_tags(&bytes[offset..])?
+        } else {
+            Vec::new()
+        };
+        offset += tags_len(&tags);
+        let lag_allowance_ms = if flags & 16 != 0 {
+            if bytes.len() < offset + 2 {
+                return Err(not_enough_bytes);
+            }
+            u16::from_be_bytes([bytes[offset], bytes[offset + 1]])
+        } else {
+            0
+        };
+
+        Ok(ChallengeBlock {
             version: bytes[0],
             network_id: bytes[1],
             id: u32::from_be_bytes(id_bytes),
-            white_public_key,
-            black_public_key,
+            white_public_key: PublicKey::from(white_public_key),
+            black_public_key: PublicKey::from(black_public_key),
             paired_game_id: u32::from_be_bytes(paired_game_id_bytes),
             timestamp: u64::from_be_bytes(timestamp_bytes),
-        }
+            variant,
+            time_control,
+            starting_fen,
+            valid_until,
+            arbiter_public_key,
+            tags,
+            simultaneous: flags & 8 != 0,
+            lag_allowance_ms,
+        })
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = vec![0; 82];
-        bytes[0] = self.version;
-        bytes[1] = self.network_id;
-        bytes[2..6].copy_from_slice(&self.id.to_be_bytes());
-        bytes[6..38].copy_from_slice(&self.white_public_key);
-        bytes[38..70].copy_from_slice(&self.black_public_key);
-        bytes[70..74].copy_from_slice(&self.paired_game_id.to_be_bytes());
-        bytes[74..82].copy_from_slice(&self.timestamp.to_be_bytes());
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        self.write_bytes(&mut bytes);
         bytes
     }
+
+    /// Appends the encoded block to `bytes`.
+    pub fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        let fen = match &self.starting_fen {
+            Some(fen) => fen.as_bytes(),
+            None => &[],
+        };
+        let (base_seconds, increment_seconds) = match self.time_control {
+            Some(time_control) => (time_control.base_seconds, time_control.increment_seconds),
+            None => (0, 0),
+        };
+        bytes.push(self.version);
+        bytes.push(self.network_id);
+        bytes.extend(&self.id.to_be_bytes());
+        bytes.extend(self.white_public_key.as_bytes());
+        bytes.extend(self.black_public_key.as_bytes());
+        bytes.extend(&self.paired_game_id.to_be_bytes());
+        bytes.extend(&self.timestamp.to_be_bytes());
+        if self.version >= 1 {
+            bytes.push(self.variant.to_byte());
+            bytes.extend(&base_seconds.to_be_bytes());
+            bytes.extend(&increment_seconds.to_be_bytes());
+            bytes.push(fen.len() as u8);
+            bytes.extend(fen);
+        }
+        if self.version >= 2 {
+            let valid_until = self.valid_until.is_some() as u8;
+            let arbiter = self.arbiter_public_key.is_some() as u8;
+            let tags = !self.tags.is_empty() as u8;
+            let simultaneous = self.simultaneous as u8;
+            let lag_allowance = (self.lag_allowance_ms > 0) as u8;
+            bytes.push(
+                valid_until | arbiter << 1 | tags << 2 | simultaneous << 3 | lag_allowance << 4,
+            );
+        }
+        if let Some(valid_until) = self.valid_until {
+            bytes.extend(&valid_until.to_be_bytes());
+        }
+        if let Some(arbiter_public_key) = &self.arbiter_public_key {
+            bytes.extend(arbiter_public_key.as_bytes());
+        }
+        if !self.tags.is_empty() {
+            bytes.push(self.tags.len() as u8);
+            for (name, value) in &self.tags {
+                bytes.push(name.len() as u8);
+                bytes.extend(name.as_bytes());
+                bytes.push(value.len() as u8);
+                bytes.extend(value.as_bytes());
+            }
+        }
+        if self.lag_allowance_ms > 0 {
+            bytes.extend(&self.lag_allowance_ms.to_be_bytes());
+        }
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        let flags = if self.version >= 2 { 1 } else { 0 };
+        let valid_until = if self.valid_until.is_some() { 8 } else { 0 };
+        let arbiter = if self.arbiter_public_key.is_some() {
+            32
+        } else {
+            0
+        };
+        let game_settings = if self.version >= 1 {
+            10 + self.starting_fen.as_ref().map_or(0, String::len)
+        } else {
+            0
+        };
+        82 + game_settings
+            + flags
+            + valid_until
+            + arbiter
+            + tags_len(&self.tags)
+            + if self.lag_allowance_ms > 0 { 2 } else { 0 }
+    }
+
+    /// The length of each accept block: the signature, preceded by the
+    /// signer's color and a timestamp when the challenge calls for them.
+    pub fn accept_len(&self) -> usize {
+        let color = if self.names_accept_signers() { 1 } else { 0 };
+        let timestamp = if self.timestamps_accepts() { 8 } else { 0 };
+        color + timestamp + 64
+    }
+
+    /// Whether accepts name the color of their signer, from version 3.
+    pub fn names_accept_signers(&self) -> bool {
+        self.version >= 3
+    }
+
+    /// Whether accepts carry the time they were signed: for challenges that
+    /// expire, and from version 3.
+    pub fn timestamps_accepts(&self) -> bool {
+        self.valid_until.is_some() || self.version >= 3
+    }
+
+    /// Fails if the challenge can't be accepted at time `now`.
+    pub fn check_valid_at(&self, now: u64) -> Result<(), Error> {
+        match self.valid_until {
+            Some(valid_until) if now < self.timestamp || now > valid_until => {
+                Err(Error::ChallengeExpired)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The public key that must sign the move at `ply`, counting from the
+    /// side to move in the starting position.
+    pub fn signer_public_key(&self, ply: usize) -> PublicKey {
+        let first = self.starting_board().side_to_move();
+        match (first, ply % 2) {
+            (Color::White, 0) | (Color::Black, 1) => self.white_public_key,
+            _ => self.black_public_key,
+        }
+    }
+
+    /// The board the game starts from: the starting FEN if there is one,
+    /// otherwise the standard starting position.
+    pub fn starting_board(&self) -> Board {
+        self.starting_fen
+            .as_ref()
+            .and_then(|fen| fen.parse::<Board>().ok())
+            .unwrap_or_default()
+    }
+
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn network_id(&self) -> u8 {
+        self.network_id
+    }
+
+    pub fn network(&self) -> Option<Network> {
+        Network::from_id(self.network_id)
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// The SHA-256 digest of the encoded challenge. Every signature in the
+    /// chain covers it, so a block signed for one game never verifies in
+    /// another.
+    pub fn canonical_id(&self) -> [u8; 32] {
+        canonical_id(&self.as_bytes())
+    }
+
+    pub fn white_public_key(&self) -> PublicKey {
+        self.white_public_key
+    }
+
+    pub fn black_public_key(&self) -> PublicKey {
+        self.black_public_key
+    }
+
+    pub fn paired_game_id(&self) -> u32 {
+        self.paired_game_id
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn time_control(&self) -> Option<TimeControl> {
+        self.time_control
+    }
+
+    pub fn starting_fen(&self) -> Option<&str> {
+        self.starting_fen.as_deref()
+    }
+
+    /// The last time, in seconds since the Unix epoch, the challenge may be
+    /// accepted.
+    pub fn valid_until(&self) -> Option<u64> {
+        self.valid_until
+    }
+
+    /// A copy of the challenge with `color`'s key replaced.
+    pub(crate) fn with_public_key(&self, color: Color, public_key: PublicKey) -> ChallengeBlock {
+        let mut challenge = self.clone();
+        match color {
+            Color::White => challenge.white_public_key = public_key,
+            Color::Black => challenge.black_public_key = public_key,
+        }
+        challenge
+    }
+
+    /// The key of the arbiter who resolves disputed results, if the game has
+    /// one.
+    pub fn arbiter_public_key(&self) -> Option<PublicKey> {
+        self.arbiter_public_key
+    }
+
+    /// The challenge's metadata, as PGN-style tag names and values, e.g.
+    /// Event, Site, Round and Board.
+    pub fn tags(&self) -> &[(String, String)] {
+        &self.tags
+    }
+
+    /// The value of the tag named `name`, if the challenge has one.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether both players commit to their moves for each full move before
+    /// either is revealed.
+    pub fn is_simultaneous(&self) -> bool {
+        self.simultaneous
+    }
+
+    /// Milliseconds of each think the clock doesn't count, agreed to make up
+    /// for network lag. 0 if there's none.
+    pub fn lag_allowance_ms(&self) -> u16 {
+        self.lag_allowance_ms
+    }
+}
+
+/// Hashes an encoded challenge into the chain's canonical id.
+pub(crate) fn canonical_id(challenge_bytes: &[u8]) -> [u8; 32] {
+    let mut id = [0; 32];
+    id.copy_from_slice(digest::digest(&digest::SHA256, challenge_bytes).as_ref());
+    id
+}
+
+/// The message a block signs: the chain's canonical id followed by the encoded
+/// chain up to and including the block's unsigned content. Blocks of version 0
+/// challenges predate canonical ids and sign the chain bytes alone, which
+/// begin with the whole challenge and so already bind them to the game.
+pub(crate) fn signed_message(canonical_id: &[u8; 32], bytes: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(32 + bytes.len());
+    if bytes.first() != Some(&0) {
+        message.extend(canonical_id);
+    }
+    message.extend(bytes);
+    message
+}
+
+fn short_key(public_key: &[u8]) -> String {
+    let fingerprint = crypto::fingerprint(public_key);
+    format!("{}…", &fingerprint[..4])
+}
+
+fn square_name(square: u8) -> String {
+    match ALL_SQUARES.get(square as usize) {
+        Some(square) => square.to_string(),
+        None => "??".to_string(),
+    }
+}
+
+impl fmt::Display for ChallengeBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Challenge {:08x}: White {} vs Black {}",
+            self.id,
+            short_key(self.white_public_key.as_ref()),
+            short_key(self.black_public_key.as_ref())
+        )
+    }
+}
+
+pub struct ChallengeBlockBuilder {
+    block: ChallengeBlock,
+}
+
+impl ChallengeBlockBuilder {
+    pub fn new(
+        white_public_key: &PublicKey,
+        black_public_key: &PublicKey,
+    ) -> ChallengeBlockBuilder {
+        ChallengeBlockBuilder {
+            block: ChallengeBlock::new(white_public_key, black_public_key),
+        }
+    }
+
+    pub fn network_id(mut self, network_id: u8) -> ChallengeBlockBuilder {
+        self.block.network_id = network_id;
+        self
+    }
+
+    pub fn network(self, network: Network) -> ChallengeBlockBuilder {
+        self.network_id(network.id())
+    }
+
+    pub fn id(mut self, id: u32) -> ChallengeBlockBuilder {
+        self.block.id = id;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: u64) -> ChallengeBlockBuilder {
+        self.block.timestamp = timestamp;
+        self
+    }
+
+    pub fn paired_game_id(mut self, paired_game_id: u32) -> ChallengeBlockBuilder {
+        self.block.paired_game_id = paired_game_id;
+        self
+    }
+
+    /// Makes the challenge expire after `valid_until`, in seconds since the
+    /// Unix epoch.
+    pub fn valid_until(mut self, valid_until: u64) -> ChallengeBlockBuilder {
+        self.block.version = self.block.version.max(1);
+        self.block.valid_until = Some(valid_until);
+        self
+    }
+
+    /// Names an arbiter who may resolve disputed results.
+    pub fn arbiter(mut self, arbiter_public_key: &PublicKey) -> ChallengeBlockBuilder {
+        self.block.version = self.block.version.max(2);
+        self.block.arbiter_public_key = Some(*arbiter_public_key);
+        self
+    }
+
+    /// Has each accept name its signer's color and record when it was
+    /// signed, so neither has to be worked out from the signatures.
+    pub fn signed_accepts(mut self) -> ChallengeBlockBuilder {
+        self.block.version = 3;
+        self
+    }
+
+    /// Adds a PGN-style tag, e.g. `tag("Event", "Club Championship")`.
+    pub fn tag(mut self, name: &str, value: &str) -> ChallengeBlockBuilder {
+        self.block.version = self.block.version.max(2);
+        self.block.tags.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Has both players commit to their moves for each full move before
+    /// either reveals theirs.
+    pub fn simultaneous(mut self) -> ChallengeBlockBuilder {
+        self.block.version = self.block.version.max(2);
+        self.block.simultaneous = true;
+        self
+    }
+
+    /// Leaves `milliseconds` of each think off the clock to make up for
+    /// network lag, as agreed with lag::agree. Needs a time control.
+    pub fn lag_allowance(mut self, milliseconds: u16) -> ChallengeBlockBuilder {
+        self.block.version = self.block.version.max(2);
+        self.block.lag_allowance_ms = milliseconds;
+        self
+    }
+
+    pub fn variant(mut self, variant: Variant) -> ChallengeBlockBuilder {
+        self.block.version = self.block.version.max(2);
+        self.block.variant = variant;
+        self
+    }
+
+    pub fn time_control(
+        mut self,
+        base_seconds: u32,
+        increment_seconds: u32,
+    ) -> ChallengeBlockBuilder {
+        self.block.version = self.block.version.max(2);
+        self.block.time_control = Some(TimeControl {
+            base_seconds,
+            increment_seconds,
+        });
+        self
+    }
+
+    pub fn starting_fen(mut self, fen: &str) -> ChallengeBlockBuilder {
+        self.block.version = self.block.version.max(2);
+        self.block.starting_fen = Some(fen.to_string());
+        self
+    }
+
+    pub fn build(self) -> Result<ChallengeBlock, Error> {
+        let block = self.block;
+        if block.white_public_key == block.black_public_key {
+            return Err(Error::InvalidChallenge("white and black keys are the same"));
+        }
+        if Network::from_id(block.network_id).is_none() {
+            return Err(Error::InvalidChallenge("unknown network"));
+        }
+        if block.paired_game_id != 0 && block.paired_game_id == block.id {
+            return Err(Error::InvalidChallenge(
+                "a game cannot be paired with itself",
+            ));
+        }
+        if block
+            .valid_until
+            .is_some_and(|valid_until| valid_until < block.timestamp)
+        {
+            return Err(Error::InvalidChallenge(
+                "challenge expires before it is issued",
+            ));
+        }
+        if let Some(arbiter) = &block.arbiter_public_key {
+            if arbiter == &block.white_public_key || arbiter == &block.black_public_key {
+                return Err(Error::InvalidChallenge("a player cannot be the arbiter"));
+            }
+        }
+        for (i, (name, value)) in block.tags.iter().enumerate() {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(Error::InvalidChallenge(
+                    "tag names must be letters, digits or underscores",
+                ));
+            }
+            if name.len() > 255 || value.len() > 255 {
+                return Err(Error::InvalidChallenge("tag is too long"));
+            }
+            if block.tags[..i].iter().any(|(other, _)| other == name) {
+                return Err(Error::InvalidChallenge("tag appears twice"));
+            }
+        }
+        if block.tags.len() > 255 || tags_len(&block.tags) > MAX_TAGS_LEN {
+            return Err(Error::InvalidChallenge("tags are too long"));
+        }
+        if let Some(time_control) = block.time_control {
+            if time_control.base_seconds == 0 {
+                return Err(Error::InvalidChallenge("time control needs a base time"));
+            }
+        }
+        if block.lag_allowance_ms > 0 && block.time_control.is_none() {
+            return Err(Error::InvalidChallenge(
+                "lag allowance needs a time control",
+            ));
+        }
+        match (block.variant, &block.starting_fen) {
+            (Variant::Standard, Some(_)) => {
+                return Err(Error::InvalidChallenge(
+                    "standard games cannot have a starting FEN",
+                ));
+            }
+            (Variant::FromPosition, None) | (Variant::Chess960, None) => {
+                return Err(Error::InvalidChallenge("this variant needs a starting FEN"));
+            }
+            (_, Some(fen)) => {
+                if fen.len() > 255 {
+                    return Err(Error::InvalidChallenge("starting FEN is too long"));
+                }
+                if fen.parse::<Board>().is_err() {
+                    return Err(Error::InvalidChallenge("starting FEN is invalid"));
+                }
+            }
+            (Variant::Standard, None) => {}
+        }
+        Ok(block)
+    }
 }
 
+/// A player's signature over the challenge. Accepts of challenges that expire
+/// also carry the time they were signed, and from version 3 every accept
+/// names its signer's color and is timestamped. Both are covered by the
+/// signature.
 #[derive(Clone, Debug, PartialEq)]
-struct AcceptBlock {
+pub struct AcceptBlock {
+    color: Option<Color>,
+    timestamp: Option<u64>,
     signature: Vec<u8>,
 }
 
 impl AcceptBlock {
-    fn new(challenge: &ChallengeBlock, key_pair: &Ed25519KeyPair) -> AcceptBlock {
-        let challenge_bytes = challenge.as_bytes();
+    fn new(challenge: &ChallengeBlock, key_pair: &Ed25519KeyPair, now: u64) -> AcceptBlock {
+        let mut block = AcceptBlock::unsigned(challenge, key_pair.public_key().as_ref(), now);
+        block.signature = crypto::sign(key_pair, &block.message(&challenge.as_bytes()));
+        block
+    }
+
+    /// The accept `public_key` would sign at `now`, without its signature.
+    pub(crate) fn unsigned(challenge: &ChallengeBlock, public_key: &[u8], now: u64) -> AcceptBlock {
+        let color = if !challenge.names_accept_signers() {
+            None
+        } else if challenge.white_public_key == *public_key {
+            Some(Color::White)
+        } else {
+            Some(Color::Black)
+        };
         AcceptBlock {
-            signature: crypto::sign(key_pair, &challenge_bytes),
+            color,
+            timestamp: if challenge.timestamps_accepts() {
+                Some(now)
+            } else {
+                None
+            },
+            signature: Vec::new(),
         }
     }
 
-    fn from_bytes(bytes: &[u8]) -> Result<AcceptBlock, &str> {
-        if bytes.len() < 64 {
-            return Err("Not enough bytes to create accept block.");
+    pub(crate) fn with_signature(mut self, signature: &[u8]) -> AcceptBlock {
+        self.signature = signature.to_vec();
+        self
+    }
+
+    /// Decodes an accept of `challenge`, whose version fixes its layout.
+    pub(crate) fn from_bytes(
+        bytes: &[u8],
+        challenge: &ChallengeBlock,
+    ) -> Result<AcceptBlock, Error> {
+        if bytes.len() < challenge.accept_len() {
+            return Err(Error::Malformed {
+                block: "accept",
+                reason: "not enough bytes",
+            });
         }
+        let mut offset = 0;
+        let color = if challenge.names_accept_signers() {
+            offset += 1;
+            match bytes[0] {
+                0 => Some(Color::White),
+                1 => Some(Color::Black),
+                _ => {
+                    return Err(Error::Malformed {
+                        block: "accept",
+                        reason: "unknown color",
+                    });
+                }
+            }
+        } else {
+            None
+        };
+        let timestamp = if challenge.timestamps_accepts() {
+            let mut timestamp_bytes = [0; 8];
+            timestamp_bytes.copy_from_slice(&bytes[offset..offset + 8]);
+            offset += 8;
+            Some(u64::from_be_bytes(timestamp_bytes))
+        } else {
+            None
+        };
         let mut signature = vec![0; 64];
-        signature.copy_from_slice(&bytes[..64]);
-        Ok(AcceptBlock { signature })
+        signature.copy_from_slice(&bytes[offset..offset + 64]);
+        Ok(AcceptBlock {
+            color,
+            timestamp,
+            signature,
+        })
     }
 
     fn as_bytes(&self) -> Vec<u8> {
-        self.signature.clone()
+        let mut bytes = Vec::with_capacity(73);
+        self.write_bytes(&mut bytes);
+        bytes
+    }
+
+    fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        if let Some(color) = self.color {
+            bytes.push(color_byte(color));
+        }
+        if let Some(timestamp) = self.timestamp {
+            bytes.extend(&timestamp.to_be_bytes());
+        }
+        bytes.extend(&self.signature);
+    }
+
+    pub(crate) fn message(&self, challenge_bytes: &[u8]) -> Vec<u8> {
+        let mut message = signed_message(&canonical_id(challenge_bytes), challenge_bytes);
+        if let Some(color) = self.color {
+            message.push(color_byte(color));
+        }
+        if let Some(timestamp) = self.timestamp {
+            message.extend(&timestamp.to_be_bytes());
+        }
+        message
+    }
+
+    /// Checks the signature given the encoded challenge.
+    pub(crate) fn verify(&self, challenge_bytes: &[u8], public_key: &[u8]) -> bool {
+        crypto::verify(public_key, &self.message(challenge_bytes), &self.signature)
+    }
+
+    /// The color of the player who signed the accept, if the signature
+    /// verifies. Only the named player's key is tried when there is one.
+    pub(crate) fn signer(
+        &self,
+        challenge: &ChallengeBlock,
+        challenge_bytes: &[u8],
+    ) -> Option<Color> {
+        let colors = match self.color {
+            Some(color) => [Some(color), None],
+            None => [Some(Color::White), Some(Color::Black)],
+        };
+        colors.iter().flatten().cloned().find(|&color| {
+            let public_key = match color {
+                Color::White => challenge.white_public_key.as_bytes(),
+                Color::Black => challenge.black_public_key.as_bytes(),
+            };
+            self.verify(challenge_bytes, public_key)
+        })
+    }
+
+    /// Fails unless the accept is timestamped exactly when the challenge
+    /// calls for it, and was signed before the challenge expired.
+    pub(crate) fn check_time(&self, challenge: &ChallengeBlock) -> Result<(), Error> {
+        match (challenge.timestamps_accepts(), self.timestamp) {
+            (false, None) => Ok(()),
+            (true, Some(timestamp)) => challenge.check_valid_at(timestamp),
+            _ => Err(Error::Malformed {
+                block: "accept",
+                reason: "timestamp does not match the challenge",
+            }),
+        }
+    }
+
+    /// The color the accept names as its signer, for version 3 challenges.
+    pub fn color(&self) -> Option<Color> {
+        self.color
+    }
+
+    /// When the accept was signed, for challenges that expire and from
+    /// version 3.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        crypto::block_hash(&self.as_bytes())
+    }
+}
+
+fn color_byte(color: Color) -> u8 {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
     }
 }
 
@@ -103,9 +975,12 @@ pub struct MoveBlock {
 }
 
 impl MoveBlock {
-    pub fn from_bytes(bytes: &[u8]) -> Result<MoveBlock, &str> {
+    pub fn from_bytes(bytes: &[u8]) -> Result<MoveBlock, Error> {
         if bytes.len() < 66 {
-            return Err("No enough bytes to create accept block.");
+            return Err(Error::Malformed {
+                block: "move",
+                reason: "not enough bytes",
+            });
         }
         let mut signature_bytes = vec![0; 64];
         signature_bytes.copy_from_slice(&bytes[2..66]);
@@ -117,217 +992,1126 @@ impl MoveBlock {
     }
 
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = vec![self.start_square, self.end_square];
-        bytes.extend(&self.signature);
+        let mut bytes = Vec::with_capacity(66);
+        self.write_bytes(&mut bytes);
         bytes
     }
-}
 
-#[derive(Clone, Debug, PartialEq)]
-pub struct GameChain {
-    challenge: ChallengeBlock,
-    accepts: [Option<AcceptBlock>; 2],
-    moves: Vec<MoveBlock>,
-}
+    pub fn hash(&self) -> [u8; 32] {
+        crypto::block_hash(&self.as_bytes())
+    }
 
-impl GameChain {
-    pub fn new(challenge: ChallengeBlock) -> GameChain {
-        GameChain {
-            challenge,
-            accepts: [None, None],
-            moves: Vec::new(),
-        }
+    pub fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.push(self.start_square);
+        bytes.push(self.end_square);
+        bytes.extend(&self.signature);
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Result<GameChain, &str> {
-        // TODO change challenge::from_bytes to use Result
-        if bytes.len() < 82 {
-            return Err("Not enough bytes to create challenge block.");
-        }
-        let challenge = ChallengeBlock::from_bytes(&bytes);
-        let mut chain = GameChain::new(challenge);
+    pub(crate) fn find_move(&self, board: &Board) -> Option<ChessMove> {
+        MoveGen::new_legal(board).find(|mv| {
+            self.start_square == mv.get_source().to_int()
+                && self.end_square == mv.get_dest().to_int()
+        })
+    }
 
-        if let Ok(accept) = AcceptBlock::from_bytes(&bytes[82..]) {
-            chain.accepts[0] = Some(accept);
+    pub fn start_square(&self) -> u8 {
+        self.start_square
+    }
+
+    pub fn end_square(&self) -> u8 {
+        self.end_square
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+}
+
+impl fmt::Display for MoveBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}{} signature {}…",
+            square_name(self.start_square),
+            square_name(self.end_square),
+            bs58::encode(&self.signature[..4]).into_string()
+        )
+    }
+}
+
+pub struct Positions<'a> {
+    moves: alloc::vec::IntoIter<&'a MoveBlock>,
+    board: Board,
+    index: usize,
+    signers: (String, String),
+}
+
+impl<'a> Iterator for Positions<'a> {
+    type Item = (usize, ChessMove, Board, String);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mv = self.moves.next()?.find_move(&self.board)?;
+        self.board = self.board.make_move_new(mv);
+        let index = self.index;
+        self.index += 1;
+        let signer = if index.is_multiple_of(2) {
+            self.signers.0.clone()
         } else {
+            self.signers.1.clone()
+        };
+        Some((index, mv, self.board, signer))
+    }
+}
+
+/// A block after the accepts: a move, with its index among the move blocks,
+/// or an extension.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChainEntry<'a> {
+    Move { index: usize, block: &'a MoveBlock },
+    Extension(&'a ExtensionBlock),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameResult {
+    Win(Color),
+    Draw,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GameStatus {
+    AwaitingAcceptance,
+    InProgress,
+    Adjourned,
+    /// A player contested the result and the arbiter hasn't resolved it.
+    Disputed,
+    Finished(GameResult),
+    Invalid,
+}
+
+#[derive(Debug)]
+pub struct GameChain {
+    challenge: ChallengeBlock,
+    accepts: [Option<AcceptBlock>; 2],
+    moves: Vec<MoveBlock>,
+    // Extension blocks, each with the number of moves that precede it.
+    extensions: Vec<(usize, ExtensionBlock)>,
+    // Spectator commentary and analysis, encoded after everything else and
+    // signed by no player.
+    commentary: Vec<ExtensionBlock>,
+    #[cfg(feature = "std")]
+    subscribers: Vec<Sender<ChainEvent>>,
+    // The position after the last move, filled in lazily by current_position
+    // and kept up to date as moves are appended.
+    position: Cell<Option<Board>>,
+}
+
+// Subscribers belong to a particular chain value, so they are neither cloned
+// nor compared.
+impl Clone for GameChain {
+    fn clone(&self) -> GameChain {
+        GameChain {
+            challenge: self.challenge.clone(),
+            accepts: self.accepts.clone(),
+            moves: self.moves.clone(),
+            extensions: self.extensions.clone(),
+            commentary: self.commentary.clone(),
+            #[cfg(feature = "std")]
+            subscribers: Vec::new(),
+            position: self.position.clone(),
+        }
+    }
+}
+
+impl PartialEq for GameChain {
+    fn eq(&self, other: &GameChain) -> bool {
+        self.challenge == other.challenge
+            && self.accepts == other.accepts
+            && self.moves == other.moves
+            && self.extensions == other.extensions
+            && self.commentary == other.commentary
+    }
+}
+
+impl GameChain {
+    pub fn new(challenge: ChallengeBlock) -> GameChain {
+        GameChain {
+            challenge,
+            accepts: [None, None],
+            moves: Vec::new(),
+            extensions: Vec::new(),
+            commentary: Vec::new(),
+            #[cfg(feature = "std")]
+            subscribers: Vec::new(),
+            position: Cell::new(None),
+        }
+    }
+
+    /// Returns a receiver for events fired whenever this chain changes.
+    #[cfg(feature = "std")]
+    pub fn subscribe(&mut self) -> Receiver<ChainEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.push(sender);
+        receiver
+    }
+
+    #[cfg(feature = "std")]
+    fn emit(&self, event: ChainEvent) {
+        for subscriber in &self.subscribers {
+            // a dropped receiver just means nobody is listening anymore
+            let _ = subscriber.send(event.clone());
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn emit(&self, _event: ChainEvent) {}
+
+    fn emit_move_added(&self) {
+        let index = self.moves.len() - 1;
+        metrics::block_appended(self.challenge.id, "move");
+        self.emit(ChainEvent::MoveAdded {
+            index,
+            start_square: self.moves[index].start_square,
+            end_square: self.moves[index].end_square,
+        });
+        let board = match self.current_position() {
+            Ok(board) => board,
+            Err(_) => return,
+        };
+        match board.status() {
+            BoardStatus::Ongoing => {}
+            BoardStatus::Stalemate => self.emit(ChainEvent::ResultRecorded { winner: None }),
+            BoardStatus::Checkmate => self.emit(ChainEvent::ResultRecorded {
+                winner: Some(!board.side_to_move()),
+            }),
+        }
+    }
+
+    pub(crate) fn from_parts(
+        challenge: ChallengeBlock,
+        accepts: [Option<AcceptBlock>; 2],
+        moves: Vec<MoveBlock>,
+        extensions: Vec<(usize, ExtensionBlock)>,
+        commentary: Vec<ExtensionBlock>,
+    ) -> GameChain {
+        GameChain {
+            challenge,
+            accepts,
+            moves,
+            extensions,
+            commentary,
+            #[cfg(feature = "std")]
+            subscribers: Vec::new(),
+            position: Cell::new(None),
+        }
+    }
+
+    /// Decodes and verifies a chain. Every byte must belong to a block, so a
+    /// chain cut off partway through a block is rejected rather than read as
+    /// the blocks before the cut. Use salvage::salvage to recover what can be
+    /// read of a damaged file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<GameChain, Error> {
+        let view = GameChainView::new(bytes)?;
+        if view.encoded_len() != bytes.len() {
+            return Err(Error::Malformed {
+                block: "chain",
+                reason: "trailing bytes",
+            });
+        }
+        let chain = view.to_chain()?;
+        if view.accept_count() < 2 {
             return Ok(chain);
         }
-        if let Ok(accept) = AcceptBlock::from_bytes(&bytes[82 + 64..]) {
-            chain.accepts[1] = Some(accept);
+        if view.is_pruned() {
+            return Err(Error::Malformed {
+                block: "chain",
+                reason: "pruned at a checkpoint",
+            });
+        }
+
+        if !view.verify() {
+            return Err(Error::VerificationFailed);
+        }
+        chain.get_game()?;
+        chain.check_extensions()?;
+        Ok(chain)
+    }
+
+    pub fn challenge(&self) -> &ChallengeBlock {
+        &self.challenge
+    }
+
+    pub fn accepts(&self) -> &[Option<AcceptBlock>; 2] {
+        &self.accepts
+    }
+
+    pub fn moves(&self) -> &[MoveBlock] {
+        &self.moves
+    }
+
+    pub fn move_count(&self) -> usize {
+        self.moves.len()
+    }
+
+    /// The chain's extension blocks, each with the number of moves before it.
+    pub fn extensions(&self) -> &[(usize, ExtensionBlock)] {
+        &self.extensions
+    }
+
+    /// The spectator commentary and analysis blocks, whether or not they
+    /// verify.
+    pub fn commentary(&self) -> &[ExtensionBlock] {
+        &self.commentary
+    }
+
+    pub(crate) fn push_commentary(&mut self, block: ExtensionBlock) -> Result<(), Error> {
+        self.check_extension_room()?;
+        self.commentary.push(block);
+        Ok(())
+    }
+
+    fn check_move_room(&self) -> Result<(), Error> {
+        if self.moves.len() >= MAX_MOVES {
+            return Err(Error::TooLarge {
+                what: "moves in a chain",
+                limit: MAX_MOVES,
+            });
+        }
+        Ok(())
+    }
+
+    fn check_extension_room(&self) -> Result<(), Error> {
+        if self.extensions.len() + self.commentary.len() >= MAX_EXTENSIONS {
+            return Err(Error::TooLarge {
+                what: "extension blocks in a chain",
+                limit: MAX_EXTENSIONS,
+            });
+        }
+        Ok(())
+    }
+
+    /// The extension blocks appended since the last move.
+    pub(crate) fn pending_extensions(&self) -> impl Iterator<Item = &ExtensionBlock> {
+        let ply = self.moves.len();
+        self.extensions
+            .iter()
+            .filter(move |(at, _)| *at == ply)
+            .map(|(_, block)| block)
+    }
+
+    /// Returns the (white, black) public keys from the challenge block.
+    pub fn players(&self) -> (PublicKey, PublicKey) {
+        (
+            self.challenge.white_public_key,
+            self.challenge.black_public_key,
+        )
+    }
+
+    pub fn id(&self) -> u32 {
+        self.challenge.id
+    }
+
+    pub fn canonical_id(&self) -> [u8; 32] {
+        self.challenge.canonical_id()
+    }
+
+    /// The message the next block signs, before its own content is added.
+    /// After a checkpoint, the checkpoint stands in for the blocks before it.
+    pub(crate) fn signing_prefix(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        self.write_game_bytes(&mut bytes);
+        let challenge_len = self.challenge.encoded_len();
+        let canonical_id = canonical_id(&bytes[..challenge_len]);
+        let has_checkpoint = self
+            .extensions
+            .iter()
+            .any(|(_, block)| block.kind() == ExtensionKind::Checkpoint);
+        let last_checkpoint = if has_checkpoint {
+            GameChainView::new(&bytes)
+                .ok()
+                .and_then(|view| view.last_checkpoint())
         } else {
-            return Ok(chain);
+            None
+        };
+        match last_checkpoint {
+            Some(checkpoint) => checkpoint::rooted_message(&canonical_id, &bytes, checkpoint),
+            None => signed_message(&canonical_id, &bytes),
         }
+    }
 
-        let mut offset = 82 + 64 + 64;
-        while bytes.len() >= offset + 66 {
-            if let Ok(move_block) = MoveBlock::from_bytes(&bytes[offset..offset + 66]) {
-                chain.moves.push(move_block);
-                offset += 66;
-            } else {
-                break;
+    /// The prefix an extension block of the given kind signs. Checkpoints
+    /// sign only the canonical id, so they verify without the blocks before
+    /// them.
+    fn extension_prefix(&self, kind: ExtensionKind) -> Vec<u8> {
+        match kind {
+            ExtensionKind::Checkpoint => self.canonical_id().to_vec(),
+            _ => self.signing_prefix(),
+        }
+    }
+
+    /// The blocks after the accepts, in encoded order.
+    pub fn entries(&self) -> Vec<ChainEntry<'_>> {
+        let mut entries = Vec::with_capacity(self.moves.len() + self.extensions.len());
+        let mut extensions = self.extensions.iter().peekable();
+        for (index, block) in self.moves.iter().enumerate() {
+            while let Some((_, extension)) = extensions.next_if(|(at, _)| *at <= index) {
+                entries.push(ChainEntry::Extension(extension));
             }
+            entries.push(ChainEntry::Move { index, block });
         }
+        entries.extend(extensions.map(|(_, extension)| ChainEntry::Extension(extension)));
+        entries
+    }
+
+    /// Follows the game line through every block, checking takebacks.
+    pub(crate) fn line(&self) -> Result<Line, Error> {
+        let mut line = Line::default();
+        for entry in self.entries() {
+            match entry {
+                ChainEntry::Move { .. } => line.push_move(),
+                ChainEntry::Extension(block) => line.push_extension(block.kind(), block.body())?,
+            }
+        }
+        Ok(line)
+    }
+
+    /// The move blocks still on the game line once accepted takebacks are
+    /// applied.
+    pub fn line_moves(&self) -> Result<Vec<&MoveBlock>, Error> {
+        let mut line = Line::default();
+        let mut moves = Vec::new();
+        for entry in self.entries() {
+            match entry {
+                ChainEntry::Move { block, .. } => {
+                    line.push_move();
+                    moves.push(block);
+                }
+                ChainEntry::Extension(block) => {
+                    line.push_extension(block.kind(), block.body())?;
+                    moves.truncate(line.len());
+                }
+            }
+        }
+        Ok(moves)
+    }
+
+    /// Replays the chain's moves from the starting position, rolling back
+    /// any accepted takebacks. Fails with the index of the first move block
+    /// that wasn't a legal move when it was played.
+    pub fn get_game(&self) -> Result<Game, Error> {
+        let start = self.challenge.starting_board();
+        let mut line = Line::default();
+        let mut boards = vec![start];
+        let mut moves = Vec::new();
+        for entry in self.entries() {
+            match entry {
+                ChainEntry::Move { index, block } => {
+                    let board = boards[boards.len() - 1];
+                    let mv = block
+                        .find_move(&board)
+                        .ok_or(Error::InvalidMoveBlock { index })?;
+                    line.push_move();
+                    boards.push(board.make_move_new(mv));
+                    moves.push(mv);
+                }
+                ChainEntry::Extension(block) => {
+                    line.push_extension(block.kind(), block.body())?;
+                    boards.truncate(line.len() + 1);
+                    moves.truncate(line.len());
+                }
+            }
+        }
+        let mut game = Game::new_with_board(start);
+        for mv in moves {
+            game.make_move(mv);
+        }
+        Ok(game)
+    }
+
+    /// The result of the game, or None if it is still in progress, disputed,
+    /// or the chain is invalid.
+    pub fn result(&self) -> Option<GameResult> {
+        match self.dispute() {
+            Some(dispute) => dispute.resolution,
+            None => self.claimed_result(),
+        }
+    }
+
+    /// The result the game's blocks show, whether or not it was disputed.
+    pub fn claimed_result(&self) -> Option<GameResult> {
+        let board = self.current_position().ok()?;
+        if let Some(forfeit) = self.time_forfeit() {
+            return Some(GameResult::Win(!forfeit.flagged));
+        }
+        if let Some(loser) = commitment::illegal_reply(self) {
+            return Some(GameResult::Win(!loser));
+        }
+        match board.status() {
+            BoardStatus::Ongoing => self.adjudication().map(|adjudication| adjudication.result),
+            BoardStatus::Stalemate => Some(GameResult::Draw),
+            BoardStatus::Checkmate => Some(GameResult::Win(!board.side_to_move())),
+        }
+    }
+
+    pub fn status(&self) -> GameStatus {
+        if self.accepts[0].is_none() || self.accepts[1].is_none() {
+            return GameStatus::AwaitingAcceptance;
+        }
+        if self.current_position().is_err() {
+            return GameStatus::Invalid;
+        }
+        if self
+            .dispute()
+            .is_some_and(|dispute| dispute.resolution.is_none())
+        {
+            return GameStatus::Disputed;
+        }
+        match self.result() {
+            Some(result) => GameStatus::Finished(result),
+            None if self.is_adjourned() => GameStatus::Adjourned,
+            None => GameStatus::InProgress,
+        }
+    }
+
+    /// The move blocks, for changing. Every change to them goes through here,
+    /// so the position cache can't outlive the moves it was read from.
+    fn moves_mut(&mut self) -> &mut Vec<MoveBlock> {
+        self.position.set(None);
+        &mut self.moves
+    }
+
+    /// The position after the last move. This is cached, so unlike get_game
+    /// it doesn't replay the whole game on every call.
+    pub fn current_position(&self) -> Result<Board, Error> {
+        if let Some(board) = self.position.get() {
+            return Ok(board);
+        }
+        let board = self.get_game()?.current_position();
+        self.position.set(Some(board));
+        Ok(board)
+    }
+
+    /// Steps through the moves on the game line, yielding the move's index on
+    /// the line, the move, the board after the move, and the fingerprint of
+    /// the key that signed it.
+    pub fn iter_positions(&self) -> Positions<'_> {
+        Positions {
+            moves: self.line_moves().unwrap_or_default().into_iter(),
+            board: self.challenge.starting_board(),
+            index: 0,
+            signers: (
+                crypto::fingerprint(self.signer_public_key(0).as_ref()),
+                crypto::fingerprint(self.signer_public_key(1).as_ref()),
+            ),
+        }
+    }
+
+    /// Accepts the challenge now. Without std there is no clock, so accepts
+    /// are stamped with the challenge's own timestamp; use accept_at or
+    /// accept_with to give the time explicitly.
+    pub fn accept(&mut self, key_pair: &Ed25519KeyPair) -> Result<(), Error> {
+        #[cfg(feature = "std")]
+        let now = SystemClock.now();
+        #[cfg(not(feature = "std"))]
+        let now = self.challenge.timestamp;
+        self.accept_at(key_pair, now)
+    }
+
+    /// Accepts the challenge at the time `clock` reads.
+    pub fn accept_with(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        clock: &dyn Clock,
+    ) -> Result<(), Error> {
+        self.accept_at(key_pair, clock.now())
+    }
+
+    /// Accepts the challenge at time `now`, in seconds since the Unix epoch.
+    /// Fails with ChallengeExpired if the challenge has expired.
+    pub fn accept_at(&mut self, key_pair: &Ed25519KeyPair, now: u64) -> Result<(), Error> {
+        let public_key = PublicKey::of(key_pair);
+        if self.challenge.white_public_key != public_key
+            && self.challenge.black_public_key != public_key
+        {
+            return Err(Error::KeyNotInChallenge);
+        }
+
+        if self.accepts[0].is_none() && self.accepts[1].is_some() {
+            self.accepts[0] = self.accepts[1].clone();
+            self.accepts[1] = None;
+        }
+
+        if self.accepts[1].is_none() {
+            self.challenge.check_valid_at(now)?;
+        }
+
+        if self.accepts[0].is_none() {
+            self.accepts[0] = Some(AcceptBlock::new(&self.challenge, key_pair, now));
+        } else if self.accepts[1].is_none() {
+            if self.accepts_signed_by(0, public_key) {
+                return Err(Error::AlreadyAccepted);
+            }
+            self.accepts[1] = Some(AcceptBlock::new(&self.challenge, key_pair, now));
+        } else {
+            return Err(Error::ChainFull);
+        }
+
+        metrics::block_appended(self.challenge.id, "accept");
+        self.emit(ChainEvent::Accepted {
+            public_key: *public_key.as_bytes(),
+        });
+        Ok(())
+    }
+
+    pub fn legal_moves(&self) -> Result<Vec<ChessMove>, Error> {
+        Ok(MoveGen::new_legal(&self.current_position()?).collect())
+    }
+
+    /// The color the given key plays in this game, if it is one of the
+    /// players' current keys.
+    pub fn side_of(&self, public_key: &[u8]) -> Option<Color> {
+        if self.current_public_key(Color::White) == *public_key {
+            Some(Color::White)
+        } else if self.current_public_key(Color::Black) == *public_key {
+            Some(Color::Black)
+        } else {
+            None
+        }
+    }
+
+    /// Whether `public_key` is to move. Never true before both players have
+    /// accepted the challenge.
+    pub fn is_my_turn(&self, public_key: &[u8]) -> bool {
+        if self.accepts[0].is_none() || self.accepts[1].is_none() {
+            return false;
+        }
+        match self.current_position() {
+            Ok(board) => self.side_of(public_key) == Some(board.side_to_move()),
+            Err(_) => false,
+        }
+    }
+
+    /// Appends an accept block signed elsewhere, e.g. received from the
+    /// opponent, after checking its signature.
+    pub fn append_accept_block(&mut self, block: AcceptBlock) -> Result<(), Error> {
+        let challenge_bytes = self.challenge.as_bytes();
+        let signer = block.signer(&self.challenge, &challenge_bytes);
+        let public_key = match signer {
+            Some(Color::White) => self.challenge.white_public_key(),
+            Some(Color::Black) => self.challenge.black_public_key(),
+            None => return Err(Error::KeyNotInChallenge),
+        };
+        block.check_time(&self.challenge)?;
+
+        if self.accepts.iter().flatten().any(|accept| {
+            accept == &block || accept.signer(&self.challenge, &challenge_bytes) == signer
+        }) {
+            return Err(Error::AlreadyAccepted);
+        }
+        if self.accepts[0].is_none() {
+            self.accepts[0] = Some(block);
+        } else if self.accepts[1].is_none() {
+            self.accepts[1] = Some(block);
+        } else {
+            return Err(Error::ChainFull);
+        }
+
+        metrics::block_appended(self.challenge.id, "accept");
+        self.emit(ChainEvent::Accepted {
+            public_key: *public_key.as_bytes(),
+        });
+        Ok(())
+    }
+
+    /// Appends a move block signed elsewhere, e.g. received from the
+    /// opponent, after checking that it is legal and correctly signed.
+    pub fn append_move_block(&mut self, block: MoveBlock) -> Result<(), Error> {
+        if self.accepts[0].is_none() || self.accepts[1].is_none() {
+            return Err(Error::VerificationFailed);
+        }
+        self.check_move_room()?;
+        let board = self.current_position()?;
+        let mv = block.find_move(&board).ok_or(Error::IllegalMove)?;
+        adjournment::check_move(self)?;
+        adjudication::check_move(self)?;
+        forfeit::check_move(self)?;
+        commitment::check_move(self, block.start_square, block.end_square)?;
+
+        let mut chain_bytes = self.signing_prefix();
+        chain_bytes.push(block.start_square);
+        chain_bytes.push(block.end_square);
+        let public_key = self.signer_for_next_move()?;
+        if !public_key.verify(&chain_bytes, &block.signature)
+            && !self.redemptions().redeems(
+                &self.canonical_id(),
+                public_key.as_bytes(),
+                [block.start_square, block.end_square],
+                &block.signature,
+            )
+        {
+            return Err(Error::VerificationFailed);
+        }
+
+        self.moves_mut().push(block);
+        self.position.set(Some(board.make_move_new(mv)));
+        self.emit_move_added();
+
+        Ok(())
+    }
+
+    pub fn make_move_block(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        action: Action,
+    ) -> Result<(), Error> {
+        let board = self.current_position()?;
+
+        if self.side_of(key_pair.public_key().as_ref()) != Some(board.side_to_move()) {
+            return Err(Error::NotYourTurn);
+        }
+
+        let (block, mv) = match action {
+            Action::MakeMove(mv) => {
+                let start_square = mv.get_source().to_int();
+                let end_square = mv.get_dest().to_int();
+
+                // A move block carries only its two squares, and replays as
+                // the first legal move between them, so refuse any move
+                // (an underpromotion) that would replay as something else.
+                let replayed = MoveGen::new_legal(&board).find(|legal| {
+                    legal.get_source() == mv.get_source() && legal.get_dest() == mv.get_dest()
+                });
+                if replayed != Some(mv) {
+                    return Err(Error::IllegalMove);
+                }
+                self.check_move_room()?;
+                adjournment::check_move(self)?;
+                adjudication::check_move(self)?;
+                forfeit::check_move(self)?;
+                commitment::check_move(self, start_square, end_square)?;
+
+                let mut chain_bytes = self.signing_prefix();
+                chain_bytes.push(start_square);
+                chain_bytes.push(end_square);
+                let signature = crypto::sign(key_pair, &chain_bytes);
+                let block = MoveBlock {
+                    start_square,
+                    end_square,
+                    signature,
+                };
+                (block, mv)
+            }
+            _ => {
+                return Err(Error::UnsupportedAction);
+            }
+        };
+
+        self.moves_mut().push(block);
+        self.position.set(Some(board.make_move_new(mv)));
+        self.emit_move_added();
+
+        Ok(())
+    }
+
+    /// Signs and appends an extension block of the given kind.
+    pub(crate) fn sign_extension(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        kind: ExtensionKind,
+        body: Vec<u8>,
+    ) -> Result<(), Error> {
+        if self.accepts[0].is_none() || self.accepts[1].is_none() {
+            return Err(Error::VerificationFailed);
+        }
+        let line = self.line()?;
+        let public_key = kind
+            .signer_public_key(&self.challenge, line.len(), &body)
+            .map(|public_key| self.current_key(public_key))
+            .ok_or(Error::Malformed {
+                block: "extension",
+                reason: "wrong body",
+            })?;
+        if public_key != PublicKey::of(key_pair) {
+            return Err(Error::NotYourTurn);
+        }
+        let block = ExtensionBlock::new(&self.extension_prefix(kind), kind, body, key_pair);
+        self.check_extension_room()?;
+        self.check_extension(line, &block)?;
+        self.extensions.push((self.moves.len(), block));
+        metrics::block_appended(self.challenge.id, "extension");
+        Ok(())
+    }
+
+    /// Appends an extension block signed elsewhere, after checking its
+    /// signature and that it may follow the blocks already in the chain.
+    pub fn append_extension_block(&mut self, block: ExtensionBlock) -> Result<(), Error> {
+        match block.kind() {
+            ExtensionKind::Commentary => return self.append_commentary_block(block),
+            ExtensionKind::Analysis => return self.append_analysis_block(block),
+            _ => {}
+        }
+        if self.accepts[0].is_none() || self.accepts[1].is_none() {
+            return Err(Error::VerificationFailed);
+        }
+        let line = self.line()?;
+        let public_key = block
+            .kind()
+            .signer_public_key(&self.challenge, line.len(), block.body())
+            .map(|public_key| self.current_key(public_key))
+            .ok_or(Error::VerificationFailed)?;
+        if !block.verify(&self.extension_prefix(block.kind()), public_key.as_ref()) {
+            return Err(Error::VerificationFailed);
+        }
+        self.check_extension_room()?;
+        self.check_extension(line, &block)?;
+        self.extensions.push((self.moves.len(), block));
+        metrics::block_appended(self.challenge.id, "extension");
+        Ok(())
+    }
+
+    /// Checks that `block` may follow the chain, whose game line is `line`.
+    fn check_extension(&self, mut line: Line, block: &ExtensionBlock) -> Result<(), Error> {
+        let settled = self.time_forfeit().is_some()
+            || self.adjudication().is_some()
+            || self.dispute().is_some();
+        if settled && !dispute::follows_result(block.kind()) {
+            return Err(Error::GameOver);
+        }
+        match block.kind() {
+            ExtensionKind::Commitment
+            | ExtensionKind::CommitmentAck
+            | ExtensionKind::CounterCommitment
+            | ExtensionKind::Reveal => commitment::check_extension(self, block),
+            ExtensionKind::Receipt => receipt::check_extension(self, block),
+            ExtensionKind::Pause | ExtensionKind::Resume => {
+                adjournment::check_extension(self, block)
+            }
+            // takebacks would throw off which player commits first
+            ExtensionKind::TakebackRequest if self.challenge.simultaneous => {
+                Err(Error::InvalidTakeback)
+            }
+            ExtensionKind::TakebackRequest => line.push_extension(block.kind(), block.body()),
+            ExtensionKind::Commentary | ExtensionKind::Analysis => Err(Error::Malformed {
+                block: "commentary",
+                reason: "not a game block",
+            }),
+            ExtensionKind::TakebackAccept => {
+                line.push_extension(block.kind(), block.body())?;
+                // the position cache holds the board before the takeback
+                self.position.set(None);
+                Ok(())
+            }
+            ExtensionKind::Checkpoint => {
+                line.push_extension(block.kind(), block.body())?;
+                checkpoint::check_extension(self, block.body())
+            }
+            ExtensionKind::Conditional => conditional::check_extension(self, block.body()),
+            ExtensionKind::ClaimTimeForfeit => forfeit::check_extension(self, block.body()),
+            ExtensionKind::Dispute | ExtensionKind::Resolution => {
+                dispute::check_extension(self, block.kind(), block.body())
+            }
+            ExtensionKind::Vacation => vacation::check_extension(self, block.body()),
+            ExtensionKind::KeyRotation => rotation::check_extension(self, block.body()),
+            ExtensionKind::Adjudication => adjudication::check_extension(self, block.body()),
+        }
+    }
+
+    /// Checks the rules for every extension block in the chain, beyond their
+    /// signatures.
+    pub(crate) fn check_extensions(&self) -> Result<(), Error> {
+        self.line()?;
+        adjournment::check_chain(self)?;
+        adjudication::check_chain(self)?;
+        checkpoint::check_chain(self)?;
+        commitment::check_chain(self)?;
+        dispute::check_chain(self)?;
+        forfeit::check_chain(self)?;
+        receipt::check_chain(self)?;
+        vacation::check_chain(self)
+    }
+
+    /// Merges another copy of the same game into this one. If one chain is a
+    /// prefix of the other, this chain becomes the longer of the two. Blocks
+    /// are indexed in encoded order: 0 is the challenge, 1 and 2 are the
+    /// accepts, and moves and extensions follow from 3.
+    pub fn merge(&mut self, other: &GameChain) -> Result<(), Error> {
+        if self.challenge.network_id != other.challenge.network_id {
+            return Err(Error::WrongNetwork {
+                expected: self.challenge.network_id,
+                found: other.challenge.network_id,
+            });
+        }
+        if self.challenge != other.challenge {
+            return Err(Error::ForkDetected { block_index: 0 });
+        }
+        for i in 0..2 {
+            if let (Some(ours), Some(theirs)) = (&self.accepts[i], &other.accepts[i]) {
+                if ours != theirs {
+                    return Err(Error::ForkDetected { block_index: 1 + i });
+                }
+            }
+        }
+        for (i, (ours, theirs)) in self.moves.iter().zip(&other.moves).enumerate() {
+            if ours != theirs {
+                let before = self.extensions.iter().filter(|(at, _)| *at <= i).count();
+                return Err(Error::ForkDetected {
+                    block_index: 3 + i + before,
+                });
+            }
+        }
+        for (i, (ours, theirs)) in self.extensions.iter().zip(&other.extensions).enumerate() {
+            if ours != theirs {
+                return Err(Error::ForkDetected {
+                    block_index: 3 + i + ours.0.min(theirs.0),
+                });
+            }
+        }
+        // An extension only one side has is a fork if the other side already
+        // has a move in its place.
+        for (longer, shorter) in [(&*self, other), (other, &*self)] {
+            for (i, (at, _)) in longer.extensions.iter().enumerate() {
+                if i >= shorter.extensions.len() && *at < shorter.moves.len() {
+                    return Err(Error::ForkDetected {
+                        block_index: 3 + i + at,
+                    });
+                }
+            }
+        }
+
+        let ours = self.accepts.iter().filter(|a| a.is_some()).count()
+            + self.moves.len()
+            + self.extensions.len();
+        let theirs = other.accepts.iter().filter(|a| a.is_some()).count()
+            + other.moves.len()
+            + other.extensions.len();
+        if theirs > ours {
+            if (!other.moves.is_empty() || !other.extensions.is_empty()) && !other.verify() {
+                return Err(Error::VerificationFailed);
+            }
+            // Accepts are checked one by one, since a chain with nothing
+            // after them doesn't verify.
+            let challenge_bytes = self.challenge.as_bytes();
+            let mut signers = [None, None];
+            for i in 0..2 {
+                let accept = match (&self.accepts[i], &other.accepts[i]) {
+                    (Some(accept), _) => accept,
+                    (None, Some(accept)) => {
+                        accept.check_time(&self.challenge)?;
+                        accept
+                    }
+                    (None, None) => continue,
+                };
+                signers[i] = Some(
+                    accept
+                        .signer(&self.challenge, &challenge_bytes)
+                        .ok_or(Error::VerificationFailed)?,
+                );
+            }
+            if signers[0].is_some() && signers[0] == signers[1] {
+                return Err(Error::AlreadyAccepted);
+            }
+            for i in 0..2 {
+                if self.accepts[i].is_none() && other.accepts[i].is_some() {
+                    self.accepts[i] = other.accepts[i].clone();
+                    let public_key = match signers[i] {
+                        Some(Color::White) => self.challenge.white_public_key(),
+                        _ => self.challenge.black_public_key(),
+                    };
+                    self.emit(ChainEvent::Accepted {
+                        public_key: *public_key.as_bytes(),
+                    });
+                }
+            }
+            self.position.set(None);
+            self.extensions = other.extensions.clone();
+            for move_block in &other.moves[self.moves.len()..] {
+                self.moves_mut().push(move_block.clone());
+                self.emit_move_added();
+            }
+        }
+        for block in &other.commentary {
+            if !self.commentary.contains(block) {
+                self.push_commentary(block.clone())?;
+            }
+        }
+        Ok(())
+    }
 
-        if chain.verify() {
-            return Ok(chain);
-        } else {
-            return Err("Chain does not verify.");
+    fn accepts_signed_by(&self, index: usize, public_key: PublicKey) -> bool {
+        match &self.accepts[index] {
+            Some(accept) => accept.verify(&self.challenge.as_bytes(), public_key.as_bytes()),
+            None => false,
         }
     }
 
-    pub fn get_game(&self) -> Game {
-        let mut game = Game::new();
-        'next_block: for move_block in &self.moves {
-            for mv in MoveGen::new_legal(&game.current_position()) {
-                if move_block.start_square == mv.get_source().to_int()
-                    && move_block.end_square == mv.get_dest().to_int()
-                {
-                    game.make_move(mv);
-                    continue 'next_block;
+    /// Checks the accepts the chain has so far, for challenges still
+    /// awaiting acceptance, which verify always fails: each must be signed
+    /// by a different player and timestamped as the challenge requires.
+    pub fn check_accepts(&self) -> Result<(), Error> {
+        let challenge_bytes = self.challenge.as_bytes();
+        let mut signers = [None, None];
+        for (signer, accept) in signers.iter_mut().zip(&self.accepts) {
+            if let Some(accept) = accept {
+                *signer = accept.signer(&self.challenge, &challenge_bytes);
+                if signer.is_none() {
+                    return Err(Error::VerificationFailed);
                 }
+                accept.check_time(&self.challenge)?;
             }
-            panic!("Invalid game chain!");
         }
-        game
+        if signers[0].is_some() && signers[0] == signers[1] {
+            return Err(Error::AlreadyAccepted);
+        }
+        Ok(())
     }
 
-    pub fn accept(&mut self, key_pair: &Ed25519KeyPair) -> Result<(), &str> {
-        let mut public_key_bytes: [u8; 32] = [0; 32];
-        public_key_bytes.copy_from_slice(key_pair.public_key().as_ref());
-        if public_key_bytes != self.challenge.white_public_key
-            && public_key_bytes != self.challenge.black_public_key
-        {
-            return Err("This key is not in the challenge block.");
+    pub fn verify(&self) -> bool {
+        let timer = metrics::VerifyTimer::start();
+        let verified = self.verify_signatures()
+            && self.check_extensions().is_ok()
+            && seeding::check_color_flip(&self.challenge).is_ok();
+        timer.finish(self.challenge.id, verified);
+        if !verified {
+            self.emit(ChainEvent::VerificationFailed);
         }
+        verified
+    }
 
-        if self.accepts[0].is_none() && self.accepts[1].is_some() {
-            self.accepts[0] = self.accepts[1].clone();
-            self.accepts[1] = None;
+    fn verify_signatures(&self) -> bool {
+        if self.accepts[0].is_none() || self.accepts[1].is_none() {
+            return false;
         }
-
-        if self.accepts[0].is_none() {
-            self.accepts[0] = Some(AcceptBlock::new(&self.challenge, key_pair));
-            return Ok(());
-        } else if self.accepts[1].is_none() {
-            if crypto::verify(
-                &public_key_bytes,
-                &self.challenge.as_bytes(),
-                &self.accepts[0].clone().unwrap().signature,
-            ) {
-                return Err("This key is already present in the chain.");
-            }
-            self.accepts[1] = Some(AcceptBlock::new(&self.challenge, key_pair));
-            return Ok(());
-        } else {
-            return Err("There are already two signatures on this chain.");
+        let bytes = self.as_bytes();
+        match GameChainView::new(&bytes) {
+            Ok(view) => view.verify(),
+            Err(_) => false,
         }
     }
 
-    pub fn make_move_block(
-        &mut self,
-        key_pair: &Ed25519KeyPair,
-        action: Action,
-    ) -> Result<(), &str> {
-        let game = self.get_game();
+    /// The public key that must sign the move at `ply`, counting from the
+    /// side to move in the starting position, after any key rotations.
+    pub fn signer_public_key(&self, ply: usize) -> PublicKey {
+        self.current_key(self.challenge.signer_public_key(ply))
+    }
 
-        let public_key_to_move = match game.side_to_move() {
-            Color::White => self.challenge.white_public_key,
-            Color::Black => self.challenge.black_public_key,
-        };
-        if public_key_to_move != key_pair.public_key().as_ref() {
-            return Err("This key cannot sign the current move.");
-        }
+    /// The public key that must sign the next move, following the game line
+    /// through any accepted takebacks.
+    pub fn signer_for_next_move(&self) -> Result<PublicKey, Error> {
+        Ok(self.signer_public_key(self.line()?.len()))
+    }
 
-        let block = match action {
-            Action::MakeMove(mv) => {
-                let start_square = mv.get_source().to_int();
-                let end_square = mv.get_dest().to_int();
+    /// Returns a copy of this chain keeping only the first `len` moves and the
+    /// extension blocks signed before the next one, without commentary.
+    pub fn truncated(&self, len: usize) -> GameChain {
+        let mut chain = self.clone();
+        chain.moves_mut().truncate(len);
+        chain.extensions.retain(|(at, _)| *at <= len);
+        chain.commentary.clear();
+        chain
+    }
 
-                if !game.current_position().legal(mv) {
-                    return Err("Invalid move.");
-                }
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        self.write_bytes(&mut bytes);
+        bytes
+    }
 
-                let mut chain_bytes = self.as_bytes();
-                chain_bytes.push(start_square);
-                chain_bytes.push(end_square);
-                let signature = crypto::sign(key_pair, &chain_bytes);
-                MoveBlock {
-                    start_square,
-                    end_square,
-                    signature,
-                }
-            }
-            _ => {
-                return Err("Action not implemented");
+    /// Appends the encoded chain to `bytes`, so callers serving many chains
+    /// can reuse one buffer.
+    pub fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        if self.write_game_bytes(bytes) {
+            for block in &self.commentary {
+                block.write_bytes(bytes);
             }
-        };
-
-        self.moves.push(block);
-
-        Ok(())
+        }
     }
 
-    pub fn verify(&self) -> bool {
-        if self.accepts[0].is_none() || self.accepts[1].is_none() {
-            return false;
-        }
-        if !((crypto::verify(
-            &self.challenge.white_public_key,
-            &self.challenge.as_bytes(),
-            &self.accepts[0].clone().unwrap().signature,
-        ) && crypto::verify(
-            &self.challenge.black_public_key,
-            &self.challenge.as_bytes(),
-            &self.accepts[1].clone().unwrap().signature,
-        )) || (crypto::verify(
-            &self.challenge.white_public_key,
-            &self.challenge.as_bytes(),
-            &self.accepts[1].clone().unwrap().signature,
-        ) && crypto::verify(
-            &self.challenge.black_public_key,
-            &self.challenge.as_bytes(),
-            &self.accepts[0].clone().unwrap().signature,
-        ))) {
-            return false;
-        }
+    /// Writes everything but the commentary, returning false if the chain
+    /// stopped short of the second accept.
+    fn write_game_bytes(&self, bytes: &mut Vec<u8>) -> bool {
+        self.challenge.write_bytes(bytes);
+        let accept = match &self.accepts[0] {
+            Some(accept) => accept,
+            None => return false,
+        };
+        accept.write_bytes(bytes);
+        let accept = match &self.accepts[1] {
+            Some(accept) => accept,
+            None => return false,
+        };
+        accept.write_bytes(bytes);
 
-        let mut chain = self.clone();
-        chain.moves = Vec::new();
-        let mut keys = (
-            chain.challenge.white_public_key,
-            chain.challenge.black_public_key,
-        );
-        for move_block in &self.moves {
-            let mut bytes = chain.as_bytes();
-            bytes.push(move_block.start_square);
-            bytes.push(move_block.end_square);
-            if !crypto::verify(&keys.0, &bytes, &move_block.signature) {
-                return false;
+        let mut extensions = self.extensions.iter().peekable();
+        for (ply, move_block) in self.moves.iter().enumerate() {
+            while let Some((_, extension)) = extensions.next_if(|(at, _)| *at <= ply) {
+                extension.write_bytes(bytes);
             }
-            chain.moves.push(move_block.clone());
-            keys = (keys.1, keys.0);
+            move_block.write_bytes(bytes);
         }
-
-        return true;
-    }
-
-    pub fn as_bytes(&self) -> Vec<u8> {
-        let mut bytes = self.challenge.as_bytes();
-        if self.accepts[0].is_none() {
-            return bytes;
+        for (_, extension) in extensions {
+            extension.write_bytes(bytes);
         }
+        true
+    }
 
-        bytes.extend(self.accepts[0].clone().unwrap().as_bytes());
-        if self.accepts[1].is_none() {
-            return bytes;
+    pub fn encoded_len(&self) -> usize {
+        let accepts = self.accepts.iter().take_while(|a| a.is_some()).count();
+        let mut len = self.challenge.encoded_len() + accepts * self.challenge.accept_len();
+        if accepts == 2 {
+            len += self.moves.len() * 66;
+            len += self
+                .extensions
+                .iter()
+                .map(|(_, extension)| extension.encoded_len())
+                .sum::<usize>();
+            len += self
+                .commentary
+                .iter()
+                .map(ExtensionBlock::encoded_len)
+                .sum::<usize>();
         }
-        bytes.extend(self.accepts[1].clone().unwrap().as_bytes());
+        len
+    }
+}
 
-        for move_block in &self.moves {
-            bytes.extend(move_block.as_bytes());
-        }
+impl GameChain {
+    /// Writes the one-line summary used by Display, naming players with
+    /// `name` where it knows them and by short fingerprint otherwise.
+    pub(crate) fn write_summary<F>(&self, f: &mut fmt::Formatter, name: F) -> fmt::Result
+    where
+        F: Fn(&[u8]) -> Option<String>,
+    {
+        let label = |public_key: &[u8]| name(public_key).unwrap_or_else(|| short_key(public_key));
+        let status = if self.accepts[0].is_none() || self.accepts[1].is_none() {
+            "awaiting acceptance"
+        } else {
+            match self.current_position() {
+                Ok(board) => match board.status() {
+                    BoardStatus::Ongoing if self.is_adjourned() => "adjourned",
+                    BoardStatus::Ongoing => "in progress",
+                    BoardStatus::Stalemate => "drawn by stalemate",
+                    BoardStatus::Checkmate => match board.side_to_move() {
+                        Color::White => "black wins by checkmate",
+                        Color::Black => "white wins by checkmate",
+                    },
+                },
+                Err(_) => "invalid",
+            }
+        };
+        write!(
+            f,
+            "Game {:08x}: White {} vs Black {}, {} moves, {}",
+            self.challenge.id,
+            label(self.challenge.white_public_key.as_ref()),
+            label(self.challenge.black_public_key.as_ref()),
+            self.moves.len(),
+            status
+        )
+    }
+}
 
-        bytes
+impl fmt::Display for GameChain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_summary(f, |_| None)
     }
 }
 
@@ -343,9 +2127,160 @@ mod test {
         let rng = crypto::new_rng();
         let white = crypto::generate_key(&rng);
         let black = crypto::generate_key(&rng);
-        let challenge =
-            ChallengeBlock::new(white.public_key().as_ref(), black.public_key().as_ref());
-        assert_eq!(challenge, ChallengeBlock::from_bytes(&challenge.as_bytes()));
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        assert_eq!(
+            challenge,
+            ChallengeBlock::from_bytes(&challenge.as_bytes()).unwrap()
+        );
+        assert_eq!(challenge.as_bytes().len(), 82);
+    }
+
+    #[test]
+    fn decode_original_chain() {
+        // Encoded as before challenges had versions past 0: an 82-byte
+        // challenge, then blocks signing the chain bytes without a canonical
+        // id.
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let mut bytes = vec![0, 0];
+        bytes.extend(&7u32.to_be_bytes());
+        bytes.extend(white.public_key().as_ref());
+        bytes.extend(black.public_key().as_ref());
+        bytes.extend(&[0; 12]);
+        let challenge_bytes = bytes.clone();
+        bytes.extend(crypto::sign(&white, &challenge_bytes));
+        bytes.extend(crypto::sign(&black, &challenge_bytes));
+        // e2e4 e7e5
+        for &(key_pair, start_square, end_square) in [(&white, 12, 28), (&black, 52, 36)].iter() {
+            bytes.push(start_square);
+            bytes.push(end_square);
+            let signature = crypto::sign(key_pair, &bytes);
+            bytes.extend(signature);
+        }
+
+        let chain = GameChain::from_bytes(&bytes).unwrap();
+        assert_eq!(chain.challenge().version(), 0);
+        assert_eq!(chain.challenge().id(), 7);
+        assert_eq!(chain.challenge().time_control(), None);
+        assert_eq!(chain.challenge().encoded_len(), 82);
+        assert_eq!(chain.moves().len(), 2);
+        assert!(chain.verify());
+        assert_eq!(chain.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn challenge_tags() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let arbiter = crypto::generate_key(&rng);
+        let builder = || {
+            ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+                .arbiter(&PublicKey::of(&arbiter))
+                .tag("Event", "Club Championship")
+                .tag("Round", "3")
+        };
+        let challenge = builder().build().unwrap();
+        assert_eq!(challenge.version(), 2);
+        assert_eq!(challenge.tag("Round"), Some("3"));
+        assert_eq!(challenge.tag("Site"), None);
+        let bytes = challenge.as_bytes();
+        assert_eq!(bytes.len(), challenge.encoded_len());
+        assert_eq!(ChallengeBlock::from_bytes(&bytes), Ok(challenge.clone()));
+        assert_ne!(
+            builder().tag("Board", "1").build().unwrap().canonical_id(),
+            challenge.canonical_id()
+        );
+
+        assert!(builder().tag("Round", "4").build().is_err());
+        assert!(builder().tag("Time Control", "G/90").build().is_err());
+        let long = "x".repeat(255);
+        assert_eq!(
+            builder().tag("A", &long).tag("B", &long).build(),
+            Err(Error::InvalidChallenge("tags are too long"))
+        );
+    }
+
+    #[test]
+    fn build_challenge() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .network(Network::Dev)
+            .id(7)
+            .timestamp(1_000)
+            .time_control(300, 5)
+            .variant(Variant::FromPosition)
+            .starting_fen(fen)
+            .build()
+            .unwrap();
+        assert_eq!(challenge.starting_fen(), Some(fen));
+        assert_eq!(
+            challenge,
+            ChallengeBlock::from_bytes(&challenge.as_bytes()).unwrap()
+        );
+
+        let mut chain = GameChain::new(challenge);
+        assert!(chain.accept(&white).is_ok());
+        assert!(chain.accept(&black).is_ok());
+        assert_eq!(chain, GameChain::from_bytes(&chain.as_bytes()).unwrap());
+        assert_eq!(
+            chain.get_game().unwrap().current_position(),
+            fen.parse::<Board>().unwrap()
+        );
+
+        assert_eq!(
+            ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+                .starting_fen(fen)
+                .build(),
+            Err(Error::InvalidChallenge(
+                "standard games cannot have a starting FEN"
+            ))
+        );
+        assert!(
+            ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+                .variant(Variant::Chess960)
+                .build()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn underpromotion() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .variant(Variant::FromPosition)
+            .starting_fen("4k3/P7/8/8/8/8/8/4K3 w - - 0 1")
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        assert!(chain.accept(&white).is_ok());
+        assert!(chain.accept(&black).is_ok());
+
+        let promotion =
+            |piece| Action::MakeMove(ChessMove::new(Square::A7, Square::A8, Some(piece)));
+        assert_eq!(
+            chain.make_move_block(&white, promotion(chess::Piece::Knight)),
+            Err(Error::IllegalMove)
+        );
+        assert!(chain.moves.is_empty());
+        assert!(chain
+            .make_move_block(&white, promotion(chess::Piece::Queen))
+            .is_ok());
+        assert_eq!(
+            chain.current_position().unwrap(),
+            chain.get_game().unwrap().current_position()
+        );
+        let decoded = GameChain::from_bytes(&chain.as_bytes()).unwrap();
+        assert_eq!(
+            decoded.current_position().unwrap(),
+            chain.current_position().unwrap()
+        );
     }
 
     #[test]
@@ -353,8 +2288,7 @@ mod test {
         let rng = crypto::new_rng();
         let white = crypto::generate_key(&rng);
         let black = crypto::generate_key(&rng);
-        let challenge =
-            ChallengeBlock::new(white.public_key().as_ref(), black.public_key().as_ref());
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
         let mut chain = GameChain::new(challenge.clone());
         assert!(chain.accept(&white).is_ok());
         assert!(chain.accept(&black).is_ok());
@@ -371,8 +2305,7 @@ mod test {
         let rng = crypto::new_rng();
         let white = crypto::generate_key(&rng);
         let black = crypto::generate_key(&rng);
-        let challenge =
-            ChallengeBlock::new(white.public_key().as_ref(), black.public_key().as_ref());
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
         let mut chain = GameChain::new(challenge);
 
         assert!(!chain.verify());
@@ -381,7 +2314,7 @@ mod test {
         assert!(!chain.verify());
 
         // sign a second time with the same key (shouldn't work)
-        assert!(!chain.accept(&white).is_ok());
+        assert!(chain.accept(&white).is_err());
         assert!(!chain.verify());
 
         // duplicate the key so both accept blocks will verify (but only for one color)
@@ -389,14 +2322,134 @@ mod test {
         assert!(!chain.verify());
     }
 
+    #[test]
+    fn challenge_expiry() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let builder = || {
+            ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+                .timestamp(1_000)
+        };
+        assert_eq!(
+            builder().valid_until(999).build(),
+            Err(Error::InvalidChallenge(
+                "challenge expires before it is issued"
+            ))
+        );
+        let challenge = builder().valid_until(2_000).build().unwrap();
+        assert_eq!(challenge.version(), 1);
+        assert_eq!(
+            ChallengeBlock::from_bytes(&challenge.as_bytes()),
+            Ok(challenge.clone())
+        );
+
+        let mut chain = GameChain::new(challenge);
+        assert_eq!(chain.accept_at(&white, 2_001), Err(Error::ChallengeExpired));
+        assert!(chain.accept_at(&white, 1_500).is_ok());
+        assert!(chain.accept_at(&black, 1_600).is_ok());
+        assert_eq!(
+            chain.accepts()[0].as_ref().unwrap().timestamp(),
+            Some(1_500)
+        );
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain.clone()));
+
+        // a timestamp outside the window fails verification even if signed
+        let mut late = GameChain::new(chain.challenge().clone());
+        late.accepts[0] = Some(AcceptBlock::new(late.challenge(), &white, 1_500));
+        late.accepts[1] = Some(AcceptBlock::new(late.challenge(), &black, 2_500));
+        assert!(!late.verify());
+    }
+
+    #[test]
+    fn signed_accepts() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .signed_accepts()
+            .build()
+            .unwrap();
+        assert_eq!(challenge.version(), 3);
+        assert_eq!(challenge.accept_len(), 73);
+
+        let mut chain = GameChain::new(challenge);
+        chain.accept_at(&black, 1_500).unwrap();
+        chain.accept_at(&white, 1_600).unwrap();
+        let accepts = chain.accepts();
+        let black_accept = accepts[0].as_ref().unwrap();
+        assert_eq!(black_accept.color(), Some(Color::Black));
+        assert_eq!(black_accept.timestamp(), Some(1_500));
+        assert_eq!(accepts[1].as_ref().unwrap().color(), Some(Color::White));
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain.clone()));
+
+        // the same player can't fill both slots, even at different times
+        let mut twice = GameChain::new(chain.challenge().clone());
+        twice.accepts[0] = Some(AcceptBlock::new(twice.challenge(), &black, 1_500));
+        twice.accepts[1] = Some(AcceptBlock::new(twice.challenge(), &black, 1_600));
+        assert!(!twice.verify());
+        let mut received = GameChain::new(chain.challenge().clone());
+        received.append_accept_block(black_accept.clone()).unwrap();
+        assert_eq!(
+            received.append_accept_block(AcceptBlock::new(received.challenge(), &black, 1_600)),
+            Err(Error::AlreadyAccepted)
+        );
+    }
+
+    #[test]
+    fn signatures_bound_to_chain() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let game = |id| {
+            let challenge =
+                ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+                    .id(id)
+                    .build()
+                    .unwrap();
+            let mut chain = GameChain::new(challenge);
+            chain.accept(&white).unwrap();
+            chain.accept(&black).unwrap();
+            chain
+        };
+        let mut first = game(1);
+        let mut second = game(2);
+        assert_ne!(first.canonical_id(), second.canonical_id());
+        assert_eq!(
+            first.canonical_id(),
+            canonical_id(&first.challenge().as_bytes())
+        );
+
+        let mut replayed = GameChain::new(second.challenge().clone());
+        assert_eq!(
+            replayed.append_accept_block(first.accepts()[0].clone().unwrap()),
+            Err(Error::KeyNotInChallenge)
+        );
+
+        let e4 = Action::MakeMove(ChessMove::new(
+            "e2".parse::<Square>().unwrap(),
+            "e4".parse::<Square>().unwrap(),
+            None,
+        ));
+        first.make_move_block(&white, e4).unwrap();
+        assert_eq!(
+            second.append_move_block(first.moves()[0].clone()),
+            Err(Error::VerificationFailed)
+        );
+    }
+
     #[test]
     fn chain_to_bytes_and_back() {
         let rng = crypto::new_rng();
         let white = crypto::generate_key(&rng);
         let black = crypto::generate_key(&rng);
-        let challenge =
-            ChallengeBlock::new(white.public_key().as_ref(), black.public_key().as_ref());
-        assert_eq!(challenge, ChallengeBlock::from_bytes(&challenge.as_bytes()));
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        assert_eq!(
+            challenge,
+            ChallengeBlock::from_bytes(&challenge.as_bytes()).unwrap()
+        );
         let mut chain = GameChain::new(challenge.clone());
         assert!(chain.accept(&white).is_ok());
         assert!(chain.accept(&black).is_ok());
@@ -407,8 +2460,8 @@ mod test {
             .make_move_block(
                 &white,
                 Action::MakeMove(ChessMove::new(
-                    Square::from_string("e2".to_string()).unwrap(),
-                    Square::from_string("e4".to_string()).unwrap(),
+                    "e2".parse::<Square>().unwrap(),
+                    "e4".parse::<Square>().unwrap(),
                     None,
                 )),
             )
@@ -418,8 +2471,8 @@ mod test {
             .make_move_block(
                 &black,
                 Action::MakeMove(ChessMove::new(
-                    Square::from_string("e7".to_string()).unwrap(),
-                    Square::from_string("e5".to_string()).unwrap(),
+                    "e7".parse::<Square>().unwrap(),
+                    "e5".parse::<Square>().unwrap(),
                     None,
                 )),
             )
@@ -430,8 +2483,8 @@ mod test {
             .make_move_block(
                 &white,
                 Action::MakeMove(ChessMove::new(
-                    Square::from_string("f2".to_string()).unwrap(),
-                    Square::from_string("f4".to_string()).unwrap(),
+                    "f2".parse::<Square>().unwrap(),
+                    "f4".parse::<Square>().unwrap(),
                     None,
                 )),
             )
@@ -440,14 +2493,241 @@ mod test {
         assert_eq!(chain, GameChain::from_bytes(&chain.as_bytes()).unwrap());
     }
 
+    #[test]
+    fn trailing_bytes_rejected() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        let trailing = |chain: &GameChain| {
+            let mut bytes = chain.as_bytes();
+            assert_eq!(*chain, GameChain::from_bytes(&bytes).unwrap());
+            bytes.push(0);
+            assert_eq!(
+                GameChain::from_bytes(&bytes),
+                Err(Error::Malformed {
+                    block: "chain",
+                    reason: "trailing bytes",
+                })
+            );
+        };
+
+        trailing(&chain);
+        assert!(chain.accept(&white).is_ok());
+        assert!(chain.accept(&black).is_ok());
+        trailing(&chain);
+        for (key, mv) in [(&white, "e2e4"), (&black, "e7e5")].iter() {
+            let mv = ChessMove::new(
+                mv[..2].parse::<Square>().unwrap(),
+                mv[2..].parse::<Square>().unwrap(),
+                None,
+            );
+            assert!(chain.make_move_block(key, Action::MakeMove(mv)).is_ok());
+        }
+        trailing(&chain);
+    }
+
+    #[test]
+    fn append_received_blocks() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut ours = GameChain::new(challenge.clone());
+        let mut theirs = GameChain::new(challenge);
+
+        assert!(theirs.accept(&black).is_ok());
+        assert!(ours
+            .append_accept_block(theirs.accepts[0].clone().unwrap())
+            .is_ok());
+        assert_eq!(
+            ours.append_accept_block(theirs.accepts[0].clone().unwrap()),
+            Err(Error::AlreadyAccepted)
+        );
+        assert!(ours.accept(&white).is_ok());
+        assert!(theirs
+            .append_accept_block(ours.accepts[1].clone().unwrap())
+            .is_ok());
+        assert_eq!(ours, theirs);
+
+        assert!(theirs
+            .make_move_block(
+                &white,
+                Action::MakeMove(ChessMove::new(
+                    "e2".parse::<Square>().unwrap(),
+                    "e4".parse::<Square>().unwrap(),
+                    None,
+                )),
+            )
+            .is_ok());
+        let mut forged = theirs.moves[0].clone();
+        forged.signature[0] ^= 1;
+        assert_eq!(
+            ours.append_move_block(forged),
+            Err(Error::VerificationFailed)
+        );
+        assert!(ours.append_move_block(theirs.moves[0].clone()).is_ok());
+        assert_eq!(ours, theirs);
+        assert!(ours.verify());
+    }
+
+    #[test]
+    fn merge_chains() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        assert!(chain.accept(&white).is_ok());
+        assert!(chain.accept(&black).is_ok());
+        let e4 = Action::MakeMove(ChessMove::new(
+            "e2".parse::<Square>().unwrap(),
+            "e4".parse::<Square>().unwrap(),
+            None,
+        ));
+        let d4 = Action::MakeMove(ChessMove::new(
+            "d2".parse::<Square>().unwrap(),
+            "d4".parse::<Square>().unwrap(),
+            None,
+        ));
+
+        let mut longer = chain.clone();
+        assert!(longer.make_move_block(&white, e4).is_ok());
+        assert!(chain.merge(&longer).is_ok());
+        assert_eq!(chain, longer);
+
+        // merging a prefix leaves the longer chain alone
+        let mut prefix = chain.clone();
+        prefix.moves_mut().clear();
+        assert!(chain.merge(&prefix).is_ok());
+        assert_eq!(chain, longer);
+
+        let mut fork = prefix.clone();
+        assert!(fork.make_move_block(&white, d4).is_ok());
+        assert_eq!(
+            chain.merge(&fork),
+            Err(Error::ForkDetected { block_index: 3 })
+        );
+        assert_eq!(chain, longer);
+
+        // commentary taken in a merge counts against the extension limit
+        let mut annotated = chain.clone();
+        annotated.annotate(&black, 0, "Best by test").unwrap();
+        let mut full = chain.clone();
+        full.annotate(&white, 0, "Not bad").unwrap();
+        full.commentary = vec![full.commentary[0].clone(); MAX_EXTENSIONS];
+        assert_eq!(
+            full.merge(&annotated),
+            Err(Error::TooLarge {
+                what: "extension blocks in a chain",
+                limit: MAX_EXTENSIONS,
+            })
+        );
+    }
+
+    #[test]
+    fn merge_checks_accepts() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let stranger = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut offered = GameChain::new(challenge.clone());
+        offered.accept(&white).unwrap();
+        let now = challenge.timestamp;
+
+        // an accept nobody in the game signed isn't taken, though nothing
+        // follows it for verify to catch
+        let mut forged = offered.clone();
+        forged.accepts[1] = Some(AcceptBlock::new(&challenge, &stranger, now));
+        let mut chain = offered.clone();
+        assert_eq!(chain.merge(&forged), Err(Error::VerificationFailed));
+        assert_eq!(chain, offered);
+
+        let mut twice = offered.clone();
+        twice.accepts[1] = Some(AcceptBlock::new(&challenge, &white, now));
+        assert_eq!(chain.merge(&twice), Err(Error::AlreadyAccepted));
+        assert_eq!(chain, offered);
+
+        let mut accepted = offered.clone();
+        accepted.accept(&black).unwrap();
+        let events = chain.subscribe();
+        assert!(chain.merge(&accepted).is_ok());
+        assert_eq!(chain, accepted);
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![ChainEvent::Accepted {
+                public_key: *PublicKey::of(&black).as_bytes()
+            }]
+        );
+    }
+
+    #[test]
+    fn chain_events() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        let events = chain.subscribe();
+
+        assert!(chain.accept(&white).is_ok());
+        assert!(!chain.verify());
+        assert!(chain.accept(&black).is_ok());
+        for (from, to, key) in &[
+            ("f2", "f3", &white),
+            ("e7", "e5", &black),
+            ("g2", "g4", &white),
+            ("d8", "h4", &black),
+        ] {
+            assert!(chain
+                .make_move_block(
+                    key,
+                    Action::MakeMove(ChessMove::new(
+                        from.parse::<Square>().unwrap(),
+                        to.parse::<Square>().unwrap(),
+                        None,
+                    )),
+                )
+                .is_ok());
+        }
+
+        let events: Vec<ChainEvent> = events.try_iter().collect();
+        assert_eq!(events.len(), 8);
+        assert_eq!(
+            events[0],
+            ChainEvent::Accepted {
+                public_key: *chain.challenge.white_public_key().as_bytes()
+            }
+        );
+        assert_eq!(events[1], ChainEvent::VerificationFailed);
+        assert_eq!(
+            events[3],
+            ChainEvent::MoveAdded {
+                index: 0,
+                start_square: 13,
+                end_square: 21
+            }
+        );
+        assert_eq!(
+            events[7],
+            ChainEvent::ResultRecorded {
+                winner: Some(Color::Black)
+            }
+        );
+    }
+
     #[test]
     fn make_moves() {
         let rng = crypto::new_rng();
         let white = crypto::generate_key(&rng);
         let black = crypto::generate_key(&rng);
-        let challenge =
-            ChallengeBlock::new(white.public_key().as_ref(), black.public_key().as_ref());
-        assert_eq!(challenge, ChallengeBlock::from_bytes(&challenge.as_bytes()));
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        assert_eq!(
+            challenge,
+            ChallengeBlock::from_bytes(&challenge.as_bytes()).unwrap()
+        );
         let mut chain = GameChain::new(challenge.clone());
         assert!(chain.accept(&white).is_ok());
         assert!(chain.accept(&black).is_ok());
@@ -456,8 +2736,8 @@ mod test {
             .make_move_block(
                 &white,
                 Action::MakeMove(ChessMove::new(
-                    Square::from_string("e2".to_string()).unwrap(),
-                    Square::from_string("e4".to_string()).unwrap(),
+                    "e2".parse::<Square>().unwrap(),
+                    "e4".parse::<Square>().unwrap(),
                     None,
                 )),
             )
@@ -467,8 +2747,8 @@ mod test {
             .make_move_block(
                 &black,
                 Action::MakeMove(ChessMove::new(
-                    Square::from_string("e7".to_string()).unwrap(),
-                    Square::from_string("e5".to_string()).unwrap(),
+                    "e7".parse::<Square>().unwrap(),
+                    "e5".parse::<Square>().unwrap(),
                     None,
                 )),
             )
@@ -478,14 +2758,28 @@ mod test {
             .make_move_block(
                 &white,
                 Action::MakeMove(ChessMove::new(
-                    Square::from_string("f2".to_string()).unwrap(),
-                    Square::from_string("f4".to_string()).unwrap(),
+                    "f2".parse::<Square>().unwrap(),
+                    "f4".parse::<Square>().unwrap(),
                     None,
                 )),
             )
             .is_ok());
         assert!(chain.verify());
 
+        let positions: Vec<_> = chain.iter_positions().collect();
+        assert_eq!(positions.len(), 3);
+        assert_eq!(positions[1].0, 1);
+        assert_eq!(positions[1].1.to_string(), "e7e5");
+        assert_eq!(
+            positions[1].3,
+            crypto::fingerprint(black.public_key().as_ref())
+        );
+        assert_eq!(positions[2].2, chain.get_game().unwrap().current_position());
+        assert_eq!(positions[2].2, chain.current_position().unwrap());
+
+        assert!(chain.to_string().ends_with("3 moves, in progress"));
+        assert!(chain.moves[0].to_string().starts_with("e2e4 signature "));
+
         chain.moves[0].signature[0] += 1;
         assert!(!chain.verify());
         chain.moves[0].signature[0] -= 1;
@@ -495,16 +2789,32 @@ mod test {
         chain.moves[2].signature[0] -= 1;
         assert!(chain.verify());
 
+        assert!(chain.is_my_turn(black.public_key().as_ref()));
+        assert!(!chain.is_my_turn(white.public_key().as_ref()));
+        assert_eq!(
+            chain.side_of(white.public_key().as_ref()),
+            Some(Color::White)
+        );
+        assert_eq!(chain.legal_moves().unwrap().len(), 30);
+
+        // a signed move block that isn't a legal move
+        let mut bad = chain.clone();
+        bad.moves[1].end_square = bad.moves[1].start_square;
+        assert_eq!(
+            bad.get_game().err(),
+            Some(Error::InvalidMoveBlock { index: 1 })
+        );
+
         // using the wrong key to make a move
-        assert!(!chain
+        assert!(chain
             .make_move_block(
                 &white,
                 Action::MakeMove(ChessMove::new(
-                    Square::from_string("e5".to_string()).unwrap(),
-                    Square::from_string("f4".to_string()).unwrap(),
+                    "e5".parse::<Square>().unwrap(),
+                    "f4".parse::<Square>().unwrap(),
                     None,
                 )),
             )
-            .is_ok());
+            .is_err());
     }
 }
@@ -0,0 +1,287 @@
+//! Bots. A MoveProvider decides what to play in a game, and `play` runs
+//! one side of a session with it, so two providers can meet over any
+//! channel and produce a chain as signed and verifiable as one between
+//! people. Providers only see chains the session has already verified.
+
+use crate::block::{GameChain, GameStatus};
+use crate::crypto::Rng;
+use crate::error::Error;
+use crate::notation;
+use crate::session::{ChannelSession, SessionEvent};
+use crate::transport::Channel;
+
+use chess::{Action, ChessMove, MoveGen};
+use ring::signature::Ed25519KeyPair;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+pub trait MoveProvider {
+    /// What to do in `chain`, where it's the provider's turn.
+    fn next_action(&mut self, chain: &GameChain) -> io::Result<Action>;
+}
+
+fn invalid_data(error: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// The moves on the game line, in UCI notation.
+fn played(chain: &GameChain) -> Vec<String> {
+    chain
+        .iter_positions()
+        .map(|(_, mv, _, _)| mv.to_string())
+        .collect()
+}
+
+/// Plays a uniformly random legal move.
+pub struct RandomMover<R> {
+    rng: R,
+}
+
+impl<R: Rng> RandomMover<R> {
+    pub fn new(rng: R) -> RandomMover<R> {
+        RandomMover { rng }
+    }
+}
+
+impl<R: Rng> MoveProvider for RandomMover<R> {
+    fn next_action(&mut self, chain: &GameChain) -> io::Result<Action> {
+        let board = chain.current_position().map_err(invalid_data)?;
+        let moves: Vec<ChessMove> = MoveGen::new_legal(&board).collect();
+        if moves.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no legal moves",
+            ));
+        }
+        let mut bytes = [0; 8];
+        self.rng.fill_bytes(&mut bytes);
+        let pick = u64::from_be_bytes(bytes) % moves.len() as u64;
+        Ok(Action::MakeMove(moves[pick as usize]))
+    }
+}
+
+/// Plays its side of a line given in full, for both players, in UCI
+/// notation. An opponent who leaves the line, or a game that outlasts it,
+/// is an error.
+pub struct ScriptedLine {
+    line: Vec<String>,
+}
+
+impl ScriptedLine {
+    pub fn new(line: &[&str]) -> ScriptedLine {
+        ScriptedLine {
+            line: line.iter().map(|mv| mv.to_string()).collect(),
+        }
+    }
+}
+
+impl MoveProvider for ScriptedLine {
+    fn next_action(&mut self, chain: &GameChain) -> io::Result<Action> {
+        let played = played(chain);
+        if !self.line.starts_with(&played) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the game left the scripted line",
+            ));
+        }
+        let uci = self.line.get(played.len()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::UnexpectedEof, "the scripted line has ended")
+        })?;
+        notation::parse_uci(uci)
+            .map(Action::MakeMove)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "bad move in script"))
+    }
+}
+
+/// An engine speaking UCI over its standard input and output, given a
+/// fixed time to think about each move.
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    movetime_ms: u32,
+}
+
+impl UciEngine {
+    /// Starts the engine and waits until it's ready.
+    pub fn new(mut command: Command, movetime_ms: u32) -> io::Result<UciEngine> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let mut engine = UciEngine {
+            child,
+            stdin,
+            stdout,
+            movetime_ms,
+        };
+        engine.send("uci")?;
+        engine.wait_for("uciok")?;
+        engine.send("isready")?;
+        engine.wait_for("readyok")?;
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    /// Reads up to the first line starting with `prefix`, returning it.
+    fn wait_for(&mut self, prefix: &str) -> io::Result<String> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "the engine exited",
+                ));
+            }
+            if line.starts_with(prefix) {
+                return Ok(line.trim_end().to_string());
+            }
+        }
+    }
+}
+
+impl MoveProvider for UciEngine {
+    fn next_action(&mut self, chain: &GameChain) -> io::Result<Action> {
+        let mut position = format!("position fen {}", chain.challenge().starting_board());
+        let played = played(chain);
+        if !played.is_empty() {
+            position.push_str(" moves ");
+            position.push_str(&played.join(" "));
+        }
+        self.send(&position)?;
+        self.send(&format!("go movetime {}", self.movetime_ms))?;
+        let line = self.wait_for("bestmove")?;
+        line.split_whitespace()
+            .nth(1)
+            .and_then(notation::parse_uci)
+            .map(Action::MakeMove)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, line))
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        if self.send("quit").is_err() || self.child.wait().is_err() {
+            let _ = self.child.kill();
+        }
+    }
+}
+
+/// Plays `key_pair`'s side of the session with `provider`, waiting for the
+/// challenge to be accepted if it hasn't been, until the game is no longer
+/// in progress. Returns how it stands then.
+pub fn play<C: Channel>(
+    session: &mut ChannelSession<C>,
+    key_pair: &Ed25519KeyPair,
+    provider: &mut dyn MoveProvider,
+) -> io::Result<GameStatus> {
+    loop {
+        match session.session().status() {
+            GameStatus::InProgress => {
+                if session.is_my_turn(key_pair) {
+                    let action = provider.next_action(session.session().chain())?;
+                    session.make_move(key_pair, action)?;
+                } else {
+                    session.wait_for_move()?;
+                }
+            }
+            GameStatus::AwaitingAcceptance => match session.next_event()? {
+                SessionEvent::ChallengeDeclined => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        "challenge declined",
+                    ))
+                }
+                SessionEvent::Rejected(error) if session.session().is_closed() => {
+                    return Err(invalid_data(error))
+                }
+                _ => {}
+            },
+            status => return Ok(status),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlock, GameResult};
+    use crate::crypto::{self, SeededRng};
+    use crate::key::PublicKey;
+    use crate::session::GameSession;
+    use crate::transport::StreamTransport;
+    use chess::Color;
+    use ring::signature::KeyPair;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn scripted_bots_play_over_tcp() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let black_chain = chain.clone();
+        let fools_mate = ["f2f3", "e7e5", "g2g4", "d8h4"];
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let black_side = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let session = GameSession::new(black.public_key().as_ref(), black_chain).unwrap();
+            let mut session = ChannelSession::new(StreamTransport::new(stream), session);
+            session.handshake().unwrap();
+            let status = play(&mut session, &black, &mut ScriptedLine::new(&fools_mate));
+            (status.unwrap(), session.hang_up().unwrap().into_chain())
+        });
+
+        let transport = crate::transport::connect_tcp(addr).unwrap();
+        let session = GameSession::new(white.public_key().as_ref(), chain).unwrap();
+        let mut session = ChannelSession::new(transport, session);
+        session.handshake().unwrap();
+        let status = play(&mut session, &white, &mut ScriptedLine::new(&fools_mate)).unwrap();
+        let won = GameStatus::Finished(GameResult::Win(Color::Black));
+        assert_eq!(status, won);
+        let session = session.hang_up().unwrap();
+        let (black_status, black_chain) = black_side.join().unwrap();
+        assert_eq!(black_status, won);
+        assert_eq!(&black_chain, session.chain());
+        assert!(black_chain.verify());
+
+        // a script can't continue once the game has left it
+        let mut detour = ScriptedLine::new(&["f2f3", "e7e6"]);
+        assert!(detour.next_action(&black_chain).is_err());
+    }
+
+    #[test]
+    fn random_moves_are_legal() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let mut mover = RandomMover::new(SeededRng::new(7));
+        for ply in 0..20 {
+            if chain.status() != GameStatus::InProgress {
+                break;
+            }
+            let key_pair = if ply % 2 == 0 { &white } else { &black };
+            let action = mover.next_action(&chain).unwrap();
+            chain.make_move_block(key_pair, action).unwrap();
+        }
+        assert!(chain.verify());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::block::GameChain;
+use crate::error::Error;
+use crate::view::GameChainView;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerificationReport {
+    pub id: u32,
+    pub move_count: usize,
+    pub result: Result<(), Error>,
+}
+
+fn verify_bytes(bytes: &[u8]) -> Result<(), Error> {
+    let view = GameChainView::new(bytes)?;
+    if !view.verify() {
+        return Err(Error::VerificationFailed);
+    }
+    let chain = view.to_chain()?;
+    chain.get_game()?;
+    chain.check_extensions()
+}
+
+/// Verifies every chain, checking signatures, move legality, and extension
+/// blocks, and returns one report per chain in the same order. With the
+/// `parallel` feature the work is spread across all available cores.
+pub fn verify_all(chains: &[GameChain]) -> Vec<VerificationReport> {
+    // GameChain caches its position in a Cell, so it can't be shared between
+    // threads; the encoded bytes can.
+    let encoded: Vec<Vec<u8>> = chains.iter().map(GameChain::as_bytes).collect();
+    let results = verify_encoded(&encoded);
+    chains
+        .iter()
+        .zip(results)
+        .map(|(chain, result)| VerificationReport {
+            id: chain.id(),
+            move_count: chain.move_count(),
+            result,
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+fn verify_encoded(encoded: &[Vec<u8>]) -> Vec<Result<(), Error>> {
+    encoded.iter().map(|bytes| verify_bytes(bytes)).collect()
+}
+
+#[cfg(feature = "parallel")]
+fn verify_encoded(encoded: &[Vec<u8>]) -> Vec<Result<(), Error>> {
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let chunk_size = encoded.len().div_ceil(threads);
+    if chunk_size == 0 {
+        return Vec::new();
+    }
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = encoded
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|bytes| verify_bytes(bytes))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+
+    #[test]
+    fn verify_many_chains() {
+        let rng = crypto::new_rng();
+        let chains: Vec<GameChain> = (0..8)
+            .map(|_| {
+                let white = crypto::generate_key(&rng);
+                let black = crypto::generate_key(&rng);
+                let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+                let mut chain = GameChain::new(challenge);
+                assert!(chain.accept(&white).is_ok());
+                assert!(chain.accept(&black).is_ok());
+                chain
+            })
+            .collect();
+        let unaccepted = GameChain::new(chains[0].challenge().clone());
+
+        let mut all = chains.clone();
+        all.push(unaccepted);
+        let reports = verify_all(&all);
+        assert_eq!(reports.len(), 9);
+        assert!(reports[..8].iter().all(|report| report.result.is_ok()));
+        assert_eq!(reports[8].result, Err(Error::VerificationFailed));
+    }
+}
@@ -0,0 +1,391 @@
+//! Bundles: many chains in one file, e.g. a tournament and all of its
+//! games, for archiving or handing to an arbiter. A bundle is the magic
+//! "LNBD", a version byte, a compression byte (0 for none, 1 for deflate),
+//! then, compressed as that byte says:
+//!
+//! - a byte that's 1 if a tournament chain follows, then its u32 big-endian
+//!   length and encoding
+//! - the u32 big-endian number of games, then each game's u32 big-endian
+//!   length and encoding
+//!
+//! Chains keep their canonical encoding inside, so signatures verify the
+//! same whether or not the bundle was compressed.
+//!
+//! An organization can certify a bundle once it has verified every chain in
+//! it, so whoever trusts the organization's key can take the games as
+//! verified without checking each one. A certification is the signer's key,
+//! the SHA-256 of the uncompressed bundle, the timestamp, and the signature
+//! over the tag "lineage certified bundle" and the hash and timestamp.
+
+use crate::block::GameChain;
+#[cfg(feature = "compression")]
+use crate::compression;
+use crate::crypto;
+use crate::error::Error;
+use crate::key::PublicKey;
+use crate::tournament::TournamentChain;
+use crate::view::GameChainView;
+
+use alloc::vec::Vec;
+use ring::digest;
+use ring::signature::Ed25519KeyPair;
+
+const MAGIC: &[u8; 4] = b"LNBD";
+const VERSION: u8 = 1;
+const NONE: u8 = 0;
+#[cfg(feature = "compression")]
+const DEFLATE: u8 = 1;
+
+/// The most a compressed bundle may expand to.
+pub const MAX_BUNDLE_LEN: usize = 1 << 28;
+
+const CERTIFICATION_TAG: &[u8] = b"lineage certified bundle";
+
+/// The encoded length of a certification.
+pub const CERTIFICATION_LEN: usize = 32 + 32 + 8 + 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    None,
+    #[cfg(feature = "compression")]
+    Deflate,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bundle {
+    pub tournament: Option<TournamentChain>,
+    pub games: Vec<GameChain>,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "bundle",
+        reason,
+    }
+}
+
+/// Splits a u32-length-prefixed field off the front of `bytes`.
+fn take_field<'a>(bytes: &mut &'a [u8]) -> Result<&'a [u8], Error> {
+    if bytes.len() < 4 {
+        return Err(malformed("truncated"));
+    }
+    let mut len = [0; 4];
+    len.copy_from_slice(&bytes[..4]);
+    let len = u32::from_be_bytes(len) as usize;
+    if bytes.len() - 4 < len {
+        return Err(malformed("truncated"));
+    }
+    let field = &bytes[4..4 + len];
+    *bytes = &bytes[4 + len..];
+    Ok(field)
+}
+
+fn put_field(bytes: &mut Vec<u8>, field: &[u8]) {
+    bytes.extend(&(field.len() as u32).to_be_bytes());
+    bytes.extend(field);
+}
+
+impl Bundle {
+    pub fn new(games: Vec<GameChain>) -> Bundle {
+        Bundle {
+            tournament: None,
+            games,
+        }
+    }
+
+    pub fn tournament(mut self, tournament: TournamentChain) -> Bundle {
+        self.tournament = Some(tournament);
+        self
+    }
+
+    pub fn as_bytes(&self, compression: Compression) -> Vec<u8> {
+        let mut body = Vec::new();
+        match &self.tournament {
+            Some(tournament) => {
+                body.push(1);
+                put_field(&mut body, &tournament.as_bytes());
+            }
+            None => body.push(0),
+        }
+        body.extend(&(self.games.len() as u32).to_be_bytes());
+        for game in &self.games {
+            put_field(&mut body, &game.as_bytes());
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        match compression {
+            Compression::None => {
+                bytes.push(NONE);
+                bytes.extend(body);
+            }
+            #[cfg(feature = "compression")]
+            Compression::Deflate => {
+                bytes.push(DEFLATE);
+                bytes.extend(compression::deflate(&body));
+            }
+        }
+        bytes
+    }
+
+    /// Decodes a bundle. The tournament chain, if any, is verified as
+    /// TournamentChain::from_bytes does, but the games are only decoded:
+    /// check them with GameChain::verify, or trust a certification.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Bundle, Error> {
+        if bytes.len() < 6 || &bytes[..4] != MAGIC {
+            return Err(malformed("not a bundle"));
+        }
+        if bytes[4] != VERSION {
+            return Err(malformed("unknown version"));
+        }
+        #[cfg(feature = "compression")]
+        let inflated;
+        let mut body = match bytes[5] {
+            NONE => &bytes[6..],
+            #[cfg(feature = "compression")]
+            DEFLATE => {
+                inflated = compression::inflate(&bytes[6..], MAX_BUNDLE_LEN, "bundle")?;
+                &inflated[..]
+            }
+            _ => return Err(malformed("unsupported compression")),
+        };
+
+        let (&has_tournament, rest) = body.split_first().ok_or_else(|| malformed("truncated"))?;
+        body = rest;
+        let tournament = match has_tournament {
+            0 => None,
+            1 => Some(TournamentChain::from_bytes(take_field(&mut body)?)?),
+            _ => return Err(malformed("bad tournament flag")),
+        };
+        if body.len() < 4 {
+            return Err(malformed("truncated"));
+        }
+        let mut count = [0; 4];
+        count.copy_from_slice(&body[..4]);
+        body = &body[4..];
+        let mut games = Vec::new();
+        for _ in 0..u32::from_be_bytes(count) {
+            games.push(decode_game(take_field(&mut body)?)?);
+        }
+        if !body.is_empty() {
+            return Err(malformed("trailing bytes"));
+        }
+        Ok(Bundle { tournament, games })
+    }
+}
+
+/// Decodes a game without verifying it, unlike GameChain::from_bytes.
+fn decode_game(bytes: &[u8]) -> Result<GameChain, Error> {
+    let view = GameChainView::new(bytes)?;
+    if view.encoded_len() != bytes.len() {
+        return Err(malformed("trailing bytes in a game"));
+    }
+    view.to_chain()
+}
+
+/// The SHA-256 of the uncompressed bundle.
+pub fn bundle_hash(bundle: &Bundle) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(
+        digest::digest(&digest::SHA256, &bundle.as_bytes(Compression::None)).as_ref(),
+    );
+    hash
+}
+
+fn certification_message(bundle_hash: &[u8; 32], timestamp: u64) -> Vec<u8> {
+    let mut message = CERTIFICATION_TAG.to_vec();
+    message.extend(bundle_hash);
+    message.extend(&timestamp.to_be_bytes());
+    message
+}
+
+/// An organization's signature saying it verified every chain in a bundle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Certification {
+    public_key: PublicKey,
+    bundle_hash: [u8; 32],
+    timestamp: u64,
+    signature: Vec<u8>,
+}
+
+impl Certification {
+    /// Verifies every chain in `bundle` and certifies it as of `timestamp`,
+    /// failing if any chain doesn't verify.
+    pub fn new(
+        key_pair: &Ed25519KeyPair,
+        bundle: &Bundle,
+        timestamp: u64,
+    ) -> Result<Certification, Error> {
+        let tournament_verifies = bundle
+            .tournament
+            .as_ref()
+            .is_none_or(TournamentChain::verify);
+        if !tournament_verifies || !bundle.games.iter().all(GameChain::verify) {
+            return Err(Error::VerificationFailed);
+        }
+        let bundle_hash = bundle_hash(bundle);
+        Ok(Certification {
+            public_key: PublicKey::of(key_pair),
+            bundle_hash,
+            timestamp,
+            signature: crypto::sign(key_pair, &certification_message(&bundle_hash, timestamp)),
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Certification, Error> {
+        if bytes.len() != CERTIFICATION_LEN {
+            return Err(Error::Malformed {
+                block: "certification",
+                reason: "wrong length",
+            });
+        }
+        let public_key = PublicKey::from_slice(&bytes[..32])?;
+        let mut bundle_hash = [0; 32];
+        bundle_hash.copy_from_slice(&bytes[32..64]);
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&bytes[64..72]);
+        Ok(Certification {
+            public_key,
+            bundle_hash,
+            timestamp: u64::from_be_bytes(timestamp),
+            signature: bytes[72..].to_vec(),
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(CERTIFICATION_LEN);
+        bytes.extend(&self.public_key);
+        bytes.extend(&self.bundle_hash);
+        bytes.extend(&self.timestamp.to_be_bytes());
+        bytes.extend(&self.signature);
+        bytes
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    pub fn bundle_hash(&self) -> &[u8; 32] {
+        &self.bundle_hash
+    }
+
+    /// When the bundle was certified, in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Checks the organization's signature, without reference to any bundle.
+    pub fn verify_signature(&self) -> bool {
+        self.public_key.verify(
+            &certification_message(&self.bundle_hash, self.timestamp),
+            &self.signature,
+        )
+    }
+}
+
+/// Checks that `certification` is a valid signature by `public_key` over
+/// exactly `bundle`. The chains themselves aren't verified; that's what the
+/// certification vouches for.
+pub fn verify_bundle_certification(
+    bundle: &Bundle,
+    certification: &Certification,
+    public_key: &PublicKey,
+) -> bool {
+    certification.public_key == *public_key
+        && certification.bundle_hash == bundle_hash(bundle)
+        && certification.verify_signature()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::notation;
+    use alloc::vec;
+    use chess::Action;
+
+    #[test]
+    fn round_trip_bundles() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let mut games = Vec::new();
+        for _ in 0..3 {
+            let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+            let mut chain = GameChain::new(challenge);
+            chain.accept(&white).unwrap();
+            chain.accept(&black).unwrap();
+            let mv = notation::parse_uci("e2e4").unwrap();
+            chain.make_move_block(&white, Action::MakeMove(mv)).unwrap();
+            games.push(chain);
+        }
+        let bundle = Bundle::new(games);
+        let bytes = bundle.as_bytes(Compression::None);
+        assert_eq!(Bundle::from_bytes(&bytes), Ok(bundle.clone()));
+        assert!(Bundle::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // games are decoded as they are, for the reader to verify
+        let mut forged = bytes.clone();
+        *forged.last_mut().unwrap() ^= 1;
+        let forged = Bundle::from_bytes(&forged).unwrap();
+        assert!(!forged.games[2].verify());
+
+        #[cfg(feature = "compression")]
+        {
+            let deflated = bundle.as_bytes(Compression::Deflate);
+            assert!(deflated.len() < bytes.len());
+            let restored = Bundle::from_bytes(&deflated).unwrap();
+            assert!(restored.games.iter().all(GameChain::verify));
+            assert_eq!(restored, bundle);
+        }
+    }
+
+    #[test]
+    fn certify_bundles() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let club = crypto::generate_key(&rng);
+        let club_key = PublicKey::of(&club);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let mut bundle = Bundle::new(vec![chain.clone()]);
+
+        let certification = Certification::new(&club, &bundle, 1_000).unwrap();
+        assert!(verify_bundle_certification(
+            &bundle,
+            &certification,
+            &club_key
+        ));
+        assert_eq!(
+            Certification::from_bytes(&certification.as_bytes()),
+            Ok(certification.clone())
+        );
+        assert!(!verify_bundle_certification(
+            &bundle,
+            &certification,
+            &PublicKey::of(&white)
+        ));
+
+        // it covers exactly the games certified
+        let mv = notation::parse_uci("e2e4").unwrap();
+        bundle.games[0]
+            .make_move_block(&white, Action::MakeMove(mv))
+            .unwrap();
+        assert!(!verify_bundle_certification(
+            &bundle,
+            &certification,
+            &club_key
+        ));
+
+        // chains that don't verify can't be certified
+        let unaccepted = GameChain::new(chain.challenge().clone());
+        assert_eq!(
+            Certification::new(&club, &Bundle::new(vec![unaccepted]), 1_000),
+            Err(Error::VerificationFailed)
+        );
+    }
+}
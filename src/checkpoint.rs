@@ -0,0 +1,377 @@
+//! Checkpoints, which let long games be pruned. Both players sign the move
+//! count and position at some point in the game, along with a digest of
+//! everything before it. Blocks after a checkpoint sign a digest of the
+//! checkpoint in place of the chain before it, so a verifier that trusts
+//! both keys can drop the earlier blocks and still check the rest.
+//!
+//! A checkpoint's body is the u32 big-endian move count on the game line, the
+//! SHA-256 digest of the signing prefix before the checkpoint, the position as
+//! FEN, and the opponent's 64-byte co-signature. The co-signature and the
+//! block's own signature cover only the canonical id and the checkpoint, so
+//! they still verify once the blocks before it are gone.
+
+use crate::block::{ChainEntry, ChallengeBlock, GameChain};
+use crate::crypto;
+use crate::error::Error;
+use crate::extension::ExtensionKind;
+use crate::key::PublicKey;
+use crate::takeback::Line;
+use crate::view::GameChainView;
+
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use chess::Board;
+use core::ops::Range;
+use ring::digest;
+use ring::signature::Ed25519KeyPair;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    /// The number of moves on the game line.
+    pub move_count: usize,
+    /// The digest of the signing prefix before the checkpoint.
+    pub prefix_digest: [u8; 32],
+    pub fen: String,
+    cosignature: Vec<u8>,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "checkpoint",
+        reason,
+    }
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(digest::digest(&digest::SHA256, bytes).as_ref());
+    hash
+}
+
+impl Checkpoint {
+    pub(crate) fn from_body(body: &[u8]) -> Result<Checkpoint, Error> {
+        if body.len() < 4 + 32 + 64 {
+            return Err(malformed("not enough bytes"));
+        }
+        let (statement, cosignature) = body.split_at(body.len() - 64);
+        let mut move_count = [0; 4];
+        move_count.copy_from_slice(&statement[..4]);
+        let mut prefix_digest = [0; 32];
+        prefix_digest.copy_from_slice(&statement[4..36]);
+        let fen =
+            core::str::from_utf8(&statement[36..]).map_err(|_| malformed("FEN is not UTF-8"))?;
+        Ok(Checkpoint {
+            move_count: u32::from_be_bytes(move_count) as usize,
+            prefix_digest,
+            fen: fen.to_string(),
+            cosignature: cosignature.to_vec(),
+        })
+    }
+
+    /// The position, if the FEN is valid.
+    pub fn board(&self) -> Option<Board> {
+        self.fen.parse::<Board>().ok()
+    }
+
+    /// The opponent's signature over the checkpoint.
+    pub fn cosignature(&self) -> &[u8] {
+        &self.cosignature
+    }
+
+    fn statement(&self) -> Vec<u8> {
+        statement(self.move_count, &self.prefix_digest, &self.fen)
+    }
+
+    /// Checks the co-signature, which comes from the player not to move.
+    fn verify_cosignature(&self, challenge: &ChallengeBlock) -> bool {
+        challenge.signer_public_key(self.move_count + 1).verify(
+            &cosigned_message(&challenge.canonical_id(), &self.statement()),
+            &self.cosignature,
+        )
+    }
+}
+
+fn statement(move_count: usize, prefix_digest: &[u8; 32], fen: &str) -> Vec<u8> {
+    let mut statement = Vec::with_capacity(36 + fen.len());
+    statement.extend(&(move_count as u32).to_be_bytes());
+    statement.extend(prefix_digest);
+    statement.extend(fen.as_bytes());
+    statement
+}
+
+fn cosigned_message(canonical_id: &[u8; 32], statement: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(33 + statement.len());
+    message.extend(canonical_id);
+    message.push(ExtensionKind::Checkpoint.to_byte());
+    message.extend(statement);
+    message
+}
+
+/// The message blocks after a checkpoint sign: the canonical id, the digest
+/// of the encoded checkpoint block at `checkpoint`, and the bytes after it.
+pub(crate) fn rooted_message(
+    canonical_id: &[u8; 32],
+    bytes: &[u8],
+    checkpoint: Range<usize>,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(64 + bytes.len() - checkpoint.end);
+    message.extend(canonical_id);
+    message.extend(&sha256(&bytes[checkpoint.clone()]));
+    message.extend(&bytes[checkpoint.end..]);
+    message
+}
+
+/// Checks both signatures on an encoded checkpoint block, and that it commits
+/// to `prefix`, the signing prefix before it. A pruned chain has no prefix to
+/// check.
+pub(crate) fn verify_block(
+    challenge: &ChallengeBlock,
+    canonical_id: &[u8; 32],
+    prefix: Option<&[u8]>,
+    bytes: &[u8],
+) -> bool {
+    let (body, signature) = bytes.split_at(bytes.len() - 64);
+    let checkpoint = match Checkpoint::from_body(&body[3..]) {
+        Ok(checkpoint) => checkpoint,
+        Err(_) => return false,
+    };
+    let mut message = canonical_id.to_vec();
+    message.extend(body);
+    challenge
+        .signer_public_key(checkpoint.move_count)
+        .verify(&message, signature)
+        && checkpoint.verify_cosignature(challenge)
+        && prefix.is_none_or(|prefix| sha256(prefix) == checkpoint.prefix_digest)
+}
+
+/// Replays the blocks of `chain` from `board`, where the game line is `line`,
+/// checking that each checkpoint's FEN matches the position it was signed at.
+/// Returns the final position.
+fn replay(chain: &GameChain, board: Board, mut line: Line) -> Result<Board, Error> {
+    let start = line.len();
+    let mut boards = vec![board];
+    for entry in chain.entries() {
+        match entry {
+            ChainEntry::Move { index, block } => {
+                let board = boards[boards.len() - 1];
+                let mv = block
+                    .find_move(&board)
+                    .ok_or(Error::InvalidMoveBlock { index })?;
+                line.push_move();
+                boards.push(board.make_move_new(mv));
+            }
+            ChainEntry::Extension(block) => {
+                line.push_extension(block.kind(), block.body())?;
+                boards.truncate(line.len() - start + 1);
+                if block.kind() == ExtensionKind::Checkpoint {
+                    let checkpoint = Checkpoint::from_body(block.body())?;
+                    if checkpoint.board() != Some(boards[boards.len() - 1]) {
+                        return Err(malformed("wrong position"));
+                    }
+                }
+            }
+        }
+    }
+    Ok(boards[boards.len() - 1])
+}
+
+pub(crate) fn check_extension(chain: &GameChain, body: &[u8]) -> Result<(), Error> {
+    let checkpoint = Checkpoint::from_body(body)?;
+    if checkpoint.prefix_digest != sha256(&chain.signing_prefix()) {
+        return Err(malformed("wrong prefix digest"));
+    }
+    if checkpoint.board() != Some(chain.current_position()?) {
+        return Err(malformed("wrong position"));
+    }
+    if !checkpoint.verify_cosignature(chain.challenge()) {
+        return Err(Error::VerificationFailed);
+    }
+    Ok(())
+}
+
+/// Checks every checkpoint's position. Signatures and digests are checked
+/// along with the rest of the chain's signatures.
+pub(crate) fn check_chain(chain: &GameChain) -> Result<(), Error> {
+    replay(chain, chain.challenge().starting_board(), Line::default())?;
+    Ok(())
+}
+
+impl GameChain {
+    fn checkpoint_statement(&self) -> Result<Vec<u8>, Error> {
+        if self.accepts()[0].is_none() || self.accepts()[1].is_none() {
+            return Err(Error::VerificationFailed);
+        }
+        Ok(statement(
+            self.line()?.len(),
+            &sha256(&self.signing_prefix()),
+            &self.current_position()?.to_string(),
+        ))
+    }
+
+    /// Co-signs a checkpoint at the current position. The player not to move
+    /// co-signs and sends the signature to the player to move, who adds the
+    /// checkpoint with `checkpoint`.
+    pub fn cosign_checkpoint(&self, key_pair: &Ed25519KeyPair) -> Result<Vec<u8>, Error> {
+        let statement = self.checkpoint_statement()?;
+        if self.signer_public_key(self.line()?.len() + 1) != PublicKey::of(key_pair) {
+            return Err(Error::NotYourTurn);
+        }
+        Ok(crypto::sign(
+            key_pair,
+            &cosigned_message(&self.canonical_id(), &statement),
+        ))
+    }
+
+    /// Signs a checkpoint at the current position, given the opponent's
+    /// co-signature.
+    pub fn checkpoint(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        cosignature: &[u8],
+    ) -> Result<(), Error> {
+        let mut body = self.checkpoint_statement()?;
+        body.extend(cosignature);
+        self.sign_extension(key_pair, ExtensionKind::Checkpoint, body)
+    }
+
+    /// The checkpoints in the chain, in order.
+    pub fn checkpoints(&self) -> Vec<Checkpoint> {
+        self.extensions()
+            .iter()
+            .filter(|(_, block)| block.kind() == ExtensionKind::Checkpoint)
+            .filter_map(|(_, block)| Checkpoint::from_body(block.body()).ok())
+            .collect()
+    }
+
+    /// A copy of the chain without the blocks before its last checkpoint, or
+    /// its commentary.
+    pub fn pruned(&self) -> Result<PrunedChain, Error> {
+        let bytes = self.as_bytes();
+        let pruned = GameChainView::new(&bytes)?
+            .pruned_bytes()
+            .ok_or_else(|| malformed("no checkpoint"))?;
+        PrunedChain::from_bytes(&pruned)
+    }
+}
+
+/// A game whose blocks before a checkpoint have been dropped. It keeps the
+/// challenge and accepts, then starts from the checkpoint. Pruned chains are
+/// for archiving and verification; games are played on full chains.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PrunedChain {
+    chain: GameChain,
+    checkpoint: Checkpoint,
+}
+
+impl PrunedChain {
+    /// Decodes and verifies a pruned chain. The first block after the accepts
+    /// must be a checkpoint.
+    pub fn from_bytes(bytes: &[u8]) -> Result<PrunedChain, Error> {
+        let view = GameChainView::new(bytes)?;
+        if view.encoded_len() != bytes.len() {
+            return Err(Error::Malformed {
+                block: "chain",
+                reason: "trailing bytes",
+            });
+        }
+        let checkpoint = view
+            .root_checkpoint()
+            .ok_or_else(|| malformed("not the first block"))?;
+        if !view.verify() {
+            return Err(Error::VerificationFailed);
+        }
+        let pruned = PrunedChain {
+            chain: view.to_chain()?,
+            checkpoint,
+        };
+        pruned.current_position()?;
+        Ok(pruned)
+    }
+
+    pub fn challenge(&self) -> &ChallengeBlock {
+        self.chain.challenge()
+    }
+
+    /// The checkpoint the chain starts from.
+    pub fn checkpoint(&self) -> &Checkpoint {
+        &self.checkpoint
+    }
+
+    /// Replays the moves after the checkpoint.
+    pub fn current_position(&self) -> Result<Board, Error> {
+        let board = self
+            .checkpoint
+            .board()
+            .ok_or_else(|| malformed("invalid FEN"))?;
+        replay(&self.chain, board, Line::at(self.checkpoint.move_count))
+    }
+
+    pub fn verify(&self) -> bool {
+        let bytes = self.as_bytes();
+        match GameChainView::new(&bytes) {
+            Ok(view) => view.verify() && self.current_position().is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.chain.as_bytes()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn prune_at_checkpoint() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let play = |chain: &mut GameChain, key_pair: &Ed25519KeyPair, uci: &str| {
+            let mv = notation::parse_uci(uci).unwrap();
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        };
+        play(&mut chain, &white, "e2e4");
+        play(&mut chain, &black, "e7e5");
+        assert!(chain.pruned().is_err());
+
+        // white is to move, so black co-signs
+        assert_eq!(chain.cosign_checkpoint(&white), Err(Error::NotYourTurn));
+        let cosignature = chain.cosign_checkpoint(&black).unwrap();
+        assert!(chain.checkpoint(&white, &[0; 64]).is_err());
+        chain.checkpoint(&white, &cosignature).unwrap();
+        play(&mut chain, &white, "g1f3");
+        // takebacks can't reach past a checkpoint
+        assert_eq!(
+            chain.request_takeback(&black, 2),
+            Err(Error::InvalidTakeback)
+        );
+
+        let chain = GameChain::from_bytes(&chain.as_bytes()).unwrap();
+        assert!(chain.verify());
+        assert_eq!(chain.checkpoints()[0].move_count, 2);
+
+        let pruned = chain.pruned().unwrap();
+        assert!(pruned.as_bytes().len() < chain.as_bytes().len());
+        assert!(pruned.verify());
+        assert_eq!(pruned.current_position(), chain.current_position());
+        let decoded = PrunedChain::from_bytes(&pruned.as_bytes()).unwrap();
+        assert_eq!(decoded, pruned);
+        assert!(GameChain::from_bytes(&pruned.as_bytes()).is_err());
+
+        let mut forged = pruned.as_bytes();
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+        assert!(PrunedChain::from_bytes(&forged).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Where the crate gets the time from. Timestamps are whole seconds since the
+//! Unix epoch. SystemClock reads the system time; ManualClock only moves
+//! when told to, so tests and simulations don't depend on real time.
+
+use core::cell::Cell;
+
+pub trait Clock {
+    /// The current time in seconds since the Unix epoch.
+    fn now(&self) -> u64;
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs())
+    }
+}
+
+/// A clock that stands still until it is set or advanced.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: Cell<u64>,
+}
+
+impl ManualClock {
+    pub fn new(now: u64) -> ManualClock {
+        ManualClock {
+            now: Cell::new(now),
+        }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.set(now);
+    }
+
+    pub fn advance(&self, seconds: u64) {
+        self.now.set(self.now.get() + seconds);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlockBuilder, GameChain};
+    use crate::crypto::{self, SeededRng};
+    use crate::error::Error;
+    use crate::key::PublicKey;
+
+    #[test]
+    fn deterministic_accepts() {
+        let play = |seed| {
+            let rng = SeededRng::new(seed);
+            let white = crypto::generate_key(&rng);
+            let black = crypto::generate_key(&rng);
+            let challenge =
+                ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+                    .id(crypto::random_id(&rng))
+                    .timestamp(1_000)
+                    .valid_until(1_100)
+                    .build()
+                    .unwrap();
+            let clock = ManualClock::new(1_050);
+            let mut chain = GameChain::new(challenge);
+            chain.accept_with(&white, &clock).unwrap();
+            clock.advance(100);
+            assert_eq!(
+                chain.accept_with(&black, &clock),
+                Err(Error::ChallengeExpired)
+            );
+            clock.set(1_100);
+            chain.accept_with(&black, &clock).unwrap();
+            chain.as_bytes()
+        };
+        assert_eq!(play(7), play(7));
+        assert_ne!(play(7), play(8));
+    }
+}
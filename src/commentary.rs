@@ -0,0 +1,167 @@
+//! Spectator commentary. Anyone can sign a comment on a move with their own
+//! key, outside the challenge. Comments are carried after the game's blocks
+//! and no game block signs them, so they can be added, dropped, or collected
+//! from several annotators without touching the game's signatures.
+
+use crate::block::GameChain;
+use crate::error::Error;
+use crate::extension::{ExtensionBlock, ExtensionKind};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::str;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comment {
+    pub annotator_public_key: [u8; 32],
+    /// The index of the move the comment is about.
+    pub move_index: usize,
+    pub text: String,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "commentary",
+        reason,
+    }
+}
+
+/// Reads a commentary body: the annotator's public key, the move index as a
+/// four-byte big-endian integer, and the UTF-8 text.
+fn parse(block: &ExtensionBlock) -> Result<Comment, Error> {
+    let body = block.body();
+    if block.kind() != ExtensionKind::Commentary || body.len() < 36 {
+        return Err(malformed("wrong body"));
+    }
+    let mut annotator_public_key = [0; 32];
+    annotator_public_key.copy_from_slice(&body[..32]);
+    let mut move_index = [0; 4];
+    move_index.copy_from_slice(&body[32..36]);
+    let text = str::from_utf8(&body[36..]).map_err(|_| malformed("text is not UTF-8"))?;
+    Ok(Comment {
+        annotator_public_key,
+        move_index: u32::from_be_bytes(move_index) as usize,
+        text: text.into(),
+    })
+}
+
+impl GameChain {
+    /// Signs a comment on the move at `move_index` with the annotator's key.
+    pub fn annotate(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        move_index: usize,
+        text: &str,
+    ) -> Result<(), Error> {
+        if move_index >= self.move_count() {
+            return Err(malformed("no such move"));
+        }
+        let mut body = Vec::with_capacity(36 + text.len());
+        body.extend(key_pair.public_key().as_ref());
+        body.extend(&(move_index as u32).to_be_bytes());
+        body.extend(text.as_bytes());
+        let block = ExtensionBlock::new(
+            &self.canonical_id(),
+            ExtensionKind::Commentary,
+            body,
+            key_pair,
+        );
+        self.append_commentary_block(block)
+    }
+
+    /// Appends a comment signed elsewhere, after checking its signature.
+    pub fn append_commentary_block(&mut self, block: ExtensionBlock) -> Result<(), Error> {
+        if self.accepts().iter().any(Option::is_none) {
+            return Err(Error::VerificationFailed);
+        }
+        let comment = parse(&block)?;
+        if !block.verify(&self.canonical_id(), &comment.annotator_public_key) {
+            return Err(Error::VerificationFailed);
+        }
+        self.push_commentary(block)
+    }
+
+    /// The comments whose signatures verify, in order. Comments that don't
+    /// verify are left out rather than failing the game.
+    pub fn comments(&self) -> Vec<Comment> {
+        let canonical_id = self.canonical_id();
+        self.commentary()
+            .iter()
+            .filter_map(|block| {
+                let comment = parse(block).ok()?;
+                if block.verify(&canonical_id, &comment.annotator_public_key) {
+                    Some(comment)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use chess::{Action, ChessMove, Square};
+
+    #[test]
+    fn annotate_moves() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let annotator = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        assert!(chain.annotate(&annotator, 0, "too early").is_err());
+
+        let e4 = ChessMove::new(
+            "e2".parse::<Square>().unwrap(),
+            "e4".parse::<Square>().unwrap(),
+            None,
+        );
+        chain.make_move_block(&white, Action::MakeMove(e4)).unwrap();
+        chain.annotate(&annotator, 0, "Best by test").unwrap();
+        assert_eq!(
+            chain.comments(),
+            vec![Comment {
+                annotator_public_key: {
+                    let mut key = [0; 32];
+                    key.copy_from_slice(annotator.public_key().as_ref());
+                    key
+                },
+                move_index: 0,
+                text: "Best by test".into(),
+            }]
+        );
+
+        // comments don't change what the players sign
+        let e5 = ChessMove::new(
+            "e7".parse::<Square>().unwrap(),
+            "e5".parse::<Square>().unwrap(),
+            None,
+        );
+        chain.make_move_block(&black, Action::MakeMove(e5)).unwrap();
+        assert!(chain.verify());
+        let restored = GameChain::from_bytes(&chain.as_bytes()).unwrap();
+        assert_eq!(restored, chain);
+        assert_eq!(restored.comments().len(), 1);
+        let bytes = chain.as_bytes();
+        let stripped = &bytes[..bytes.len() - chain.commentary()[0].encoded_len()];
+        assert!(GameChain::from_bytes(stripped).unwrap().verify());
+
+        // a forged comment isn't counted
+        let mut forged = chain.commentary()[0].as_bytes();
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+        assert_eq!(
+            chain.append_commentary_block(ExtensionBlock::from_bytes(&forged).unwrap()),
+            Err(Error::VerificationFailed)
+        );
+    }
+}
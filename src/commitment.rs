@@ -0,0 +1,443 @@
+//! Two-phase moves. Instead of signing a move directly, the player to move
+//! signs a commitment to a hash of the move and a secret salt. The opponent
+//! acknowledges it, and only then does the player reveal the salt followed by
+//! the move itself. Relays passing the commitment along learn nothing about
+//! the move, and neither player can change a move once it is committed.
+//!
+//! In simultaneous games every move is committed. The opponent answers the
+//! first commitment of each full move with a counter commitment to their
+//! reply instead of an acknowledgement, so both moves are fixed before either
+//! is seen. The first move is then revealed and played, and the reply is
+//! revealed with its squares before it is played. A reply that turns out to
+//! be illegal loses the game.
+
+use crate::block::GameChain;
+use crate::crypto::Rng;
+use crate::error::Error;
+use crate::extension::{ExtensionBlock, ExtensionKind};
+
+use alloc::vec::Vec;
+use chess::{Action, ChessMove, Color, MoveGen};
+use ring::digest;
+use ring::signature::Ed25519KeyPair;
+
+const SEQUENCE: [ExtensionKind; 3] = [
+    ExtensionKind::Commitment,
+    ExtensionKind::CommitmentAck,
+    ExtensionKind::Reveal,
+];
+
+const SIMULTANEOUS: [ExtensionKind; 3] = [
+    ExtensionKind::Commitment,
+    ExtensionKind::CounterCommitment,
+    ExtensionKind::Reveal,
+];
+
+const REPLY: [ExtensionKind; 1] = [ExtensionKind::Reveal];
+
+/// The hash a player commits to: SHA-256 of the start square, end square, and
+/// salt.
+pub fn commitment_hash(start_square: u8, end_square: u8, salt: &[u8]) -> [u8; 32] {
+    let mut context = digest::Context::new(&digest::SHA256);
+    context.update(&[start_square, end_square]);
+    context.update(salt);
+    let mut hash = [0; 32];
+    hash.copy_from_slice(context.finish().as_ref());
+    hash
+}
+
+impl GameChain {
+    /// Commits to a move without revealing it. Returns the salt, which the
+    /// caller must keep secret until it is passed to reveal_move.
+    pub fn commit_move(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        mv: ChessMove,
+        rng: &dyn Rng,
+    ) -> Result<[u8; 32], Error> {
+        if !self.current_position()?.legal(mv) {
+            return Err(Error::IllegalMove);
+        }
+        let mut salt = [0; 32];
+        rng.fill_bytes(&mut salt);
+        let hash = commitment_hash(mv.get_source().to_int(), mv.get_dest().to_int(), &salt);
+        self.sign_extension(key_pair, ExtensionKind::Commitment, hash.to_vec())?;
+        Ok(salt)
+    }
+
+    /// Acknowledges the opponent's pending commitment.
+    pub fn acknowledge_commitment(&mut self, key_pair: &Ed25519KeyPair) -> Result<(), Error> {
+        self.sign_extension(key_pair, ExtensionKind::CommitmentAck, Vec::new())
+    }
+
+    /// Reveals and plays an acknowledged committed move.
+    pub fn reveal_move(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        mv: ChessMove,
+        salt: &[u8; 32],
+    ) -> Result<(), Error> {
+        let pending: Vec<&ExtensionBlock> = pending_commitment(self);
+        if pending.len() != 2 {
+            return Err(Error::CommitmentPending);
+        }
+        let hash = commitment_hash(mv.get_source().to_int(), mv.get_dest().to_int(), salt);
+        if pending[0].body() != hash {
+            return Err(Error::CommitmentMismatch);
+        }
+        self.sign_extension(key_pair, ExtensionKind::Reveal, salt.to_vec())?;
+        self.make_move_block(key_pair, Action::MakeMove(mv))
+    }
+
+    /// In a simultaneous game, commits to the reply to the opponent's
+    /// committed move before it is revealed. Returns the salt, which the
+    /// caller must keep secret until it is passed to reveal_reply.
+    pub fn commit_reply(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        mv: ChessMove,
+        rng: &dyn Rng,
+    ) -> Result<[u8; 32], Error> {
+        let mut salt = [0; 32];
+        rng.fill_bytes(&mut salt);
+        let hash = commitment_hash(mv.get_source().to_int(), mv.get_dest().to_int(), &salt);
+        self.sign_extension(key_pair, ExtensionKind::CounterCommitment, hash.to_vec())?;
+        Ok(salt)
+    }
+
+    /// Reveals the committed reply and plays it. If it isn't legal after the
+    /// opponent's move, the reveal stands and the game is lost.
+    pub fn reveal_reply(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        mv: ChessMove,
+        salt: &[u8; 32],
+    ) -> Result<(), Error> {
+        let mut body = salt.to_vec();
+        body.push(mv.get_source().to_int());
+        body.push(mv.get_dest().to_int());
+        self.sign_extension(key_pair, ExtensionKind::Reveal, body)?;
+        if illegal_reply(self).is_some() {
+            return Ok(());
+        }
+        self.make_move_block(key_pair, Action::MakeMove(mv))
+    }
+}
+
+fn pending_commitment(chain: &GameChain) -> Vec<&ExtensionBlock> {
+    chain
+        .pending_extensions()
+        .filter(|block| {
+            SEQUENCE.contains(&block.kind()) || block.kind() == ExtensionKind::CounterCommitment
+        })
+        .collect()
+}
+
+/// Whether the move at `ply` is a reply committed to before the move it
+/// answers was revealed.
+fn is_reply(chain: &GameChain, ply: usize) -> bool {
+    chain.challenge().is_simultaneous() && ply % 2 == 1
+}
+
+/// The blocks that must come before the move at `ply`, in order. Outside
+/// simultaneous games they're optional.
+fn sequence(chain: &GameChain, ply: usize) -> &'static [ExtensionKind] {
+    if !chain.challenge().is_simultaneous() {
+        &SEQUENCE
+    } else if is_reply(chain, ply) {
+        &REPLY
+    } else {
+        &SIMULTANEOUS
+    }
+}
+
+/// The counter commitment to the reply at `ply`, signed before the move
+/// before it.
+fn counter_commitment(chain: &GameChain, ply: usize) -> Option<&ExtensionBlock> {
+    chain
+        .extensions()
+        .iter()
+        .find(|(at, block)| *at + 1 == ply && block.kind() == ExtensionKind::CounterCommitment)
+        .map(|(_, block)| block)
+}
+
+/// Checks that a commitment extension may follow the blocks already in the
+/// chain.
+pub(crate) fn check_extension(chain: &GameChain, block: &ExtensionBlock) -> Result<(), Error> {
+    let ply = chain.move_count();
+    let pending = pending_commitment(chain);
+    if sequence(chain, ply).get(pending.len()) != Some(&block.kind()) {
+        return Err(Error::Malformed {
+            block: "commitment",
+            reason: "out of order",
+        });
+    }
+    check_body(chain, ply, block)
+}
+
+fn check_body(chain: &GameChain, ply: usize, block: &ExtensionBlock) -> Result<(), Error> {
+    let reply = is_reply(chain, ply);
+    let expected = match block.kind() {
+        ExtensionKind::CommitmentAck => 0,
+        ExtensionKind::Reveal if reply => 34,
+        _ => 32,
+    };
+    if block.body().len() != expected {
+        return Err(Error::Malformed {
+            block: "commitment",
+            reason: "wrong body length",
+        });
+    }
+    if reply {
+        let body = block.body();
+        let committed = counter_commitment(chain, ply).ok_or(Error::CommitmentPending)?;
+        if committed.body() != commitment_hash(body[32], body[33], &body[..32]) {
+            return Err(Error::CommitmentMismatch);
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the next move may be appended: either nothing is committed,
+/// or the commitment is acknowledged, revealed, and matches the move.
+pub(crate) fn check_move(chain: &GameChain, start_square: u8, end_square: u8) -> Result<(), Error> {
+    let ply = chain.move_count();
+    check_reveal(
+        chain,
+        ply,
+        &pending_commitment(chain),
+        start_square,
+        end_square,
+    )
+}
+
+fn check_reveal(
+    chain: &GameChain,
+    ply: usize,
+    blocks: &[&ExtensionBlock],
+    start_square: u8,
+    end_square: u8,
+) -> Result<(), Error> {
+    match blocks {
+        [] if !chain.challenge().is_simultaneous() => Ok(()),
+        [reveal] if is_reply(chain, ply) => {
+            if reveal.body()[32..] == [start_square, end_square] {
+                Ok(())
+            } else {
+                Err(Error::CommitmentMismatch)
+            }
+        }
+        [commitment, _, reveal] if !is_reply(chain, ply) => {
+            if commitment.body() == commitment_hash(start_square, end_square, reveal.body()) {
+                Ok(())
+            } else {
+                Err(Error::CommitmentMismatch)
+            }
+        }
+        _ => Err(Error::CommitmentPending),
+    }
+}
+
+/// The player who revealed a reply that isn't legal after the move it
+/// answers, losing the game, if there is one.
+pub(crate) fn illegal_reply(chain: &GameChain) -> Option<Color> {
+    let ply = chain.move_count();
+    if !is_reply(chain, ply) {
+        return None;
+    }
+    let reveal = chain
+        .pending_extensions()
+        .find(|block| block.kind() == ExtensionKind::Reveal)?;
+    let squares = reveal.body().get(32..34)?;
+    let board = chain.current_position().ok()?;
+    let legal = MoveGen::new_legal(&board)
+        .any(|mv| mv.get_source().to_int() == squares[0] && mv.get_dest().to_int() == squares[1]);
+    if legal {
+        None
+    } else {
+        Some(board.side_to_move())
+    }
+}
+
+/// Checks every commitment in the chain: each must come in order, and each
+/// move that follows one must match it.
+pub(crate) fn check_chain(chain: &GameChain) -> Result<(), Error> {
+    if chain.challenge().is_simultaneous()
+        && chain
+            .extensions()
+            .iter()
+            .any(|(_, block)| block.kind() == ExtensionKind::TakebackRequest)
+    {
+        return Err(Error::InvalidTakeback);
+    }
+    // extensions are kept in order, so one pass groups them by ply
+    let mut extensions = chain.extensions().iter().peekable();
+    for ply in 0..=chain.move_count() {
+        let mut blocks: Vec<&ExtensionBlock> = Vec::new();
+        while let Some((_, block)) = extensions.next_if(|(at, _)| *at == ply) {
+            if SEQUENCE.contains(&block.kind()) || block.kind() == ExtensionKind::CounterCommitment
+            {
+                blocks.push(block);
+            }
+        }
+        let sequence = sequence(chain, ply);
+        for (block, kind) in blocks.iter().zip(sequence.iter()) {
+            if block.kind() != *kind {
+                return Err(Error::Malformed {
+                    block: "commitment",
+                    reason: "out of order",
+                });
+            }
+            check_body(chain, ply, block)?;
+        }
+        if blocks.len() > sequence.len() {
+            return Err(Error::Malformed {
+                block: "commitment",
+                reason: "out of order",
+            });
+        }
+        if let Some(move_block) = chain.moves().get(ply) {
+            check_reveal(
+                chain,
+                ply,
+                &blocks,
+                move_block.start_square(),
+                move_block.end_square(),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlock, ChallengeBlockBuilder, GameResult, GameStatus};
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use chess::Square;
+
+    fn uci(mv: &str) -> ChessMove {
+        ChessMove::new(
+            mv[..2].parse::<Square>().unwrap(),
+            mv[2..4].parse::<Square>().unwrap(),
+            None,
+        )
+    }
+
+    #[test]
+    fn commit_and_reveal() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+
+        let salt = chain.commit_move(&white, uci("e2e4"), &rng).unwrap();
+        assert_eq!(
+            chain.make_move_block(&white, Action::MakeMove(uci("e2e4"))),
+            Err(Error::CommitmentPending)
+        );
+        assert_eq!(
+            chain.reveal_move(&white, uci("e2e4"), &salt),
+            Err(Error::CommitmentPending)
+        );
+        assert_eq!(
+            chain.acknowledge_commitment(&white),
+            Err(Error::NotYourTurn)
+        );
+        chain.acknowledge_commitment(&black).unwrap();
+        assert_eq!(
+            chain.reveal_move(&white, uci("d2d4"), &salt),
+            Err(Error::CommitmentMismatch)
+        );
+        chain.reveal_move(&white, uci("e2e4"), &salt).unwrap();
+        assert_eq!(chain.move_count(), 1);
+        assert_eq!(chain.extensions().len(), 3);
+
+        chain
+            .make_move_block(&black, Action::MakeMove(uci("e7e5")))
+            .unwrap();
+        assert!(chain.verify());
+        let bytes = chain.as_bytes();
+        assert_eq!(bytes.len(), chain.encoded_len());
+        assert_eq!(GameChain::from_bytes(&bytes), Ok(chain.clone()));
+
+        // a receiver replaying the blocks one at a time ends up with the same chain
+        let mut received = GameChain::new(chain.challenge().clone());
+        for accept in chain.accepts() {
+            received
+                .append_accept_block(accept.clone().unwrap())
+                .unwrap();
+        }
+        for (_, block) in chain.extensions() {
+            received.append_extension_block(block.clone()).unwrap();
+            if block.kind() == ExtensionKind::Reveal {
+                received
+                    .append_move_block(chain.moves()[0].clone())
+                    .unwrap();
+            }
+        }
+        received
+            .append_move_block(chain.moves()[1].clone())
+            .unwrap();
+        assert_eq!(received, chain);
+    }
+
+    #[test]
+    fn simultaneous_moves() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .simultaneous()
+            .build()
+            .unwrap();
+        assert!(ChallengeBlock::from_bytes(&challenge.as_bytes())
+            .unwrap()
+            .is_simultaneous());
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+
+        // every move is committed, and the reply before the move is revealed
+        assert_eq!(
+            chain.make_move_block(&white, Action::MakeMove(uci("e2e4"))),
+            Err(Error::CommitmentPending)
+        );
+        let salt = chain.commit_move(&white, uci("e2e4"), &rng).unwrap();
+        assert!(chain.acknowledge_commitment(&black).is_err());
+        assert_eq!(
+            chain.reveal_move(&white, uci("e2e4"), &salt),
+            Err(Error::CommitmentPending)
+        );
+        let reply_salt = chain.commit_reply(&black, uci("e7e5"), &rng).unwrap();
+        chain.reveal_move(&white, uci("e2e4"), &salt).unwrap();
+        assert_eq!(
+            chain.reveal_reply(&black, uci("d7d5"), &reply_salt),
+            Err(Error::CommitmentMismatch)
+        );
+        chain
+            .reveal_reply(&black, uci("e7e5"), &reply_salt)
+            .unwrap();
+        assert_eq!(chain.move_count(), 2);
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain.clone()));
+
+        // a reply that doesn't fit the move it answers loses
+        let salt = chain.commit_move(&white, uci("d1h5"), &rng).unwrap();
+        let reply_salt = chain.commit_reply(&black, uci("e5e4"), &rng).unwrap();
+        chain.reveal_move(&white, uci("d1h5"), &salt).unwrap();
+        chain
+            .reveal_reply(&black, uci("e5e4"), &reply_salt)
+            .unwrap();
+        assert_eq!(chain.move_count(), 3);
+        assert_eq!(
+            chain.status(),
+            GameStatus::Finished(GameResult::Win(Color::White))
+        );
+        assert!(chain.verify());
+    }
+}
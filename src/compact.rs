@@ -0,0 +1,195 @@
+//! Compact moves for bandwidth-sensitive transports. Peers that negotiated
+//! wire version 6 or later send new moves as a CompactMoves message instead
+//! of as move blocks: every move packed into a big-endian u16, then all of
+//! their signatures in the same order.
+//!
+//! - bits 0-5: the start square
+//! - bits 6-11: the end square
+//! - bits 12-14: the promotion: 0 for none, then knight, bishop, rook, queen
+//! - bit 15: zero
+//!
+//! The promotion is the one the move block resolves to on the board it's
+//! played on; a compact move naming a different one is rejected. Ed25519
+//! signatures can't be aggregated, so every move still carries its own, but
+//! keeping them after the moves means a transport can read the moves without
+//! buffering the signatures.
+
+use crate::block::{GameChain, MoveBlock};
+use crate::error::Error;
+
+use alloc::vec::Vec;
+use chess::{Board, ChessMove, Piece};
+
+/// The length of a packed move.
+const PACKED_LEN: usize = 2;
+
+/// The length of a move's signature.
+const SIGNATURE_LEN: usize = 64;
+
+/// The encoded length of a compact move, counting its signature.
+pub const COMPACT_MOVE_LEN: usize = PACKED_LEN + SIGNATURE_LEN;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompactMove {
+    packed: u16,
+    signature: Vec<u8>,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "compact move",
+        reason,
+    }
+}
+
+fn pack(mv: ChessMove) -> u16 {
+    let promotion = match mv.get_promotion() {
+        None => 0,
+        Some(Piece::Knight) => 1,
+        Some(Piece::Bishop) => 2,
+        Some(Piece::Rook) => 3,
+        Some(_) => 4,
+    };
+    mv.get_source().to_int() as u16 | (mv.get_dest().to_int() as u16) << 6 | promotion << 12
+}
+
+impl CompactMove {
+    /// Packs `block`, which must be legal on `board`, the position before
+    /// it.
+    pub fn new(block: &MoveBlock, board: &Board) -> Result<CompactMove, Error> {
+        let mv = block.find_move(board).ok_or(Error::IllegalMove)?;
+        Ok(CompactMove {
+            packed: pack(mv),
+            signature: block.signature().to_vec(),
+        })
+    }
+
+    pub fn packed(&self) -> u16 {
+        self.packed
+    }
+
+    pub fn start_square(&self) -> u8 {
+        (self.packed & 0x3f) as u8
+    }
+
+    pub fn end_square(&self) -> u8 {
+        ((self.packed >> 6) & 0x3f) as u8
+    }
+
+    /// Unpacks the move block, checking the promotion against `board`, the
+    /// position before it.
+    pub fn to_move_block(&self, board: &Board) -> Result<MoveBlock, Error> {
+        let mut bytes = Vec::with_capacity(COMPACT_MOVE_LEN);
+        bytes.push(self.start_square());
+        bytes.push(self.end_square());
+        bytes.extend(&self.signature);
+        let block = MoveBlock::from_bytes(&bytes)?;
+        match block.find_move(board) {
+            Some(mv) if pack(mv) == self.packed => Ok(block),
+            _ => Err(Error::IllegalMove),
+        }
+    }
+}
+
+/// Decodes a run of compact moves: the packed moves, then their signatures.
+pub fn from_bytes(bytes: &[u8]) -> Result<Vec<CompactMove>, Error> {
+    if bytes.len() % COMPACT_MOVE_LEN != 0 {
+        return Err(malformed("wrong length"));
+    }
+    let (packed, signatures) = bytes.split_at(bytes.len() / COMPACT_MOVE_LEN * PACKED_LEN);
+    packed
+        .chunks(PACKED_LEN)
+        .zip(signatures.chunks(SIGNATURE_LEN))
+        .map(|(packed, signature)| {
+            let packed = u16::from_be_bytes([packed[0], packed[1]]);
+            if packed >> 15 != 0 || (packed >> 12) & 7 > 4 {
+                return Err(malformed("unknown bits set"));
+            }
+            Ok(CompactMove {
+                packed,
+                signature: signature.to_vec(),
+            })
+        })
+        .collect()
+}
+
+/// Encodes a run of compact moves, as read by `from_bytes`.
+pub fn write_bytes(moves: &[CompactMove], bytes: &mut Vec<u8>) {
+    for mv in moves {
+        bytes.extend(&mv.packed.to_be_bytes());
+    }
+    for mv in moves {
+        bytes.extend(&mv.signature);
+    }
+}
+
+impl GameChain {
+    /// The last move, packed, for sending to a peer that has everything
+    /// before it.
+    pub fn last_compact_move(&self) -> Result<CompactMove, Error> {
+        let count = self.move_count();
+        let block = self.moves().last().ok_or(Error::IllegalMove)?;
+        let board = self.truncated(count - 1).current_position()?;
+        CompactMove::new(block, &board)
+    }
+
+    /// Appends a move received in compact form.
+    pub fn append_compact_move(&mut self, mv: &CompactMove) -> Result<(), Error> {
+        let block = mv.to_move_block(&self.current_position()?)?;
+        self.append_move_block(block)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlockBuilder, Variant};
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn pack_promotion() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .variant(Variant::FromPosition)
+            .starting_fen("8/P6k/8/8/8/8/8/K7 w - - 0 1")
+            .build()
+            .unwrap();
+        let mut sender = GameChain::new(challenge);
+        sender.accept(&white).unwrap();
+        sender.accept(&black).unwrap();
+        let mut receiver = sender.clone();
+
+        let promotion = notation::parse_uci("a7a8q").unwrap();
+        sender
+            .make_move_block(&white, Action::MakeMove(promotion))
+            .unwrap();
+        let mv = sender.last_compact_move().unwrap();
+        assert_eq!(mv.start_square(), 48);
+        assert_eq!(mv.end_square(), 56);
+        let mut bytes = Vec::new();
+        write_bytes(&[mv.clone(), mv.clone()], &mut bytes);
+        assert_eq!(bytes.len(), 2 * COMPACT_MOVE_LEN);
+        // the packed moves come first, then the signatures
+        assert_eq!(&bytes[..2], &bytes[2..4]);
+        assert_eq!(&bytes[4..68], &mv.signature[..]);
+        assert_eq!(from_bytes(&bytes), Ok(vec![mv.clone(), mv.clone()]));
+        assert!(from_bytes(&bytes[1..]).is_err());
+
+        // naming a different promotion doesn't unpack
+        let mut knight = Vec::new();
+        write_bytes(&[mv.clone()], &mut knight);
+        knight[0] = knight[0] & 0x8f | 0x10;
+        let knight = from_bytes(&knight).unwrap();
+        assert_eq!(
+            receiver.append_compact_move(&knight[0]),
+            Err(Error::IllegalMove)
+        );
+        receiver.append_compact_move(&mv).unwrap();
+        assert_eq!(receiver, sender);
+    }
+}
@@ -0,0 +1,58 @@
+//! Deflate compression for bundles and messages. Only the encoding on disk
+//! or on the wire is compressed: blocks are signed, and verified, over their
+//! uncompressed canonical bytes.
+
+use crate::error::Error;
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use std::io::{Read, Write};
+
+pub fn deflate(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder
+        .write_all(bytes)
+        .expect("writing to a Vec can't fail");
+    encoder.finish().expect("writing to a Vec can't fail")
+}
+
+/// Decompresses `bytes`, refusing to produce more than `max_len` bytes so
+/// that a small input can't expand without limit.
+pub fn inflate(bytes: &[u8], max_len: usize, block: &'static str) -> Result<Vec<u8>, Error> {
+    let mut inflated = Vec::new();
+    DeflateDecoder::new(bytes)
+        .take(max_len as u64 + 1)
+        .read_to_end(&mut inflated)
+        .map_err(|_| Error::Malformed {
+            block,
+            reason: "bad compressed data",
+        })?;
+    if inflated.len() > max_len {
+        return Err(Error::TooLarge {
+            what: block,
+            limit: max_len,
+        });
+    }
+    Ok(inflated)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn deflate_and_inflate() {
+        let bytes = vec![7; 10_000];
+        let deflated = deflate(&bytes);
+        assert!(deflated.len() < 100);
+        assert_eq!(inflate(&deflated, 10_000, "test"), Ok(bytes));
+        assert_eq!(
+            inflate(&deflated, 9_999, "test"),
+            Err(Error::TooLarge {
+                what: "test",
+                limit: 9_999,
+            })
+        );
+        assert!(inflate(&[0xff; 8], 10_000, "test").is_err());
+    }
+}
@@ -0,0 +1,309 @@
+//! Conditional moves for correspondence play. Right after moving, a player
+//! can sign "if my opponent plays this, I reply with that". The opponent's
+//! client redeems it by appending the reply straight after the matching
+//! move, saving a round trip.
+//!
+//! A conditional's body is the trigger's start and end squares, the reply's
+//! start and end squares, the SHA-256 digest of the signing prefix before the
+//! conditional, and the reply's 64-byte signature. A redeemed reply is an
+//! ordinary move block carrying that signature, which covers the canonical
+//! id, the digest, and the four squares rather than the chain before it.
+
+use crate::block::{ChainEntry, GameChain, MoveBlock};
+use crate::crypto;
+use crate::error::Error;
+use crate::extension::ExtensionKind;
+
+use alloc::vec::Vec;
+use chess::{Board, ChessMove, MoveGen};
+use ring::digest;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConditionalMove {
+    /// The start and end squares of the opponent's move.
+    pub trigger: [u8; 2],
+    /// The start and end squares of the reply.
+    pub reply: [u8; 2],
+    prefix_digest: [u8; 32],
+    reply_signature: Vec<u8>,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "conditional",
+        reason,
+    }
+}
+
+fn sha256(bytes: &[u8]) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(digest::digest(&digest::SHA256, bytes).as_ref());
+    hash
+}
+
+fn squares(mv: ChessMove) -> [u8; 2] {
+    [mv.get_source().to_int(), mv.get_dest().to_int()]
+}
+
+fn find_move(board: &Board, start_and_end: [u8; 2]) -> Option<ChessMove> {
+    MoveGen::new_legal(board).find(|mv| squares(*mv) == start_and_end)
+}
+
+fn reply_message(
+    canonical_id: &[u8; 32],
+    prefix_digest: &[u8; 32],
+    trigger: [u8; 2],
+    reply: [u8; 2],
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(68);
+    message.extend(canonical_id);
+    message.extend(prefix_digest);
+    message.extend(&trigger);
+    message.extend(&reply);
+    message
+}
+
+impl ConditionalMove {
+    pub(crate) fn from_body(body: &[u8]) -> Result<ConditionalMove, Error> {
+        if body.len() != 4 + 32 + 64 {
+            return Err(malformed("wrong body length"));
+        }
+        let mut prefix_digest = [0; 32];
+        prefix_digest.copy_from_slice(&body[4..36]);
+        Ok(ConditionalMove {
+            trigger: [body[0], body[1]],
+            reply: [body[2], body[3]],
+            prefix_digest,
+            reply_signature: body[36..].to_vec(),
+        })
+    }
+
+    /// Whether the conditional was made after `prefix`, the signing prefix
+    /// before it.
+    pub(crate) fn follows(&self, prefix: &[u8]) -> bool {
+        sha256(prefix) == self.prefix_digest
+    }
+
+    fn verify_reply(&self, canonical_id: &[u8; 32], public_key: &[u8]) -> bool {
+        crypto::verify(
+            public_key,
+            &reply_message(canonical_id, &self.prefix_digest, self.trigger, self.reply),
+            &self.reply_signature,
+        )
+    }
+
+    /// The move block that redeems the conditional.
+    pub fn reply_block(&self) -> MoveBlock {
+        let mut bytes = self.reply.to_vec();
+        bytes.extend(&self.reply_signature);
+        // 66 bytes always make a move block
+        MoveBlock::from_bytes(&bytes).unwrap()
+    }
+}
+
+/// Follows the conditionals that could be redeemed as blocks are read in
+/// encoded order. Conditionals are offered until the next move; those it
+/// triggers may be redeemed by the move straight after it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Redemptions {
+    offered: Vec<ConditionalMove>,
+    armed: Vec<ConditionalMove>,
+}
+
+impl Redemptions {
+    pub(crate) fn push_move(&mut self, squares: [u8; 2]) {
+        self.armed = self
+            .offered
+            .drain(..)
+            .filter(|conditional| conditional.trigger == squares)
+            .collect();
+    }
+
+    pub(crate) fn push_extension(&mut self, kind: ExtensionKind, body: &[u8]) {
+        self.armed.clear();
+        match kind {
+            ExtensionKind::Conditional => {
+                if let Ok(conditional) = ConditionalMove::from_body(body) {
+                    self.offered.push(conditional);
+                }
+            }
+            // the position the conditionals were made in is gone
+            ExtensionKind::TakebackAccept => self.offered.clear(),
+            _ => {}
+        }
+    }
+
+    /// Whether a move with the given squares and signature, which
+    /// `public_key` must sign, redeems a triggered conditional.
+    pub(crate) fn redeems(
+        &self,
+        canonical_id: &[u8; 32],
+        public_key: &[u8],
+        squares: [u8; 2],
+        signature: &[u8],
+    ) -> bool {
+        self.redeemed_message(canonical_id, public_key, squares, signature)
+            .is_some()
+    }
+
+    /// The message a move redeeming a triggered conditional was signed
+    /// over, if it redeems one.
+    pub(crate) fn redeemed_message(
+        &self,
+        canonical_id: &[u8; 32],
+        public_key: &[u8],
+        squares: [u8; 2],
+        signature: &[u8],
+    ) -> Option<Vec<u8>> {
+        self.armed
+            .iter()
+            .find(|conditional| {
+                conditional.reply == squares
+                    && conditional.reply_signature == signature
+                    && conditional.verify_reply(canonical_id, public_key)
+            })
+            .map(|conditional| {
+                reply_message(
+                    canonical_id,
+                    &conditional.prefix_digest,
+                    conditional.trigger,
+                    conditional.reply,
+                )
+            })
+    }
+}
+
+pub(crate) fn check_extension(chain: &GameChain, body: &[u8]) -> Result<(), Error> {
+    let conditional = ConditionalMove::from_body(body)?;
+    if !conditional.follows(&chain.signing_prefix()) {
+        return Err(malformed("wrong prefix digest"));
+    }
+    let board = chain.current_position()?;
+    let trigger = find_move(&board, conditional.trigger).ok_or(Error::IllegalMove)?;
+    if find_move(&board.make_move_new(trigger), conditional.reply).is_none() {
+        return Err(Error::IllegalMove);
+    }
+    let public_key = chain.signer_public_key(chain.line()?.len() + 1);
+    if !conditional.verify_reply(&chain.canonical_id(), public_key.as_ref()) {
+        return Err(Error::VerificationFailed);
+    }
+    Ok(())
+}
+
+impl GameChain {
+    /// Offers `reply` should the opponent play `trigger` next. Signed by the
+    /// player who just moved.
+    pub fn offer_conditional(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        trigger: ChessMove,
+        reply: ChessMove,
+    ) -> Result<(), Error> {
+        let board = self.current_position()?;
+        if self.side_of(key_pair.public_key().as_ref()) != Some(!board.side_to_move()) {
+            return Err(Error::NotYourTurn);
+        }
+        if !board.legal(trigger) || !board.make_move_new(trigger).legal(reply) {
+            return Err(Error::IllegalMove);
+        }
+        let prefix_digest = sha256(&self.signing_prefix());
+        let (trigger, reply) = (squares(trigger), squares(reply));
+        let signature = crypto::sign(
+            key_pair,
+            &reply_message(&self.canonical_id(), &prefix_digest, trigger, reply),
+        );
+        let mut body = Vec::with_capacity(100);
+        body.extend(&trigger);
+        body.extend(&reply);
+        body.extend(&prefix_digest);
+        body.extend(signature);
+        self.sign_extension(key_pair, ExtensionKind::Conditional, body)
+    }
+
+    /// The conditionals the last move triggered, which the next move may
+    /// redeem.
+    pub(crate) fn redemptions(&self) -> Redemptions {
+        let mut redemptions = Redemptions::default();
+        for entry in self.entries() {
+            match entry {
+                ChainEntry::Move { block, .. } => {
+                    redemptions.push_move([block.start_square(), block.end_square()])
+                }
+                ChainEntry::Extension(block) => {
+                    redemptions.push_extension(block.kind(), block.body())
+                }
+            }
+        }
+        redemptions
+    }
+
+    /// Appends the reply to a conditional the opponent offered, if the last
+    /// move triggered one. Returns whether there was one to redeem.
+    pub fn redeem_conditional(&mut self) -> Result<bool, Error> {
+        let conditional = match self.redemptions().armed.into_iter().next() {
+            Some(conditional) => conditional,
+            None => return Ok(false),
+        };
+        self.append_move_block(conditional.reply_block())?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn redeem_conditional_move() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let uci = |uci| notation::parse_uci(uci).unwrap();
+        chain
+            .make_move_block(&white, Action::MakeMove(uci("e2e4")))
+            .unwrap();
+
+        assert_eq!(
+            chain.offer_conditional(&black, uci("e7e5"), uci("g1f3")),
+            Err(Error::NotYourTurn)
+        );
+        assert_eq!(
+            chain.offer_conditional(&white, uci("e7e5"), uci("e1e3")),
+            Err(Error::IllegalMove)
+        );
+        chain
+            .offer_conditional(&white, uci("e7e5"), uci("g1f3"))
+            .unwrap();
+        chain
+            .offer_conditional(&white, uci("c7c5"), uci("b1c3"))
+            .unwrap();
+
+        // nothing to redeem until black plays a trigger
+        assert_eq!(chain.redeem_conditional(), Ok(false));
+        chain
+            .make_move_block(&black, Action::MakeMove(uci("c7c5")))
+            .unwrap();
+        assert_eq!(chain.redeem_conditional(), Ok(true));
+        assert_eq!(
+            chain.moves()[2].end_square(),
+            uci("b1c3").get_dest().to_int()
+        );
+        assert!(chain.verify());
+        assert!(GameChain::from_bytes(&chain.as_bytes()).is_ok());
+
+        // a reply that doesn't match the conditional doesn't verify
+        let mut bytes = chain.as_bytes();
+        let last = bytes.len() - 66;
+        bytes[last + 1] = uci("b1a3").get_dest().to_int();
+        assert!(GameChain::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,363 @@
+//! Checks another implementation against the wire protocol by playing the
+//! opening of a game with it, for authors of third-party clients. We say
+//! hello, challenge the key the peer introduces itself with (playing white),
+//! check that a peer speaking wire version 4 or later sends its capabilities
+//! before anything about the game, play 1. e4 and wait for a reply, then send
+//! malformed frames and check that the peer still answers a request for the
+//! chain. The implementation under test should accept the challenge and move
+//! on its own.
+
+use crate::block::{ChallengeBlockBuilder, GameChain};
+use crate::crypto;
+use crate::key::PublicKey;
+use crate::message::{Message, WIRE_VERSION};
+use crate::notation;
+use crate::session::{GameSession, SessionEvent};
+use crate::transport::{Channel, Transport};
+
+use chess::Action;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use std::fmt;
+use std::io;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    Handshake,
+    Capabilities,
+    Challenge,
+    Moves,
+    MalformedInput,
+}
+
+const STEPS: [Step; 5] = [
+    Step::Handshake,
+    Step::Capabilities,
+    Step::Challenge,
+    Step::Moves,
+    Step::MalformedInput,
+];
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Step::Handshake => "handshake",
+            Step::Capabilities => "capabilities",
+            Step::Challenge => "challenge",
+            Step::Moves => "moves",
+            Step::MalformedInput => "malformed input",
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    /// The peer did something the protocol doesn't allow, as described.
+    Deviated(String),
+    /// An earlier step failed, so this one couldn't be tried.
+    Skipped,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Report {
+    pub results: Vec<(Step, Outcome)>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.results
+            .iter()
+            .all(|(_, outcome)| *outcome == Outcome::Passed)
+    }
+}
+
+fn describe(error: io::Error) -> String {
+    format!("the connection failed: {}", error)
+}
+
+/// Runs every step against the peer at the other end of `transport`,
+/// signing as `key_pair`.
+pub fn run<T: Transport>(transport: &mut T, key_pair: &Ed25519KeyPair) -> Report {
+    let mut report = Report::default();
+    let mut session = None;
+    for &step in &STEPS {
+        if !report.passed() {
+            report.results.push((step, Outcome::Skipped));
+            continue;
+        }
+        let result = match (step, session.as_mut()) {
+            (Step::Handshake, _) => {
+                handshake(transport, key_pair).map(|started| session = Some(started))
+            }
+            (Step::Capabilities, Some(session)) => capabilities(transport, session),
+            (Step::Challenge, Some(session)) => pump(transport, session, |event| {
+                *event == SessionEvent::ChallengeAccepted
+            }),
+            (Step::Moves, Some(session)) => moves(transport, session, key_pair),
+            (Step::MalformedInput, Some(session)) => malformed_input(transport, session),
+            (_, None) => Err("no session".to_string()),
+        };
+        let outcome = match result {
+            Ok(()) => Outcome::Passed,
+            Err(deviation) => Outcome::Deviated(deviation),
+        };
+        report.results.push((step, outcome));
+    }
+    report
+}
+
+/// Swaps hellos, then starts a session challenging the peer's key.
+fn handshake<T: Transport>(
+    transport: &mut T,
+    key_pair: &Ed25519KeyPair,
+) -> Result<GameSession, String> {
+    let mut public_key = [0; 32];
+    public_key.copy_from_slice(key_pair.public_key().as_ref());
+    transport
+        .send_message(&Message::Hello {
+            public_key,
+            wire_version: WIRE_VERSION,
+        })
+        .map_err(describe)?;
+    let hello = transport.recv_message().map_err(describe)?;
+    let peer = match hello {
+        Message::Hello { public_key, .. } => PublicKey::from(public_key),
+        _ => return Err("the first message wasn't a hello".to_string()),
+    };
+    if peer == public_key {
+        return Err("the peer said hello with our key".to_string());
+    }
+    let challenge = ChallengeBlockBuilder::new(&PublicKey::from(public_key), &peer)
+        .id(crypto::random_id(&crypto::new_rng()))
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut chain = GameChain::new(challenge);
+    chain.accept(key_pair).map_err(|error| error.to_string())?;
+    let mut session = GameSession::new(&public_key, chain).map_err(|error| error.to_string())?;
+    // our hello has already gone
+    session.poll_transmit();
+    session.handle(hello);
+    Ok(session)
+}
+
+/// Sends our capabilities and the challenge, then reads until the peer's
+/// capabilities arrive, if its wire version has them. Whatever else comes
+/// first is handed to the session, and its events kept for the next step.
+fn capabilities<T: Transport>(transport: &mut T, session: &mut GameSession) -> Result<(), String> {
+    while let Some(message) = session.poll_transmit() {
+        transport.send_message(&message).map_err(describe)?;
+    }
+    if session.wire_version() < 4 {
+        return Ok(());
+    }
+    loop {
+        match transport.recv_message().map_err(describe)? {
+            message @ Message::Capabilities { .. } => {
+                session.handle(message);
+                return Ok(());
+            }
+            Message::Chain(_) => {
+                return Err("the peer sent the game before its capabilities".to_string())
+            }
+            message => session.handle(message),
+        }
+    }
+}
+
+/// Exchanges messages until the session raises an event `done` accepts.
+fn pump<T, F>(transport: &mut T, session: &mut GameSession, done: F) -> Result<(), String>
+where
+    T: Transport,
+    F: Fn(&SessionEvent) -> bool,
+{
+    loop {
+        while let Some(message) = session.poll_transmit() {
+            transport.send_message(&message).map_err(describe)?;
+        }
+        while let Some(event) = session.poll_event() {
+            match event {
+                event if done(&event) => return Ok(()),
+                SessionEvent::ChallengeDeclined => {
+                    return Err("the peer declined the challenge".to_string())
+                }
+                SessionEvent::Rejected(error) => {
+                    return Err(format!("the peer sent something invalid: {}", error))
+                }
+                _ => {}
+            }
+        }
+        let message = transport.recv_message().map_err(describe)?;
+        session.handle(message);
+    }
+}
+
+/// Plays 1. e4 and waits for black's reply.
+fn moves<T: Transport>(
+    transport: &mut T,
+    session: &mut GameSession,
+    key_pair: &Ed25519KeyPair,
+) -> Result<(), String> {
+    let e4 = notation::parse_uci("e2e4").expect("e2e4 is a move");
+    session
+        .make_move(key_pair, Action::MakeMove(e4))
+        .map_err(|error| error.to_string())?;
+    pump(transport, session, |event| {
+        *event == SessionEvent::MoveReceived { index: 1 }
+    })
+}
+
+/// Sends an unknown message and a truncated one, then asks for the chain,
+/// which the peer should still send.
+fn malformed_input<T: Transport>(
+    transport: &mut T,
+    session: &mut GameSession,
+) -> Result<(), String> {
+    let chain = Message::Chain(Box::new(session.chain().clone())).as_bytes();
+    transport
+        .send_frame(&[0xff, 0x00, 0x01])
+        .map_err(describe)?;
+    transport
+        .send_frame(&chain[..chain.len() / 2])
+        .map_err(describe)?;
+    let id = session.chain().id();
+    transport
+        .send_message(&Message::Request { id })
+        .map_err(describe)?;
+    loop {
+        let message = transport.recv_message().map_err(|error| {
+            format!(
+                "the peer stopped answering after malformed input: {}",
+                error
+            )
+        })?;
+        // our capabilities may have let the peer compress it
+        #[cfg(feature = "compression")]
+        let message = match message {
+            Message::Compressed(message) => *message,
+            message => message,
+        };
+        match message {
+            Message::Chain(theirs) if theirs.id() == id => {
+                return if theirs.move_count() >= session.chain().move_count() && theirs.verify() {
+                    Ok(())
+                } else {
+                    Err("the peer sent back a chain that doesn't hold the game".to_string())
+                };
+            }
+            Message::Ping { nonce } => transport
+                .send_message(&Message::Pong { nonce })
+                .map_err(describe)?,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::transport::StreamTransport;
+    use std::net::{Shutdown, TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    // long enough for a slow machine, short enough that a stuck peer fails
+    // the test instead of hanging it
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// A peer built on GameSession that accepts the challenge, or declines
+    /// it, and answers 1. e4 with 1... e5.
+    fn peer(stream: TcpStream, decline: bool) {
+        let rng = crypto::new_rng();
+        let key_pair = crypto::generate_key(&rng);
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(key_pair.public_key().as_ref());
+        let mut transport = StreamTransport::new(stream);
+        let hello = transport.recv_message().unwrap();
+        transport
+            .send_message(&Message::Hello {
+                public_key,
+                wire_version: WIRE_VERSION,
+            })
+            .unwrap();
+        // the runner's capabilities come before the challenge
+        let mut capabilities = None;
+        let chain = loop {
+            match transport.recv_message().unwrap() {
+                Message::Chain(chain) => break *chain,
+                message @ Message::Capabilities { .. } if capabilities.is_none() => {
+                    capabilities = Some(message)
+                }
+                message => panic!("expected a challenge, got {:?}", message),
+            }
+        };
+        let mut session = GameSession::new(&public_key, chain).unwrap();
+        session.poll_transmit();
+        session.handle(hello);
+        if let Some(capabilities) = capabilities {
+            session.handle(capabilities);
+        }
+        if decline {
+            session.decline();
+        } else {
+            session.accept(&key_pair).unwrap();
+        }
+        loop {
+            // answering a move queues it, so events come before sending
+            while let Some(event) = session.poll_event() {
+                if let SessionEvent::MoveReceived { .. } = event {
+                    let e5 = notation::parse_uci("e7e5").unwrap();
+                    session.make_move(&key_pair, Action::MakeMove(e5)).unwrap();
+                }
+            }
+            while let Some(message) = session.poll_transmit() {
+                transport.send_message(&message).unwrap();
+            }
+            match transport.recv_message() {
+                Ok(message) => session.handle(message),
+                Err(error) if error.kind() == io::ErrorKind::InvalidData => {}
+                Err(_) => return,
+            }
+        }
+    }
+
+    fn check(decline: bool) -> Report {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let peer_side = thread::spawn(move || {
+            let stream = listener.accept().unwrap().0;
+            stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+            peer(stream, decline)
+        });
+        let key_pair = crypto::generate_key(&crypto::new_rng());
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let mut transport = StreamTransport::new(stream);
+        let report = run(&mut transport, &key_pair);
+        // the peer answers until the connection ends
+        let _ = transport.into_inner().shutdown(Shutdown::Both);
+        peer_side.join().unwrap();
+        report
+    }
+
+    #[test]
+    fn conforming_peer_passes() {
+        let report = check(false);
+        assert!(report.passed(), "{:?}", report);
+        assert_eq!(report.results.len(), STEPS.len());
+    }
+
+    #[test]
+    fn declining_peer_deviates() {
+        let report = check(true);
+        assert_eq!(report.results[0], (Step::Handshake, Outcome::Passed));
+        assert_eq!(report.results[1], (Step::Capabilities, Outcome::Passed));
+        assert_eq!(
+            report.results[2],
+            (
+                Step::Challenge,
+                Outcome::Deviated("the peer declined the challenge".to_string())
+            )
+        );
+        assert_eq!(report.results[4], (Step::MalformedInput, Outcome::Skipped));
+    }
+}
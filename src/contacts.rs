@@ -0,0 +1,213 @@
+//! An address book of petnames: names the user picks for other players' keys.
+//! Contacts are stored one per line as a name and a base58 public key, the
+//! same format used to import and export them.
+
+use crate::block::GameChain;
+use crate::crypto;
+use crate::error::Error;
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AddressBook {
+    contacts: BTreeMap<String, [u8; 32]>,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "contacts",
+        reason,
+    }
+}
+
+/// The config directory: LINEAGE_CONFIG if set, or else ~/.config/lineage.
+pub fn config_dir() -> PathBuf {
+    match std::env::var_os("LINEAGE_CONFIG") {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::var_os("HOME")
+            .map(PathBuf::from)
+            .unwrap_or_default()
+            .join(".config")
+            .join("lineage"),
+    }
+}
+
+impl AddressBook {
+    pub fn new() -> AddressBook {
+        AddressBook::default()
+    }
+
+    /// Where the address book lives: contacts in the config directory.
+    pub fn default_path() -> PathBuf {
+        config_dir().join("contacts")
+    }
+
+    /// Adds or renames a contact. Names can't be empty or contain
+    /// whitespace, and each key has at most one name.
+    pub fn add(&mut self, name: &str, public_key: &[u8]) -> Result<(), Error> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(malformed("bad name"));
+        }
+        if public_key.len() != 32 {
+            return Err(Error::InvalidKey);
+        }
+        let mut key = [0; 32];
+        key.copy_from_slice(public_key);
+        self.contacts.retain(|_, existing| *existing != key);
+        self.contacts.insert(name.into(), key);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<[u8; 32]> {
+        self.contacts.remove(name)
+    }
+
+    pub fn key(&self, name: &str) -> Option<&[u8; 32]> {
+        self.contacts.get(name)
+    }
+
+    pub fn name(&self, public_key: &[u8]) -> Option<&str> {
+        self.contacts
+            .iter()
+            .find(|(_, key)| &key[..] == public_key)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// The contact's name, or the key's fingerprint for strangers.
+    pub fn label(&self, public_key: &[u8]) -> String {
+        self.name(public_key)
+            .map_or_else(|| crypto::fingerprint(public_key), String::from)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8; 32])> {
+        self.contacts.iter().map(|(name, key)| (name.as_str(), key))
+    }
+
+    pub fn from_text(text: &str) -> Result<AddressBook, Error> {
+        let mut book = AddressBook::new();
+        book.import(text)?;
+        Ok(book)
+    }
+
+    /// Adds every contact in `text`, returning how many there were. Blank
+    /// lines and lines starting with # are skipped.
+    pub fn import(&mut self, text: &str) -> Result<usize, Error> {
+        let mut count = 0;
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let (name, key) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(name), Some(key), None) => (name, key),
+                _ => return Err(malformed("expected a name and a key")),
+            };
+            let key = bs58::decode(key)
+                .into_vec()
+                .map_err(|_| Error::InvalidKey)?;
+            self.add(name, &key)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    pub fn to_text(&self) -> String {
+        self.contacts
+            .iter()
+            .map(|(name, key)| format!("{} {}\n", name, bs58::encode(key).into_string()))
+            .collect()
+    }
+
+    /// Loads the address book at `path`, which is empty if the file doesn't
+    /// exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<AddressBook> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(AddressBook::new()),
+            Err(error) => return Err(error),
+        };
+        AddressBook::from_text(&text)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_text())
+    }
+}
+
+/// A chain displayed with players' petnames in place of their fingerprints.
+pub struct NamedChain<'a> {
+    chain: &'a GameChain,
+    contacts: &'a AddressBook,
+}
+
+impl<'a> fmt::Display for NamedChain<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.chain.write_summary(f, |public_key| {
+            self.contacts.name(public_key).map(String::from)
+        })
+    }
+}
+
+impl GameChain {
+    pub fn with_contacts<'a>(&'a self, contacts: &'a AddressBook) -> NamedChain<'a> {
+        NamedChain {
+            chain: self,
+            contacts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::key::PublicKey;
+    use ring::signature::KeyPair;
+
+    #[test]
+    fn petnames() {
+        let rng = crypto::new_rng();
+        let alice = crypto::generate_key(&rng);
+        let bob = crypto::generate_key(&rng);
+        let mut book = AddressBook::new();
+        book.add("alice", alice.public_key().as_ref()).unwrap();
+        book.add("bob", bob.public_key().as_ref()).unwrap();
+        assert!(book.add("two words", bob.public_key().as_ref()).is_err());
+        assert_eq!(book.label(alice.public_key().as_ref()), "alice");
+
+        // renaming replaces the old name
+        book.add("robert", bob.public_key().as_ref()).unwrap();
+        assert_eq!(book.key("bob"), None);
+        assert_eq!(book.name(bob.public_key().as_ref()), Some("robert"));
+
+        let exported = book.to_text();
+        let mut imported = AddressBook::new();
+        assert_eq!(
+            imported
+                .import(&format!("# my contacts\n\n{}", exported))
+                .unwrap(),
+            2
+        );
+        assert_eq!(imported, book);
+        assert!(AddressBook::from_text("alice").is_err());
+
+        let challenge = ChallengeBlock::new(&PublicKey::of(&alice), &PublicKey::of(&bob));
+        let chain = GameChain::new(challenge);
+        let named = chain.with_contacts(&book).to_string();
+        assert!(named.contains("White alice vs Black robert"), "{}", named);
+
+        book.remove("alice");
+        assert_eq!(
+            book.label(alice.public_key().as_ref()),
+            crypto::fingerprint(alice.public_key().as_ref())
+        );
+    }
+}
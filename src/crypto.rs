@@ -1,33 +1,132 @@
+extern crate bs58;
 extern crate ring;
-extern crate untrusted;
 
 use ring::{
+    digest,
     rand::{SecureRandom, SystemRandom},
-    signature::{self, Ed25519KeyPair},
+    signature::{self, Ed25519KeyPair, KeyPair, UnparsedPublicKey},
 };
-use untrusted::Input;
+
+use crate::error::Error;
+use crate::metrics;
+use crate::offline::{PendingBlock, PendingKind};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+/// A source of random bytes for keys, ids, and salts. The system RNG is used
+/// in practice; SeededRng makes tests and simulations reproducible.
+pub trait Rng {
+    fn fill_bytes(&self, dest: &mut [u8]);
+}
+
+impl Rng for SystemRandom {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        SecureRandom::fill(self, dest).expect("the system RNG failed")
+    }
+}
+
+/// A deterministic RNG (splitmix64) for tests. Never use it for real keys.
+#[derive(Debug)]
+pub struct SeededRng {
+    state: Cell<u64>,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> SeededRng {
+        SeededRng {
+            state: Cell::new(seed),
+        }
+    }
+
+    fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl Rng for SeededRng {
+    fn fill_bytes(&self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_be_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
 
 pub fn new_rng() -> SystemRandom {
     SystemRandom::new()
 }
 
-pub fn generate_key(rng: &dyn SecureRandom) -> Ed25519KeyPair {
-    Ed25519KeyPair::from_pkcs8(Input::from(
-        Ed25519KeyPair::generate_pkcs8(rng).unwrap().as_ref(),
-    ))
-    .unwrap()
+/// Generates a key from a seed drawn from `rng`.
+pub fn generate_key(rng: &dyn Rng) -> Ed25519KeyPair {
+    let mut seed = [0; 32];
+    rng.fill_bytes(&mut seed);
+    // any 32 bytes make a valid Ed25519 seed
+    Ed25519KeyPair::from_seed_unchecked(&seed).unwrap()
+}
+
+/// A random game id.
+pub fn random_id(rng: &dyn Rng) -> u32 {
+    let mut id = [0; 4];
+    rng.fill_bytes(&mut id);
+    u32::from_be_bytes(id)
+}
+
+/// Generates a new key as a PKCS#8 document, for callers that need to store
+/// the key themselves. PKCS#8 generation needs the system RNG.
+pub fn generate_pkcs8(rng: &dyn SecureRandom) -> Vec<u8> {
+    Ed25519KeyPair::generate_pkcs8(rng)
+        .unwrap()
+        .as_ref()
+        .to_vec()
+}
+
+pub fn key_from_pkcs8(pkcs8: &[u8]) -> Result<Ed25519KeyPair, Error> {
+    Ed25519KeyPair::from_pkcs8(pkcs8).map_err(|_| Error::InvalidKey)
 }
 
 pub fn sign(key_pair: &Ed25519KeyPair, msg: &[u8]) -> Vec<u8> {
-    key_pair.sign(msg).as_ref().iter().cloned().collect()
+    metrics::signature_created();
+    key_pair.sign(msg).as_ref().to_vec()
+}
+
+/// Signs a block exported for offline signing, after checking that this key
+/// is the one that must sign it.
+pub fn sign_detached(key_pair: &Ed25519KeyPair, pending: &PendingBlock) -> Result<Vec<u8>, Error> {
+    if key_pair.public_key().as_ref() != pending.signer_public_key() {
+        return Err(match pending.kind() {
+            PendingKind::Accept { .. } => Error::KeyNotInChallenge,
+            PendingKind::Move { .. } => Error::NotYourTurn,
+        });
+    }
+    Ok(sign(key_pair, pending.message()))
 }
 
 pub fn verify(public_key: &[u8], msg: &[u8], sig: &[u8]) -> bool {
-    signature::verify(
-        &signature::ED25519,
-        Input::from(public_key),
-        Input::from(msg),
-        Input::from(sig),
-    )
-    .is_ok()
+    let valid = UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(msg, sig)
+        .is_ok();
+    metrics::signature_checked(valid);
+    valid
+}
+
+/// The SHA-256 digest of a block's encoding, which identifies the block when
+/// a resubmission has to be told apart from a new one.
+pub fn block_hash(block_bytes: &[u8]) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(digest::digest(&digest::SHA256, block_bytes).as_ref());
+    hash
+}
+
+/// Short, human-readable identifier for a public key: the base58 encoding of
+/// the first eight bytes of its SHA-256 digest.
+pub fn fingerprint(public_key: &[u8]) -> String {
+    let digest = digest::digest(&digest::SHA256, public_key);
+    bs58::encode(&digest.as_ref()[..8]).into_string()
 }
@@ -0,0 +1,343 @@
+//! Move deadlines in correspondence games, for the daemon to keep an eye on.
+//! A DeadlineScheduler reads the clock of the player to move in every timed
+//! game from its receipts, reminds them as their time falls below each
+//! threshold, and once they've run out, claims the forfeit if the node plays
+//! the other side. Reminders are signed by the node, when it has a key, so
+//! whoever a webhook passes them on to can tell where they came from. The
+//! signature is over the tag "lineage reminder", the chain's 32-byte
+//! canonical id, the color to move as a byte, and the deadline and remaining
+//! seconds as eight-byte big-endian integers. The short game id isn't
+//! signed, since anyone can start a game under any id.
+
+use crate::block::{GameChain, GameStatus};
+use crate::crypto;
+use crate::identity::Identity;
+use crate::key::PublicKey;
+use crate::manager::GameManager;
+
+use chess::Color;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+const REMINDER_TAG: &[u8] = b"lineage reminder";
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A nudge that the player to move is running short of time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reminder {
+    pub id: u32,
+    pub canonical_id: [u8; 32],
+    pub color: Color,
+    /// When they run out, in seconds since the Unix epoch, if the clock
+    /// keeps running.
+    pub due: u64,
+    pub remaining_seconds: i64,
+    /// The node's key and signature, unless it's watch-only.
+    pub node_public_key: Option<PublicKey>,
+    pub signature: Option<Vec<u8>>,
+}
+
+impl Reminder {
+    pub fn message(&self) -> Vec<u8> {
+        let mut message = REMINDER_TAG.to_vec();
+        message.extend(&self.canonical_id);
+        message.push(match self.color {
+            Color::White => 0,
+            Color::Black => 1,
+        });
+        message.extend(&self.due.to_be_bytes());
+        message.extend(&self.remaining_seconds.to_be_bytes());
+        message
+    }
+
+    /// Whether the reminder is signed by the node it names.
+    pub fn verify(&self) -> bool {
+        match (&self.node_public_key, &self.signature) {
+            (Some(public_key), Some(signature)) => public_key.verify(&self.message(), signature),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeadlineEvent {
+    Reminder(Reminder),
+    /// The player to move ran out of time, but the node didn't claim it.
+    Passed {
+        id: u32,
+        color: Color,
+    },
+    /// The node signed a time forfeit claim against `color`.
+    ClaimedForfeit {
+        id: u32,
+        color: Color,
+    },
+}
+
+fn color_name(color: Color) -> &'static str {
+    match color {
+        Color::White => "white",
+        Color::Black => "black",
+    }
+}
+
+pub fn event_json(event: &DeadlineEvent) -> Value {
+    match event {
+        DeadlineEvent::Reminder(reminder) => json!({
+            "event": "reminder",
+            "id": reminder.id,
+            "canonical_id": bs58::encode(reminder.canonical_id).into_string(),
+            "color": color_name(reminder.color),
+            "due": reminder.due,
+            "remaining_seconds": reminder.remaining_seconds,
+            "node": reminder
+                .node_public_key
+                .as_ref()
+                .map(PublicKey::to_base58),
+            "signature": reminder
+                .signature
+                .as_ref()
+                .map(|signature| bs58::encode(signature).into_string()),
+        }),
+        DeadlineEvent::Passed { id, color } => json!({
+            "event": "deadline_passed",
+            "id": id,
+            "color": color_name(*color),
+        }),
+        DeadlineEvent::ClaimedForfeit { id, color } => json!({
+            "event": "forfeit_claimed",
+            "id": id,
+            "color": color_name(*color),
+        }),
+    }
+}
+
+/// Posts `event` as JSON to `url`, given as http://host:port/path.
+pub fn post_webhook(url: &str, event: &DeadlineEvent) -> io::Result<()> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "webhooks must be http://"))?;
+    let (addr, path) = match rest.find('/') {
+        Some(slash) => (&rest[..slash], &rest[slash..]),
+        None => (rest, "/"),
+    };
+    let body = event_json(event).to_string();
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        addr,
+        body.len(),
+        body
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    match response.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        Some(status) => Err(io::Error::other(format!("webhook answered {}", status))),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not an HTTP response",
+        )),
+    }
+}
+
+/// Tracks deadlines across a GameManager's games. Each reminder threshold
+/// fires once per move, and a deadline passing is reported once.
+#[derive(Default)]
+pub struct DeadlineScheduler {
+    thresholds: Vec<u64>,
+    claim_forfeits: bool,
+    // (game, move count, threshold) for reminders sent, and (game, move
+    // count) for deadlines reported passed
+    reminded: HashSet<(u32, usize, u64)>,
+    passed: HashSet<(u32, usize)>,
+}
+
+impl DeadlineScheduler {
+    pub fn new() -> DeadlineScheduler {
+        DeadlineScheduler::default()
+    }
+
+    /// Reminds the player to move once they have `seconds` or less left.
+    pub fn remind_at(mut self, seconds: u64) -> DeadlineScheduler {
+        self.thresholds.push(seconds);
+        self.thresholds.sort_unstable();
+        self.thresholds.dedup();
+        self
+    }
+
+    /// Signs a time forfeit claim as soon as the opponent runs out, in games
+    /// the node plays.
+    pub fn claim_forfeits(mut self, claim_forfeits: bool) -> DeadlineScheduler {
+        self.claim_forfeits = claim_forfeits;
+        self
+    }
+
+    fn check(
+        &mut self,
+        chain: &mut GameChain,
+        identity: &Identity,
+        now: u64,
+    ) -> Option<DeadlineEvent> {
+        if chain.status() != GameStatus::InProgress {
+            return None;
+        }
+        let reading = chain.clock_at(now).ok()?;
+        let id = chain.id();
+        let move_count = chain.move_count();
+        let color = reading.color;
+        if reading.flagged() {
+            if !self.passed.insert((id, move_count)) {
+                return None;
+            }
+            let claimer = identity
+                .signer()
+                .ok()
+                .filter(|key_pair| chain.side_of(PublicKey::of(key_pair).as_ref()) == Some(!color));
+            if let (true, Some(key_pair)) = (self.claim_forfeits, claimer) {
+                if chain.claim_time_forfeit(key_pair, now).is_ok() {
+                    return Some(DeadlineEvent::ClaimedForfeit { id, color });
+                }
+            }
+            return Some(DeadlineEvent::Passed { id, color });
+        }
+
+        let remaining_seconds = reading.remaining_seconds();
+        let crossed: Vec<u64> = self
+            .thresholds
+            .iter()
+            .cloned()
+            .filter(|&threshold| remaining_seconds <= threshold as i64)
+            .filter(|&threshold| !self.reminded.contains(&(id, move_count, threshold)))
+            .collect();
+        if crossed.is_empty() {
+            return None;
+        }
+        // starting late only sends the tightest reminder
+        for threshold in crossed {
+            self.reminded.insert((id, move_count, threshold));
+        }
+        let mut reminder = Reminder {
+            id,
+            canonical_id: chain.canonical_id(),
+            color,
+            due: now + remaining_seconds as u64,
+            remaining_seconds,
+            node_public_key: None,
+            signature: None,
+        };
+        if let Ok(key_pair) = identity.signer() {
+            reminder.node_public_key = Some(PublicKey::of(key_pair));
+            reminder.signature = Some(crypto::sign(key_pair, &reminder.message()));
+        }
+        Some(DeadlineEvent::Reminder(reminder))
+    }
+
+    /// Checks every game in `manager` at `now`, returning what's due, by
+    /// game.
+    pub fn tick(
+        &mut self,
+        manager: &GameManager,
+        identity: &Identity,
+        now: u64,
+    ) -> Vec<DeadlineEvent> {
+        let mut ids = manager.ids();
+        ids.sort_unstable();
+        ids.into_iter()
+            .filter_map(|id| {
+                manager
+                    .with_chain(id, |chain| self.check(chain, identity, now))
+                    .ok()?
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlockBuilder, GameResult};
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn remind_then_claim() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let day = 24 * 60 * 60;
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .timestamp(0)
+            .time_control(3 * day as u32, 0)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let e4 = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(e4)).unwrap();
+        chain.acknowledge_move(&black, 100).unwrap();
+        let manager = GameManager::new();
+        let id = manager.insert(chain).unwrap();
+
+        let node = Identity::from(white);
+        let mut scheduler = DeadlineScheduler::new()
+            .remind_at(day)
+            .remind_at(60 * 60)
+            .claim_forfeits(true);
+        assert_eq!(scheduler.tick(&manager, &node, 100 + day), Vec::new());
+
+        let events = scheduler.tick(&manager, &node, 100 + 2 * day);
+        let reminder = match &events[..] {
+            [DeadlineEvent::Reminder(reminder)] => reminder.clone(),
+            _ => panic!("expected a reminder, got {:?}", events),
+        };
+        assert_eq!(reminder.color, Color::Black);
+        assert_eq!(reminder.due, 100 + 3 * day);
+        assert!(reminder.verify());
+        let mut other_game = reminder.clone();
+        other_game.canonical_id[0] ^= 1;
+        assert!(!other_game.verify());
+        assert_eq!(
+            scheduler.tick(&manager, &node, 100 + 2 * day + 1),
+            Vec::new()
+        );
+
+        // a watch-only mirror reports the deadline but can't claim it
+        let mut mirror = DeadlineScheduler::new().claim_forfeits(true);
+        let late = 101 + 3 * day;
+        assert_eq!(
+            mirror.tick(&manager, &Identity::WatchOnly, late),
+            vec![DeadlineEvent::Passed {
+                id,
+                color: Color::Black
+            }]
+        );
+        assert_eq!(
+            mirror.tick(&manager, &Identity::WatchOnly, late),
+            Vec::new()
+        );
+
+        assert_eq!(
+            scheduler.tick(&manager, &node, late),
+            vec![DeadlineEvent::ClaimedForfeit {
+                id,
+                color: Color::Black
+            }]
+        );
+        let chain = manager.get(id).unwrap();
+        assert_eq!(
+            chain.status(),
+            GameStatus::Finished(GameResult::Win(Color::White))
+        );
+        assert!(chain.verify());
+    }
+}
@@ -0,0 +1,148 @@
+//! Comparing two copies of a game, to see why they won't merge or what one
+//! peer has that the other lacks. Blocks are indexed in encoded order, as in
+//! GameChain::merge: 0 is the challenge, 1 and 2 are the accepts, and moves
+//! and extensions follow from 3.
+
+use crate::block::{AcceptBlock, ChallengeBlock, GameChain};
+
+use alloc::vec::Vec;
+use chess::Color;
+
+/// Where two chains differ, from the point of view of the first.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChainDiff {
+    /// The chains are for different games; nothing else is compared.
+    pub different_challenge: bool,
+    /// Players whose accept only the other chain has.
+    pub missing_accepts: Vec<Color>,
+    /// Players whose accept only this chain has.
+    pub extra_accepts: Vec<Color>,
+    /// The first block both chains have but disagree on.
+    pub conflict: Option<usize>,
+    /// Blocks after the last shared block that only the other chain has.
+    pub missing_blocks: usize,
+    /// Blocks after the last shared block that only this chain has.
+    pub extra_blocks: usize,
+}
+
+impl ChainDiff {
+    /// Whether the chains hold the same game blocks. Commentary isn't
+    /// compared.
+    pub fn is_empty(&self) -> bool {
+        self == &ChainDiff::default()
+    }
+
+    /// Whether the chains can be merged: one holds everything the other
+    /// does.
+    pub fn is_prefix(&self) -> bool {
+        !self.different_challenge
+            && self.conflict.is_none()
+            && ((self.missing_accepts.is_empty() && self.missing_blocks == 0)
+                || (self.extra_accepts.is_empty() && self.extra_blocks == 0))
+    }
+}
+
+fn accepted(challenge: &ChallengeBlock, accepts: &[Option<AcceptBlock>; 2]) -> Vec<Color> {
+    let challenge_bytes = challenge.as_bytes();
+    accepts
+        .iter()
+        .flatten()
+        .filter_map(|accept| accept.signer(challenge, &challenge_bytes))
+        .collect()
+}
+
+impl GameChain {
+    /// Describes where this chain and `other` diverge.
+    pub fn diff(&self, other: &GameChain) -> ChainDiff {
+        if self.challenge() != other.challenge() {
+            return ChainDiff {
+                different_challenge: true,
+                ..ChainDiff::default()
+            };
+        }
+        let mut diff = ChainDiff::default();
+        let ours = accepted(self.challenge(), self.accepts());
+        let theirs = accepted(other.challenge(), other.accepts());
+        diff.missing_accepts = theirs
+            .iter()
+            .filter(|c| !ours.contains(c))
+            .cloned()
+            .collect();
+        diff.extra_accepts = ours
+            .iter()
+            .filter(|c| !theirs.contains(c))
+            .cloned()
+            .collect();
+        for (i, pair) in self.accepts().iter().zip(other.accepts()).enumerate() {
+            if let (Some(ours), Some(theirs)) = pair {
+                if ours != theirs && diff.conflict.is_none() {
+                    diff.conflict = Some(1 + i);
+                }
+            }
+        }
+
+        let (ours, theirs) = (self.entries(), other.entries());
+        let shared = ours
+            .iter()
+            .zip(&theirs)
+            .take_while(|(ours, theirs)| ours == theirs)
+            .count();
+        if shared < ours.len().min(theirs.len()) && diff.conflict.is_none() {
+            diff.conflict = Some(3 + shared);
+        }
+        diff.missing_blocks = theirs.len() - shared;
+        diff.extra_blocks = ours.len() - shared;
+        diff
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+    use ring::signature::Ed25519KeyPair;
+
+    #[test]
+    fn diverging_chains() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        let behind = chain.clone();
+        chain.accept(&black).unwrap();
+        let play = |chain: &mut GameChain, key_pair: &Ed25519KeyPair, uci: &str| {
+            let mv = notation::parse_uci(uci).unwrap();
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        };
+        play(&mut chain, &white, "e2e4");
+
+        assert!(chain.diff(&chain).is_empty());
+        let diff = behind.diff(&chain);
+        assert_eq!(diff.missing_accepts, vec![Color::Black]);
+        assert_eq!(diff.missing_blocks, 1);
+        assert!(diff.is_prefix());
+        assert_eq!(chain.diff(&behind).extra_blocks, 1);
+
+        let mut fork = chain.clone();
+        play(&mut chain, &black, "e7e5");
+        play(&mut fork, &black, "c7c5");
+        play(&mut fork, &white, "g1f3");
+        let diff = chain.diff(&fork);
+        assert_eq!(diff.conflict, Some(4));
+        assert_eq!((diff.extra_blocks, diff.missing_blocks), (1, 2));
+        assert!(!diff.is_prefix());
+
+        let other = GameChain::new(ChallengeBlock::new(
+            &PublicKey::of(&black),
+            &PublicKey::of(&white),
+        ));
+        assert!(chain.diff(&other).different_challenge);
+    }
+}
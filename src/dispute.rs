@@ -0,0 +1,219 @@
+//! Result disputes. Once a game has a result, either player can sign a
+//! dispute with their color and a reason, which holds the result back until
+//! the arbiter named in the challenge signs a resolution. A game without an
+//! arbiter stays disputed.
+
+use crate::block::{ChainEntry, GameChain, GameResult};
+use crate::error::Error;
+use crate::extension::ExtensionKind;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use chess::Color;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dispute {
+    pub disputed_by: Color,
+    pub reason: String,
+    /// The arbiter's result, once they've signed one.
+    pub resolution: Option<GameResult>,
+}
+
+fn malformed(block: &'static str, reason: &'static str) -> Error {
+    Error::Malformed { block, reason }
+}
+
+/// The player who signed a dispute with the given body.
+pub(crate) fn disputer(body: &[u8]) -> Option<Color> {
+    match body.first() {
+        Some(0) => Some(Color::White),
+        Some(1) => Some(Color::Black),
+        _ => None,
+    }
+}
+
+fn reason(body: &[u8]) -> Result<String, Error> {
+    String::from_utf8(body.get(1..).unwrap_or_default().to_vec())
+        .map_err(|_| malformed("dispute", "reason is not UTF-8"))
+}
+
+fn resolution(body: &[u8]) -> Result<GameResult, Error> {
+    match body {
+        [0] => Ok(GameResult::Win(Color::White)),
+        [1] => Ok(GameResult::Win(Color::Black)),
+        [2] => Ok(GameResult::Draw),
+        _ => Err(malformed("resolution", "unknown result")),
+    }
+}
+
+/// Whether a block of this kind may follow a result.
+pub(crate) fn follows_result(kind: ExtensionKind) -> bool {
+    matches!(kind, ExtensionKind::Dispute | ExtensionKind::Resolution)
+}
+
+pub(crate) fn check_extension(
+    chain: &GameChain,
+    kind: ExtensionKind,
+    body: &[u8],
+) -> Result<(), Error> {
+    match (kind, chain.dispute()) {
+        (ExtensionKind::Dispute, None) => {
+            reason(body)?;
+            if chain.claimed_result().is_none() {
+                return Err(malformed("dispute", "the game has no result"));
+            }
+            Ok(())
+        }
+        (ExtensionKind::Dispute, Some(_)) => Err(malformed("dispute", "already disputed")),
+        (
+            ExtensionKind::Resolution,
+            Some(Dispute {
+                resolution: None, ..
+            }),
+        ) => resolution(body).map(|_| ()),
+        (_, _) => Err(malformed("resolution", "no open dispute")),
+    }
+}
+
+/// Checks that a dispute follows a result and that nothing but a single
+/// resolution follows the dispute.
+pub(crate) fn check_chain(chain: &GameChain) -> Result<(), Error> {
+    let entries = chain.entries();
+    let start = match entries.iter().position(|entry| match entry {
+        ChainEntry::Extension(block) => follows_result(block.kind()),
+        _ => false,
+    }) {
+        Some(start) => start,
+        None => return Ok(()),
+    };
+    let kinds: Vec<_> = entries[start..]
+        .iter()
+        .map(|entry| match entry {
+            ChainEntry::Extension(block) => Some(block.kind()),
+            _ => None,
+        })
+        .collect();
+    match kinds.as_slice() {
+        [Some(ExtensionKind::Dispute)]
+        | [Some(ExtensionKind::Dispute), Some(ExtensionKind::Resolution)] => {}
+        _ => return Err(malformed("dispute", "out of order")),
+    }
+    if chain.claimed_result().is_none() {
+        return Err(malformed("dispute", "the game has no result"));
+    }
+    chain
+        .dispute()
+        .map(|_| ())
+        .ok_or_else(|| malformed("dispute", "wrong body"))
+}
+
+impl GameChain {
+    /// Contests the game's result, signed by either player.
+    pub fn dispute_result(&mut self, key_pair: &Ed25519KeyPair, reason: &str) -> Result<(), Error> {
+        let color = self
+            .side_of(key_pair.public_key().as_ref())
+            .ok_or(Error::KeyNotInChallenge)?;
+        let mut body = Vec::with_capacity(1 + reason.len());
+        body.push(if color == Color::White { 0 } else { 1 });
+        body.extend(reason.as_bytes());
+        self.sign_extension(key_pair, ExtensionKind::Dispute, body)
+    }
+
+    /// Settles an open dispute with `result`, signed by the game's arbiter.
+    pub fn resolve_dispute(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        result: GameResult,
+    ) -> Result<(), Error> {
+        if self.challenge().arbiter_public_key().is_none() {
+            return Err(malformed("resolution", "the game has no arbiter"));
+        }
+        let body = match result {
+            GameResult::Win(Color::White) => 0,
+            GameResult::Win(Color::Black) => 1,
+            GameResult::Draw => 2,
+        };
+        self.sign_extension(key_pair, ExtensionKind::Resolution, [body].to_vec())
+    }
+
+    /// The game's dispute, if a player contested the result.
+    pub fn dispute(&self) -> Option<Dispute> {
+        let mut dispute = None;
+        for entry in self.entries() {
+            if let ChainEntry::Extension(block) = entry {
+                match block.kind() {
+                    ExtensionKind::Dispute => {
+                        dispute = Some(Dispute {
+                            disputed_by: disputer(block.body())?,
+                            reason: reason(block.body()).ok()?,
+                            resolution: None,
+                        })
+                    }
+                    ExtensionKind::Resolution => {
+                        dispute.as_mut()?.resolution = resolution(block.body()).ok()
+                    }
+                    _ => {}
+                }
+            }
+        }
+        dispute
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlockBuilder, GameStatus};
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn arbiter_resolves_dispute() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let arbiter = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .timestamp(1_000)
+            .time_control(60, 0)
+            .arbiter(&PublicKey::of(&arbiter))
+            .build()
+            .unwrap();
+        assert_eq!(challenge.version(), 2);
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let mv = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(mv)).unwrap();
+        assert!(chain.dispute_result(&black, "the game isn't over").is_err());
+
+        chain.acknowledge_move(&black, 1_000).unwrap();
+        chain.claim_time_forfeit(&white, 1_100).unwrap();
+        chain
+            .dispute_result(&black, "my connection dropped")
+            .unwrap();
+        assert_eq!(chain.status(), GameStatus::Disputed);
+        assert_eq!(chain.result(), None);
+        assert!(chain.dispute_result(&white, "again").is_err());
+        assert_eq!(
+            chain.resolve_dispute(&white, GameResult::Draw),
+            Err(Error::NotYourTurn)
+        );
+
+        chain.resolve_dispute(&arbiter, GameResult::Draw).unwrap();
+        assert_eq!(
+            chain.dispute(),
+            Some(Dispute {
+                disputed_by: Color::Black,
+                reason: "my connection dropped".into(),
+                resolution: Some(GameResult::Draw),
+            })
+        );
+        assert_eq!(chain.status(), GameStatus::Finished(GameResult::Draw));
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain));
+    }
+}
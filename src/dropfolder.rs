@@ -0,0 +1,223 @@
+//! A transport through a shared folder, such as one synced by Dropbox or
+//! Syncthing or carried on a USB stick. Both players sync their local store
+//! with the folder: chains found there are merged in, and local chains that
+//! are ahead are written back out.
+
+use crate::block::GameChain;
+use crate::message::Message;
+use crate::store::GameStore;
+use crate::transport::Channel;
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// What one sync did.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SyncReport {
+    /// Games the local store picked up from the folder.
+    pub imported: usize,
+    /// Games written to the folder.
+    pub exported: usize,
+    /// Folder files that couldn't be merged: unreadable, half-synced, or
+    /// forked from the local copy. These are left alone.
+    pub skipped: Vec<PathBuf>,
+}
+
+pub struct DropFolder {
+    dir: PathBuf,
+}
+
+impl DropFolder {
+    /// Uses `dir` as the shared folder, creating it if needed.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<DropFolder> {
+        fs::create_dir_all(&dir)?;
+        Ok(DropFolder {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    fn chain_path(&self, id: u32) -> PathBuf {
+        self.dir.join(format!("{:08x}.chain", id))
+    }
+
+    fn chain_paths(&self) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|found| found == "chain") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Writes `chain` to the folder. It's written under a temporary name
+    /// first so that the other side never syncs a partly written chain.
+    pub fn write(&self, chain: &GameChain) -> io::Result<PathBuf> {
+        let path = self.chain_path(chain.id());
+        let partial = path.with_extension("partial");
+        fs::write(&partial, chain.as_bytes())?;
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+
+    /// Merges every chain in the folder into `store`, then writes each
+    /// stored chain the folder doesn't have yet.
+    pub fn sync(&self, store: &GameStore) -> io::Result<SyncReport> {
+        let mut report = SyncReport::default();
+        for path in self.chain_paths()? {
+            let merged = fs::read(&path).ok().and_then(|bytes| {
+                let chain = GameChain::from_bytes(&bytes).ok()?;
+                store.merge(&chain).ok()
+            });
+            match merged {
+                Some(true) => report.imported += 1,
+                Some(false) => {}
+                None => report.skipped.push(path),
+            }
+        }
+
+        for chain in store.load_all()? {
+            let path = self.chain_path(chain.id());
+            if report.skipped.contains(&path) {
+                continue;
+            }
+            if fs::read(&path).ok() == Some(chain.as_bytes()) {
+                continue;
+            }
+            self.write(&chain)?;
+            report.exported += 1;
+        }
+        Ok(report)
+    }
+
+    /// Syncs every `interval`, calling `on_change` after each sync that
+    /// imported or exported a game. Only returns on an error.
+    pub fn watch<F>(
+        &self,
+        store: &GameStore,
+        interval: Duration,
+        mut on_change: F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(&SyncReport),
+    {
+        loop {
+            let report = self.sync(store)?;
+            if report.imported > 0 || report.exported > 0 {
+                on_change(&report);
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+/// A channel through the folder. Like mail, the folder only carries
+/// chains, so other messages are dropped. Received chains are the
+/// folder's chain files that changed since they were last seen.
+pub struct FolderChannel {
+    folder: DropFolder,
+    interval: Duration,
+    seen: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl FolderChannel {
+    /// A channel checking the folder every five seconds. Chains already in
+    /// the folder count as seen.
+    pub fn new(folder: DropFolder) -> io::Result<FolderChannel> {
+        let mut seen = HashMap::new();
+        for path in folder.chain_paths()? {
+            let bytes = fs::read(&path)?;
+            seen.insert(path, bytes);
+        }
+        Ok(FolderChannel {
+            folder,
+            interval: Duration::from_secs(5),
+            seen,
+        })
+    }
+
+    pub fn interval(mut self, interval: Duration) -> FolderChannel {
+        self.interval = interval;
+        self
+    }
+}
+
+impl Channel for FolderChannel {
+    fn send_message(&mut self, message: &Message) -> io::Result<()> {
+        if let Message::Chain(chain) = message {
+            let path = self.folder.write(chain)?;
+            self.seen.insert(path, chain.as_bytes());
+        }
+        Ok(())
+    }
+
+    fn recv_message(&mut self) -> io::Result<Message> {
+        loop {
+            for path in self.folder.chain_paths()? {
+                let bytes = match fs::read(&path) {
+                    Ok(bytes) => bytes,
+                    Err(_) => continue,
+                };
+                if self.seen.get(&path) == Some(&bytes) {
+                    continue;
+                }
+                let chain = GameChain::from_bytes(&bytes);
+                self.seen.insert(path, bytes);
+                if let Ok(chain) = chain {
+                    return Ok(Message::Chain(Box::new(chain)));
+                }
+            }
+            thread::sleep(self.interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn sync_through_folder() {
+        let root = std::env::temp_dir().join(format!("lineage-drop-{}", std::process::id()));
+        let folder = DropFolder::open(root.join("shared")).unwrap();
+        let white_store = GameStore::open(root.join("white")).unwrap();
+        let black_store = GameStore::open(root.join("black")).unwrap();
+
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        white_store.save(&chain).unwrap();
+
+        let report = folder.sync(&white_store).unwrap();
+        assert_eq!((report.imported, report.exported), (0, 1));
+        let report = folder.sync(&black_store).unwrap();
+        assert_eq!((report.imported, report.exported), (1, 0));
+
+        let mv = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(mv)).unwrap();
+        white_store.save(&chain).unwrap();
+        folder.sync(&white_store).unwrap();
+        fs::write(root.join("shared").join("junk.chain"), b"junk").unwrap();
+        let report = folder.sync(&black_store).unwrap();
+        assert_eq!(report.imported, 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(black_store.load(chain.id()).unwrap(), chain);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
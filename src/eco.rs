@@ -0,0 +1,225 @@
+//! Opening classification using the Encyclopaedia of Chess Openings codes.
+
+use crate::block::GameChain;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use chess::Board;
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Opening {
+    pub code: &'static str,
+    pub name: &'static str,
+}
+
+impl fmt::Display for Opening {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code, self.name)
+    }
+}
+
+// Each line's moves are in UCI notation. The longest line that the game
+// starts with wins, so more specific lines don't need to come first.
+const TABLE: &[(&str, &str, &str)] = &[
+    ("A00", "Polish Opening", "b2b4"),
+    ("A00", "Grob Opening", "g2g4"),
+    ("A01", "Nimzo-Larsen Attack", "b2b3"),
+    ("A02", "Bird's Opening", "f2f4"),
+    ("A04", "Reti Opening", "g1f3"),
+    ("A10", "English Opening", "c2c4"),
+    ("A20", "English Opening, King's English", "c2c4 e7e5"),
+    ("A40", "Queen's Pawn Game", "d2d4"),
+    ("A45", "Indian Defence", "d2d4 g8f6"),
+    ("A50", "Indian Defence", "d2d4 g8f6 c2c4"),
+    ("A56", "Benoni Defence", "d2d4 g8f6 c2c4 c7c5"),
+    ("A57", "Benko Gambit", "d2d4 g8f6 c2c4 c7c5 d4d5 b7b5"),
+    ("A80", "Dutch Defence", "d2d4 f7f5"),
+    ("B00", "King's Pawn Opening", "e2e4"),
+    ("B01", "Scandinavian Defence", "e2e4 d7d5"),
+    ("B02", "Alekhine's Defence", "e2e4 g8f6"),
+    ("B06", "Modern Defence", "e2e4 g7g6"),
+    ("B07", "Pirc Defence", "e2e4 d7d6 d2d4 g8f6"),
+    ("B10", "Caro-Kann Defence", "e2e4 c7c6"),
+    (
+        "B12",
+        "Caro-Kann Defence, Advance Variation",
+        "e2e4 c7c6 d2d4 d7d5 e4e5",
+    ),
+    ("B20", "Sicilian Defence", "e2e4 c7c5"),
+    (
+        "B21",
+        "Sicilian Defence, Smith-Morra Gambit",
+        "e2e4 c7c5 d2d4 c5d4 c2c3",
+    ),
+    (
+        "B22",
+        "Sicilian Defence, Alapin Variation",
+        "e2e4 c7c5 c2c3",
+    ),
+    ("B23", "Sicilian Defence, Closed", "e2e4 c7c5 b1c3"),
+    ("B30", "Sicilian Defence", "e2e4 c7c5 g1f3 b8c6"),
+    ("B40", "Sicilian Defence", "e2e4 c7c5 g1f3 e7e6"),
+    ("B50", "Sicilian Defence", "e2e4 c7c5 g1f3 d7d6"),
+    (
+        "B70",
+        "Sicilian Defence, Dragon Variation",
+        "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 g7g6",
+    ),
+    (
+        "B90",
+        "Sicilian Defence, Najdorf Variation",
+        "e2e4 c7c5 g1f3 d7d6 d2d4 c5d4 f3d4 g8f6 b1c3 a7a6",
+    ),
+    ("C00", "French Defence", "e2e4 e7e6"),
+    (
+        "C01",
+        "French Defence, Exchange Variation",
+        "e2e4 e7e6 d2d4 d7d5 e4d5",
+    ),
+    (
+        "C02",
+        "French Defence, Advance Variation",
+        "e2e4 e7e6 d2d4 d7d5 e4e5",
+    ),
+    (
+        "C03",
+        "French Defence, Tarrasch Variation",
+        "e2e4 e7e6 d2d4 d7d5 b1d2",
+    ),
+    (
+        "C10",
+        "French Defence, Paulsen Variation",
+        "e2e4 e7e6 d2d4 d7d5 b1c3",
+    ),
+    ("C20", "King's Pawn Game", "e2e4 e7e5"),
+    ("C21", "Centre Game", "e2e4 e7e5 d2d4 e5d4"),
+    ("C23", "Bishop's Opening", "e2e4 e7e5 f1c4"),
+    ("C25", "Vienna Game", "e2e4 e7e5 b1c3"),
+    ("C30", "King's Gambit", "e2e4 e7e5 f2f4"),
+    ("C33", "King's Gambit Accepted", "e2e4 e7e5 f2f4 e5f4"),
+    ("C40", "King's Knight Opening", "e2e4 e7e5 g1f3"),
+    ("C41", "Philidor Defence", "e2e4 e7e5 g1f3 d7d6"),
+    ("C42", "Petrov's Defence", "e2e4 e7e5 g1f3 g8f6"),
+    ("C44", "King's Pawn Game", "e2e4 e7e5 g1f3 b8c6"),
+    ("C45", "Scotch Game", "e2e4 e7e5 g1f3 b8c6 d2d4"),
+    ("C46", "Three Knights Opening", "e2e4 e7e5 g1f3 b8c6 b1c3"),
+    ("C47", "Four Knights Game", "e2e4 e7e5 g1f3 b8c6 b1c3 g8f6"),
+    ("C50", "Italian Game", "e2e4 e7e5 g1f3 b8c6 f1c4"),
+    ("C50", "Giuoco Piano", "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5"),
+    ("C51", "Evans Gambit", "e2e4 e7e5 g1f3 b8c6 f1c4 f8c5 b2b4"),
+    (
+        "C55",
+        "Two Knights Defence",
+        "e2e4 e7e5 g1f3 b8c6 f1c4 g8f6",
+    ),
+    ("C60", "Ruy Lopez", "e2e4 e7e5 g1f3 b8c6 f1b5"),
+    (
+        "C65",
+        "Ruy Lopez, Berlin Defence",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 g8f6",
+    ),
+    (
+        "C68",
+        "Ruy Lopez, Exchange Variation",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5c6",
+    ),
+    (
+        "C70",
+        "Ruy Lopez, Morphy Defence",
+        "e2e4 e7e5 g1f3 b8c6 f1b5 a7a6 b5a4",
+    ),
+    ("D00", "Queen's Pawn Game", "d2d4 d7d5"),
+    ("D06", "Queen's Gambit", "d2d4 d7d5 c2c4"),
+    ("D10", "Slav Defence", "d2d4 d7d5 c2c4 c7c6"),
+    ("D20", "Queen's Gambit Accepted", "d2d4 d7d5 c2c4 d5c4"),
+    ("D30", "Queen's Gambit Declined", "d2d4 d7d5 c2c4 e7e6"),
+    ("D80", "Grunfeld Defence", "d2d4 g8f6 c2c4 g7g6 b1c3 d7d5"),
+    ("E00", "Catalan Opening", "d2d4 g8f6 c2c4 e7e6 g2g3"),
+    (
+        "E12",
+        "Queen's Indian Defence",
+        "d2d4 g8f6 c2c4 e7e6 g1f3 b7b6",
+    ),
+    (
+        "E20",
+        "Nimzo-Indian Defence",
+        "d2d4 g8f6 c2c4 e7e6 b1c3 f8b4",
+    ),
+    ("E60", "King's Indian Defence", "d2d4 g8f6 c2c4 g7g6"),
+];
+
+/// The opening for a game that began with `moves`, given in UCI notation
+/// from the standard starting position.
+pub fn classify(moves: &[String]) -> Option<Opening> {
+    TABLE
+        .iter()
+        .filter_map(|&(code, name, line)| {
+            let len = line.split(' ').count();
+            if len <= moves.len() && line.split(' ').eq(moves[..len].iter().map(String::as_str)) {
+                Some((len, Opening { code, name }))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, opening)| opening)
+}
+
+impl GameChain {
+    /// The opening played, if the game started from the standard position
+    /// and its moves are legal.
+    pub fn opening(&self) -> Option<Opening> {
+        if self.challenge().starting_board() != Board::default() || self.get_game().is_err() {
+            return None;
+        }
+        let moves: Vec<String> = self
+            .iter_positions()
+            .map(|(_, mv, _, _)| mv.to_string())
+            .collect();
+        classify(&moves)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn classify_openings() {
+        let line = |moves: &str| -> Vec<String> { moves.split(' ').map(String::from).collect() };
+        assert_eq!(
+            classify(&line("e2e4 e7e5 g1f3 b8c6 f1b5 g8f6 e1g1"))
+                .unwrap()
+                .to_string(),
+            "C65 Ruy Lopez, Berlin Defence"
+        );
+        assert_eq!(
+            classify(&line("e2e4 e7e5 g1f3 b8c6 f1c4")).unwrap().code,
+            "C50"
+        );
+        assert_eq!(classify(&line("h2h3")), None);
+
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        assert_eq!(chain.opening(), None);
+        for (i, mv) in ["d2d4", "g8f6", "c2c4", "g7g6"].iter().enumerate() {
+            let key_pair = if i % 2 == 0 { &white } else { &black };
+            let mv = notation::parse_uci(mv).unwrap();
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        }
+        assert_eq!(chain.opening().unwrap().name, "King's Indian Defence");
+    }
+}
@@ -0,0 +1,368 @@
+//! Correspondence play over email. Outgoing chains are sent over SMTP as
+//! armored attachments, and incoming mail is fetched over IMAP, with any
+//! chains found merged into a local store.
+//!
+//! Only plain TCP is spoken; servers that require TLS should be reached
+//! through a local tunnel such as stunnel.
+
+use crate::block::GameChain;
+use crate::message::Message;
+use crate::store::GameStore;
+use crate::transport::Channel;
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+const ARMOR_BEGIN: &str = "-----BEGIN LINEAGE CHAIN-----";
+const ARMOR_END: &str = "-----END LINEAGE CHAIN-----";
+const ARMOR_WIDTH: usize = 64;
+const BOUNDARY: &str = "lineage-chain-boundary";
+
+/// Where and how to send and receive mail.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmailConfig {
+    /// The player's own address, used as the sender.
+    pub address: String,
+    /// SMTP server as host:port.
+    pub smtp: String,
+    /// IMAP server as host:port.
+    pub imap: String,
+    pub username: String,
+    pub password: String,
+}
+
+/// A chain as ASCII text that survives being pasted into mail.
+pub fn armor(chain: &GameChain) -> String {
+    let encoded = bs58::encode(chain.as_bytes()).into_string();
+    let mut text = String::from(ARMOR_BEGIN);
+    text.push_str("\r\n");
+    // base58 is ASCII, so chunks split on character boundaries
+    for line in encoded.as_bytes().chunks(ARMOR_WIDTH) {
+        text.push_str(std::str::from_utf8(line).unwrap());
+        text.push_str("\r\n");
+    }
+    text.push_str(ARMOR_END);
+    text.push_str("\r\n");
+    text
+}
+
+/// The bytes of every armored chain in `text`, in order, including chains
+/// quoted in replies. Armor blocks that aren't valid base58 are skipped.
+pub fn dearmor(text: &str) -> Vec<Vec<u8>> {
+    let mut chains = Vec::new();
+    let mut current: Option<String> = None;
+    for line in text.lines() {
+        let line = line.trim_start_matches(['>', ' ']).trim();
+        match current.take() {
+            None if line == ARMOR_BEGIN => current = Some(String::new()),
+            None => {}
+            Some(encoded) if line == ARMOR_END => {
+                if let Ok(bytes) = bs58::decode(encoded).into_vec() {
+                    chains.push(bytes);
+                }
+            }
+            Some(mut encoded) => {
+                encoded.push_str(line);
+                current = Some(encoded);
+            }
+        }
+    }
+    chains
+}
+
+/// An RFC 5322 message from `from` to `to` carrying `chain` as an
+/// attachment.
+pub fn compose(from: &str, to: &str, chain: &GameChain) -> String {
+    let subject = format!("lineage game {:08x}", chain.id());
+    format!(
+        "From: {from}\r\n\
+         To: {to}\r\n\
+         Subject: {subject}\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/mixed; boundary=\"{boundary}\"\r\n\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         \r\n\
+         {summary}\r\n\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: application/x-lineage-chain\r\n\
+         Content-Transfer-Encoding: 7bit\r\n\
+         Content-Disposition: attachment; filename=\"{id:08x}.chain.asc\"\r\n\
+         \r\n\
+         {armor}\
+         --{boundary}--\r\n",
+        from = from,
+        to = to,
+        subject = subject,
+        boundary = BOUNDARY,
+        summary = chain,
+        id = chain.id(),
+        armor = armor(chain),
+    )
+}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                text.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+struct Smtp {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Smtp {
+    /// Reads a possibly multiline reply, failing unless its code starts
+    /// with `expected`.
+    fn reply(&mut self, expected: char) -> io::Result<()> {
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if line.len() < 4 || !line.starts_with(expected) {
+                return Err(protocol_error(format!("SMTP: {}", line.trim_end())));
+            }
+            if line.as_bytes()[3] == b' ' {
+                return Ok(());
+            }
+        }
+    }
+
+    fn command(&mut self, command: &str, expected: char) -> io::Result<()> {
+        write!(self.writer, "{}\r\n", command)?;
+        self.reply(expected)
+    }
+}
+
+/// Sends `chain` to `to` over SMTP.
+pub fn send(config: &EmailConfig, to: &str, chain: &GameChain) -> io::Result<()> {
+    let stream = TcpStream::connect(&config.smtp)?;
+    let mut smtp = Smtp {
+        reader: BufReader::new(stream.try_clone()?),
+        writer: stream,
+    };
+    smtp.reply('2')?;
+    smtp.command("EHLO lineage", '2')?;
+    if !config.username.is_empty() {
+        let credentials = format!("\0{}\0{}", config.username, config.password);
+        smtp.command(
+            &format!("AUTH PLAIN {}", base64(credentials.as_bytes())),
+            '2',
+        )?;
+    }
+    smtp.command(&format!("MAIL FROM:<{}>", config.address), '2')?;
+    smtp.command(&format!("RCPT TO:<{}>", to), '2')?;
+    smtp.command("DATA", '3')?;
+    for line in compose(&config.address, to, chain).lines() {
+        // lines starting with a dot are escaped by doubling it
+        let stuffed = if line.starts_with('.') { "." } else { "" };
+        write!(smtp.writer, "{}{}\r\n", stuffed, line)?;
+    }
+    smtp.command(".", '2')?;
+    smtp.command("QUIT", '2')
+}
+
+struct Imap {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    tag: u32,
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+impl Imap {
+    /// Sends a command and returns its untagged response data, with any
+    /// literals inlined, failing unless the command completes OK.
+    fn command(&mut self, command: &str) -> io::Result<Vec<u8>> {
+        self.tag += 1;
+        let tag = format!("a{} ", self.tag);
+        write!(self.writer, "{}{}\r\n", tag, command)?;
+        let mut data = Vec::new();
+        loop {
+            let mut line = Vec::new();
+            if self.reader.read_until(b'\n', &mut line)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if line.starts_with(tag.as_bytes()) {
+                let status = String::from_utf8_lossy(&line[tag.len()..]);
+                if status.starts_with("OK") {
+                    return Ok(data);
+                }
+                return Err(protocol_error(format!("IMAP: {}", status.trim_end())));
+            }
+            let literal_len = literal_len(&line);
+            data.extend(line);
+            if let Some(len) = literal_len {
+                let start = data.len();
+                data.resize(start + len, 0);
+                self.reader.read_exact(&mut data[start..])?;
+            }
+        }
+    }
+}
+
+/// The length of the literal announced at the end of `line`, as in
+/// `* 1 FETCH (BODY[] {512}`.
+fn literal_len(line: &[u8]) -> Option<usize> {
+    let line = std::str::from_utf8(line).ok()?.trim_end();
+    let open = line.rfind('{')?;
+    line[open + 1..].strip_suffix('}')?.parse().ok()
+}
+
+/// Fetches unread mail over IMAP, returning every verified chain found and
+/// marking the messages read.
+pub fn fetch(config: &EmailConfig) -> io::Result<Vec<GameChain>> {
+    let stream = TcpStream::connect(&config.imap)?;
+    let mut imap = Imap {
+        reader: BufReader::new(stream.try_clone()?),
+        writer: stream,
+        tag: 0,
+    };
+    let mut greeting = String::new();
+    imap.reader.read_line(&mut greeting)?;
+    if !greeting.starts_with("* OK") {
+        return Err(protocol_error(format!("IMAP: {}", greeting.trim_end())));
+    }
+    imap.command(&format!(
+        "LOGIN {} {}",
+        quote(&config.username),
+        quote(&config.password)
+    ))?;
+    imap.command("SELECT INBOX")?;
+    let search = String::from_utf8_lossy(&imap.command("SEARCH UNSEEN")?).into_owned();
+    let messages: Vec<u32> = search
+        .lines()
+        .filter_map(|line| line.strip_prefix("* SEARCH"))
+        .flat_map(str::split_whitespace)
+        .filter_map(|number| number.parse().ok())
+        .collect();
+
+    let mut chains = Vec::new();
+    for number in messages {
+        let message = imap.command(&format!("FETCH {} BODY[]", number))?;
+        // mail can carry anything, so chains that don't verify are ignored
+        chains.extend(
+            dearmor(&String::from_utf8_lossy(&message))
+                .iter()
+                .filter_map(|bytes| GameChain::from_bytes(bytes).ok()),
+        );
+        imap.command(&format!("STORE {} +FLAGS (\\Seen)", number))?;
+    }
+    imap.command("LOGOUT")?;
+    Ok(chains)
+}
+
+/// Fetches unread mail and merges every chain found into `store`. Returns
+/// the number of games that changed; chains that don't fit the stored game
+/// are ignored.
+pub fn poll(config: &EmailConfig, store: &GameStore) -> io::Result<usize> {
+    Ok(fetch(config)?
+        .iter()
+        .filter(|chain| store.merge(chain).unwrap_or(false))
+        .count())
+}
+
+/// A channel to one opponent by email. Mail only carries chains: other
+/// messages are dropped, since signed chains identify themselves.
+pub struct MailChannel {
+    config: EmailConfig,
+    to: String,
+    interval: Duration,
+    inbox: VecDeque<GameChain>,
+}
+
+impl MailChannel {
+    /// A channel sending to `to` and checking for mail every minute.
+    pub fn new(config: EmailConfig, to: &str) -> MailChannel {
+        MailChannel {
+            config,
+            to: to.into(),
+            interval: Duration::from_secs(60),
+            inbox: VecDeque::new(),
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> MailChannel {
+        self.interval = interval;
+        self
+    }
+}
+
+impl Channel for MailChannel {
+    fn send_message(&mut self, message: &Message) -> io::Result<()> {
+        match message {
+            Message::Chain(chain) => send(&self.config, &self.to, chain),
+            _ => Ok(()),
+        }
+    }
+
+    fn recv_message(&mut self) -> io::Result<Message> {
+        loop {
+            if let Some(chain) = self.inbox.pop_front() {
+                return Ok(Message::Chain(Box::new(chain)));
+            }
+            self.inbox.extend(fetch(&self.config)?);
+            if self.inbox.is_empty() {
+                thread::sleep(self.interval);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+
+    #[test]
+    fn armored_mail() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+
+        let armored = armor(&chain);
+        assert!(armored.lines().all(|line| line.len() <= ARMOR_WIDTH));
+        let message = compose("white@example.org", "black@example.org", &chain);
+        let subject = format!("Subject: lineage game {:08x}\r\n", chain.id());
+        assert!(message.contains(&subject));
+        // quoting the message in a reply doesn't hide the chain
+        let reply = format!("> {}\r\n{}", message.replace("\r\n", "\r\n> "), message);
+        assert_eq!(dearmor(&reply), vec![chain.as_bytes(), chain.as_bytes()]);
+
+        assert_eq!(base64(b"\0user\0pass"), "AHVzZXIAcGFzcw==");
+        assert_eq!(literal_len(b"* 1 FETCH (BODY[] {512}\r\n"), Some(512));
+        assert_eq!(literal_len(b"* SEARCH 1 2\r\n"), None);
+    }
+}
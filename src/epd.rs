@@ -0,0 +1,118 @@
+//! EPD export, for feeding positions from signed games into analysis tools
+//! and test suites. Each line is the first four FEN fields followed by
+//! opcodes:
+//!
+//! - `id`: the chain id in hex and the number of moves played, e.g.
+//!   `"0000002a:12"`
+//! - `c0`, `c1`: the fingerprints of the white and black keys
+//! - `c2`: the fingerprint of the key that signed the last move, if any
+//! - `pm`: the move played next on the game line, in UCI, if any
+
+use crate::block::GameChain;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use chess::{Board, ChessMove};
+
+fn epd_line(
+    chain: &GameChain,
+    ply: usize,
+    board: &Board,
+    signer: Option<&str>,
+    next: Option<ChessMove>,
+) -> String {
+    let fen = format!("{}", board);
+    let fields: Vec<&str> = fen.split_whitespace().take(4).collect();
+    let mut line = format!(
+        "{} id \"{:08x}:{}\"; c0 \"{}\"; c1 \"{}\";",
+        fields.join(" "),
+        chain.id(),
+        ply,
+        chain.challenge().white_public_key().fingerprint(),
+        chain.challenge().black_public_key().fingerprint(),
+    );
+    if let Some(signer) = signer {
+        line.push_str(&format!(" c2 \"{}\";", signer));
+    }
+    if let Some(next) = next {
+        line.push_str(&format!(" pm {};", next));
+    }
+    line
+}
+
+impl GameChain {
+    /// The position after `move_index` moves on the game line as an EPD
+    /// line, or None if the line is shorter than that or doesn't replay.
+    /// Index 0 is the starting position.
+    pub fn to_epd(&self, move_index: usize) -> Option<String> {
+        self.epd_lines().into_iter().nth(move_index)
+    }
+
+    /// Every position on the game line as an EPD line, starting from the
+    /// starting position.
+    pub fn epd_lines(&self) -> Vec<String> {
+        let positions: Vec<_> = self.iter_positions().collect();
+        let mut lines = Vec::with_capacity(positions.len() + 1);
+        lines.push(epd_line(
+            self,
+            0,
+            &self.challenge().starting_board(),
+            None,
+            positions.first().map(|&(_, mv, _, _)| mv),
+        ));
+        for (i, (index, _, board, signer)) in positions.iter().enumerate() {
+            let next = positions.get(i + 1).map(|&(_, mv, _, _)| mv);
+            lines.push(epd_line(self, index + 1, board, Some(signer), next));
+        }
+        lines
+    }
+
+    /// Every position on the game line as an EPD file.
+    pub fn to_epd_file(&self) -> String {
+        let mut file = String::new();
+        for line in self.epd_lines() {
+            file.push_str(&line);
+            file.push('\n');
+        }
+        file
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+    use ring::signature::KeyPair;
+
+    #[test]
+    fn export_positions() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let mv = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(mv)).unwrap();
+
+        let white_fingerprint = crypto::fingerprint(white.public_key().as_ref());
+        let start = chain.to_epd(0).unwrap();
+        assert!(start.starts_with("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - id "));
+        assert!(start.contains(&format!("id \"{:08x}:0\";", chain.id())));
+        assert!(start.contains(&format!("c0 \"{}\";", white_fingerprint)));
+        assert!(start.ends_with(" pm e2e4;"));
+        assert!(!start.contains("c2"));
+
+        let after = chain.to_epd(1).unwrap();
+        assert!(after.contains(" b KQkq "));
+        assert!(after.ends_with(&format!("c2 \"{}\";", white_fingerprint)));
+        assert_eq!(chain.to_epd(2), None);
+        assert_eq!(chain.to_epd_file().lines().count(), 2);
+    }
+}
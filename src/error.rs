@@ -0,0 +1,103 @@
+use core::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    KeyNotInChallenge,
+    AlreadyAccepted,
+    ChainFull,
+    NotYourTurn,
+    IllegalMove,
+    UnsupportedAction,
+    VerificationFailed,
+    InvalidChallenge(&'static str),
+    InvalidMoveBlock {
+        index: usize,
+    },
+    InvalidKey,
+    NotOrganizer,
+    NotAParticipant,
+    TournamentFull,
+    UnknownGame(u32),
+    DuplicateGame(u32),
+    ForkDetected {
+        block_index: usize,
+    },
+    Malformed {
+        block: &'static str,
+        reason: &'static str,
+    },
+    CommitmentPending,
+    CommitmentMismatch,
+    WrongNetwork {
+        expected: u8,
+        found: u8,
+    },
+    ChallengeExpired,
+    InvalidTakeback,
+    GameAdjourned,
+    GameOver,
+    RoundUnfinished,
+    ChallengeRevoked,
+    TooLarge {
+        what: &'static str,
+        limit: usize,
+    },
+    WatchOnly,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::KeyNotInChallenge => write!(f, "This key is not in the challenge block."),
+            Error::AlreadyAccepted => write!(f, "This key is already present in the chain."),
+            Error::ChainFull => write!(f, "There are already two signatures on this chain."),
+            Error::NotYourTurn => write!(f, "This key cannot sign the current move."),
+            Error::IllegalMove => write!(f, "Invalid move."),
+            Error::UnsupportedAction => write!(f, "Action not implemented."),
+            Error::VerificationFailed => write!(f, "Chain does not verify."),
+            Error::InvalidChallenge(reason) => write!(f, "Invalid challenge: {}", reason),
+            Error::InvalidMoveBlock { index } => {
+                write!(f, "Move block {} is not a legal move.", index)
+            }
+            Error::InvalidKey => write!(f, "Invalid key."),
+            Error::NotOrganizer => write!(f, "This key is not the tournament organizer."),
+            Error::NotAParticipant => write!(f, "A player is not a tournament participant."),
+            Error::TournamentFull => write!(f, "All of the tournament's rounds are linked."),
+            Error::UnknownGame(id) => write!(f, "No game with id {:08x}.", id),
+            Error::DuplicateGame(id) => write!(f, "A game with id {:08x} already exists.", id),
+            Error::ForkDetected { block_index } => {
+                write!(f, "Chains diverge at block {}.", block_index)
+            }
+            Error::Malformed { block, reason } => {
+                write!(f, "Malformed {} block: {}", block, reason)
+            }
+            Error::CommitmentPending => {
+                write!(
+                    f,
+                    "The committed move must be acknowledged and revealed first."
+                )
+            }
+            Error::CommitmentMismatch => {
+                write!(f, "The revealed move does not match its commitment.")
+            }
+            Error::WrongNetwork { expected, found } => write!(
+                f,
+                "Expected a block from network {} but it is from network {}.",
+                expected, found
+            ),
+            Error::ChallengeExpired => write!(f, "The challenge has expired."),
+            Error::InvalidTakeback => write!(f, "No takeback fits the game so far."),
+            Error::GameAdjourned => write!(f, "The game is adjourned."),
+            Error::GameOver => write!(f, "The game is over."),
+            Error::RoundUnfinished => write!(f, "The last round has unfinished games."),
+            Error::ChallengeRevoked => write!(f, "The challenge has been revoked."),
+            Error::TooLarge { what, limit } => {
+                write!(f, "Exceeded the limit on {}: {}.", what, limit)
+            }
+            Error::WatchOnly => write!(f, "This node is watch-only and cannot sign."),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
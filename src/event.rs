@@ -0,0 +1,20 @@
+use chess::Color;
+
+/// Changes to a GameChain, delivered to receivers returned by
+/// GameChain::subscribe.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChainEvent {
+    Accepted {
+        public_key: [u8; 32],
+    },
+    MoveAdded {
+        index: usize,
+        start_square: u8,
+        end_square: u8,
+    },
+    /// The game has ended. `winner` is None for a draw.
+    ResultRecorded {
+        winner: Option<Color>,
+    },
+    VerificationFailed,
+}
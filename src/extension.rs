@@ -0,0 +1,299 @@
+use crate::block::ChallengeBlock;
+use crate::checkpoint::Checkpoint;
+use crate::crypto;
+use crate::dispute;
+use crate::error::Error;
+use crate::key::PublicKey;
+use crate::rotation;
+use crate::takeback;
+use crate::vacation;
+
+use alloc::vec::Vec;
+use chess::Color;
+use ring::signature::Ed25519KeyPair;
+
+/// Move blocks start with a square index, which is always below 64, so a first
+/// byte at or above this marks an extension block instead.
+pub const EXTENSION_TAG_MIN: u8 = 0x80;
+
+/// The kinds of block that may follow the accepts besides moves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExtensionKind {
+    /// A hash of the next move and a salt, signed by the player to move.
+    Commitment,
+    /// The opponent's acknowledgement of a commitment.
+    CommitmentAck,
+    /// The salt for a commitment, signed by the player to move just before
+    /// the move itself.
+    Reveal,
+    /// A timestamp signed by the player to move, acknowledging receipt of the
+    /// opponent's last move.
+    Receipt,
+    /// A request by either player to take back the last few moves.
+    TakebackRequest,
+    /// The opponent's agreement to an open takeback request.
+    TakebackAccept,
+    /// A timestamp signed by the player to move, adjourning the game.
+    Pause,
+    /// A timestamp signed by the player to move, resuming an adjourned game.
+    Resume,
+    /// A comment on a move, signed by an annotator outside the challenge.
+    /// Comments come after the game's blocks and aren't part of the game.
+    Commentary,
+    /// The move count and position, signed by both players so that the
+    /// blocks before it can be pruned.
+    Checkpoint,
+    /// A reply to a given opponent's move, signed in advance by the player
+    /// who just moved.
+    Conditional,
+    /// A timestamp signed by the player not to move, claiming the opponent
+    /// has run out of time.
+    ClaimTimeForfeit,
+    /// A player's color and reason, signed by that player, contesting the
+    /// game's result.
+    Dispute,
+    /// The result of a disputed game, signed by the arbiter.
+    Resolution,
+    /// An engine evaluation of a move, signed by an annotator outside the
+    /// challenge. Carried with the commentary.
+    Analysis,
+    /// A player's color and the start and end of a vacation, signed by that
+    /// player.
+    Vacation,
+    /// A player's color and a new key, with the new key's signature, signed
+    /// by the key it replaces.
+    KeyRotation,
+    /// In simultaneous games, a hash of the reply and a salt, signed by the
+    /// player not to move in place of acknowledging the commitment.
+    CounterCommitment,
+    /// The assessed result of an unfinished game and a reference to the
+    /// evaluation behind it, signed by the arbiter.
+    Adjudication,
+}
+
+impl ExtensionKind {
+    pub(crate) fn from_byte(byte: u8) -> Option<ExtensionKind> {
+        match byte {
+            0x80 => Some(ExtensionKind::Commitment),
+            0x81 => Some(ExtensionKind::CommitmentAck),
+            0x82 => Some(ExtensionKind::Reveal),
+            0x83 => Some(ExtensionKind::Receipt),
+            0x84 => Some(ExtensionKind::TakebackRequest),
+            0x85 => Some(ExtensionKind::TakebackAccept),
+            0x86 => Some(ExtensionKind::Pause),
+            0x87 => Some(ExtensionKind::Resume),
+            0x88 => Some(ExtensionKind::Commentary),
+            0x89 => Some(ExtensionKind::Checkpoint),
+            0x8a => Some(ExtensionKind::Conditional),
+            0x8b => Some(ExtensionKind::ClaimTimeForfeit),
+            0x8c => Some(ExtensionKind::Dispute),
+            0x8d => Some(ExtensionKind::Resolution),
+            0x8e => Some(ExtensionKind::Analysis),
+            0x8f => Some(ExtensionKind::Vacation),
+            0x90 => Some(ExtensionKind::KeyRotation),
+            0x91 => Some(ExtensionKind::CounterCommitment),
+            0x92 => Some(ExtensionKind::Adjudication),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn to_byte(self) -> u8 {
+        match self {
+            ExtensionKind::Commitment => 0x80,
+            ExtensionKind::CommitmentAck => 0x81,
+            ExtensionKind::Reveal => 0x82,
+            ExtensionKind::Receipt => 0x83,
+            ExtensionKind::TakebackRequest => 0x84,
+            ExtensionKind::TakebackAccept => 0x85,
+            ExtensionKind::Pause => 0x86,
+            ExtensionKind::Resume => 0x87,
+            ExtensionKind::Commentary => 0x88,
+            ExtensionKind::Checkpoint => 0x89,
+            ExtensionKind::Conditional => 0x8a,
+            ExtensionKind::ClaimTimeForfeit => 0x8b,
+            ExtensionKind::Dispute => 0x8c,
+            ExtensionKind::Resolution => 0x8d,
+            ExtensionKind::Analysis => 0x8e,
+            ExtensionKind::Vacation => 0x8f,
+            ExtensionKind::KeyRotation => 0x90,
+            ExtensionKind::CounterCommitment => 0x91,
+            ExtensionKind::Adjudication => 0x92,
+        }
+    }
+
+    /// The key that must sign an extension of this kind with the given body,
+    /// when the game line is `ply` moves long. None if the body doesn't name
+    /// a valid signer, and for commentary and analysis, which no player
+    /// signs. Players are given by their keys in the challenge, which key
+    /// rotations can replace.
+    pub fn signer_public_key(
+        self,
+        challenge: &ChallengeBlock,
+        ply: usize,
+        body: &[u8],
+    ) -> Option<PublicKey> {
+        match self {
+            ExtensionKind::Commitment
+            | ExtensionKind::Reveal
+            | ExtensionKind::Receipt
+            | ExtensionKind::Pause
+            | ExtensionKind::Resume => Some(challenge.signer_public_key(ply)),
+            ExtensionKind::CommitmentAck
+            | ExtensionKind::CounterCommitment
+            | ExtensionKind::Conditional
+            | ExtensionKind::ClaimTimeForfeit => Some(challenge.signer_public_key(ply + 1)),
+            ExtensionKind::TakebackRequest | ExtensionKind::TakebackAccept => {
+                match takeback::takeback_signer(self, body)? {
+                    Color::White => Some(challenge.white_public_key()),
+                    Color::Black => Some(challenge.black_public_key()),
+                }
+            }
+            ExtensionKind::Dispute => match dispute::disputer(body)? {
+                Color::White => Some(challenge.white_public_key()),
+                Color::Black => Some(challenge.black_public_key()),
+            },
+            ExtensionKind::Vacation => match vacation::vacationer(body)? {
+                Color::White => Some(challenge.white_public_key()),
+                Color::Black => Some(challenge.black_public_key()),
+            },
+            ExtensionKind::KeyRotation => match rotation::rotator(body)? {
+                Color::White => Some(challenge.white_public_key()),
+                Color::Black => Some(challenge.black_public_key()),
+            },
+            ExtensionKind::Resolution | ExtensionKind::Adjudication => {
+                challenge.arbiter_public_key()
+            }
+            ExtensionKind::Commentary | ExtensionKind::Analysis => None,
+            // the player to move at the checkpoint, which a pruned chain
+            // can't tell from the blocks before it
+            ExtensionKind::Checkpoint => {
+                let checkpoint = Checkpoint::from_body(body).ok()?;
+                Some(challenge.signer_public_key(checkpoint.move_count))
+            }
+        }
+    }
+}
+
+/// A block that isn't a move, encoded as a tag byte, a two-byte big-endian
+/// body length, the body, and a signature over the chain's canonical id,
+/// everything before it in the chain, and its own tag, length, and body.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExtensionBlock {
+    kind: ExtensionKind,
+    body: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl ExtensionBlock {
+    /// Signs a new extension block given the chain's signing prefix.
+    pub(crate) fn new(
+        prefix: &[u8],
+        kind: ExtensionKind,
+        body: Vec<u8>,
+        key_pair: &Ed25519KeyPair,
+    ) -> ExtensionBlock {
+        let mut block = ExtensionBlock {
+            kind,
+            body,
+            signature: Vec::new(),
+        };
+        let mut message = prefix.to_vec();
+        block.write_unsigned(&mut message);
+        block.signature = crypto::sign(key_pair, &message);
+        block
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ExtensionBlock, Error> {
+        if bytes.len() < 3 {
+            return Err(Error::Malformed {
+                block: "extension",
+                reason: "not enough bytes",
+            });
+        }
+        let kind = ExtensionKind::from_byte(bytes[0]).ok_or(Error::Malformed {
+            block: "extension",
+            reason: "unknown kind",
+        })?;
+        let body_len = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+        if bytes.len() < 3 + body_len + 64 {
+            return Err(Error::Malformed {
+                block: "extension",
+                reason: "not enough bytes",
+            });
+        }
+        Ok(ExtensionBlock {
+            kind,
+            body: bytes[3..3 + body_len].to_vec(),
+            signature: bytes[3 + body_len..3 + body_len + 64].to_vec(),
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        self.write_bytes(&mut bytes);
+        bytes
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        crypto::block_hash(&self.as_bytes())
+    }
+
+    pub fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        self.write_unsigned(bytes);
+        bytes.extend(&self.signature);
+    }
+
+    fn write_unsigned(&self, bytes: &mut Vec<u8>) {
+        bytes.push(self.kind.to_byte());
+        bytes.extend(&(self.body.len() as u16).to_be_bytes());
+        bytes.extend(&self.body);
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        3 + self.body.len() + 64
+    }
+
+    /// Checks the signature given the signing prefix of the chain this block
+    /// follows.
+    pub(crate) fn verify(&self, prefix: &[u8], public_key: &[u8]) -> bool {
+        let mut message = prefix.to_vec();
+        self.write_unsigned(&mut message);
+        crypto::verify(public_key, &message, &self.signature)
+    }
+
+    pub fn kind(&self) -> ExtensionKind {
+        self.kind
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::signature::KeyPair;
+
+    #[test]
+    fn extension_to_bytes_and_back() {
+        let rng = crypto::new_rng();
+        let key_pair = crypto::generate_key(&rng);
+        let block = ExtensionBlock::new(b"prefix", ExtensionKind::Reveal, vec![7; 32], &key_pair);
+        let bytes = block.as_bytes();
+        assert_eq!(bytes.len(), block.encoded_len());
+        assert_eq!(bytes[0], 0x82);
+        assert_eq!(ExtensionBlock::from_bytes(&bytes), Ok(block.clone()));
+        assert!(block.verify(b"prefix", key_pair.public_key().as_ref()));
+        assert!(!block.verify(b"prefiz", key_pair.public_key().as_ref()));
+
+        assert!(ExtensionBlock::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut unknown = bytes;
+        unknown[0] = 0xff;
+        assert!(ExtensionBlock::from_bytes(&unknown).is_err());
+    }
+}
@@ -0,0 +1,319 @@
+//! C API. Chains are opaque pointers owned by the caller and released with
+//! lineage_chain_free. Byte buffers returned by the library are owned by the
+//! caller and released with lineage_buffer_free; buffers passed in are only
+//! borrowed for the duration of the call.
+//!
+//! The crate builds as an rlib; build the C library with e.g. `cargo rustc
+//! --lib --crate-type cdylib,staticlib --features ffi`.
+//!
+//! No panic unwinds into the caller: a function that panics returns
+//! LINEAGE_STATUS_OTHER, null, an empty buffer, false, or zero instead.
+
+use crate::block::{ChallengeBlock, GameChain};
+use crate::crypto;
+use crate::error::Error;
+use crate::key::PublicKey;
+use crate::notation;
+
+use chess::Action;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LineageStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidKey = 2,
+    KeyNotInChallenge = 3,
+    AlreadyAccepted = 4,
+    NotYourTurn = 5,
+    IllegalMove = 6,
+    VerificationFailed = 7,
+    Malformed = 8,
+    Other = 255,
+}
+
+impl From<Error> for LineageStatus {
+    fn from(error: Error) -> LineageStatus {
+        match error {
+            Error::InvalidKey => LineageStatus::InvalidKey,
+            Error::KeyNotInChallenge => LineageStatus::KeyNotInChallenge,
+            Error::AlreadyAccepted | Error::ChainFull => LineageStatus::AlreadyAccepted,
+            Error::NotYourTurn => LineageStatus::NotYourTurn,
+            Error::IllegalMove | Error::InvalidMoveBlock { .. } => LineageStatus::IllegalMove,
+            Error::VerificationFailed => LineageStatus::VerificationFailed,
+            Error::Malformed { .. } => LineageStatus::Malformed,
+            _ => LineageStatus::Other,
+        }
+    }
+}
+
+#[repr(C)]
+pub struct LineageBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl LineageBuffer {
+    fn null() -> LineageBuffer {
+        LineageBuffer {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn from_vec(bytes: Vec<u8>) -> LineageBuffer {
+        let mut bytes = bytes.into_boxed_slice();
+        let buffer = LineageBuffer {
+            data: bytes.as_mut_ptr(),
+            len: bytes.len(),
+        };
+        std::mem::forget(bytes);
+        buffer
+    }
+}
+
+/// Runs `f`, returning `fallback` if it panics, since unwinding across the
+/// C boundary is undefined behaviour.
+fn catch<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(fallback)
+}
+
+unsafe fn borrow<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if data.is_null() {
+        None
+    } else {
+        Some(slice::from_raw_parts(data, len))
+    }
+}
+
+/// Creates a new, unaccepted chain. Both keys must point to 32 bytes.
+///
+/// # Safety
+/// The key pointers must be null or valid for 32 bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lineage_chain_new(
+    white_public_key: *const u8,
+    black_public_key: *const u8,
+) -> *mut GameChain {
+    catch(ptr::null_mut(), || {
+        let key = |data| borrow(data, 32).and_then(|bytes| PublicKey::from_slice(bytes).ok());
+        match (key(white_public_key), key(black_public_key)) {
+            (Some(white), Some(black)) => Box::into_raw(Box::new(GameChain::new(
+                ChallengeBlock::new(&white, &black),
+            ))),
+            _ => ptr::null_mut(),
+        }
+    })
+}
+
+/// Parses and verifies an encoded chain. Returns null if it is invalid.
+///
+/// # Safety
+/// `bytes` must be null or valid for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lineage_chain_from_bytes(bytes: *const u8, len: usize) -> *mut GameChain {
+    catch(ptr::null_mut(), || {
+        match borrow(bytes, len).map(GameChain::from_bytes) {
+            Some(Ok(chain)) => Box::into_raw(Box::new(chain)),
+            _ => ptr::null_mut(),
+        }
+    })
+}
+
+/// # Safety
+/// `chain` must be null or a pointer returned by this library that hasn't
+/// been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn lineage_chain_free(chain: *mut GameChain) {
+    catch((), || {
+        if !chain.is_null() {
+            drop(Box::from_raw(chain));
+        }
+    })
+}
+
+/// Encodes the chain into a new buffer, to be freed with lineage_buffer_free.
+///
+/// # Safety
+/// `chain` must be null or a live chain pointer.
+#[no_mangle]
+pub unsafe extern "C" fn lineage_chain_to_bytes(chain: *const GameChain) -> LineageBuffer {
+    catch(LineageBuffer::null(), || match chain.as_ref() {
+        Some(chain) => LineageBuffer::from_vec(chain.as_bytes()),
+        None => LineageBuffer::null(),
+    })
+}
+
+/// # Safety
+/// `buffer` must have been returned by this library and not freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn lineage_buffer_free(buffer: LineageBuffer) {
+    catch((), || {
+        if !buffer.data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+                buffer.data,
+                buffer.len,
+            )));
+        }
+    })
+}
+
+/// Generates a new key as a PKCS#8 document, to be freed with
+/// lineage_buffer_free.
+#[no_mangle]
+pub extern "C" fn lineage_generate_key() -> LineageBuffer {
+    catch(LineageBuffer::null(), || {
+        LineageBuffer::from_vec(crypto::generate_pkcs8(&crypto::new_rng()))
+    })
+}
+
+/// Signs an accept block with the PKCS#8 key.
+///
+/// # Safety
+/// `chain` must be null or a live chain pointer, and `pkcs8` must be null or
+/// valid for `pkcs8_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn lineage_chain_accept(
+    chain: *mut GameChain,
+    pkcs8: *const u8,
+    pkcs8_len: usize,
+) -> LineageStatus {
+    catch(LineageStatus::Other, || {
+        let (chain, pkcs8) = match (chain.as_mut(), borrow(pkcs8, pkcs8_len)) {
+            (Some(chain), Some(pkcs8)) => (chain, pkcs8),
+            _ => return LineageStatus::NullPointer,
+        };
+        match crypto::key_from_pkcs8(pkcs8).and_then(|key_pair| chain.accept(&key_pair)) {
+            Ok(()) => LineageStatus::Ok,
+            Err(error) => error.into(),
+        }
+    })
+}
+
+/// Signs a move given as a NUL-terminated UCI string, e.g. "e2e4".
+///
+/// # Safety
+/// `chain` must be null or a live chain pointer, `pkcs8` must be null or
+/// valid for `pkcs8_len` bytes, and `uci` must be null or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn lineage_chain_make_move(
+    chain: *mut GameChain,
+    pkcs8: *const u8,
+    pkcs8_len: usize,
+    uci: *const c_char,
+) -> LineageStatus {
+    catch(LineageStatus::Other, || {
+        if uci.is_null() {
+            return LineageStatus::NullPointer;
+        }
+        let (chain, pkcs8) = match (chain.as_mut(), borrow(pkcs8, pkcs8_len)) {
+            (Some(chain), Some(pkcs8)) => (chain, pkcs8),
+            _ => return LineageStatus::NullPointer,
+        };
+        let mv = match CStr::from_ptr(uci)
+            .to_str()
+            .ok()
+            .and_then(notation::parse_uci)
+        {
+            Some(mv) => mv,
+            None => return LineageStatus::IllegalMove,
+        };
+        let result = crypto::key_from_pkcs8(pkcs8)
+            .and_then(|key_pair| chain.make_move_block(&key_pair, Action::MakeMove(mv)));
+        match result {
+            Ok(()) => LineageStatus::Ok,
+            Err(error) => error.into(),
+        }
+    })
+}
+
+/// # Safety
+/// `chain` must be null or a live chain pointer.
+#[no_mangle]
+pub unsafe extern "C" fn lineage_chain_verify(chain: *const GameChain) -> bool {
+    catch(false, || match chain.as_ref() {
+        Some(chain) => chain.verify(),
+        None => false,
+    })
+}
+
+/// # Safety
+/// `chain` must be null or a live chain pointer.
+#[no_mangle]
+pub unsafe extern "C" fn lineage_chain_move_count(chain: *const GameChain) -> usize {
+    catch(0, || match chain.as_ref() {
+        Some(chain) => chain.move_count(),
+        None => 0,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ring::signature::KeyPair;
+    use std::ffi::CString;
+
+    #[test]
+    fn play_through_c_api() {
+        unsafe {
+            let white = lineage_generate_key();
+            let black = lineage_generate_key();
+            let white_pkcs8 = slice::from_raw_parts(white.data, white.len);
+            let black_pkcs8 = slice::from_raw_parts(black.data, black.len);
+            let white_key = crypto::key_from_pkcs8(white_pkcs8).unwrap();
+            let black_key = crypto::key_from_pkcs8(black_pkcs8).unwrap();
+
+            let chain = lineage_chain_new(
+                white_key.public_key().as_ref().as_ptr(),
+                black_key.public_key().as_ref().as_ptr(),
+            );
+            assert!(!chain.is_null());
+            assert_eq!(
+                lineage_chain_accept(chain, white.data, white.len),
+                LineageStatus::Ok
+            );
+            assert_eq!(
+                lineage_chain_accept(chain, black.data, black.len),
+                LineageStatus::Ok
+            );
+            let e4 = CString::new("e2e4").unwrap();
+            assert_eq!(
+                lineage_chain_make_move(chain, black.data, black.len, e4.as_ptr()),
+                LineageStatus::NotYourTurn
+            );
+            assert_eq!(
+                lineage_chain_make_move(chain, white.data, white.len, e4.as_ptr()),
+                LineageStatus::Ok
+            );
+            assert!(lineage_chain_verify(chain));
+
+            let bytes = lineage_chain_to_bytes(chain);
+            let copy = lineage_chain_from_bytes(bytes.data, bytes.len);
+            assert!(!copy.is_null());
+            assert_eq!(lineage_chain_move_count(copy), 1);
+
+            lineage_buffer_free(bytes);
+            lineage_chain_free(copy);
+            lineage_chain_free(chain);
+            lineage_buffer_free(white);
+            lineage_buffer_free(black);
+        }
+    }
+
+    #[test]
+    fn panics_stay_on_this_side() {
+        assert_eq!(
+            catch(LineageStatus::Other, || panic!("bug")),
+            LineageStatus::Other
+        );
+        assert_eq!(
+            catch(LineageStatus::Other, || LineageStatus::Ok),
+            LineageStatus::Ok
+        );
+    }
+}
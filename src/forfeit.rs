@@ -0,0 +1,303 @@
+//! Time forfeits. Clocks are read from receipts: a player's time on a move
+//! runs from their receipt of the opponent's move to the opponent's receipt of
+//! their reply, leaving out adjournments and their vacations. Time on a move
+//! nobody signed a receipt for isn't counted, so missing receipts only ever
+//! favour the player on the clock. A challenge's lag allowance comes off every
+//! think. Once the player to move has run out, the opponent can sign a claim
+//! with the time they make it, which ends the game.
+
+use crate::block::{ChainEntry, GameChain};
+use crate::dispute;
+use crate::error::Error;
+use crate::extension::ExtensionKind;
+use crate::takeback::Line;
+
+use chess::Color;
+use ring::signature::Ed25519KeyPair;
+
+/// A player's clock at some moment, in whole seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClockReading {
+    pub color: Color,
+    /// The base time plus an increment for every move they've made.
+    pub allowed_seconds: u64,
+    pub used_seconds: u64,
+}
+
+impl ClockReading {
+    pub fn remaining_seconds(&self) -> i64 {
+        self.allowed_seconds as i64 - self.used_seconds as i64
+    }
+
+    pub fn flagged(&self) -> bool {
+        self.used_seconds > self.allowed_seconds
+    }
+}
+
+/// A claim that the player to move ran out of time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeForfeit {
+    /// The player who ran out of time.
+    pub flagged: Color,
+    /// When the claim was made, in seconds since the Unix epoch.
+    pub claimed_at: u64,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "time forfeit",
+        reason,
+    }
+}
+
+fn timestamp(body: &[u8]) -> Option<u64> {
+    if body.len() != 8 {
+        return None;
+    }
+    let mut timestamp = [0; 8];
+    timestamp.copy_from_slice(body);
+    Some(u64::from_be_bytes(timestamp))
+}
+
+fn index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+fn color(index: usize) -> Color {
+    if index == 0 {
+        Color::White
+    } else {
+        Color::Black
+    }
+}
+
+/// Reads the clock of the player to move at `now`, from the blocks before any
+/// time forfeit claim.
+fn read_clock(chain: &GameChain, now: u64) -> Result<ClockReading, Error> {
+    let time_control = chain
+        .challenge()
+        .time_control()
+        .ok_or_else(|| malformed("the game is untimed"))?;
+    let first = chain.challenge().starting_board().side_to_move();
+    let color_at = |ply: usize| if ply.is_multiple_of(2) { first } else { !first };
+
+    let mut line = Line::default();
+    // when each player's current think began, and when the think for a move
+    // still waiting on the opponent's receipt began
+    let mut thinking = [None, None];
+    let mut unreceipted = [None, None];
+    // milliseconds used, each think less the lag allowance, saturating since
+    // receipt timestamps are whatever the receiver signed
+    let lag_allowance_ms = u64::from(chain.challenge().lag_allowance_ms());
+    let think_ms = |seconds: u64| {
+        seconds
+            .saturating_mul(1000)
+            .saturating_sub(lag_allowance_ms)
+    };
+    let mut used_ms = [0u64; 2];
+    let mut moves = [0; 2];
+    for entry in chain.entries() {
+        match entry {
+            ChainEntry::Move { .. } => {
+                let mover = index(color_at(line.len()));
+                moves[mover] += 1;
+                unreceipted[mover] = thinking[mover].take();
+                line.push_move();
+            }
+            ChainEntry::Extension(block) => {
+                match (block.kind(), timestamp(block.body())) {
+                    (ExtensionKind::ClaimTimeForfeit, _) => break,
+                    (ExtensionKind::Receipt, Some(received_at)) => {
+                        let receiver = index(color_at(line.len()));
+                        thinking[receiver] = Some(received_at);
+                        if let Some(start) = unreceipted[1 - receiver].take() {
+                            used_ms[1 - receiver] = used_ms[1 - receiver].saturating_add(think_ms(
+                                chain.clock_seconds(color(1 - receiver), start, received_at),
+                            ));
+                        }
+                    }
+                    _ => {}
+                }
+                line.push_extension(block.kind(), block.body())?;
+            }
+        }
+    }
+
+    let color = color_at(line.len());
+    let since = thinking[index(color)].ok_or_else(|| malformed("no receipt for the last move"))?;
+    Ok(ClockReading {
+        color,
+        allowed_seconds: time_control.base_seconds as u64
+            + time_control.increment_seconds as u64 * moves[index(color)],
+        used_seconds: used_ms[index(color)].saturating_add(think_ms(chain.clock_seconds(
+            color,
+            since,
+            now.max(since),
+        ))) / 1000,
+    })
+}
+
+pub(crate) fn check_extension(chain: &GameChain, body: &[u8]) -> Result<(), Error> {
+    let claimed_at = timestamp(body).ok_or_else(|| malformed("wrong body length"))?;
+    if !read_clock(chain, claimed_at)?.flagged() {
+        return Err(malformed("time has not run out"));
+    }
+    Ok(())
+}
+
+/// Checks that no move follows a time forfeit.
+pub(crate) fn check_move(chain: &GameChain) -> Result<(), Error> {
+    if chain.time_forfeit().is_some() {
+        return Err(Error::GameOver);
+    }
+    Ok(())
+}
+
+/// Checks that only a dispute and its resolution follow a time forfeit claim
+/// and that the receipts before it add up to the flagged player running out
+/// of time.
+pub(crate) fn check_chain(chain: &GameChain) -> Result<(), Error> {
+    let claimed_at = match chain.time_forfeit() {
+        Some(forfeit) => forfeit.claimed_at,
+        None => return Ok(()),
+    };
+    let entries = chain.entries();
+    let after_claim = entries.iter().rev().take_while(|entry| match entry {
+        ChainEntry::Extension(block) => block.kind() != ExtensionKind::ClaimTimeForfeit,
+        _ => true,
+    });
+    for entry in after_claim {
+        match entry {
+            ChainEntry::Extension(block) if dispute::follows_result(block.kind()) => {}
+            _ => return Err(Error::GameOver),
+        }
+    }
+    if !read_clock(chain, claimed_at)?.flagged() {
+        return Err(malformed("time has not run out"));
+    }
+    Ok(())
+}
+
+impl GameChain {
+    /// The clock of the player to move at `now`, read from receipts. Fails
+    /// if the game is untimed or the player to move hasn't signed a receipt
+    /// for the last move, since their clock can't be shown to be running.
+    pub fn clock_at(&self, now: u64) -> Result<ClockReading, Error> {
+        read_clock(self, now)
+    }
+
+    /// Claims at `timestamp` that the opponent, who is to move, has run out
+    /// of time.
+    pub fn claim_time_forfeit(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        timestamp: u64,
+    ) -> Result<(), Error> {
+        self.sign_extension(
+            key_pair,
+            ExtensionKind::ClaimTimeForfeit,
+            timestamp.to_be_bytes().to_vec(),
+        )
+    }
+
+    /// The game's time forfeit, if one was claimed.
+    pub fn time_forfeit(&self) -> Option<TimeForfeit> {
+        let first = self.challenge().starting_board().side_to_move();
+        let mut line = Line::default();
+        for entry in self.entries() {
+            match entry {
+                ChainEntry::Move { .. } => line.push_move(),
+                ChainEntry::Extension(block) => {
+                    if block.kind() == ExtensionKind::ClaimTimeForfeit {
+                        let flagged = if line.len() % 2 == 0 { first } else { !first };
+                        return Some(TimeForfeit {
+                            flagged,
+                            claimed_at: timestamp(block.body())?,
+                        });
+                    }
+                    line.push_extension(block.kind(), block.body()).ok()?;
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlockBuilder, GameResult, GameStatus};
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn claim_on_time() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .timestamp(1_000)
+            .time_control(60, 10)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let play = |chain: &mut GameChain, key_pair: &Ed25519KeyPair, uci: &str| {
+            let mv = notation::parse_uci(uci).unwrap();
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        };
+
+        play(&mut chain, &white, "e2e4");
+        // black can't be flagged before acknowledging white's move
+        assert!(chain.clock_at(2_000).is_err());
+        chain.acknowledge_move(&black, 1_000).unwrap();
+        play(&mut chain, &black, "e7e5");
+        chain.acknowledge_move(&white, 1_050).unwrap();
+        play(&mut chain, &white, "g1f3");
+        chain.acknowledge_move(&black, 1_060).unwrap();
+
+        // black used 50 of 60 seconds on the first move and gained 10
+        let reading = chain.clock_at(1_060).unwrap();
+        assert_eq!(reading.color, Color::Black);
+        assert_eq!(reading.allowed_seconds, 70);
+        assert_eq!(reading.used_seconds, 50);
+        assert_eq!(reading.remaining_seconds(), 20);
+        assert!(chain.claim_time_forfeit(&white, 1_080).is_err());
+        assert_eq!(
+            chain.claim_time_forfeit(&black, 1_100),
+            Err(Error::NotYourTurn)
+        );
+
+        // a far-off time saturates rather than overflowing
+        assert!(chain.clock_at(u64::MAX).unwrap().flagged());
+        chain.claim_time_forfeit(&white, 1_081).unwrap();
+        assert_eq!(
+            chain.time_forfeit(),
+            Some(TimeForfeit {
+                flagged: Color::Black,
+                claimed_at: 1_081,
+            })
+        );
+        assert_eq!(
+            chain.status(),
+            GameStatus::Finished(GameResult::Win(Color::White))
+        );
+        assert_eq!(
+            chain.make_move_block(
+                &black,
+                Action::MakeMove(notation::parse_uci("b8c6").unwrap())
+            ),
+            Err(Error::GameOver)
+        );
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain));
+    }
+}
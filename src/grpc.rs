@@ -0,0 +1,158 @@
+//! gRPC service over a GameManager. The server and client stubs are generated
+//! from proto/lineage.proto at build time and re-exported from `proto`.
+
+use crate::block::{AcceptBlock, MoveBlock};
+use crate::error::Error;
+use crate::event::ChainEvent;
+use crate::extension::{ExtensionBlock, EXTENSION_TAG_MIN};
+use crate::manager::GameManager;
+
+use chess::Color;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("lineage");
+}
+
+pub use proto::lineage_client::LineageClient;
+pub use proto::lineage_server::LineageServer;
+
+use proto::{
+    event::Kind, result_recorded::Winner, ChainReply, Event, GetChainRequest, StreamEventsRequest,
+    SubmitBlockRequest,
+};
+
+impl From<Error> for Status {
+    fn from(error: Error) -> Status {
+        match error {
+            Error::UnknownGame(_) => Status::not_found(error.to_string()),
+            Error::DuplicateGame(_) | Error::AlreadyAccepted | Error::ChainFull => {
+                Status::already_exists(error.to_string())
+            }
+            Error::Malformed { .. } => Status::invalid_argument(error.to_string()),
+            _ => Status::failed_precondition(error.to_string()),
+        }
+    }
+}
+
+fn event_message(chain_id: u32, event: ChainEvent) -> Event {
+    let kind = match event {
+        ChainEvent::Accepted { public_key } => Kind::Accepted(proto::Accepted {
+            public_key: public_key.to_vec(),
+        }),
+        ChainEvent::MoveAdded {
+            index,
+            start_square,
+            end_square,
+        } => Kind::MoveAdded(proto::MoveAdded {
+            index: index as u32,
+            start_square: start_square.into(),
+            end_square: end_square.into(),
+        }),
+        ChainEvent::ResultRecorded { winner } => {
+            let winner = match winner {
+                Some(Color::White) => Winner::White,
+                Some(Color::Black) => Winner::Black,
+                None => Winner::Draw,
+            };
+            Kind::ResultRecorded(proto::ResultRecorded {
+                winner: winner as i32,
+            })
+        }
+        ChainEvent::VerificationFailed => Kind::VerificationFailed(proto::VerificationFailed {}),
+    };
+    Event {
+        chain_id,
+        kind: Some(kind),
+    }
+}
+
+pub struct LineageService {
+    manager: Arc<GameManager>,
+}
+
+impl LineageService {
+    pub fn new(manager: Arc<GameManager>) -> LineageService {
+        LineageService { manager }
+    }
+
+    /// Wraps the service for use with tonic::transport::Server.
+    pub fn into_server(self) -> LineageServer<LineageService> {
+        LineageServer::new(self)
+    }
+
+    fn chain_reply(&self, chain_id: u32) -> Result<ChainReply, Error> {
+        let chain = self
+            .manager
+            .get(chain_id)
+            .ok_or(Error::UnknownGame(chain_id))?;
+        Ok(ChainReply {
+            chain_id,
+            chain: chain.as_bytes(),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl proto::lineage_server::Lineage for LineageService {
+    async fn submit_block(
+        &self,
+        request: Request<SubmitBlockRequest>,
+    ) -> Result<Response<ChainReply>, Status> {
+        let request = request.into_inner();
+        let block = request.block;
+        self.manager
+            .with_chain(request.chain_id, |chain| match block.len() {
+                _ if block.first() >= Some(&EXTENSION_TAG_MIN) => {
+                    chain.append_extension_block(ExtensionBlock::from_bytes(&block)?)
+                }
+                len if len == chain.challenge().accept_len() => {
+                    chain.append_accept_block(AcceptBlock::from_bytes(&block, chain.challenge())?)
+                }
+                66 => chain.append_move_block(MoveBlock::from_bytes(&block)?),
+                _ => Err(Error::Malformed {
+                    block: "submitted",
+                    reason: "not an accept, move, or extension block",
+                }),
+            })??;
+        Ok(Response::new(self.chain_reply(request.chain_id)?))
+    }
+
+    async fn get_chain(
+        &self,
+        request: Request<GetChainRequest>,
+    ) -> Result<Response<ChainReply>, Status> {
+        Ok(Response::new(
+            self.chain_reply(request.into_inner().chain_id)?,
+        ))
+    }
+
+    type StreamEventsStream = ReceiverStream<Result<Event, Status>>;
+
+    async fn stream_events(
+        &self,
+        request: Request<StreamEventsRequest>,
+    ) -> Result<Response<Self::StreamEventsStream>, Status> {
+        let chain_id = request.into_inner().chain_id;
+        let events = self
+            .manager
+            .with_chain(chain_id, |chain| chain.subscribe())?;
+        let (sender, receiver) = mpsc::channel(16);
+        // chain events arrive on a std channel, so forward them from a
+        // blocking thread
+        tokio::task::spawn_blocking(move || {
+            for event in events {
+                if sender
+                    .blocking_send(Ok(event_message(chain_id, event)))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
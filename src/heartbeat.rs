@@ -0,0 +1,164 @@
+//! Heartbeats: a player's signed "still here at time T" for one game. They
+//! stay out of the chain, since a long correspondence game would otherwise
+//! fill up with them, and travel and get stored alongside it instead. A
+//! player who has run out of time but keeps sending heartbeats is thinking;
+//! one who has gone silent has likely abandoned the game, and the autopilot
+//! can claim the forfeit without waiting out its grace period.
+
+use crate::block::GameChain;
+use crate::crypto;
+use crate::error::Error;
+
+use alloc::vec::Vec;
+use chess::Color;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+// Keeps a heartbeat signature from being mistaken for a signature on
+// anything else.
+const DOMAIN: &[u8] = b"lineage heartbeat";
+
+/// The length of an encoded heartbeat.
+pub const HEARTBEAT_LEN: usize = 32 + 32 + 8 + 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heartbeat {
+    canonical_id: [u8; 32],
+    public_key: [u8; 32],
+    timestamp: u64,
+    signature: Vec<u8>,
+}
+
+fn message(canonical_id: &[u8; 32], timestamp: u64) -> Vec<u8> {
+    let mut message = DOMAIN.to_vec();
+    message.extend(canonical_id);
+    message.extend(&timestamp.to_be_bytes());
+    message
+}
+
+impl Heartbeat {
+    /// The canonical id of the game it's for.
+    pub fn canonical_id(&self) -> &[u8; 32] {
+        &self.canonical_id
+    }
+
+    pub fn public_key(&self) -> &[u8; 32] {
+        &self.public_key
+    }
+
+    /// When the player was there, in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Whether the heartbeat is for `chain` and signed by one of its
+    /// players, returning which.
+    pub fn signer(&self, chain: &GameChain) -> Option<Color> {
+        if self.canonical_id != chain.canonical_id() {
+            return None;
+        }
+        let color = chain.side_of(&self.public_key)?;
+        let message = message(&self.canonical_id, self.timestamp);
+        if !crypto::verify(&self.public_key, &message, &self.signature) {
+            return None;
+        }
+        Some(color)
+    }
+
+    /// Encoded as the game's canonical id, the signer's key, the timestamp,
+    /// and the signature.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEARTBEAT_LEN);
+        bytes.extend(&self.canonical_id);
+        bytes.extend(&self.public_key);
+        bytes.extend(&self.timestamp.to_be_bytes());
+        bytes.extend(&self.signature);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Heartbeat, Error> {
+        if bytes.len() != HEARTBEAT_LEN {
+            return Err(Error::Malformed {
+                block: "heartbeat",
+                reason: "wrong length",
+            });
+        }
+        let mut canonical_id = [0; 32];
+        canonical_id.copy_from_slice(&bytes[..32]);
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(&bytes[32..64]);
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&bytes[64..72]);
+        Ok(Heartbeat {
+            canonical_id,
+            public_key,
+            timestamp: u64::from_be_bytes(timestamp),
+            signature: bytes[72..].to_vec(),
+        })
+    }
+}
+
+/// When `color` was last known to be there: their latest heartbeat for
+/// `chain` that verifies.
+pub fn last_seen(chain: &GameChain, heartbeats: &[Heartbeat], color: Color) -> Option<u64> {
+    heartbeats
+        .iter()
+        .filter(|heartbeat| heartbeat.signer(chain) == Some(color))
+        .map(Heartbeat::timestamp)
+        .max()
+}
+
+impl GameChain {
+    /// Signs a heartbeat saying `key_pair`'s player was there at
+    /// `timestamp`.
+    pub fn heartbeat(&self, key_pair: &Ed25519KeyPair, timestamp: u64) -> Result<Heartbeat, Error> {
+        let public_key = key_pair.public_key().as_ref();
+        self.side_of(public_key).ok_or(Error::KeyNotInChallenge)?;
+        let canonical_id = self.canonical_id();
+        let mut key = [0; 32];
+        key.copy_from_slice(public_key);
+        Ok(Heartbeat {
+            canonical_id,
+            public_key: key,
+            timestamp,
+            signature: crypto::sign(key_pair, &message(&canonical_id, timestamp)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::key::PublicKey;
+
+    #[test]
+    fn sign_heartbeats() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let stranger = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let chain = GameChain::new(challenge);
+        let other = GameChain::new(ChallengeBlock::new(
+            &PublicKey::of(&white),
+            &PublicKey::of(&stranger),
+        ));
+
+        let early = chain.heartbeat(&black, 1_000).unwrap();
+        let late = chain.heartbeat(&black, 2_000).unwrap();
+        assert_eq!(Heartbeat::from_bytes(&late.as_bytes()), Ok(late.clone()));
+        assert_eq!(late.signer(&chain), Some(Color::Black));
+        assert_eq!(late.signer(&other), None);
+        assert!(chain.heartbeat(&stranger, 1_000).is_err());
+
+        // a heartbeat moved to another time doesn't verify
+        let mut bytes = early.as_bytes();
+        bytes[71] ^= 0xff;
+        let forged = Heartbeat::from_bytes(&bytes).unwrap();
+        assert_eq!(forged.signer(&chain), None);
+
+        let heartbeats = [early, forged, late];
+        assert_eq!(last_seen(&chain, &heartbeats, Color::Black), Some(2_000));
+        assert_eq!(last_seen(&chain, &heartbeats, Color::White), None);
+    }
+}
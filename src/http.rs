@@ -0,0 +1,504 @@
+//! Minimal HTTP/1.1 API over a GameManager:
+//!
+//! - `GET /games` lists games, optionally filtered with `?player=<fingerprint>`
+//! - `POST /games` imports a chain sent as `{"chain": "<base58>"}`, once a
+//!   player has accepted it
+//! - `GET /games/{id}` returns a game as JSON
+//! - `POST /games/{id}` appends a signed block sent as
+//!   `{"kind": "accept" | "move" | "extension", "block": "<base58>"}`, after
+//!   verifying it
+//!
+//! Each connection carries a single request.
+
+use crate::block::{AcceptBlock, GameChain, MoveBlock};
+use crate::error::Error;
+use crate::extension::ExtensionBlock;
+use crate::manager::GameManager;
+use crate::rpc::{chain_json, read_line};
+
+use serde_json::{json, Value};
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+
+const MAX_BODY_LEN: usize = 1 << 20;
+const MAX_LINE_LEN: usize = 8 << 10;
+
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Why a request was turned away before it was routed.
+#[derive(Debug, PartialEq)]
+enum Rejected {
+    Malformed,
+    TooLarge,
+}
+
+struct Response {
+    status: u16,
+    body: Value,
+}
+
+impl Response {
+    fn ok(body: Value) -> Response {
+        Response { status: 200, body }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response {
+            status,
+            body: json!({ "error": message }),
+        }
+    }
+}
+
+impl From<Error> for Response {
+    fn from(error: Error) -> Response {
+        let status = match error {
+            Error::UnknownGame(_) => 404,
+            Error::DuplicateGame(_) | Error::AlreadyAccepted | Error::ChainFull => 409,
+            _ => 422,
+        };
+        Response::error(status, &error.to_string())
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Unprocessable Entity",
+    }
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Result<Request, Rejected>> {
+    let mut reader = BufReader::new(stream);
+    let line = match read_line(&mut reader, MAX_LINE_LEN)? {
+        Some(line) => String::from_utf8_lossy(&line).into_owned(),
+        None => return Ok(Err(Rejected::TooLarge)),
+    };
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Ok(Err(Rejected::Malformed)),
+    };
+
+    let mut content_length = 0;
+    loop {
+        let header = match read_line(&mut reader, MAX_LINE_LEN)? {
+            Some(header) => String::from_utf8_lossy(&header).into_owned(),
+            None => return Ok(Err(Rejected::TooLarge)),
+        };
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if content_length > MAX_BODY_LEN {
+        return Ok(Err(Rejected::TooLarge));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target, None),
+    };
+    Ok(Ok(Request {
+        method,
+        path,
+        query,
+        body,
+    }))
+}
+
+fn base58_field(body: &[u8], field: &str) -> Option<Vec<u8>> {
+    let body: Value = serde_json::from_slice(body).ok()?;
+    bs58::decode(body[field].as_str()?).into_vec().ok()
+}
+
+pub struct HttpServer {
+    manager: Arc<GameManager>,
+}
+
+impl HttpServer {
+    pub fn new(manager: Arc<GameManager>) -> HttpServer {
+        HttpServer { manager }
+    }
+
+    /// Accepts connections forever, serving each on its own thread.
+    pub fn listen<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = HttpServer {
+                manager: Arc::clone(&self.manager),
+            };
+            thread::spawn(move || {
+                let _ = server.serve(stream);
+            });
+        }
+        Ok(())
+    }
+
+    fn serve(&self, mut stream: TcpStream) -> io::Result<()> {
+        let response = match read_request(&stream)? {
+            Ok(request) => self.handle(&request),
+            Err(Rejected::Malformed) => Response::error(400, "malformed request"),
+            Err(Rejected::TooLarge) => Response::error(413, "request too large"),
+        };
+        let body = response.body.to_string();
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.status,
+            reason(response.status),
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+
+    fn handle(&self, request: &Request) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        let result = match (request.method.as_str(), &segments[..]) {
+            ("GET", ["games"]) => Ok(self.list_games(request.query.as_ref())),
+            ("POST", ["games"]) => self.import_chain(&request.body),
+            ("GET", ["games", id]) => match id.parse() {
+                Ok(id) => self.get_game(id),
+                Err(_) => return Response::error(404, "not found"),
+            },
+            ("POST", ["games", id]) => match id.parse() {
+                Ok(id) => self.append_block(id, &request.body),
+                Err(_) => return Response::error(404, "not found"),
+            },
+            (_, ["games"]) | (_, ["games", _]) => {
+                return Response::error(405, "method not allowed")
+            }
+            _ => return Response::error(404, "not found"),
+        };
+        match result {
+            Ok(response) => response,
+            Err(error) => error.into(),
+        }
+    }
+
+    fn list_games(&self, query: Option<&String>) -> Response {
+        let player = query.and_then(|query| {
+            query
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| *key == "player")
+                .map(|(_, value)| value.to_string())
+        });
+        let games: Vec<Value> = self
+            .manager
+            .ids()
+            .into_iter()
+            .filter_map(|id| self.manager.get(id))
+            .filter(|chain| match &player {
+                Some(player) => {
+                    let (white, black) = chain.players();
+                    white.fingerprint() == *player || black.fingerprint() == *player
+                }
+                None => true,
+            })
+            .map(|chain| json!({ "id": chain.id(), "summary": chain.to_string() }))
+            .collect();
+        Response::ok(json!(games))
+    }
+
+    fn import_chain(&self, body: &[u8]) -> Result<Response, Error> {
+        let bytes = match base58_field(body, "chain") {
+            Some(bytes) => bytes,
+            None => return Ok(Response::error(400, "expected {\"chain\": \"<base58>\"}")),
+        };
+        let chain = GameChain::from_bytes(&bytes)?;
+        let id = self.manager.import(chain)?;
+        Ok(Response::ok(json!({ "id": id })))
+    }
+
+    fn get_game(&self, id: u32) -> Result<Response, Error> {
+        let chain = self.manager.get(id).ok_or(Error::UnknownGame(id))?;
+        Ok(Response::ok(chain_json(&chain)))
+    }
+
+    fn append_block(&self, id: u32, body: &[u8]) -> Result<Response, Error> {
+        let body: Value = serde_json::from_slice(body).unwrap_or(Value::Null);
+        let append: fn(&mut GameChain, &[u8]) -> Result<(), Error> = match body["kind"].as_str() {
+            Some("accept") => |chain, bytes| {
+                let block = AcceptBlock::from_bytes(bytes, chain.challenge())?;
+                chain.append_accept_block(block)
+            },
+            Some("move") => |chain, bytes| chain.append_move_block(MoveBlock::from_bytes(bytes)?),
+            Some("extension") => {
+                |chain, bytes| chain.append_extension_block(ExtensionBlock::from_bytes(bytes)?)
+            }
+            _ => {
+                return Ok(Response::error(
+                    400,
+                    "expected kind accept, move or extension",
+                ))
+            }
+        };
+        let bytes = match body["block"]
+            .as_str()
+            .and_then(|block| bs58::decode(block).into_vec().ok())
+        {
+            Some(bytes) => bytes,
+            None => return Ok(Response::error(400, "expected {\"block\": \"<base58>\"}")),
+        };
+        self.manager
+            .with_chain(id, |chain| append(chain, &bytes))??;
+        self.get_game(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlockBuilder;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+
+    use chess::{Action, Color};
+
+    fn connection() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+
+    fn request(method: &str, target: &str, body: Value) -> Request {
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), Some(query.to_string())),
+            None => (target.to_string(), None),
+        };
+        Request {
+            method: method.to_string(),
+            path,
+            query,
+            body: if body.is_null() {
+                Vec::new()
+            } else {
+                body.to_string().into_bytes()
+            },
+        }
+    }
+
+    fn base58(bytes: &[u8]) -> String {
+        bs58::encode(bytes).into_string()
+    }
+
+    #[test]
+    fn read_requests() {
+        let (mut client, server) = connection();
+        write!(
+            client,
+            "POST /games/4?player=abc HTTP/1.1\r\nHost: x\r\ncontent-length: 2\r\n\r\n{{}}"
+        )
+        .unwrap();
+        let request = read_request(&server).unwrap().unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/games/4");
+        assert_eq!(request.query.as_deref(), Some("player=abc"));
+        assert_eq!(request.body, b"{}");
+
+        let (mut client, server) = connection();
+        client.write_all(b"\r\n\r\n").unwrap();
+        assert_eq!(
+            read_request(&server).unwrap().err(),
+            Some(Rejected::Malformed)
+        );
+
+        let (mut client, server) = connection();
+        write!(
+            client,
+            "POST /games HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_LEN + 1
+        )
+        .unwrap();
+        assert_eq!(
+            read_request(&server).unwrap().err(),
+            Some(Rejected::TooLarge)
+        );
+
+        // a header that never ends
+        let (mut client, server) = connection();
+        write!(
+            client,
+            "GET /games HTTP/1.1\r\nX-Padding: {}",
+            "a".repeat(MAX_LINE_LEN)
+        )
+        .unwrap();
+        assert_eq!(
+            read_request(&server).unwrap().err(),
+            Some(Rejected::TooLarge)
+        );
+
+        // the body is cut short
+        let (mut client, server) = connection();
+        client
+            .write_all(b"POST /games HTTP/1.1\r\nContent-Length: 10\r\n\r\n{}")
+            .unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        assert!(read_request(&server).is_err());
+    }
+
+    #[test]
+    fn serve_malformed_request() {
+        let (mut client, server) = connection();
+        client.write_all(b"nonsense\r\n\r\n").unwrap();
+        HttpServer::new(Arc::new(GameManager::new()))
+            .serve(server)
+            .unwrap();
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(response.ends_with("\r\n\r\n{\"error\":\"malformed request\"}"));
+    }
+
+    #[test]
+    fn routes() {
+        let server = HttpServer::new(Arc::new(GameManager::new()));
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .id(3)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+
+        let response = server.handle(&request("GET", "/games", Value::Null));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, json!([]));
+        let unsigned = GameChain::new(chain.challenge().clone());
+        let unsigned = json!({ "chain": base58(&unsigned.as_bytes()) });
+        let response = server.handle(&request("POST", "/games", unsigned));
+        assert_eq!(response.status, 422);
+        let import = json!({ "chain": base58(&chain.as_bytes()) });
+        let response = server.handle(&request("POST", "/games", import.clone()));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, json!({ "id": 3 }));
+        let response = server.handle(&request("POST", "/games", import));
+        assert_eq!(response.status, 409);
+
+        let response = server.handle(&request("GET", "/games/3", Value::Null));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, chain_json(&chain));
+        let summary = json!([{ "id": 3, "summary": chain.to_string() }]);
+        let fingerprint = PublicKey::of(&black).fingerprint();
+        let target = format!("/games?player={}", fingerprint);
+        let response = server.handle(&request("GET", &target, Value::Null));
+        assert_eq!(response.body, summary);
+        let response = server.handle(&request("GET", "/games?player=nobody", Value::Null));
+        assert_eq!(response.body, json!([]));
+
+        // black accepts and white plays e4, both signed elsewhere
+        chain.accept(&black).unwrap();
+        let accept_len = chain.challenge().accept_len();
+        let offset = chain.challenge().encoded_len() + accept_len;
+        let accept = base58(&chain.as_bytes()[offset..offset + accept_len]);
+        let response = server.handle(&request(
+            "POST",
+            "/games/3",
+            json!({ "kind": "accept", "block": accept }),
+        ));
+        assert_eq!(response.status, 200, "{}", response.body);
+        let e4 = Action::MakeMove(notation::parse_uci("e2e4").unwrap());
+        chain.make_move_block(&white, e4).unwrap();
+        let block = json!({ "kind": "move", "block": base58(&chain.moves()[0].as_bytes()) });
+        let response = server.handle(&request("POST", "/games/3", block.clone()));
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, chain_json(&chain));
+        chain.take_vacation(&black, Color::Black, 100, 200).unwrap();
+        let (_, vacation) = &chain.extensions()[0];
+        let vacation = json!({ "kind": "extension", "block": base58(&vacation.as_bytes()) });
+        let response = server.handle(&request("POST", "/games/3", vacation));
+        assert_eq!(response.status, 200);
+        assert!(server
+            .manager
+            .get(3)
+            .unwrap()
+            .on_vacation(Color::Black, 150));
+        // appended twice, the move is out of turn
+        let response = server.handle(&request("POST", "/games/3", block.clone()));
+        assert_eq!(response.status, 422);
+        let response = server.handle(&request("POST", "/games/9", block));
+        assert_eq!(response.status, 404);
+    }
+
+    #[test]
+    fn malformed_routes() {
+        let server = HttpServer::new(Arc::new(GameManager::new()));
+        for (method, target, body, status) in vec![
+            ("GET", "/", Value::Null, 404),
+            ("GET", "/games/3/moves", Value::Null, 404),
+            ("GET", "/games/three", Value::Null, 404),
+            ("POST", "/games/three", json!({ "block": "1" }), 404),
+            ("GET", "/games/3", Value::Null, 404),
+            ("DELETE", "/games", Value::Null, 405),
+            ("PUT", "/games/3", Value::Null, 405),
+            ("POST", "/games", Value::Null, 400),
+            ("POST", "/games", json!({ "chain": 3 }), 400),
+            ("POST", "/games", json!({ "chain": "0OIl" }), 400),
+            ("POST", "/games", json!({ "chain": "1" }), 422),
+            ("POST", "/games/3", json!({ "chain": "1" }), 400),
+            ("POST", "/games/3", json!({ "block": "1" }), 400),
+            (
+                "POST",
+                "/games/3",
+                json!({ "kind": "resign", "block": "1" }),
+                400,
+            ),
+            (
+                "POST",
+                "/games/3",
+                json!({ "kind": "move", "block": 1 }),
+                400,
+            ),
+        ] {
+            let response = server.handle(&request(method, target, body));
+            assert_eq!(response.status, status, "{} {}", method, target);
+            assert!(response.body["error"].is_string());
+        }
+
+        let rng = crypto::new_rng();
+        let challenge = ChallengeBlockBuilder::new(
+            &PublicKey::of(&crypto::generate_key(&rng)),
+            &PublicKey::of(&crypto::generate_key(&rng)),
+        )
+        .id(3)
+        .build()
+        .unwrap();
+        server.manager.create(challenge).unwrap();
+        let block = json!({ "kind": "move", "block": "1" });
+        let response = server.handle(&request("POST", "/games/3", block));
+        assert_eq!(response.status, 422);
+        assert_eq!(
+            response.body["error"],
+            Error::Malformed {
+                block: "move",
+                reason: "not enough bytes",
+            }
+            .to_string()
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Who a node is. Most nodes hold a key pair and sign with it, but archive
+//! mirrors and verification services can run watch-only: they receive,
+//! verify, store, relay and serve chains without any private key. Signing
+//! goes through `Identity::signer`, so a watch-only node has nothing to sign
+//! with and every signing call fails with WatchOnly.
+
+use crate::error::Error;
+use crate::key::PublicKey;
+
+use ring::signature::Ed25519KeyPair;
+
+pub enum Identity {
+    Signing(Ed25519KeyPair),
+    WatchOnly,
+}
+
+impl Identity {
+    /// The key pair to sign with. Fails with WatchOnly if there is none.
+    pub fn signer(&self) -> Result<&Ed25519KeyPair, Error> {
+        match self {
+            Identity::Signing(key_pair) => Ok(key_pair),
+            Identity::WatchOnly => Err(Error::WatchOnly),
+        }
+    }
+
+    pub fn public_key(&self) -> Option<PublicKey> {
+        match self {
+            Identity::Signing(key_pair) => Some(PublicKey::of(key_pair)),
+            Identity::WatchOnly => None,
+        }
+    }
+
+    pub fn is_watch_only(&self) -> bool {
+        match self {
+            Identity::Signing(_) => false,
+            Identity::WatchOnly => true,
+        }
+    }
+}
+
+impl From<Ed25519KeyPair> for Identity {
+    fn from(key_pair: Ed25519KeyPair) -> Identity {
+        Identity::Signing(key_pair)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlock, GameChain};
+    use crate::crypto;
+
+    #[test]
+    fn watch_only_cannot_sign() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+
+        let watcher = Identity::WatchOnly;
+        assert!(watcher.is_watch_only());
+        assert_eq!(watcher.public_key(), None);
+        assert_eq!(watcher.signer().err(), Some(Error::WatchOnly));
+
+        // it can still check and keep what others signed
+        let copy = GameChain::from_bytes(&chain.as_bytes()).unwrap();
+        assert!(copy.verify());
+
+        let player = Identity::from(white);
+        assert!(!player.is_watch_only());
+        assert_eq!(
+            player.public_key(),
+            Some(chain.challenge().white_public_key())
+        );
+        assert!(player.signer().is_ok());
+    }
+}
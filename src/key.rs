@@ -0,0 +1,149 @@
+//! Public keys as a type of their own rather than loose byte arrays, so a
+//! key can't be mixed up with a hash or an id of the same length. Equality
+//! runs in constant time, keys print as base58, and parsing checks the
+//! length.
+
+use crate::crypto;
+use crate::error::Error;
+
+use alloc::string::String;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::str::FromStr;
+use ring::constant_time;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+/// The length of an Ed25519 public key.
+pub const PUBLIC_KEY_LEN: usize = 32;
+
+#[derive(Clone, Copy)]
+pub struct PublicKey([u8; PUBLIC_KEY_LEN]);
+
+impl PublicKey {
+    /// Fails with InvalidKey unless `bytes` is exactly a key long.
+    pub fn from_slice(bytes: &[u8]) -> Result<PublicKey, Error> {
+        if bytes.len() != PUBLIC_KEY_LEN {
+            return Err(Error::InvalidKey);
+        }
+        let mut key = [0; PUBLIC_KEY_LEN];
+        key.copy_from_slice(bytes);
+        Ok(PublicKey(key))
+    }
+
+    pub fn of(key_pair: &Ed25519KeyPair) -> PublicKey {
+        let mut key = [0; PUBLIC_KEY_LEN];
+        key.copy_from_slice(key_pair.public_key().as_ref());
+        PublicKey(key)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; PUBLIC_KEY_LEN] {
+        &self.0
+    }
+
+    /// Parses a base58 key.
+    pub fn from_base58(text: &str) -> Result<PublicKey, Error> {
+        let bytes = bs58::decode(text)
+            .into_vec()
+            .map_err(|_| Error::InvalidKey)?;
+        PublicKey::from_slice(&bytes)
+    }
+
+    pub fn to_base58(&self) -> String {
+        bs58::encode(&self.0[..]).into_string()
+    }
+
+    /// The short identifier from crypto::fingerprint.
+    pub fn fingerprint(&self) -> String {
+        crypto::fingerprint(&self.0)
+    }
+
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        crypto::verify(&self.0, message, signature)
+    }
+}
+
+impl AsRef<[u8]> for PublicKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<[u8; PUBLIC_KEY_LEN]> for PublicKey {
+    fn from(bytes: [u8; PUBLIC_KEY_LEN]) -> PublicKey {
+        PublicKey(bytes)
+    }
+}
+
+impl PartialEq for PublicKey {
+    fn eq(&self, other: &PublicKey) -> bool {
+        constant_time::verify_slices_are_equal(&self.0, &other.0).is_ok()
+    }
+}
+
+impl Eq for PublicKey {}
+
+impl PartialEq<[u8]> for PublicKey {
+    fn eq(&self, other: &[u8]) -> bool {
+        constant_time::verify_slices_are_equal(&self.0, other).is_ok()
+    }
+}
+
+impl PartialEq<[u8; PUBLIC_KEY_LEN]> for PublicKey {
+    fn eq(&self, other: &[u8; PUBLIC_KEY_LEN]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl Hash for PublicKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state)
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_base58())
+    }
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PublicKey({})", self.to_base58())
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<PublicKey, Error> {
+        PublicKey::from_base58(text)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_and_compare_keys() {
+        let key_pair = crypto::generate_key(&crypto::new_rng());
+        let key = PublicKey::of(&key_pair);
+        assert_eq!(key, *key_pair.public_key().as_ref());
+        assert_eq!(key.to_string().parse::<PublicKey>(), Ok(key));
+        assert_eq!(
+            PublicKey::from_slice(key_pair.public_key().as_ref()),
+            Ok(key)
+        );
+        assert_eq!(PublicKey::from_slice(&[0; 31]), Err(Error::InvalidKey));
+        assert_eq!("0OIl".parse::<PublicKey>(), Err(Error::InvalidKey));
+        assert_eq!(
+            PublicKey::from_base58(&bs58::encode(&[1; 33][..]).into_string()),
+            Err(Error::InvalidKey)
+        );
+
+        let mut other = *key.as_bytes();
+        other[31] ^= 1;
+        assert_ne!(key, PublicKey::from(other));
+        assert!(key != other);
+    }
+}
@@ -0,0 +1,232 @@
+//! Agreeing on a lag allowance. Before a timed game, peers that speak wire
+//! version 2 ping each other, each proposes half its median round trip as the
+//! time a move spends in flight, and both settle on the smaller proposal so
+//! neither side can inflate it alone. The agreed allowance goes in the
+//! challenge, where it comes off every think when clocks are read, so anyone
+//! checking a time forfeit later counts time the same way.
+
+use crate::block::ChallengeBlock;
+use crate::error::Error;
+use crate::message::Message;
+#[cfg(feature = "net")]
+use crate::transport::Channel;
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+#[cfg(feature = "net")]
+use std::{io, time::Instant};
+
+/// The largest lag allowance proposed, however slow the link.
+pub const MAX_LAG_ALLOWANCE_MS: u16 = 5_000;
+
+/// The allowance `round_trips_ms` call for: half the median, rounded up.
+pub fn allowance_for(round_trips_ms: &[u64]) -> u16 {
+    if round_trips_ms.is_empty() {
+        return 0;
+    }
+    let mut sorted = round_trips_ms.to_vec();
+    sorted.sort_unstable();
+    let median = sorted[sorted.len() / 2];
+    median.div_ceil(2).min(u64::from(MAX_LAG_ALLOWANCE_MS)) as u16
+}
+
+/// The allowance both peers settle on.
+pub fn agree(ours: u16, theirs: u16) -> u16 {
+    ours.min(theirs)
+}
+
+/// Checks that `challenge` allows no more lag than was agreed.
+pub fn check_challenge(challenge: &ChallengeBlock, agreed_ms: u16) -> Result<(), Error> {
+    if challenge.lag_allowance_ms() > agreed_ms {
+        return Err(Error::InvalidChallenge(
+            "lag allowance is more than was agreed",
+        ));
+    }
+    Ok(())
+}
+
+/// One side of a lag measurement, as a state machine doing no I/O. Pings go
+/// out one at a time; once they've all been answered the proposal is sent.
+/// The peer's pings are answered throughout.
+#[derive(Clone, Debug)]
+pub struct LagProbe {
+    pings: usize,
+    // the nonce of the ping waiting for a pong, and when it was sent
+    outstanding: Option<(u64, u64)>,
+    round_trips_ms: Vec<u64>,
+    theirs: Option<u16>,
+    outgoing: VecDeque<Message>,
+}
+
+impl LagProbe {
+    /// A probe that measures `pings` round trips, sending the first ping at
+    /// `now_ms`.
+    pub fn new(pings: usize, now_ms: u64) -> LagProbe {
+        let mut probe = LagProbe {
+            pings,
+            outstanding: None,
+            round_trips_ms: Vec::new(),
+            theirs: None,
+            outgoing: VecDeque::new(),
+        };
+        probe.send_next(now_ms);
+        probe
+    }
+
+    fn send_next(&mut self, now_ms: u64) {
+        let nonce = self.round_trips_ms.len() as u64;
+        if self.round_trips_ms.len() < self.pings {
+            self.outstanding = Some((nonce, now_ms));
+            self.outgoing.push_back(Message::Ping { nonce });
+        } else {
+            self.outstanding = None;
+            self.outgoing.push_back(Message::LagProposal {
+                allowance_ms: self.proposal_ms(),
+            });
+        }
+    }
+
+    /// The next message to send, if any.
+    pub fn poll_transmit(&mut self) -> Option<Message> {
+        self.outgoing.pop_front()
+    }
+
+    /// Handles a message from the peer that arrived at `now_ms`. Anything
+    /// else the peer sends is ignored.
+    pub fn handle(&mut self, message: Message, now_ms: u64) {
+        match message {
+            Message::Ping { nonce } => self.outgoing.push_back(Message::Pong { nonce }),
+            Message::Pong { nonce } => {
+                if let Some((outstanding, sent_at)) = self.outstanding {
+                    if nonce == outstanding {
+                        self.round_trips_ms.push(now_ms.saturating_sub(sent_at));
+                        self.send_next(now_ms);
+                    }
+                }
+            }
+            Message::LagProposal { allowance_ms } => self.theirs = Some(allowance_ms),
+            _ => {}
+        }
+    }
+
+    pub fn round_trips_ms(&self) -> &[u64] {
+        &self.round_trips_ms
+    }
+
+    /// What we propose from the round trips measured so far.
+    pub fn proposal_ms(&self) -> u16 {
+        allowance_for(&self.round_trips_ms)
+    }
+
+    /// The agreed allowance, once our pings are done and the peer's proposal
+    /// has arrived.
+    pub fn agreed_ms(&self) -> Option<u16> {
+        if self.round_trips_ms.len() < self.pings {
+            return None;
+        }
+        Some(agree(self.proposal_ms(), self.theirs?))
+    }
+}
+
+/// Measures `pings` round trips over `channel` and agrees an allowance with
+/// the peer, who must be doing the same. Run it before the game session
+/// starts, since other messages are dropped meanwhile.
+#[cfg(feature = "net")]
+pub fn measure<C: Channel>(channel: &mut C, pings: usize) -> io::Result<u16> {
+    let start = Instant::now();
+    let now_ms = || start.elapsed().as_millis() as u64;
+    let mut probe = LagProbe::new(pings, now_ms());
+    loop {
+        while let Some(message) = probe.poll_transmit() {
+            channel.send_message(&message)?;
+        }
+        if let Some(agreed) = probe.agreed_ms() {
+            return Ok(agreed);
+        }
+        let message = channel.recv_message()?;
+        probe.handle(message, now_ms());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlockBuilder, GameChain};
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+    use ring::signature::Ed25519KeyPair;
+
+    #[test]
+    fn agree_on_lag() {
+        assert_eq!(allowance_for(&[]), 0);
+        assert_eq!(allowance_for(&[90, 301, 120]), 60);
+        assert_eq!(allowance_for(&[60_000]), MAX_LAG_ALLOWANCE_MS);
+
+        // messages take 100ms each way by the second peer's clock, and 250ms
+        // by the first's, which runs fast
+        let mut a = LagProbe::new(3, 0);
+        let mut b = LagProbe::new(3, 0);
+        let mut now = 0;
+        while a.agreed_ms().is_none() || b.agreed_ms().is_none() {
+            now += 100;
+            let to_b: Vec<_> = core::iter::from_fn(|| a.poll_transmit()).collect();
+            let to_a: Vec<_> = core::iter::from_fn(|| b.poll_transmit()).collect();
+            for message in to_b {
+                b.handle(message, now);
+            }
+            for message in to_a {
+                a.handle(message, now * 5 / 2);
+            }
+        }
+        assert_eq!(a.round_trips_ms(), [500, 500, 500]);
+        assert_eq!(b.round_trips_ms(), [200, 200, 200]);
+        assert_eq!(a.agreed_ms(), Some(100));
+        assert_eq!(b.agreed_ms(), Some(100));
+
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let builder = || {
+            ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+                .timestamp(1_000)
+        };
+        assert_eq!(
+            builder().lag_allowance(100).build(),
+            Err(Error::InvalidChallenge(
+                "lag allowance needs a time control"
+            ))
+        );
+        let challenge = builder()
+            .time_control(60, 0)
+            .lag_allowance(100)
+            .build()
+            .unwrap();
+        assert_eq!(
+            ChallengeBlock::from_bytes(&challenge.as_bytes()),
+            Ok(challenge.clone())
+        );
+        assert!(check_challenge(&challenge, 100).is_ok());
+        assert!(check_challenge(&challenge, 99).is_err());
+
+        // black's 50 second think counts as 49.9
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let play = |chain: &mut GameChain, key_pair: &Ed25519KeyPair, uci: &str| {
+            let mv = notation::parse_uci(uci).unwrap();
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        };
+        play(&mut chain, &white, "e2e4");
+        chain.acknowledge_move(&black, 1_000).unwrap();
+        play(&mut chain, &black, "e7e5");
+        chain.acknowledge_move(&white, 1_050).unwrap();
+        play(&mut chain, &white, "g1f3");
+        chain.acknowledge_move(&black, 1_060).unwrap();
+        assert_eq!(chain.clock_at(1_060).unwrap().used_seconds, 49);
+        assert_eq!(chain.clock_at(1_071).unwrap().used_seconds, 60);
+    }
+}
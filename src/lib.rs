@@ -1,2 +1,112 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod adjournment;
+pub mod adjudication;
+pub mod analysis;
+#[cfg(feature = "animation")]
+pub mod animation;
+pub mod audit;
+#[cfg(feature = "net")]
+pub mod autopilot;
+#[cfg(feature = "store")]
+pub mod backup;
 pub mod block;
+#[cfg(feature = "interop")]
+pub mod bot;
+#[cfg(feature = "std")]
+pub mod bulk;
+pub mod bundle;
+pub mod checkpoint;
+pub mod clock;
+pub mod commentary;
+pub mod commitment;
+pub mod compact;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod conditional;
+#[cfg(feature = "interop")]
+pub mod conformance;
+#[cfg(feature = "std")]
+pub mod contacts;
 pub mod crypto;
+#[cfg(feature = "rpc")]
+pub mod deadline;
+pub mod diff;
+pub mod dispute;
+#[cfg(all(feature = "net", feature = "store"))]
+pub mod dropfolder;
+pub mod eco;
+#[cfg(feature = "email")]
+pub mod email;
+pub mod epd;
+pub mod error;
+pub mod event;
+pub mod extension;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod forfeit;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod heartbeat;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod identity;
+pub mod key;
+pub mod lag;
+pub mod link;
+#[cfg(feature = "net")]
+pub mod manager;
+pub mod message;
+pub mod metrics;
+#[cfg(feature = "testing")]
+pub mod mock_network;
+pub mod network;
+pub mod notary;
+pub mod notation;
+pub mod offline;
+pub mod open_challenge;
+#[cfg(feature = "store")]
+pub mod outbox;
+pub mod pairing;
+pub mod profile;
+pub mod proof;
+pub mod provenance;
+#[cfg(all(feature = "rpc", feature = "store"))]
+pub mod publish;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "store")]
+pub mod query;
+#[cfg(feature = "std")]
+pub mod ratings;
+pub mod receipt;
+pub mod render;
+pub mod revocation;
+pub mod rotation;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+pub mod salvage;
+pub mod seeding;
+#[cfg(feature = "net")]
+pub mod session;
+pub mod sql;
+pub mod stats;
+#[cfg(feature = "store")]
+pub mod store;
+pub mod takeback;
+pub mod test_vectors;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tournament;
+#[cfg(feature = "net")]
+pub mod transport;
+pub mod vacation;
+pub mod view;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "std")]
+pub use bulk::verify_all;
+pub use error::Error;
@@ -0,0 +1,151 @@
+//! lineage:// links for sharing challenges over chat or email. A link holds
+//! the challenge block in base58, optionally followed by the address of a
+//! node to meet the challenger at:
+//!
+//! lineage://challenge/<challenge>?via=<host:port>
+
+use crate::block::ChallengeBlock;
+use crate::error::Error;
+
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::str::FromStr;
+
+pub const SCHEME: &str = "lineage://";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChallengeLink {
+    pub challenge: ChallengeBlock,
+    /// Where to reach the challenger, if they gave an address.
+    pub rendezvous: Option<String>,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "link",
+        reason,
+    }
+}
+
+/// Addresses go into links unescaped, so they can't contain characters
+/// that end a URI component.
+fn valid_address(address: &str) -> bool {
+    !address.is_empty()
+        && !address
+            .chars()
+            .any(|c| c.is_whitespace() || c == '?' || c == '&' || c == '#' || c == '/')
+}
+
+impl ChallengeLink {
+    pub fn new(challenge: ChallengeBlock) -> ChallengeLink {
+        ChallengeLink {
+            challenge,
+            rendezvous: None,
+        }
+    }
+
+    pub fn rendezvous(mut self, address: &str) -> Result<ChallengeLink, Error> {
+        if !valid_address(address) {
+            return Err(malformed("bad rendezvous address"));
+        }
+        self.rendezvous = Some(address.into());
+        Ok(self)
+    }
+
+    pub fn parse(uri: &str) -> Result<ChallengeLink, Error> {
+        let rest = uri
+            .trim()
+            .strip_prefix(SCHEME)
+            .ok_or_else(|| malformed("not a lineage:// link"))?;
+        let rest = rest
+            .strip_prefix("challenge/")
+            .ok_or_else(|| malformed("unknown link type"))?;
+        let (encoded, query) = match rest.find('?') {
+            Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+            None => (rest, None),
+        };
+        let bytes = bs58::decode(encoded.trim_end_matches('/'))
+            .into_vec()
+            .map_err(|_| malformed("bad base58"))?;
+        let mut link = ChallengeLink::new(ChallengeBlock::from_bytes(&bytes)?);
+        if let Some(query) = query {
+            for pair in query.split('&') {
+                match pair.find('=').map(|i| (&pair[..i], &pair[i + 1..])) {
+                    Some(("via", address)) => link = link.rendezvous(address)?,
+                    // unknown parameters are left for newer clients
+                    Some(_) => {}
+                    None => return Err(malformed("bad query")),
+                }
+            }
+        }
+        Ok(link)
+    }
+}
+
+impl fmt::Display for ChallengeLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}challenge/{}",
+            SCHEME,
+            bs58::encode(self.challenge.as_bytes()).into_string()
+        )?;
+        if let Some(address) = &self.rendezvous {
+            write!(f, "?via={}", address)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ChallengeLink {
+    type Err = Error;
+
+    fn from_str(uri: &str) -> Result<ChallengeLink, Error> {
+        ChallengeLink::parse(uri)
+    }
+}
+
+impl ChallengeBlock {
+    /// A lineage:// link to this challenge.
+    pub fn to_link(&self) -> String {
+        ChallengeLink::new(self.clone()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlockBuilder;
+    use crate::crypto;
+    use crate::key::PublicKey;
+
+    #[test]
+    fn round_trip_links() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .id(7)
+            .build()
+            .unwrap();
+
+        let uri = challenge.to_link();
+        assert!(uri.starts_with("lineage://challenge/"));
+        assert_eq!(ChallengeLink::parse(&uri).unwrap().challenge, challenge);
+
+        let link = ChallengeLink::new(challenge)
+            .rendezvous("node.example.org:10153")
+            .unwrap();
+        let uri = link.to_string();
+        assert!(uri.ends_with("?via=node.example.org:10153"));
+        assert_eq!(uri.parse::<ChallengeLink>().unwrap(), link);
+        assert_eq!(
+            ChallengeLink::parse(&format!("{}&lang=en", uri)).unwrap(),
+            link
+        );
+
+        assert!(link.clone().rendezvous("two words").is_err());
+        assert!(ChallengeLink::parse("https://example.org").is_err());
+        assert!(ChallengeLink::parse("lineage://challenge/0OIl").is_err());
+    }
+}
@@ -2,15 +2,95 @@ extern crate lineage;
 
 extern crate bs58;
 extern crate ring;
-extern crate untrusted;
 
-//use std::io::prelude::*;
-
-//use std::net::{TcpListener, TcpStream};
-
-use ring::signature::KeyPair;
+use lineage::clock::Clock;
 
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("daemon") => {
+            daemon(args.get(2).map_or("127.0.0.1:10153", String::as_str));
+            return;
+        }
+        Some("serve") => {
+            serve(args.get(2).map_or("127.0.0.1:8080", String::as_str));
+            return;
+        }
+        Some("show") if args.len() == 3 => {
+            show(&args[2]);
+            return;
+        }
+        Some("games") if args.len() >= 3 => {
+            games(&args[2], &args[3..]);
+            return;
+        }
+        Some("backup") if args.len() >= 3 => {
+            backup(&args[2], &args[3..]);
+            return;
+        }
+        Some("restore") if args.len() == 3 || args.len() == 4 => {
+            restore(&args[2], args.get(3).map_or(".", String::as_str));
+            return;
+        }
+        Some("gc") => {
+            gc(&args[2..]);
+            return;
+        }
+        Some("challenge") if args.len() == 4 => {
+            challenge(&args[2], &args[3]);
+            return;
+        }
+        Some("simul") if args.len() >= 4 => {
+            simul(&args[2], &args[3..]);
+            return;
+        }
+        Some("contacts") => {
+            contacts(&args[2..]);
+            return;
+        }
+        Some("mail") => {
+            mail(&args[2..]);
+            return;
+        }
+        Some("sync") if args.len() >= 3 => {
+            sync(&args[2], args.get(3).map(String::as_str) == Some("--watch"));
+            return;
+        }
+        Some("open") if args.len() == 3 => {
+            open(&args[2]);
+            return;
+        }
+        Some("epd") if args.len() == 3 || args.len() == 4 => {
+            epd(&args[2], args.get(3).map(String::as_str));
+            return;
+        }
+        Some("export-sql") if args.len() <= 3 => {
+            export_sql(args.get(2).map(String::as_str));
+            return;
+        }
+        Some("test-vectors") => {
+            test_vectors();
+            return;
+        }
+        Some("inspect") if args.len() == 3 => {
+            inspect(&args[2]);
+            return;
+        }
+        Some("salvage") if args.len() == 4 => {
+            salvage(&args[2], &args[3]);
+            return;
+        }
+        Some("conformance") if args.len() >= 3 => {
+            conformance(&args[2..]);
+            return;
+        }
+        Some("sign") if args.len() == 5 => {
+            sign(&args[2], &args[3], &args[4]);
+            return;
+        }
+        _ => {}
+    }
+
     println!("setting up RNG...");
     let rng = lineage::crypto::new_rng();
 
@@ -20,8 +100,8 @@ fn main() {
     let black = lineage::crypto::generate_key(&rng);
 
     let challenge = lineage::block::ChallengeBlock::new(
-        white.public_key().as_ref(),
-        black.public_key().as_ref(),
+        &lineage::key::PublicKey::of(&white),
+        &lineage::key::PublicKey::of(&black),
     );
 
     let mut _chain = lineage::block::GameChain::new(challenge);
@@ -31,16 +111,858 @@ fn main() {
         dbg!(m.to_string());
         dbg!(m);
     }
+}
+
+/// Opens the local store in LINEAGE_STORE, defaulting to ./games.
+fn open_store() -> std::io::Result<lineage::store::GameStore> {
+    let dir = std::env::var("LINEAGE_STORE").unwrap_or_else(|_| "games".into());
+    lineage::store::GameStore::open(dir)
+}
+
+/// Prints a summary of the chain saved at `path`, with its opening.
+fn show(path: &str) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let chain = lineage::block::GameChain::from_bytes(&std::fs::read(path)?)?;
+        let contacts =
+            lineage::contacts::AddressBook::load(lineage::contacts::AddressBook::default_path())?;
+        println!("{}", chain.with_contacts(&contacts));
+        if let Some(opening) = chain.opening() {
+            println!("opening: {}", opening);
+        }
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not read chain: {}", error);
+    }
+}
+
+/// Prints the positions of the chain saved at `path` as EPD, or only the
+/// position after `move_index` moves.
+fn epd(path: &str, move_index: Option<&str>) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let chain = lineage::block::GameChain::from_bytes(&std::fs::read(path)?)?;
+        match move_index {
+            Some(move_index) => {
+                let move_index = move_index.parse()?;
+                let line = chain
+                    .to_epd(move_index)
+                    .ok_or_else(|| format!("no position after {} moves", move_index))?;
+                println!("{}", line);
+            }
+            None => print!("{}", chain.to_epd_file()),
+        }
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not export chain: {}", error);
+    }
+}
+
+/// Writes every stored game as a SQLite script to `path`, or to stdout.
+fn export_sql(path: Option<&str>) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let chains = open_store()?.load_all()?;
+        let sql = lineage::sql::export(&chains);
+        match path {
+            Some(path) => std::fs::write(path, sql)?,
+            None => print!("{}", sql),
+        }
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not export games: {}", error);
+    }
+}
+
+/// Checks the interop test vectors and prints them, one per line as the name
+/// and hex, for other implementations to test against.
+fn test_vectors() {
+    use lineage::test_vectors;
+
+    if let Err(error) = test_vectors::check_vectors() {
+        eprintln!("test vectors don't reproduce: {}", error);
+        return;
+    }
+    println!("white seed: {:02x}", test_vectors::WHITE_SEED[0]);
+    println!("black seed: {:02x}", test_vectors::BLACK_SEED[0]);
+    for vector in test_vectors::VECTORS.iter() {
+        println!("{} ({}): {}", vector.name, vector.description, vector.hex);
+    }
+}
+
+/// How long ago `then` was, e.g. "5m" or "3d".
+fn elapsed(now: u64, then: u64) -> String {
+    let seconds = now.saturating_sub(then);
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+/// Lists the games in the local store (LINEAGE_STORE, defaulting to
+/// ./games) played by the base58 public key `player`. Options: `--sort id`,
+/// `--sort turn` (games where it's your move first), or `--sort age` (least
+/// recently active first); `--my-turn` to show only games waiting on you;
+/// and `--status <status>` to show only games with that status.
+fn games(player: &str, options: &[String]) {
+    use lineage::block::GameStatus;
+
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let public_key = bs58::decode(player).into_vec()?;
+        let contacts =
+            lineage::contacts::AddressBook::load(lineage::contacts::AddressBook::default_path())?;
+        let mut summaries = open_store()?.summaries(&public_key, &contacts)?;
+
+        let mut options = options.iter().map(String::as_str);
+        while let Some(option) = options.next() {
+            match (option, options.next()) {
+                ("--sort", Some("id")) => summaries.sort_by_key(|summary| summary.id),
+                ("--sort", Some("turn")) => summaries.sort_by_key(|summary| !summary.my_turn),
+                ("--sort", Some("age")) => summaries.sort_by_key(|summary| summary.last_activity),
+                ("--my-turn", next) => {
+                    summaries.retain(|summary| summary.my_turn);
+                    if let Some(next) = next {
+                        return Err(format!("unexpected argument {}", next).into());
+                    }
+                }
+                ("--status", Some(status)) => summaries.retain(|summary| {
+                    let name = match summary.status {
+                        GameStatus::AwaitingAcceptance => "awaiting",
+                        GameStatus::InProgress => "in-progress",
+                        GameStatus::Adjourned => "adjourned",
+                        GameStatus::Disputed => "disputed",
+                        GameStatus::Finished(_) => "finished",
+                        GameStatus::Invalid => "invalid",
+                    };
+                    name == status
+                }),
+                _ => return Err(format!("unknown option {}", option).into()),
+            }
+        }
+
+        let now = lineage::clock::SystemClock.now();
+        println!(
+            "{:<8}  {:<20}  {:<20}  {:<8}  last move",
+            "id", "opponent", "status", "turn"
+        );
+        for summary in summaries {
+            println!(
+                "{:08x}  {:<20}  {:<20}  {:<8}  {}",
+                summary.id,
+                summary.opponent,
+                format!("{:?}", summary.status),
+                if summary.my_turn { "you" } else { "" },
+                summary
+                    .last_activity
+                    .map_or_else(|| "-".into(), |then| elapsed(now, then))
+            );
+        }
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not list games: {}", error);
+    }
+}
+
+/// The backup passphrase: LINEAGE_PASSPHRASE if set, or else a line read
+/// from stdin.
+fn passphrase() -> std::io::Result<String> {
+    if let Ok(passphrase) = std::env::var("LINEAGE_PASSPHRASE") {
+        return Ok(passphrase);
+    }
+    eprint!("passphrase: ");
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    Ok(line.trim_end_matches(&['\r', '\n'][..]).into())
+}
+
+/// Writes an encrypted archive of the config directory, the local store, and
+/// the given PKCS#8 key files to `path`. The store can be in use meanwhile.
+fn backup(path: &str, keys: &[String]) {
+    use lineage::backup::{Archive, Section};
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let mut archive = Archive::new();
+        for key in keys {
+            archive.add_file(Section::Key, key)?;
+        }
+        archive.add_dir(Section::Config, lineage::contacts::config_dir())?;
+        for skipped in archive.add_store(&open_store()?)? {
+            eprintln!("skipping {}: does not verify", skipped.display());
+        }
+        let passphrase = passphrase()?;
+        std::fs::write(path, archive.seal(&passphrase, &lineage::crypto::new_rng()))?;
+        println!("backed up {} files", archive.entries().len());
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not back up: {}", error);
+    }
+}
+
+/// Restores the archive at `path`, once every chain in it verifies: key files
+/// into `key_dir`, config files the config directory lacks, and chains merged
+/// into the local store.
+fn restore(path: &str, key_dir: &str) {
+    use lineage::backup::{Archive, Section};
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let archive = Archive::open(&std::fs::read(path)?, &passphrase()?)?;
+        let chains = archive.verify()?;
+        println!("{} chains verified", chains);
+        for written in archive.restore_files(Section::Key, key_dir)? {
+            println!("restored {}", written.display());
+        }
+        for written in archive.restore_files(Section::Config, lineage::contacts::config_dir())? {
+            println!("restored {}", written.display());
+        }
+        let changed = archive.restore_store(&open_store()?)?;
+        println!("{} games restored", changed);
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not restore: {}", error);
+    }
+}
+
+/// Removes old games from the local store. Options: `--finished-days <n>` to
+/// remove finished games idle for n days, `--only <key>` to remove games the
+/// base58 public key doesn't play in, `--drop-disputed` to let disputed games
+/// go too, and `--dry-run` to only list what would be removed.
+fn gc(options: &[String]) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let mut policy = lineage::store::RetentionPolicy::new();
+        let mut dry_run = false;
+        let mut options = options.iter().map(String::as_str);
+        while let Some(option) = options.next() {
+            match option {
+                "--finished-days" => {
+                    let days: u64 = options
+                        .next()
+                        .ok_or("--finished-days needs a value")?
+                        .parse()?;
+                    policy = policy.keep_finished_for(days * 24 * 60 * 60);
+                }
+                "--only" => {
+                    let key = options.next().ok_or("--only needs a public key")?;
+                    policy = policy.only_involving(&bs58::decode(key).into_vec()?);
+                }
+                "--drop-disputed" => policy = policy.keep_disputed(false),
+                "--dry-run" => dry_run = true,
+                _ => return Err(format!("unknown option {}", option).into()),
+            }
+        }
+        let store = open_store()?;
+        let now = lineage::clock::SystemClock.now();
+        let ids = if dry_run {
+            store.collectable(&policy, now)?
+        } else {
+            store.gc(&policy, now)?
+        };
+        for id in &ids {
+            println!("{:08x}", id);
+        }
+        println!(
+            "{} {} game(s)",
+            if dry_run { "would remove" } else { "removed" },
+            ids.len()
+        );
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not collect games: {}", error);
+    }
+}
+
+/// Challenges `opponent`, a contact's name or a base58 public key, to a game
+/// with the PKCS#8 key file `key` playing white. The accepted challenge is
+/// saved in the local store.
+fn challenge(opponent: &str, key: &str) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let contacts =
+            lineage::contacts::AddressBook::load(lineage::contacts::AddressBook::default_path())?;
+        let opponent = contact_or_key(&contacts, opponent)?;
+        let key_pair = lineage::crypto::key_from_pkcs8(&std::fs::read(key)?)?;
+        let challenge = lineage::block::ChallengeBlockBuilder::new(
+            &lineage::key::PublicKey::of(&key_pair),
+            &opponent,
+        )
+        .id(lineage::crypto::random_id(&lineage::crypto::new_rng()))
+        .build()?;
+        let mut chain = lineage::block::GameChain::new(challenge);
+        chain.accept(&key_pair)?;
+        open_store()?.save(&chain)?;
+        println!("{}", chain.with_contacts(&contacts));
+        println!("{}", chain.challenge().to_link());
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not create challenge: {}", error);
+    }
+}
+
+/// Looks up `name` in the address book, falling back to reading it as a
+/// base58 public key.
+fn contact_or_key(
+    contacts: &lineage::contacts::AddressBook,
+    name: &str,
+) -> Result<lineage::key::PublicKey, String> {
+    if let Some(public_key) = contacts.key(name) {
+        return Ok(lineage::key::PublicKey::from(*public_key));
+    }
+    lineage::key::PublicKey::from_base58(name)
+        .map_err(|_| format!("{} is not a contact or a key", name))
+}
+
+/// Challenges each opponent (a contact name or base58 key) to a game against
+/// the PKCS#8 key `key`, which plays white on every board, saving the games
+/// to the local store and printing a link for each opponent.
+fn simul(key: &str, opponents: &[String]) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let contacts =
+            lineage::contacts::AddressBook::load(lineage::contacts::AddressBook::default_path())?;
+        let opponents = opponents
+            .iter()
+            .map(|opponent| contact_or_key(&contacts, opponent))
+            .collect::<Result<Vec<_>, _>>()?;
+        let key_pair = lineage::crypto::key_from_pkcs8(&std::fs::read(key)?)?;
+        let manager = lineage::manager::GameManager::with_network(network());
+        let ids = manager.create_simul(
+            &key_pair,
+            chess::Color::White,
+            &opponents,
+            &lineage::crypto::new_rng(),
+        )?;
+        let store = open_store()?;
+        for id in ids {
+            let chain = manager.get(id).ok_or("game vanished")?;
+            store.save(&chain)?;
+            println!("{}", chain.with_contacts(&contacts));
+            println!("{}", chain.challenge().to_link());
+        }
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not start simul: {}", error);
+    }
+}
+
+/// Opens a lineage:// challenge link, saving the challenge in the local store
+/// so it can be accepted.
+fn open(uri: &str) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let link = lineage::link::ChallengeLink::parse(uri)?;
+        let contacts =
+            lineage::contacts::AddressBook::load(lineage::contacts::AddressBook::default_path())?;
+        let chain = lineage::block::GameChain::new(link.challenge);
+        open_store()?.save(&chain)?;
+        println!("{}", chain.with_contacts(&contacts));
+        if let Some(address) = link.rendezvous {
+            println!("challenger is reachable at {}", address);
+        }
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not open link: {}", error);
+    }
+}
+
+/// Syncs the local store with the shared folder `dir`, once or, with
+/// `--watch`, every few seconds until interrupted.
+fn sync(dir: &str, watch: bool) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let folder = lineage::dropfolder::DropFolder::open(dir)?;
+        let store = open_store()?;
+        let print = |report: &lineage::dropfolder::SyncReport| {
+            println!("imported {}, exported {}", report.imported, report.exported);
+            for path in &report.skipped {
+                println!("skipped {}", path.display());
+            }
+        };
+        if watch {
+            folder.watch(&store, std::time::Duration::from_secs(5), print)?;
+        } else {
+            print(&folder.sync(&store)?);
+        }
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("sync failed: {}", error);
+    }
+}
+
+/// Manages the address book: `contacts list`, `contacts add <name> <key>`,
+/// `contacts remove <name>`, `contacts import <file>`, or
+/// `contacts export <file>`.
+fn contacts(args: &[String]) {
+    use lineage::contacts::AddressBook;
+
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let path = AddressBook::default_path();
+        let mut book = AddressBook::load(&path)?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            [] | ["list"] => {
+                for (name, public_key) in book.iter() {
+                    println!("{:<20}  {}", name, bs58::encode(public_key).into_string());
+                }
+                return Ok(());
+            }
+            ["add", name, public_key] => book.add(name, &bs58::decode(public_key).into_vec()?)?,
+            ["remove", name] => {
+                book.remove(name)
+                    .ok_or_else(|| format!("no contact named {}", name))?;
+            }
+            ["import", file] => {
+                let count = book.import(&std::fs::read_to_string(file)?)?;
+                println!("imported {} contacts", count);
+            }
+            ["export", file] => {
+                std::fs::write(file, book.to_text())?;
+                return Ok(());
+            }
+            _ => return Err("unknown contacts command".into()),
+        }
+        book.save(&path)?;
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("contacts: {}", error);
+    }
+}
+
+/// Prints the chain saved at `path` block by block, with offsets, fields,
+/// and whether each signature verifies. Unlike show, this works on chains
+/// that fail verification.
+fn inspect(path: &str) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let bytes = std::fs::read(path)?;
+        let view = lineage::view::GameChainView::new(&bytes)?;
+        println!("offset   len  block");
+        for block in view.describe() {
+            println!("{}", block);
+        }
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not read chain: {}", error);
+    }
+}
+
+/// Recovers what verifies of the damaged chain at `path`, writing it to
+/// `output` and reporting what was dropped.
+fn salvage(path: &str, output: &str) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let salvaged = lineage::salvage::salvage(&std::fs::read(path)?)?;
+        match &salvaged.error {
+            None => println!("chain is intact"),
+            Some(error) => println!(
+                "{}: kept {} bytes with {} moves, dropped {} bytes in {} whole blocks",
+                error,
+                salvaged.kept_len,
+                salvaged.chain.move_count(),
+                salvaged.dropped_len,
+                salvaged.dropped_blocks
+            ),
+        }
+        std::fs::write(output, salvaged.chain.as_bytes())?;
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("could not salvage chain: {}", error);
+    }
+}
+
+/// Signs an exported pending block with a PKCS#8 key file, writing the raw
+/// signature to `output`, for use on a machine with no network access.
+fn sign(pending: &str, key: &str, output: &str) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let pending = lineage::offline::PendingBlock::load(pending)?;
+        let key_pair = lineage::crypto::key_from_pkcs8(&std::fs::read(key)?)?;
+        println!("signing {}", pending);
+        let signature = lineage::crypto::sign_detached(&key_pair, &pending)?;
+        std::fs::write(output, signature)?;
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("signing failed: {}", error);
+    }
+}
+
+/// Checks another implementation against the protocol, either one listening
+/// at `--tcp host:port` or a program (with any arguments) that speaks it over
+/// its standard input and output. Exits with status 1 on any deviation.
+fn conformance(args: &[String]) {
+    let result = (|| -> Result<lineage::conformance::Report, Box<dyn std::error::Error>> {
+        let key_pair = lineage::crypto::generate_key(&lineage::crypto::new_rng());
+        if args[0] == "--tcp" && args.len() == 2 {
+            let stream = std::net::TcpStream::connect(&args[1])?;
+            stream.set_read_timeout(Some(std::time::Duration::from_secs(30)))?;
+            let mut transport = lineage::transport::StreamTransport::new(stream);
+            Ok(lineage::conformance::run(&mut transport, &key_pair))
+        } else {
+            let mut command = std::process::Command::new(&args[0]);
+            command.args(&args[1..]);
+            let mut transport = lineage::transport::spawn(&mut command)?;
+            Ok(lineage::conformance::run(&mut transport, &key_pair))
+        }
+    })();
+    match result {
+        Ok(report) => {
+            for (step, outcome) in &report.results {
+                match outcome {
+                    lineage::conformance::Outcome::Passed => println!("{}: passed", step),
+                    lineage::conformance::Outcome::Deviated(deviation) => {
+                        println!("{}: {}", step, deviation)
+                    }
+                    lineage::conformance::Outcome::Skipped => println!("{}: skipped", step),
+                }
+            }
+            if !report.passed() {
+                std::process::exit(1);
+            }
+        }
+        Err(error) => {
+            eprintln!("couldn't reach the implementation: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The network named by LINEAGE_NETWORK (main, test, or dev), defaulting to
+/// main.
+fn network() -> lineage::network::Network {
+    let name = match std::env::var("LINEAGE_NETWORK") {
+        Ok(name) => name,
+        Err(_) => return lineage::network::Network::default(),
+    };
+    lineage::network::Network::from_name(&name).unwrap_or_else(|| {
+        eprintln!("unknown network {}, using main", name);
+        lineage::network::Network::default()
+    })
+}
+
+/// Plays by email: `mail send <game id> <address>` mails a stored game to the
+/// opponent, and `mail poll` merges games from unread mail into the store.
+/// The account is configured with LINEAGE_EMAIL (your address),
+/// LINEAGE_SMTP and LINEAGE_IMAP (host:port), and LINEAGE_EMAIL_USER and
+/// LINEAGE_EMAIL_PASSWORD.
+#[cfg(feature = "email")]
+fn mail(args: &[String]) {
+    let result = (|| -> Result<(), Box<dyn std::error::Error>> {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let config = lineage::email::EmailConfig {
+            address: var("LINEAGE_EMAIL"),
+            smtp: var("LINEAGE_SMTP"),
+            imap: var("LINEAGE_IMAP"),
+            username: var("LINEAGE_EMAIL_USER"),
+            password: var("LINEAGE_EMAIL_PASSWORD"),
+        };
+        let store = open_store()?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        match args.as_slice() {
+            ["send", id, to] => {
+                let chain = store.load(u32::from_str_radix(id, 16)?)?;
+                lineage::email::send(&config, to, &chain)?;
+                println!("sent game {:08x} to {}", chain.id(), to);
+            }
+            ["poll"] => {
+                let changed = lineage::email::poll(&config, &store)?;
+                println!("{} games updated", changed);
+            }
+            _ => return Err("usage: mail send <game id> <address> | mail poll".into()),
+        }
+        Ok(())
+    })();
+    if let Err(error) = result {
+        eprintln!("mail: {}", error);
+    }
+}
+
+#[cfg(not(feature = "email"))]
+fn mail(_args: &[String]) {
+    eprintln!("lineage was built without the email feature");
+}
+
+#[cfg(feature = "rpc")]
+fn daemon(addr: &str) {
+    use std::sync::Arc;
+
+    // LINEAGE_KEY is the node's PKCS#8 key file. LINEAGE_WATCH_ONLY=1 runs
+    // the node without a private key instead, e.g. as an archive mirror: it
+    // verifies, stores, relays and serves chains, but signs nothing
+    let pkcs8 = if std::env::var("LINEAGE_WATCH_ONLY").is_ok_and(|v| v == "1") {
+        None
+    } else {
+        let path = match std::env::var("LINEAGE_KEY") {
+            Ok(path) => path,
+            Err(_) => {
+                eprintln!("set LINEAGE_KEY to the node's key file, or LINEAGE_WATCH_ONLY=1");
+                return;
+            }
+        };
+        match std::fs::read(&path) {
+            Ok(pkcs8) => Some(pkcs8),
+            Err(error) => {
+                eprintln!("couldn't read the key file {}: {}", path, error);
+                return;
+            }
+        }
+    };
+    let key_pair = match pkcs8
+        .as_ref()
+        .map(|pkcs8| lineage::crypto::key_from_pkcs8(pkcs8))
+        .transpose()
+    {
+        Ok(key_pair) => key_pair,
+        Err(error) => {
+            eprintln!("couldn't load the node key: {}", error);
+            return;
+        }
+    };
+    match &key_pair {
+        Some(key_pair) => {
+            let public_key = lineage::key::PublicKey::of(key_pair);
+            println!(
+                "node key {} ({})",
+                public_key.to_base58(),
+                public_key.fingerprint()
+            )
+        }
+        None => println!("watch-only node, with no signing key"),
+    }
+    println!("listening for JSON-RPC on {}", addr);
+    let manager = Arc::new(lineage::manager::GameManager::with_network(network()));
+    println!("on the {} network", manager.network());
+    // LINEAGE_AUTO_RECEIPTS=1 signs receipts for incoming moves, and
+    // LINEAGE_AUTO_CLAIM_DAYS claims forfeits from opponents that many days
+    // out of time
+    let mut policy = lineage::autopilot::AutoPolicy::new()
+        .acknowledge_moves(std::env::var("LINEAGE_AUTO_RECEIPTS").is_ok_and(|v| v == "1"));
+    if let Some(days) = std::env::var("LINEAGE_AUTO_CLAIM_DAYS")
+        .ok()
+        .and_then(|days| days.parse::<u64>().ok())
+    {
+        policy = policy.claim_after(days * 24 * 60 * 60);
+    }
+    // LINEAGE_AUTO_ACCEPT=1 accepts challenges, limited to the address book
+    // by LINEAGE_AUTO_ACCEPT_CONTACTS=1, to time controls given as a comma
+    // separated list of base+increment seconds (or "none" for untimed) by
+    // LINEAGE_AUTO_ACCEPT_TIME_CONTROLS, and to a number of games underway
+    // by LINEAGE_AUTO_ACCEPT_MAX_GAMES
+    if std::env::var("LINEAGE_AUTO_ACCEPT").is_ok_and(|v| v == "1") {
+        let mut accept = lineage::autopilot::AcceptPolicy::new();
+        if std::env::var("LINEAGE_AUTO_ACCEPT_CONTACTS").is_ok_and(|v| v == "1") {
+            let path = lineage::contacts::AddressBook::default_path();
+            match lineage::contacts::AddressBook::load(&path) {
+                Ok(contacts) => accept = accept.contacts_only(&contacts),
+                Err(error) => {
+                    eprintln!("couldn't load contacts from {}: {}", path.display(), error);
+                    return;
+                }
+            }
+        }
+        if let Ok(time_controls) = std::env::var("LINEAGE_AUTO_ACCEPT_TIME_CONTROLS") {
+            for time_control in time_controls.split(',') {
+                match parse_time_control(time_control) {
+                    Some(time_control) => accept = accept.time_control(time_control),
+                    None => {
+                        eprintln!("couldn't parse time control {}", time_control);
+                        return;
+                    }
+                }
+            }
+        }
+        if let Some(games) = std::env::var("LINEAGE_AUTO_ACCEPT_MAX_GAMES")
+            .ok()
+            .and_then(|games| games.parse::<usize>().ok())
+        {
+            accept = accept.max_games(games);
+        }
+        policy = policy.accept_challenges(accept);
+    }
+    if policy != lineage::autopilot::AutoPolicy::default() {
+        let pkcs8 = match &pkcs8 {
+            Some(pkcs8) => pkcs8,
+            None => {
+                eprintln!("the autopilot signs, so it can't run on a watch-only node");
+                return;
+            }
+        };
+        let manager = Arc::clone(&manager);
+        let key_pair = lineage::crypto::key_from_pkcs8(pkcs8).unwrap();
+        std::thread::spawn(move || loop {
+            for action in
+                manager.apply_policy(&key_pair, &policy, lineage::clock::SystemClock.now())
+            {
+                println!("autopilot: {:?}", action);
+            }
+            std::thread::sleep(std::time::Duration::from_secs(60));
+        });
+    }
+    // LINEAGE_PUBLISH pushes finished games to archives, given as a comma
+    // separated list of http://host:port, node://host:port, or folders
+    if let Ok(endpoints) = std::env::var("LINEAGE_PUBLISH") {
+        let mut publisher = endpoints
+            .split(',')
+            .filter(|endpoint| !endpoint.is_empty())
+            .map(lineage::publish::Endpoint::parse)
+            .fold(lineage::publish::Publisher::new(), |publisher, endpoint| {
+                publisher.endpoint(endpoint)
+            });
+        let manager = Arc::clone(&manager);
+        std::thread::spawn(move || loop {
+            let now = lineage::clock::SystemClock.now();
+            publisher.offer_finished(&manager, now);
+            for delivery in publisher.publish(now) {
+                println!("published {:08x} to {:?}", delivery.id, delivery.endpoint);
+            }
+            std::thread::sleep(std::time::Duration::from_secs(60));
+        });
+    }
+    // LINEAGE_REMIND_AT reminds the player to move as their time falls below
+    // each of a comma separated list of seconds, posting every reminder to
+    // LINEAGE_WEBHOOKS, a comma separated list of http://host:port/path, and
+    // LINEAGE_DEADLINE_CLAIMS=1 claims forfeits as soon as deadlines pass
+    let claim_forfeits = std::env::var("LINEAGE_DEADLINE_CLAIMS").is_ok_and(|v| v == "1");
+    let remind_at = std::env::var("LINEAGE_REMIND_AT").unwrap_or_default();
+    if claim_forfeits || !remind_at.is_empty() {
+        let mut scheduler =
+            lineage::deadline::DeadlineScheduler::new().claim_forfeits(claim_forfeits);
+        for seconds in remind_at.split(',').filter(|seconds| !seconds.is_empty()) {
+            match seconds.parse() {
+                Ok(seconds) => scheduler = scheduler.remind_at(seconds),
+                Err(_) => {
+                    eprintln!("couldn't parse reminder threshold {}", seconds);
+                    return;
+                }
+            }
+        }
+        let webhooks: Vec<String> = std::env::var("LINEAGE_WEBHOOKS")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|url| !url.is_empty())
+            .map(String::from)
+            .collect();
+        let identity = match &pkcs8 {
+            Some(pkcs8) => {
+                lineage::identity::Identity::from(lineage::crypto::key_from_pkcs8(pkcs8).unwrap())
+            }
+            None => lineage::identity::Identity::WatchOnly,
+        };
+        let manager = Arc::clone(&manager);
+        std::thread::spawn(move || loop {
+            let now = lineage::clock::SystemClock.now();
+            for event in scheduler.tick(&manager, &identity, now) {
+                println!("deadlines: {:?}", event);
+                for url in &webhooks {
+                    if let Err(error) = lineage::deadline::post_webhook(url, &event) {
+                        eprintln!("webhook {}: {}", url, error);
+                    }
+                }
+            }
+            std::thread::sleep(std::time::Duration::from_secs(60));
+        });
+    }
+    // LINEAGE_METRICS (host:port) turns on a Prometheus endpoint at /metrics
+    if let Ok(metrics_addr) = std::env::var("LINEAGE_METRICS") {
+        println!("serving metrics on {}", metrics_addr);
+        std::thread::spawn(move || {
+            if let Err(error) = lineage::metrics::serve(&metrics_addr) {
+                eprintln!("metrics endpoint stopped: {}", error);
+            }
+        });
+    }
+    let mut server = match key_pair {
+        Some(key_pair) => lineage::rpc::RpcServer::new(manager, key_pair),
+        None => lineage::rpc::RpcServer::watch_only(manager),
+    };
+    // LINEAGE_OUTBOX=1 keeps chains queued with send_chain in the local
+    // store's outbox, retrying each peer until it takes them
+    if std::env::var("LINEAGE_OUTBOX").is_ok_and(|v| v == "1") {
+        let outbox = match open_store().and_then(|store| lineage::outbox::Outbox::open(&store)) {
+            Ok(outbox) => Arc::new(outbox),
+            Err(error) => {
+                eprintln!("couldn't open the outbox: {}", error);
+                return;
+            }
+        };
+        server = server.outbox(Arc::clone(&outbox));
+        // a watch-only node relays without introducing itself
+        let public_key = pkcs8.as_ref().map(|pkcs8| {
+            let key_pair = lineage::crypto::key_from_pkcs8(pkcs8).unwrap();
+            *lineage::key::PublicKey::of(&key_pair).as_bytes()
+        });
+        std::thread::spawn(move || loop {
+            use lineage::transport::Channel;
+
+            let now = lineage::clock::SystemClock.now();
+            let report = outbox.flush(now, |to, message| {
+                let mut transport = lineage::transport::connect_tcp(to)?;
+                if let Some(public_key) = public_key {
+                    transport.send_message(&lineage::message::Message::Hello {
+                        public_key,
+                        wire_version: lineage::message::WIRE_VERSION,
+                    })?;
+                }
+                transport.send_message(message)
+            });
+            match report {
+                Ok(report) => {
+                    for seq in report.delivered {
+                        println!("delivered outbox message {}", seq);
+                    }
+                    for seq in report.quarantined {
+                        eprintln!("outbox: set aside undecodable message {}", seq);
+                    }
+                }
+                Err(error) => eprintln!("outbox: {}", error),
+            }
+            std::thread::sleep(std::time::Duration::from_secs(5));
+        });
+    }
+    if let Err(error) = server.listen(addr) {
+        eprintln!("daemon stopped: {}", error);
+    }
+}
+
+/// Parses base+increment seconds, or "none" for untimed games.
+#[cfg(feature = "rpc")]
+fn parse_time_control(text: &str) -> Option<Option<lineage::block::TimeControl>> {
+    if text == "none" {
+        return Some(None);
+    }
+    let mut parts = text.splitn(2, '+');
+    let base_seconds = parts.next()?.parse().ok()?;
+    let increment_seconds = parts.next().map_or(Some(0), |inc| inc.parse().ok())?;
+    Some(Some(lineage::block::TimeControl {
+        base_seconds,
+        increment_seconds,
+    }))
+}
+
+#[cfg(not(feature = "rpc"))]
+fn daemon(_addr: &str) {
+    eprintln!("lineage was built without the rpc feature");
+}
+
+#[cfg(feature = "http")]
+fn serve(addr: &str) {
+    use std::sync::Arc;
+
+    let manager = lineage::manager::GameManager::with_network(network());
+    println!(
+        "serving HTTP on {} for the {} network",
+        addr,
+        manager.network()
+    );
+    let server = lineage::http::HttpServer::new(Arc::new(manager));
+    if let Err(error) = server.listen(addr) {
+        eprintln!("server stopped: {}", error);
+    }
+}
 
-    //    let listener = TcpListener::bind("0.0.0.0:10152").unwrap();
-    //
-    //    for stream in listener.incoming() {
-    //        let mut stream = stream.unwrap();
-    //
-    //        let mut msg = String::new();
-    //        stream.read_to_string(&mut msg);
-    //        println!("{}", msg);
-    //        stream.write(msg.as_ref());
-    //        stream.flush();
-    //    }
+#[cfg(not(feature = "http"))]
+fn serve(_addr: &str) {
+    eprintln!("lineage was built without the http feature");
 }
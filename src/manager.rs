@@ -0,0 +1,580 @@
+use crate::block::{
+    AcceptBlock, ChainEntry, ChallengeBlock, ChallengeBlockBuilder, GameChain, MoveBlock,
+};
+use crate::crypto::{self, Rng};
+use crate::error::Error;
+use crate::extension::ExtensionBlock;
+use crate::heartbeat::Heartbeat;
+use crate::key::PublicKey;
+use crate::network::Network;
+use crate::revocation::{self, RevocationList};
+
+use chess::{Action, Color};
+use ring::signature::Ed25519KeyPair;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, RwLock};
+
+/// A block signed elsewhere, submitted to a game the manager holds.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubmittedBlock {
+    Accept(AcceptBlock),
+    Move(MoveBlock),
+    Extension(ExtensionBlock),
+}
+
+impl SubmittedBlock {
+    pub fn hash(&self) -> [u8; 32] {
+        match self {
+            SubmittedBlock::Accept(block) => block.hash(),
+            SubmittedBlock::Move(block) => block.hash(),
+            SubmittedBlock::Extension(block) => block.hash(),
+        }
+    }
+}
+
+/// What became of a submitted block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Submission {
+    Appended,
+    /// The same block was already in the chain, e.g. because the client
+    /// retried after a timeout.
+    AlreadyAppended,
+}
+
+/// Whether `chain` holds a block whose hash is `hash`.
+fn contains_block(chain: &GameChain, hash: &[u8; 32]) -> bool {
+    chain
+        .accepts()
+        .iter()
+        .flatten()
+        .any(|block| block.hash() == *hash)
+        || chain.entries().iter().any(|entry| match entry {
+            ChainEntry::Move { block, .. } => block.hash() == *hash,
+            ChainEntry::Extension(block) => block.hash() == *hash,
+        })
+}
+
+/// Holds many games keyed by challenge id. Each game has its own lock, so
+/// moves in different games don't block each other; wrap the manager in an
+/// Arc to share it between network tasks. A manager serves one network and
+/// refuses games from any other. The latest heartbeat from each player is
+/// kept alongside their games, as are the hashes of blocks submitted to
+/// them, so that resubmissions succeed without being appended twice. The
+/// latest revocation list from each signer is kept too, and challenges it
+/// withdraws aren't accepted.
+#[derive(Default)]
+pub struct GameManager {
+    network: Network,
+    games: RwLock<HashMap<u32, Mutex<GameChain>>>,
+    heartbeats: RwLock<HashMap<u32, Vec<Heartbeat>>>,
+    block_hashes: RwLock<HashMap<u32, HashSet<[u8; 32]>>>,
+    revocations: RwLock<Vec<RevocationList>>,
+}
+
+impl GameManager {
+    pub fn new() -> GameManager {
+        GameManager::default()
+    }
+
+    pub fn with_network(network: Network) -> GameManager {
+        GameManager {
+            network,
+            games: RwLock::default(),
+            heartbeats: RwLock::default(),
+            block_hashes: RwLock::default(),
+            revocations: RwLock::default(),
+        }
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Starts tracking a new game from its challenge, returning its id.
+    pub fn create(&self, challenge: ChallengeBlock) -> Result<u32, Error> {
+        self.insert(GameChain::new(challenge))
+    }
+
+    /// Starts tracking an existing chain, e.g. one received from a peer.
+    pub fn insert(&self, chain: GameChain) -> Result<u32, Error> {
+        self.network.check(chain.challenge().network_id())?;
+        let id = chain.id();
+        let mut games = self.games.write().unwrap();
+        if games.contains_key(&id) {
+            return Err(Error::DuplicateGame(id));
+        }
+        games.insert(id, Mutex::new(chain));
+        Ok(id)
+    }
+
+    /// Starts tracking a chain sent by a client or peer. Unlike insert, it
+    /// refuses a chain that no player has signed, so nobody can take a
+    /// game's id with a challenge they never meant to play.
+    pub fn import(&self, chain: GameChain) -> Result<u32, Error> {
+        let challenge_bytes = chain.challenge().as_bytes();
+        let signed = chain.accepts()[0].is_some()
+            && chain.accepts().iter().flatten().all(|accept| {
+                accept.check_time(chain.challenge()).is_ok()
+                    && accept.signer(chain.challenge(), &challenge_bytes).is_some()
+            });
+        if !signed {
+            return Err(Error::VerificationFailed);
+        }
+        self.insert(chain)
+    }
+
+    pub fn remove(&self, id: u32) -> Option<GameChain> {
+        self.heartbeats.write().unwrap().remove(&id);
+        self.block_hashes.write().unwrap().remove(&id);
+        let mut games = self.games.write().unwrap();
+        games.remove(&id).map(|chain| chain.into_inner().unwrap())
+    }
+
+    /// Keeps a heartbeat for the game with the given id if it's signed by
+    /// one of its players and newer than the one it replaces.
+    pub fn record_heartbeat(&self, id: u32, heartbeat: Heartbeat) -> Result<(), Error> {
+        self.with_chain(id, |chain| heartbeat.signer(chain))?
+            .ok_or(Error::VerificationFailed)?;
+        let mut heartbeats = self.heartbeats.write().unwrap();
+        let kept = heartbeats.entry(id).or_default();
+        match kept
+            .iter_mut()
+            .find(|kept| kept.public_key() == heartbeat.public_key())
+        {
+            Some(kept) if kept.timestamp() < heartbeat.timestamp() => *kept = heartbeat,
+            Some(_) => {}
+            None => kept.push(heartbeat),
+        }
+        Ok(())
+    }
+
+    /// The latest heartbeat from each player of the game with the given id.
+    pub fn heartbeats(&self, id: u32) -> Vec<Heartbeat> {
+        let heartbeats = self.heartbeats.read().unwrap();
+        heartbeats.get(&id).cloned().unwrap_or_default()
+    }
+
+    /// Keeps a revocation list if it verifies and is the newest from its
+    /// signer, returning whether it was kept and so should be passed on.
+    pub fn record_revocations(&self, list: RevocationList) -> Result<bool, Error> {
+        if !list.verify() {
+            return Err(Error::VerificationFailed);
+        }
+        Ok(revocation::keep(
+            &mut self.revocations.write().unwrap(),
+            list,
+        ))
+    }
+
+    /// The latest revocation list from each signer, e.g. to gossip to peers.
+    pub fn revocations(&self) -> Vec<RevocationList> {
+        self.revocations.read().unwrap().clone()
+    }
+
+    /// Runs `f` with exclusive access to the game with the given id.
+    pub fn with_chain<F, R>(&self, id: u32, f: F) -> Result<R, Error>
+    where
+        F: FnOnce(&mut GameChain) -> R,
+    {
+        let games = self.games.read().unwrap();
+        let chain = games.get(&id).ok_or(Error::UnknownGame(id))?;
+        let mut chain = chain.lock().unwrap();
+        Ok(f(&mut chain))
+    }
+
+    pub fn accept(&self, id: u32, key_pair: &Ed25519KeyPair) -> Result<(), Error> {
+        let revocations = self.revocations();
+        self.with_chain(id, |chain| {
+            revocation::check(chain, &revocations)?;
+            chain.accept(key_pair)
+        })?
+    }
+
+    pub fn make_move(
+        &self,
+        id: u32,
+        key_pair: &Ed25519KeyPair,
+        action: Action,
+    ) -> Result<(), Error> {
+        self.with_chain(id, |chain| chain.make_move_block(key_pair, action))?
+    }
+
+    /// Appends a block signed elsewhere to the game with the given id. A
+    /// block that's already there, found by its hash, is reported as such
+    /// instead of failing, so clients can safely retry submissions.
+    pub fn submit(&self, id: u32, block: SubmittedBlock) -> Result<Submission, Error> {
+        let hash = block.hash();
+        let revocations = self.revocations();
+        self.with_chain(id, |chain| {
+            let seen = self
+                .block_hashes
+                .read()
+                .unwrap()
+                .get(&id)
+                .is_some_and(|hashes| hashes.contains(&hash));
+            if seen {
+                return Ok(Submission::AlreadyAppended);
+            }
+            let submission = match match block {
+                SubmittedBlock::Accept(block) => revocation::check(chain, &revocations)
+                    .and_then(|()| chain.append_accept_block(block)),
+                SubmittedBlock::Move(block) => chain.append_move_block(block),
+                SubmittedBlock::Extension(block) => chain.append_extension_block(block),
+            } {
+                Ok(()) => Submission::Appended,
+                // appended some other way, e.g. in a whole chain
+                Err(_) if contains_block(chain, &hash) => Submission::AlreadyAppended,
+                Err(error) => return Err(error),
+            };
+            self.block_hashes
+                .write()
+                .unwrap()
+                .entry(id)
+                .or_default()
+                .insert(hash);
+            Ok(submission)
+        })?
+    }
+
+    /// Returns a copy of the game with the given id.
+    pub fn get(&self, id: u32) -> Option<GameChain> {
+        self.with_chain(id, |chain| chain.clone()).ok()
+    }
+
+    /// Stops tracking challenges that expired before `now` without being
+    /// accepted by both players, returning their ids.
+    pub fn remove_expired(&self, now: u64) -> Vec<u32> {
+        let mut games = self.games.write().unwrap();
+        let expired: Vec<u32> = games
+            .iter()
+            .filter(|(_, chain)| {
+                let chain = chain.lock().unwrap();
+                chain.accepts().iter().any(Option::is_none)
+                    && chain
+                        .challenge()
+                        .valid_until()
+                        .is_some_and(|valid_until| valid_until < now)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        let mut heartbeats = self.heartbeats.write().unwrap();
+        let mut block_hashes = self.block_hashes.write().unwrap();
+        for id in &expired {
+            games.remove(id);
+            heartbeats.remove(id);
+            block_hashes.remove(id);
+        }
+        expired
+    }
+
+    /// Starts a simultaneous exhibition: `host` challenges every opponent,
+    /// playing `host_color` on each board, and accepts each challenge. Game
+    /// ids are drawn from `rng`. Returns the ids in the order of `opponents`.
+    pub fn create_simul(
+        &self,
+        host: &Ed25519KeyPair,
+        host_color: Color,
+        opponents: &[PublicKey],
+        rng: &dyn Rng,
+    ) -> Result<Vec<u32>, Error> {
+        let host_key = PublicKey::of(host);
+        let mut chains: Vec<GameChain> = Vec::with_capacity(opponents.len());
+        for &opponent in opponents {
+            let (white, black) = match host_color {
+                Color::White => (host_key, opponent),
+                Color::Black => (opponent, host_key),
+            };
+            let mut chain = loop {
+                let challenge = ChallengeBlockBuilder::new(&white, &black)
+                    .network(self.network)
+                    .id(crypto::random_id(rng))
+                    .build()?;
+                let id = challenge.id();
+                if !self.games.read().unwrap().contains_key(&id)
+                    && chains.iter().all(|chain| chain.id() != id)
+                {
+                    break GameChain::new(challenge);
+                }
+            };
+            chain.accept(host)?;
+            chains.push(chain);
+        }
+
+        // every board goes in or none do
+        let mut games = self.games.write().unwrap();
+        if let Some(chain) = chains.iter().find(|chain| games.contains_key(&chain.id())) {
+            return Err(Error::DuplicateGame(chain.id()));
+        }
+        let ids = chains.iter().map(GameChain::id).collect();
+        for chain in chains {
+            games.insert(chain.id(), Mutex::new(chain));
+        }
+        Ok(ids)
+    }
+
+    /// The games where it's `public_key`'s move, by id: the queue a simul
+    /// host works through.
+    pub fn awaiting_move(&self, public_key: &[u8]) -> Vec<u32> {
+        let games = self.games.read().unwrap();
+        let mut ids: Vec<u32> = games
+            .iter()
+            .filter(|(_, chain)| chain.lock().unwrap().is_my_turn(public_key))
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// The next board where it's `public_key`'s move, going round the games
+    /// in id order from just after `after`, the way a simul host walks from
+    /// board to board. None if no game is waiting on them.
+    pub fn next_board(&self, public_key: &[u8], after: Option<u32>) -> Option<u32> {
+        let waiting = self.awaiting_move(public_key);
+        let after = match after {
+            Some(after) => after,
+            None => return waiting.first().cloned(),
+        };
+        waiting
+            .iter()
+            .find(|&&id| id > after)
+            .or_else(|| waiting.first())
+            .cloned()
+    }
+
+    pub fn ids(&self) -> Vec<u32> {
+        self.games.read().unwrap().keys().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto;
+    use chess::{ChessMove, Square};
+    use ring::signature::KeyPair;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn concurrent_games() {
+        let manager = Arc::new(GameManager::new());
+        let handles: Vec<_> = (1..=4)
+            .map(|id| {
+                let manager = Arc::clone(&manager);
+                thread::spawn(move || {
+                    let rng = crypto::new_rng();
+                    let white = crypto::generate_key(&rng);
+                    let black = crypto::generate_key(&rng);
+                    let challenge =
+                        ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+                            .id(id)
+                            .build()
+                            .unwrap();
+                    assert_eq!(manager.create(challenge.clone()), Ok(id));
+                    assert_eq!(manager.create(challenge), Err(Error::DuplicateGame(id)));
+                    assert!(manager.accept(id, &white).is_ok());
+                    assert!(manager.accept(id, &black).is_ok());
+                    assert!(manager
+                        .make_move(
+                            id,
+                            &white,
+                            Action::MakeMove(ChessMove::new(
+                                "e2".parse::<Square>().unwrap(),
+                                "e4".parse::<Square>().unwrap(),
+                                None,
+                            )),
+                        )
+                        .is_ok());
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(manager.len(), 4);
+        for id in manager.ids() {
+            let chain = manager.get(id).unwrap();
+            assert!(chain.verify());
+            assert_eq!(chain.move_count(), 1);
+        }
+        assert!(manager
+            .accept(99, &crypto::generate_key(&crypto::new_rng()))
+            .is_err());
+        assert!(manager.remove(1).is_some());
+        assert_eq!(manager.len(), 3);
+
+        let rng = crypto::new_rng();
+        let test_challenge = ChallengeBlockBuilder::new(
+            &PublicKey::of(&crypto::generate_key(&rng)),
+            &PublicKey::of(&crypto::generate_key(&rng)),
+        )
+        .network(Network::Test)
+        .build()
+        .unwrap();
+        assert_eq!(
+            manager.create(test_challenge.clone()),
+            Err(Error::WrongNetwork {
+                expected: 0,
+                found: 1
+            })
+        );
+        assert!(GameManager::with_network(Network::Test)
+            .create(test_challenge)
+            .is_ok());
+
+        let expiring = ChallengeBlockBuilder::new(
+            &PublicKey::of(&crypto::generate_key(&rng)),
+            &PublicKey::of(&crypto::generate_key(&rng)),
+        )
+        .id(50)
+        .valid_until(1_000)
+        .build()
+        .unwrap();
+        manager.create(expiring).unwrap();
+        assert!(manager.remove_expired(1_000).is_empty());
+        assert_eq!(manager.remove_expired(1_001), vec![50]);
+        assert_eq!(manager.len(), 3);
+
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let withdrawn = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .id(60)
+            .build()
+            .unwrap();
+        manager.create(withdrawn).unwrap();
+        manager.accept(60, &white).unwrap();
+        let list = RevocationList::new(&white, 1_000, &[60]);
+        assert_eq!(manager.record_revocations(list.clone()), Ok(true));
+        assert_eq!(manager.record_revocations(list.clone()), Ok(false));
+        assert_eq!(manager.revocations(), vec![list]);
+        assert_eq!(manager.accept(60, &black), Err(Error::ChallengeRevoked));
+    }
+
+    #[test]
+    fn simul_rotation() {
+        let rng = crypto::new_rng();
+        let host = crypto::generate_key(&rng);
+        let host_key = host.public_key().as_ref();
+        let opponents: Vec<_> = (0..3).map(|_| crypto::generate_key(&rng)).collect();
+        let opponent_keys: Vec<PublicKey> = opponents.iter().map(PublicKey::of).collect();
+
+        let manager = GameManager::new();
+        // a bad opponent partway through leaves no boards behind
+        let mut with_host = opponent_keys.clone();
+        with_host.insert(1, PublicKey::of(&host));
+        assert_eq!(
+            manager.create_simul(&host, Color::White, &with_host, &rng),
+            Err(Error::InvalidChallenge("white and black keys are the same"))
+        );
+        assert_eq!(manager.len(), 0);
+        let ids = manager
+            .create_simul(&host, Color::White, &opponent_keys, &rng)
+            .unwrap();
+        assert_eq!(manager.len(), 3);
+        assert!(manager.awaiting_move(host_key).is_empty());
+        for (id, opponent) in ids.iter().zip(&opponents) {
+            manager.accept(*id, opponent).unwrap();
+        }
+
+        let mut waiting = ids.clone();
+        waiting.sort_unstable();
+        assert_eq!(manager.awaiting_move(host_key), waiting);
+        assert_eq!(manager.next_board(host_key, None), Some(waiting[0]));
+        assert_eq!(
+            manager.next_board(host_key, Some(waiting[0])),
+            Some(waiting[1])
+        );
+        assert_eq!(
+            manager.next_board(host_key, Some(waiting[2])),
+            Some(waiting[0])
+        );
+
+        let e4 = Action::MakeMove(ChessMove::new(
+            "e2".parse::<Square>().unwrap(),
+            "e4".parse::<Square>().unwrap(),
+            None,
+        ));
+        manager.make_move(waiting[1], &host, e4).unwrap();
+        assert_eq!(
+            manager.next_board(host_key, Some(waiting[0])),
+            Some(waiting[2])
+        );
+        assert_eq!(manager.awaiting_move(host_key).len(), 2);
+    }
+
+    #[test]
+    fn resubmitted_blocks() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .build()
+            .unwrap();
+        let mut signed = GameChain::new(challenge.clone());
+        signed.accept(&white).unwrap();
+        signed.accept(&black).unwrap();
+        let e4 = Action::MakeMove(ChessMove::new(
+            "e2".parse::<Square>().unwrap(),
+            "e4".parse::<Square>().unwrap(),
+            None,
+        ));
+        signed.make_move_block(&white, e4).unwrap();
+
+        let manager = GameManager::new();
+        let id = manager.create(challenge).unwrap();
+        for accept in signed.accepts().iter().flatten() {
+            let accept = SubmittedBlock::Accept(accept.clone());
+            assert_eq!(manager.submit(id, accept.clone()), Ok(Submission::Appended));
+            assert_eq!(manager.submit(id, accept), Ok(Submission::AlreadyAppended));
+        }
+        let mv = SubmittedBlock::Move(signed.moves()[0].clone());
+        assert_eq!(manager.submit(id, mv.clone()), Ok(Submission::Appended));
+        assert_eq!(
+            manager.submit(id, mv.clone()),
+            Ok(Submission::AlreadyAppended)
+        );
+        assert_eq!(manager.get(id), Some(signed.clone()));
+
+        // blocks appended some other way are found in the chain
+        manager.remove(id);
+        manager.insert(signed).unwrap();
+        assert_eq!(
+            manager.submit(id, mv.clone()),
+            Ok(Submission::AlreadyAppended)
+        );
+        assert_eq!(manager.submit(99, mv), Err(Error::UnknownGame(99)));
+    }
+
+    #[test]
+    fn import_needs_a_signature() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .id(7)
+            .build()
+            .unwrap();
+        let manager = GameManager::new();
+        assert_eq!(
+            manager.import(GameChain::new(challenge.clone())),
+            Err(Error::VerificationFailed)
+        );
+
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        let mut forged = chain.as_bytes();
+        *forged.last_mut().unwrap() ^= 1;
+        let forged = GameChain::from_bytes(&forged).unwrap();
+        assert_eq!(manager.import(forged), Err(Error::VerificationFailed));
+        assert_eq!(manager.import(chain), Ok(7));
+    }
+}
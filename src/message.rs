@@ -0,0 +1,363 @@
+//! Messages exchanged between peers, whatever carries them. Each message is a
+//! tag byte followed by its body:
+//!
+//! - 0x00 Hello: the sender's 32-byte public key, then the wire version it
+//!   speaks as one byte, which version 0 peers leave out
+//! - 0x01 Request: a u32 big-endian chain id, asking for that chain
+//! - 0x02 Chain: an encoded chain
+//! - 0x03 Decline: a u32 big-endian chain id, turning down that challenge
+//! - 0x04 Moves: from wire version 1, a u32 big-endian chain id, the u32
+//!   big-endian index of the first move, then encoded move blocks, so a
+//!   peer that has the rest of the chain isn't sent it again
+//! - 0x05 Ping: from wire version 2, a u64 big-endian nonce, which the peer
+//!   echoes back in a Pong
+//! - 0x06 Pong: from wire version 2, the nonce of the ping it answers
+//! - 0x07 LagProposal: from wire version 2, the u16 big-endian lag allowance
+//!   in milliseconds the sender proposes after measuring round trips
+//! - 0x08 Heartbeat: from wire version 3, an encoded heartbeat
+//! - 0x09 Capabilities: from wire version 4, a byte of flags for the
+//!   optional features the sender supports, so far only CAN_DEFLATE
+//! - 0x0a Compressed: only to peers that can decompress it, an algorithm
+//!   byte (1 for deflate) followed by another message, compressed
+//! - 0x0b Revocations: from wire version 5, an encoded revocation list,
+//!   passed along to every peer so challenges stop being accepted once
+//!   their issuer withdraws them
+//! - 0x0c CompactMoves: from wire version 6, like Moves but with the moves
+//!   packed into two bytes each and their signatures after them, as
+//!   described in the compact module
+
+use crate::block::{GameChain, MoveBlock};
+use crate::compact::{self, CompactMove};
+use crate::error::Error;
+use crate::heartbeat::Heartbeat;
+use crate::revocation::RevocationList;
+
+#[cfg(feature = "compression")]
+use crate::compression;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// The newest wire version this crate speaks. Peers use the lower of their
+/// two versions.
+pub const WIRE_VERSION: u8 = 6;
+
+/// The largest encoded message, which leaves room for any chain.
+pub const MAX_FRAME_LEN: usize = 1 << 20;
+
+/// The encoded length of a move block.
+const MOVE_BLOCK_LEN: usize = 66;
+
+/// The capability flag for accepting deflated messages.
+pub const CAN_DEFLATE: u8 = 1;
+
+/// The capability flags this build sends, which depend on its features.
+pub fn capabilities() -> u8 {
+    if cfg!(feature = "compression") {
+        CAN_DEFLATE
+    } else {
+        0
+    }
+}
+
+const HELLO: u8 = 0x00;
+const REQUEST: u8 = 0x01;
+const CHAIN: u8 = 0x02;
+const DECLINE: u8 = 0x03;
+const MOVES: u8 = 0x04;
+const PING: u8 = 0x05;
+const PONG: u8 = 0x06;
+const LAG_PROPOSAL: u8 = 0x07;
+const HEARTBEAT: u8 = 0x08;
+const CAPABILITIES: u8 = 0x09;
+const COMPRESSED: u8 = 0x0a;
+const REVOCATIONS: u8 = 0x0b;
+const COMPACT_MOVES: u8 = 0x0c;
+
+#[cfg(feature = "compression")]
+const DEFLATE: u8 = 1;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    Hello {
+        public_key: [u8; 32],
+        wire_version: u8,
+    },
+    Request {
+        id: u32,
+    },
+    Chain(Box<GameChain>),
+    Decline {
+        id: u32,
+    },
+    Moves {
+        id: u32,
+        first: u32,
+        moves: Vec<MoveBlock>,
+    },
+    Ping {
+        nonce: u64,
+    },
+    Pong {
+        nonce: u64,
+    },
+    LagProposal {
+        allowance_ms: u16,
+    },
+    Heartbeat(Heartbeat),
+    Capabilities {
+        flags: u8,
+    },
+    /// Another message, sent compressed.
+    #[cfg(feature = "compression")]
+    Compressed(Box<Message>),
+    Revocations(RevocationList),
+    CompactMoves {
+        id: u32,
+        first: u32,
+        moves: Vec<CompactMove>,
+    },
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    let mut value = [0; 4];
+    value.copy_from_slice(&bytes[..4]);
+    u32::from_be_bytes(value)
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "message",
+        reason,
+    }
+}
+
+impl Message {
+    /// Decodes a message. Chains must verify.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Message, Error> {
+        if bytes.len() > MAX_FRAME_LEN {
+            return Err(Error::TooLarge {
+                what: "message bytes",
+                limit: MAX_FRAME_LEN,
+            });
+        }
+        let (&tag, body) = bytes.split_first().ok_or_else(|| malformed("empty"))?;
+        match tag {
+            HELLO if body.len() == 32 || body.len() == 33 => {
+                let mut public_key = [0; 32];
+                public_key.copy_from_slice(&body[..32]);
+                Ok(Message::Hello {
+                    public_key,
+                    wire_version: body.get(32).cloned().unwrap_or(0),
+                })
+            }
+            REQUEST | DECLINE if body.len() == 4 => {
+                let id = read_u32(body);
+                if tag == REQUEST {
+                    Ok(Message::Request { id })
+                } else {
+                    Ok(Message::Decline { id })
+                }
+            }
+            CHAIN => Ok(Message::Chain(Box::new(GameChain::from_bytes(body)?))),
+            MOVES if body.len() >= 8 && (body.len() - 8) % MOVE_BLOCK_LEN == 0 => {
+                Ok(Message::Moves {
+                    id: read_u32(body),
+                    first: read_u32(&body[4..]),
+                    moves: body[8..]
+                        .chunks(MOVE_BLOCK_LEN)
+                        .map(MoveBlock::from_bytes)
+                        .collect::<Result<_, _>>()?,
+                })
+            }
+            PING | PONG if body.len() == 8 => {
+                let mut nonce = [0; 8];
+                nonce.copy_from_slice(body);
+                let nonce = u64::from_be_bytes(nonce);
+                if tag == PING {
+                    Ok(Message::Ping { nonce })
+                } else {
+                    Ok(Message::Pong { nonce })
+                }
+            }
+            LAG_PROPOSAL if body.len() == 2 => Ok(Message::LagProposal {
+                allowance_ms: u16::from_be_bytes([body[0], body[1]]),
+            }),
+            HEARTBEAT => Ok(Message::Heartbeat(Heartbeat::from_bytes(body)?)),
+            CAPABILITIES if body.len() == 1 => Ok(Message::Capabilities { flags: body[0] }),
+            #[cfg(feature = "compression")]
+            COMPRESSED if body.first() == Some(&DEFLATE) => {
+                let inner = compression::inflate(&body[1..], MAX_FRAME_LEN, "message")?;
+                if inner.first() == Some(&COMPRESSED) {
+                    return Err(malformed("compressed twice"));
+                }
+                Ok(Message::Compressed(Box::new(Message::from_bytes(&inner)?)))
+            }
+            COMPRESSED => Err(malformed("unsupported compression")),
+            REVOCATIONS => Ok(Message::Revocations(RevocationList::from_bytes(body)?)),
+            COMPACT_MOVES if body.len() >= 8 => Ok(Message::CompactMoves {
+                id: read_u32(body),
+                first: read_u32(&body[4..]),
+                moves: compact::from_bytes(&body[8..])?,
+            }),
+            HELLO | REQUEST | DECLINE | MOVES | PING | PONG | LAG_PROPOSAL | CAPABILITIES
+            | COMPACT_MOVES => Err(malformed("wrong length")),
+            _ => Err(malformed("unknown tag")),
+        }
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Message::Hello {
+                public_key,
+                wire_version,
+            } => {
+                bytes.push(HELLO);
+                bytes.extend(public_key);
+                if *wire_version > 0 {
+                    bytes.push(*wire_version);
+                }
+            }
+            Message::Request { id } => {
+                bytes.push(REQUEST);
+                bytes.extend(&id.to_be_bytes());
+            }
+            Message::Chain(chain) => {
+                bytes.push(CHAIN);
+                bytes.extend(chain.as_bytes());
+            }
+            Message::Decline { id } => {
+                bytes.push(DECLINE);
+                bytes.extend(&id.to_be_bytes());
+            }
+            Message::Moves { id, first, moves } => {
+                bytes.push(MOVES);
+                bytes.extend(&id.to_be_bytes());
+                bytes.extend(&first.to_be_bytes());
+                for mv in moves {
+                    mv.write_bytes(&mut bytes);
+                }
+            }
+            Message::Ping { nonce } => {
+                bytes.push(PING);
+                bytes.extend(&nonce.to_be_bytes());
+            }
+            Message::Pong { nonce } => {
+                bytes.push(PONG);
+                bytes.extend(&nonce.to_be_bytes());
+            }
+            Message::LagProposal { allowance_ms } => {
+                bytes.push(LAG_PROPOSAL);
+                bytes.extend(&allowance_ms.to_be_bytes());
+            }
+            Message::Heartbeat(heartbeat) => {
+                bytes.push(HEARTBEAT);
+                bytes.extend(heartbeat.as_bytes());
+            }
+            Message::Capabilities { flags } => {
+                bytes.push(CAPABILITIES);
+                bytes.push(*flags);
+            }
+            #[cfg(feature = "compression")]
+            Message::Compressed(message) => {
+                bytes.push(COMPRESSED);
+                bytes.push(DEFLATE);
+                bytes.extend(compression::deflate(&message.as_bytes()));
+            }
+            Message::Revocations(list) => {
+                bytes.push(REVOCATIONS);
+                bytes.extend(list.as_bytes());
+            }
+            Message::CompactMoves { id, first, moves } => {
+                bytes.push(COMPACT_MOVES);
+                bytes.extend(&id.to_be_bytes());
+                bytes.extend(&first.to_be_bytes());
+                compact::write_bytes(moves, &mut bytes);
+            }
+        }
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+    use ring::signature::KeyPair;
+
+    #[test]
+    fn round_trip_messages() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let mv = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(mv)).unwrap();
+
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(white.public_key().as_ref());
+        #[cfg(feature = "compression")]
+        let compressed = Message::Compressed(Box::new(Message::Chain(Box::new(chain.clone()))));
+        for message in &[
+            Message::Hello {
+                public_key,
+                wire_version: 0,
+            },
+            Message::Hello {
+                public_key,
+                wire_version: WIRE_VERSION,
+            },
+            Message::Request { id: 0x1234 },
+            Message::Moves {
+                id: chain.id(),
+                first: 0,
+                moves: chain.moves().to_vec(),
+            },
+            Message::CompactMoves {
+                id: chain.id(),
+                first: 0,
+                moves: vec![chain.last_compact_move().unwrap()],
+            },
+            Message::Heartbeat(chain.heartbeat(&black, 1_000).unwrap()),
+            Message::Chain(Box::new(chain)),
+            Message::Decline { id: 7 },
+            Message::Ping { nonce: 3 },
+            Message::Pong { nonce: u64::MAX },
+            Message::LagProposal { allowance_ms: 250 },
+            Message::Capabilities { flags: CAN_DEFLATE },
+            Message::Revocations(RevocationList::new(&white, 1_000, &[7, 9])),
+        ] {
+            assert_eq!(
+                Message::from_bytes(&message.as_bytes()).as_ref(),
+                Ok(message)
+            );
+        }
+        assert!(Message::from_bytes(&[]).is_err());
+        assert_eq!(
+            Message::from_bytes(&vec![CHAIN; MAX_FRAME_LEN + 1]),
+            Err(Error::TooLarge {
+                what: "message bytes",
+                limit: MAX_FRAME_LEN,
+            })
+        );
+        assert!(Message::from_bytes(&[HELLO, 1, 2]).is_err());
+        assert!(Message::from_bytes(&[0x7f]).is_err());
+        assert!(Message::from_bytes(&[COMPRESSED, 0x7f]).is_err());
+
+        #[cfg(feature = "compression")]
+        {
+            let bytes = compressed.as_bytes();
+            assert_eq!(Message::from_bytes(&bytes), Ok(compressed.clone()));
+            let twice = Message::Compressed(Box::new(compressed));
+            assert!(Message::from_bytes(&twice.as_bytes()).is_err());
+        }
+    }
+}
@@ -0,0 +1,236 @@
+//! Process-wide counters for node operators, rendered in the Prometheus text
+//! format. Each hook that bumps a counter also emits a tracing event when the
+//! tracing feature is on.
+
+use alloc::string::String;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Counter {
+    BlocksAppended,
+    SignaturesCreated,
+    SignaturesChecked,
+    SignatureFailures,
+    Verifications,
+    VerificationFailures,
+    VerificationMicros,
+    BytesSent,
+    BytesReceived,
+    HandshakeFailures,
+}
+
+impl Counter {
+    pub const ALL: [Counter; 10] = [
+        Counter::BlocksAppended,
+        Counter::SignaturesCreated,
+        Counter::SignaturesChecked,
+        Counter::SignatureFailures,
+        Counter::Verifications,
+        Counter::VerificationFailures,
+        Counter::VerificationMicros,
+        Counter::BytesSent,
+        Counter::BytesReceived,
+        Counter::HandshakeFailures,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Counter::BlocksAppended => "lineage_blocks_appended_total",
+            Counter::SignaturesCreated => "lineage_signatures_created_total",
+            Counter::SignaturesChecked => "lineage_signatures_checked_total",
+            Counter::SignatureFailures => "lineage_signature_failures_total",
+            Counter::Verifications => "lineage_verifications_total",
+            Counter::VerificationFailures => "lineage_verification_failures_total",
+            Counter::VerificationMicros => "lineage_verification_microseconds_total",
+            Counter::BytesSent => "lineage_bytes_sent_total",
+            Counter::BytesReceived => "lineage_bytes_received_total",
+            Counter::HandshakeFailures => "lineage_handshake_failures_total",
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Counter::BlocksAppended => "Blocks appended to chains.",
+            Counter::SignaturesCreated => "Signatures made with local keys.",
+            Counter::SignaturesChecked => "Signatures checked.",
+            Counter::SignatureFailures => "Signatures that failed to verify.",
+            Counter::Verifications => "Whole chains verified.",
+            Counter::VerificationFailures => "Whole chains that failed verification.",
+            Counter::VerificationMicros => "Time spent verifying whole chains.",
+            Counter::BytesSent => "Bytes sent over transports.",
+            Counter::BytesReceived => "Bytes received over transports.",
+            Counter::HandshakeFailures => "Peers that failed the session handshake.",
+        }
+    }
+
+    fn cell(self) -> &'static AtomicU64 {
+        &COUNTERS[self as usize]
+    }
+
+    pub fn get(self) -> u64 {
+        self.cell().load(Ordering::Relaxed)
+    }
+
+    fn add(self, amount: u64) {
+        self.cell().fetch_add(amount, Ordering::Relaxed);
+    }
+}
+
+static COUNTERS: [AtomicU64; 10] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+/// Every counter in the Prometheus text exposition format.
+pub fn render() -> String {
+    let mut text = String::new();
+    for &counter in Counter::ALL.iter() {
+        let _ = write!(
+            text,
+            "# HELP {name} {help}\n# TYPE {name} counter\n{name} {value}\n",
+            name = counter.name(),
+            help = counter.help(),
+            value = counter.get()
+        );
+    }
+    text
+}
+
+pub(crate) fn block_appended(chain_id: u32, kind: &'static str) {
+    Counter::BlocksAppended.add(1);
+    #[cfg(feature = "tracing")]
+    tracing::debug!(chain_id, kind, "block appended");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (chain_id, kind);
+}
+
+pub(crate) fn signature_created() {
+    Counter::SignaturesCreated.add(1);
+}
+
+pub(crate) fn signature_checked(valid: bool) {
+    Counter::SignaturesChecked.add(1);
+    if !valid {
+        Counter::SignatureFailures.add(1);
+        #[cfg(feature = "tracing")]
+        tracing::debug!("signature failed to verify");
+    }
+}
+
+#[cfg(feature = "net")]
+pub(crate) fn bytes_sent(len: usize) {
+    Counter::BytesSent.add(len as u64);
+    #[cfg(feature = "tracing")]
+    tracing::trace!(len, "bytes sent");
+}
+
+#[cfg(feature = "net")]
+pub(crate) fn bytes_received(len: usize) {
+    Counter::BytesReceived.add(len as u64);
+    #[cfg(feature = "tracing")]
+    tracing::trace!(len, "bytes received");
+}
+
+#[cfg(feature = "net")]
+pub(crate) fn handshake_failed() {
+    Counter::HandshakeFailures.add(1);
+    #[cfg(feature = "tracing")]
+    tracing::warn!("peer failed the handshake");
+}
+
+/// Times a whole-chain verification. Without std there's no clock, so only
+/// the counts are kept.
+pub(crate) struct VerifyTimer {
+    #[cfg(feature = "std")]
+    start: std::time::Instant,
+}
+
+impl VerifyTimer {
+    pub(crate) fn start() -> VerifyTimer {
+        VerifyTimer {
+            #[cfg(feature = "std")]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn finish(self, chain_id: u32, verified: bool) {
+        Counter::Verifications.add(1);
+        if !verified {
+            Counter::VerificationFailures.add(1);
+        }
+        #[cfg(feature = "std")]
+        {
+            let micros = self.start.elapsed().as_micros() as u64;
+            Counter::VerificationMicros.add(micros);
+            #[cfg(feature = "tracing")]
+            tracing::debug!(chain_id, verified, micros, "chain verified");
+        }
+        #[cfg(not(all(feature = "std", feature = "tracing")))]
+        let _ = chain_id;
+    }
+}
+
+/// Serves the counters over HTTP at /metrics for a Prometheus scraper, one
+/// connection at a time.
+#[cfg(feature = "std")]
+pub fn serve<A: std::net::ToSocketAddrs>(addr: A) -> std::io::Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind(addr)?;
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let mut request_line = String::new();
+        if BufReader::new(&stream)
+            .read_line(&mut request_line)
+            .is_err()
+        {
+            continue;
+        }
+        let (status, body) = if request_line.starts_with("GET /metrics ") {
+            ("200 OK", render())
+        } else {
+            ("404 Not Found", String::new())
+        };
+        // a scraper that hung up early isn't the server's problem
+        let _ = write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_counters() {
+        let before = Counter::BytesSent.get();
+        Counter::BytesSent.add(100);
+        signature_checked(false);
+        // other tests update the counters concurrently
+        assert!(Counter::BytesSent.get() >= before + 100);
+        assert!(Counter::SignatureFailures.get() >= 1);
+
+        let text = render();
+        assert_eq!(text.lines().count(), 3 * Counter::ALL.len());
+        assert!(text.contains("# TYPE lineage_bytes_sent_total counter\n"));
+        assert!(text
+            .lines()
+            .any(|line| line.starts_with("lineage_handshake_failures_total ")));
+    }
+}
@@ -0,0 +1,303 @@
+//! An in-memory network between two sessions, for testing sync and resume
+//! logic without sockets. Messages take a configurable latency, with
+//! jitter, and can be dropped or held back so later ones overtake them.
+//! Time is simulated in milliseconds and every random choice comes from a
+//! seeded RNG, so a run with the same seed always plays out the same way.
+
+use crate::crypto::{Rng, SeededRng};
+use crate::message::Message;
+use crate::session::{GameSession, SessionEvent};
+
+use std::collections::VecDeque;
+
+struct InFlight {
+    to: usize,
+    deliver_at: u64,
+    // breaks ties between messages due at the same time, oldest first
+    seq: u64,
+    message: Message,
+}
+
+/// What happened to the messages sent so far.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetworkStats {
+    pub sent: usize,
+    pub delivered: usize,
+    pub dropped: usize,
+    pub reordered: usize,
+}
+
+pub struct MockNetwork {
+    sessions: [GameSession; 2],
+    events: [VecDeque<SessionEvent>; 2],
+    rng: SeededRng,
+    latency_ms: u64,
+    jitter_ms: u64,
+    drop_rate: f64,
+    reorder_rate: f64,
+    online: bool,
+    now_ms: u64,
+    next_seq: u64,
+    in_flight: Vec<InFlight>,
+    stats: NetworkStats,
+}
+
+impl MockNetwork {
+    /// Connects sessions 0 and 1, with random choices drawn from `seed`.
+    /// Messages arrive instantly and reliably until configured otherwise.
+    pub fn new(first: GameSession, second: GameSession, seed: u64) -> MockNetwork {
+        MockNetwork {
+            sessions: [first, second],
+            events: [VecDeque::new(), VecDeque::new()],
+            rng: SeededRng::new(seed),
+            latency_ms: 0,
+            jitter_ms: 0,
+            drop_rate: 0.0,
+            reorder_rate: 0.0,
+            online: true,
+            now_ms: 0,
+            next_seq: 0,
+            in_flight: Vec::new(),
+            stats: NetworkStats::default(),
+        }
+    }
+
+    /// How long every message takes to arrive.
+    pub fn latency(mut self, milliseconds: u64) -> MockNetwork {
+        self.latency_ms = milliseconds;
+        self
+    }
+
+    /// The most extra time, chosen at random, a message may take on top of
+    /// the latency.
+    pub fn jitter(mut self, milliseconds: u64) -> MockNetwork {
+        self.jitter_ms = milliseconds;
+        self
+    }
+
+    /// The chance, from 0 to 1, that a message is lost.
+    pub fn drop_rate(mut self, rate: f64) -> MockNetwork {
+        self.drop_rate = rate;
+        self
+    }
+
+    /// The chance, from 0 to 1, that a message is held back long enough for
+    /// the next ones to overtake it.
+    pub fn reorder_rate(mut self, rate: f64) -> MockNetwork {
+        self.reorder_rate = rate;
+        self
+    }
+
+    /// Takes the link down or brings it back. While it's down, everything
+    /// sent and everything still in flight is lost.
+    pub fn set_online(&mut self, online: bool) {
+        self.online = online;
+        if !online {
+            self.stats.dropped += self.in_flight.len();
+            self.in_flight.clear();
+        }
+    }
+
+    pub fn session(&self, index: usize) -> &GameSession {
+        &self.sessions[index]
+    }
+
+    /// The session, to make moves or resync with. Whatever it queues is sent
+    /// on the next step.
+    pub fn session_mut(&mut self, index: usize) -> &mut GameSession {
+        &mut self.sessions[index]
+    }
+
+    /// The simulated time, in milliseconds since the network was made.
+    pub fn now_ms(&self) -> u64 {
+        self.now_ms
+    }
+
+    pub fn stats(&self) -> NetworkStats {
+        self.stats
+    }
+
+    /// The events session `index` has raised since this was last called.
+    pub fn events(&mut self, index: usize) -> Vec<SessionEvent> {
+        self.events[index].drain(..).collect()
+    }
+
+    /// A random number in [0, 1).
+    fn roll(&self) -> f64 {
+        let mut bytes = [0; 8];
+        self.rng.fill_bytes(&mut bytes);
+        (u64::from_be_bytes(bytes) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Puts everything the sessions have queued on the wire and collects
+    /// their events.
+    fn transmit(&mut self) {
+        for from in 0..2 {
+            while let Some(message) = self.sessions[from].poll_transmit() {
+                self.stats.sent += 1;
+                if !self.online || self.roll() < self.drop_rate {
+                    self.stats.dropped += 1;
+                    continue;
+                }
+                let mut delay = self.latency_ms;
+                if self.jitter_ms > 0 {
+                    let mut bytes = [0; 8];
+                    self.rng.fill_bytes(&mut bytes);
+                    delay += u64::from_be_bytes(bytes) % (self.jitter_ms + 1);
+                }
+                if self.roll() < self.reorder_rate {
+                    self.stats.reordered += 1;
+                    delay += self.latency_ms + self.jitter_ms + 1;
+                }
+                self.in_flight.push(InFlight {
+                    to: 1 - from,
+                    deliver_at: self.now_ms + delay,
+                    seq: self.next_seq,
+                    message,
+                });
+                self.next_seq += 1;
+            }
+            while let Some(event) = self.sessions[from].poll_event() {
+                self.events[from].push_back(event);
+            }
+        }
+    }
+
+    /// Sends what the sessions have queued, then advances time to the next
+    /// message due and delivers it. Returns false once nothing is in
+    /// flight.
+    pub fn step(&mut self) -> bool {
+        self.transmit();
+        let next = self
+            .in_flight
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, in_flight)| (in_flight.deliver_at, in_flight.seq))
+            .map(|(i, _)| i);
+        let in_flight = match next {
+            Some(i) => self.in_flight.remove(i),
+            None => return false,
+        };
+        self.now_ms = self.now_ms.max(in_flight.deliver_at);
+        self.stats.delivered += 1;
+        self.sessions[in_flight.to].handle(in_flight.message);
+        self.transmit();
+        true
+    }
+
+    /// Steps until nothing is in flight or `max_steps` messages have been
+    /// delivered, returning how many were.
+    pub fn run(&mut self, max_steps: usize) -> usize {
+        let mut steps = 0;
+        while steps < max_steps && self.step() {
+            steps += 1;
+        }
+        steps
+    }
+
+    /// Lets `milliseconds` of simulated time pass, delivering whatever falls
+    /// due meanwhile.
+    pub fn advance(&mut self, milliseconds: u64) {
+        let until = self.now_ms + milliseconds;
+        loop {
+            self.transmit();
+            let due = self
+                .in_flight
+                .iter()
+                .any(|in_flight| in_flight.deliver_at <= until);
+            if !due {
+                break;
+            }
+            self.step();
+        }
+        self.now_ms = until;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::{ChallengeBlock, GameChain};
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+    use ring::signature::KeyPair;
+
+    fn network(seed: u64) -> (MockNetwork, [ring::signature::Ed25519KeyPair; 2]) {
+        let rng = SeededRng::new(seed);
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut offered = GameChain::new(challenge.clone());
+        offered.accept(&white).unwrap();
+        let ours = GameSession::new(white.public_key().as_ref(), offered).unwrap();
+        let theirs =
+            GameSession::new(black.public_key().as_ref(), GameChain::new(challenge)).unwrap();
+        (MockNetwork::new(ours, theirs, seed), [white, black])
+    }
+
+    fn play(network: &mut MockNetwork, keys: &[ring::signature::Ed25519KeyPair; 2]) {
+        for (i, uci) in ["e2e4", "e7e5", "g1f3", "b8c6"].iter().enumerate() {
+            let mv = notation::parse_uci(uci).unwrap();
+            network
+                .session_mut(i % 2)
+                .make_move(&keys[i % 2], Action::MakeMove(mv))
+                .unwrap();
+            network.run(100);
+        }
+    }
+
+    /// Plays four moves over a slow link that shuffles messages.
+    fn shuffled_game(seed: u64) -> MockNetwork {
+        let (network, keys) = network(seed);
+        let mut network = network.latency(50).jitter(30).reorder_rate(0.3);
+        network.run(100);
+        assert!(network.events(1).contains(&SessionEvent::ChallengeReceived));
+        network.session_mut(1).accept(&keys[1]).unwrap();
+        network.run(100);
+        play(&mut network, &keys);
+        network
+    }
+
+    #[test]
+    fn slow_and_shuffled_links() {
+        let network = shuffled_game(1);
+        assert!(network.now_ms() >= 50 * 6);
+        assert_eq!(network.session(0).chain(), network.session(1).chain());
+        assert_eq!(network.session(0).chain().move_count(), 4);
+        assert_eq!(network.stats().delivered, network.stats().sent);
+
+        // the same seed plays out the same way
+        let again = shuffled_game(1);
+        assert_eq!(again.now_ms(), network.now_ms());
+        assert_eq!(again.stats(), network.stats());
+    }
+
+    #[test]
+    fn resume_after_outage() {
+        let (network, keys) = network(2);
+        let mut network = network.latency(20);
+        network.run(100);
+        network.session_mut(1).accept(&keys[1]).unwrap();
+        network.run(100);
+
+        network.set_online(false);
+        let mv = notation::parse_uci("e2e4").unwrap();
+        network
+            .session_mut(0)
+            .make_move(&keys[0], Action::MakeMove(mv))
+            .unwrap();
+        network.advance(1_000);
+        assert_eq!(network.session(1).chain().move_count(), 0);
+        assert_eq!(network.stats().dropped, 1);
+
+        network.set_online(true);
+        network.session_mut(1).resync();
+        network.run(100);
+        assert_eq!(network.session(1).chain().move_count(), 1);
+        assert!(network
+            .events(1)
+            .contains(&SessionEvent::MoveReceived { index: 0 }));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::error::Error;
+
+use core::fmt;
+
+/// The networks a chain can belong to. Signatures cover the challenge, which
+/// records the network id, so a game signed on one network can't be replayed
+/// on another.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Network {
+    #[default]
+    Main,
+    Test,
+    Dev,
+}
+
+impl Network {
+    pub const ALL: [Network; 3] = [Network::Main, Network::Test, Network::Dev];
+
+    pub fn from_id(id: u8) -> Option<Network> {
+        Network::ALL
+            .iter()
+            .cloned()
+            .find(|network| network.id() == id)
+    }
+
+    pub fn id(self) -> u8 {
+        match self {
+            Network::Main => 0,
+            Network::Test => 1,
+            Network::Dev => 2,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Network> {
+        Network::ALL
+            .iter()
+            .cloned()
+            .find(|network| network.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Network::Main => "main",
+            Network::Test => "test",
+            Network::Dev => "dev",
+        }
+    }
+
+    /// Fails unless `id` is this network's id.
+    pub fn check(self, id: u8) -> Result<(), Error> {
+        if id == self.id() {
+            Ok(())
+        } else {
+            Err(Error::WrongNetwork {
+                expected: self.id(),
+                found: id,
+            })
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn network_registry() {
+        for network in &Network::ALL {
+            assert_eq!(Network::from_id(network.id()), Some(*network));
+            assert_eq!(Network::from_name(network.name()), Some(*network));
+        }
+        assert_eq!(Network::default(), Network::Main);
+        assert_eq!(Network::from_id(3), None);
+        assert_eq!(Network::from_name("moon"), None);
+        assert!(Network::Test.check(1).is_ok());
+        assert_eq!(
+            Network::Test.check(0),
+            Err(Error::WrongNetwork {
+                expected: 1,
+                found: 0
+            })
+        );
+    }
+}
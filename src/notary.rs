@@ -0,0 +1,148 @@
+//! Notarized chains. A node can witness a chain by signing its hash and the
+//! time it saw it, so anyone holding the witness can later show the chain
+//! existed by then without trusting either player. The signature is over the
+//! tag "lineage witness", the SHA-256 of the encoded chain, and the timestamp
+//! as an eight-byte big-endian integer.
+
+use crate::block::GameChain;
+use crate::crypto;
+use crate::error::Error;
+
+use alloc::vec::Vec;
+use ring::digest;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+const WITNESS_TAG: &[u8] = b"lineage witness";
+
+/// The encoded length of a witness.
+pub const WITNESS_LEN: usize = 32 + 32 + 8 + 64;
+
+/// The SHA-256 of the encoded chain, commentary included.
+pub fn chain_hash(chain: &GameChain) -> [u8; 32] {
+    let mut hash = [0; 32];
+    hash.copy_from_slice(digest::digest(&digest::SHA256, &chain.as_bytes()).as_ref());
+    hash
+}
+
+fn message(chain_hash: &[u8; 32], timestamp: u64) -> Vec<u8> {
+    let mut message = WITNESS_TAG.to_vec();
+    message.extend(chain_hash);
+    message.extend(&timestamp.to_be_bytes());
+    message
+}
+
+/// A node's detached signature saying it saw a chain at some time. Encoded
+/// as the node's key, the chain hash, the timestamp, and the signature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Witness {
+    node_public_key: [u8; 32],
+    chain_hash: [u8; 32],
+    timestamp: u64,
+    signature: Vec<u8>,
+}
+
+impl Witness {
+    /// Witnesses `chain` as seen at `timestamp`.
+    pub fn new(key_pair: &Ed25519KeyPair, chain: &GameChain, timestamp: u64) -> Witness {
+        let mut node_public_key = [0; 32];
+        node_public_key.copy_from_slice(key_pair.public_key().as_ref());
+        let chain_hash = chain_hash(chain);
+        Witness {
+            node_public_key,
+            chain_hash,
+            timestamp,
+            signature: crypto::sign(key_pair, &message(&chain_hash, timestamp)),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Witness, Error> {
+        if bytes.len() != WITNESS_LEN {
+            return Err(Error::Malformed {
+                block: "witness",
+                reason: "wrong length",
+            });
+        }
+        let mut node_public_key = [0; 32];
+        node_public_key.copy_from_slice(&bytes[..32]);
+        let mut chain_hash = [0; 32];
+        chain_hash.copy_from_slice(&bytes[32..64]);
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&bytes[64..72]);
+        Ok(Witness {
+            node_public_key,
+            chain_hash,
+            timestamp: u64::from_be_bytes(timestamp),
+            signature: bytes[72..].to_vec(),
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(WITNESS_LEN);
+        bytes.extend(&self.node_public_key);
+        bytes.extend(&self.chain_hash);
+        bytes.extend(&self.timestamp.to_be_bytes());
+        bytes.extend(&self.signature);
+        bytes
+    }
+
+    pub fn node_public_key(&self) -> &[u8; 32] {
+        &self.node_public_key
+    }
+
+    pub fn chain_hash(&self) -> &[u8; 32] {
+        &self.chain_hash
+    }
+
+    /// When the node saw the chain, in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Checks the node's signature, without reference to any chain.
+    pub fn verify_signature(&self) -> bool {
+        crypto::verify(
+            &self.node_public_key,
+            &message(&self.chain_hash, self.timestamp),
+            &self.signature,
+        )
+    }
+}
+
+/// Checks that `witness` is a valid signature over exactly `chain`.
+pub fn verify_witness(chain: &GameChain, witness: &Witness) -> bool {
+    witness.chain_hash == chain_hash(chain) && witness.verify_signature()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::key::PublicKey;
+
+    #[test]
+    fn witness_chain() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let node = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        let witness = Witness::new(&node, &chain, 1_000);
+        assert_eq!(witness.timestamp(), 1_000);
+        assert!(verify_witness(&chain, &witness));
+        let decoded = Witness::from_bytes(&witness.as_bytes()).unwrap();
+        assert_eq!(decoded, witness);
+
+        // it doesn't cover a later chain, or a different time
+        let earlier = chain.clone();
+        chain.accept(&black).unwrap();
+        assert!(!verify_witness(&chain, &witness));
+        let mut bytes = witness.as_bytes();
+        bytes[71] ^= 1;
+        assert!(!verify_witness(
+            &earlier,
+            &Witness::from_bytes(&bytes).unwrap()
+        ));
+    }
+}
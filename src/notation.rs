@@ -0,0 +1,191 @@
+use chess::{Board, BoardStatus, ChessMove, MoveGen, Piece, Square};
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// How pieces are named in move text and on ASCII boards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PieceNames {
+    /// The letters for the king, queen, rook, bishop, knight and pawn, in
+    /// that order. Pawns only appear by letter on boards.
+    Letters([char; 6]),
+    /// Figurine algebraic notation, with the white piece symbols for both
+    /// sides in move text.
+    Figurines,
+}
+
+impl PieceNames {
+    pub const ENGLISH: PieceNames = PieceNames::Letters(['K', 'Q', 'R', 'B', 'N', 'P']);
+    pub const GERMAN: PieceNames = PieceNames::Letters(['K', 'D', 'T', 'L', 'S', 'B']);
+    pub const FRENCH: PieceNames = PieceNames::Letters(['R', 'D', 'T', 'F', 'C', 'P']);
+    pub const SPANISH: PieceNames = PieceNames::Letters(['R', 'D', 'T', 'A', 'C', 'P']);
+    pub const ITALIAN: PieceNames = PieceNames::Letters(['R', 'D', 'T', 'A', 'C', 'P']);
+
+    /// The piece letters for a two-letter language code, e.g. "de".
+    pub fn for_language(code: &str) -> Option<PieceNames> {
+        match code {
+            "en" => Some(PieceNames::ENGLISH),
+            "de" => Some(PieceNames::GERMAN),
+            "fr" => Some(PieceNames::FRENCH),
+            "es" => Some(PieceNames::SPANISH),
+            "it" => Some(PieceNames::ITALIAN),
+            _ => None,
+        }
+    }
+
+    /// The uppercase letter or white figurine for `piece`.
+    pub fn name(&self, piece: Piece) -> char {
+        let index = match piece {
+            Piece::King => 0,
+            Piece::Queen => 1,
+            Piece::Rook => 2,
+            Piece::Bishop => 3,
+            Piece::Knight => 4,
+            Piece::Pawn => 5,
+        };
+        match self {
+            PieceNames::Letters(letters) => letters[index],
+            PieceNames::Figurines => ['♔', '♕', '♖', '♗', '♘', '♙'][index],
+        }
+    }
+}
+
+impl Default for PieceNames {
+    fn default() -> PieceNames {
+        PieceNames::ENGLISH
+    }
+}
+
+fn file_char(square: Square) -> char {
+    (b'a' + square.get_file().to_index() as u8) as char
+}
+
+fn rank_char(square: Square) -> char {
+    (b'1' + square.get_rank().to_index() as u8) as char
+}
+
+/// Writes `mv`, which must be legal on `board`, in standard algebraic
+/// notation with pieces named by `pieces`, e.g. "Nbd7", "exd5", "O-O" or
+/// "e8=Q+".
+pub fn san(board: &Board, mv: ChessMove, pieces: PieceNames) -> String {
+    let source = mv.get_source();
+    let dest = mv.get_dest();
+    let piece = board.piece_on(source).unwrap_or(Piece::Pawn);
+    let file_distance = source.get_file().to_index() as i8 - dest.get_file().to_index() as i8;
+    let mut out = String::new();
+    if piece == Piece::King && file_distance.abs() == 2 {
+        out.push_str(if file_distance < 0 { "O-O" } else { "O-O-O" });
+    } else if piece == Piece::Pawn {
+        if file_distance != 0 {
+            out.push(file_char(source));
+            out.push('x');
+        }
+        out.push_str(&dest.to_string());
+        if let Some(promotion) = mv.get_promotion() {
+            out.push('=');
+            out.push(pieces.name(promotion));
+        }
+    } else {
+        out.push(pieces.name(piece));
+        let rivals: Vec<Square> = MoveGen::new_legal(board)
+            .filter(|other| {
+                other.get_dest() == dest
+                    && other.get_source() != source
+                    && board.piece_on(other.get_source()) == Some(piece)
+            })
+            .map(|other| other.get_source())
+            .collect();
+        if !rivals.is_empty() {
+            if rivals
+                .iter()
+                .all(|rival| rival.get_file() != source.get_file())
+            {
+                out.push(file_char(source));
+            } else if rivals
+                .iter()
+                .all(|rival| rival.get_rank() != source.get_rank())
+            {
+                out.push(rank_char(source));
+            } else {
+                out.push(file_char(source));
+                out.push(rank_char(source));
+            }
+        }
+        if board.piece_on(dest).is_some() {
+            out.push('x');
+        }
+        out.push_str(&dest.to_string());
+    }
+    let after = board.make_move_new(mv);
+    if after.status() == BoardStatus::Checkmate {
+        out.push('#');
+    } else if after.checkers().popcnt() > 0 {
+        out.push('+');
+    }
+    out
+}
+
+/// Parses a move in UCI long algebraic notation, e.g. "e2e4" or "e7e8q".
+pub fn parse_uci(uci: &str) -> Option<ChessMove> {
+    if !uci.is_ascii() || (uci.len() != 4 && uci.len() != 5) {
+        return None;
+    }
+    let source = uci[0..2].parse::<Square>().ok()?;
+    let dest = uci[2..4].parse::<Square>().ok()?;
+    let promotion = match uci.get(4..5) {
+        None => None,
+        Some("n") => Some(Piece::Knight),
+        Some("b") => Some(Piece::Bishop),
+        Some("r") => Some(Piece::Rook),
+        Some("q") => Some(Piece::Queen),
+        Some(_) => return None,
+    };
+    Some(ChessMove::new(source, dest, promotion))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_uci_moves() {
+        assert_eq!(parse_uci("e2e4").unwrap().to_string(), "e2e4");
+        assert_eq!(
+            parse_uci("e7e8q").unwrap().get_promotion(),
+            Some(Piece::Queen)
+        );
+        assert!(parse_uci("e7e8k").is_none());
+        assert!(parse_uci("e2").is_none());
+        assert!(parse_uci("z2e4").is_none());
+    }
+
+    fn san_of(fen: &str, uci: &str, pieces: PieceNames) -> String {
+        let board = fen.parse::<Board>().unwrap();
+        san(&board, parse_uci(uci).unwrap(), pieces)
+    }
+
+    #[test]
+    fn standard_algebraic_notation() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san_of(start, "g1f3", PieceNames::ENGLISH), "Nf3");
+        assert_eq!(san_of(start, "g1f3", PieceNames::GERMAN), "Sf3");
+        assert_eq!(san_of(start, "g1f3", PieceNames::Figurines), "♘f3");
+        assert_eq!(san_of(start, "e2e4", PieceNames::FRENCH), "e4");
+
+        let capture = "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2";
+        assert_eq!(san_of(capture, "e4d5", PieceNames::ENGLISH), "exd5");
+
+        let rooks = "4k3/R7/8/8/8/8/4K3/R6R w - - 0 1";
+        assert_eq!(san_of(rooks, "a1d1", PieceNames::ENGLISH), "Rad1");
+        assert_eq!(san_of(rooks, "a1a4", PieceNames::GERMAN), "T1a4");
+
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(san_of(castling, "e1g1", PieceNames::ENGLISH), "O-O");
+        assert_eq!(san_of(castling, "e1c1", PieceNames::Figurines), "O-O-O");
+
+        let promotion = "4k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san_of(promotion, "a7a8q", PieceNames::SPANISH), "a8=D+");
+        assert_eq!(PieceNames::for_language("de"), Some(PieceNames::GERMAN));
+        assert_eq!(PieceNames::for_language("xx"), None);
+    }
+}
@@ -0,0 +1,315 @@
+//! Signing on a separate machine. A networked device exports a PendingBlock
+//! holding the exact bytes to be signed, the signature is made offline with
+//! crypto::sign_detached, and the signature is imported back into the chain.
+
+use crate::adjournment;
+use crate::block::{AcceptBlock, GameChain, MoveBlock};
+use crate::commitment;
+use crate::error::Error;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use chess::ChessMove;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::{fs, io, path::Path};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PendingKind {
+    /// An accept, timestamped if the challenge calls for it.
+    Accept {
+        timestamp: Option<u64>,
+    },
+    Move {
+        start_square: u8,
+        end_square: u8,
+    },
+}
+
+/// A block waiting for a signature: the chain it belongs to, the key that must
+/// sign it, and the message to sign.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingBlock {
+    chain_id: u32,
+    kind: PendingKind,
+    signer_public_key: [u8; 32],
+    message: Vec<u8>,
+}
+
+impl PendingBlock {
+    pub fn chain_id(&self) -> u32 {
+        self.chain_id
+    }
+
+    pub fn kind(&self) -> PendingKind {
+        self.kind
+    }
+
+    pub fn signer_public_key(&self) -> &[u8; 32] {
+        &self.signer_public_key
+    }
+
+    /// The exact bytes the signature must cover.
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+
+    /// Encoded as: chain id (4 bytes), kind (1 byte: 0 for an accept, 1 for a
+    /// move followed by its start and end squares, or 2 for a timestamped
+    /// accept followed by the timestamp), signer key (32 bytes), message
+    /// length (4 bytes), and the message.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(43 + self.message.len());
+        bytes.extend(&self.chain_id.to_be_bytes());
+        match self.kind {
+            PendingKind::Accept { timestamp: None } => bytes.push(0),
+            PendingKind::Accept {
+                timestamp: Some(timestamp),
+            } => {
+                bytes.push(2);
+                bytes.extend(&timestamp.to_be_bytes());
+            }
+            PendingKind::Move {
+                start_square,
+                end_square,
+            } => bytes.extend(&[1, start_square, end_square]),
+        }
+        bytes.extend(&self.signer_public_key);
+        bytes.extend(&(self.message.len() as u32).to_be_bytes());
+        bytes.extend(&self.message);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<PendingBlock, Error> {
+        let malformed = |reason| Error::Malformed {
+            block: "pending",
+            reason,
+        };
+        if bytes.len() < 5 {
+            return Err(malformed("not enough bytes"));
+        }
+        let mut id = [0; 4];
+        id.copy_from_slice(&bytes[..4]);
+        let (kind, rest) = match bytes[4] {
+            0 => (PendingKind::Accept { timestamp: None }, &bytes[5..]),
+            1 if bytes.len() >= 7 => (
+                PendingKind::Move {
+                    start_square: bytes[5],
+                    end_square: bytes[6],
+                },
+                &bytes[7..],
+            ),
+            2 if bytes.len() >= 13 => {
+                let mut timestamp = [0; 8];
+                timestamp.copy_from_slice(&bytes[5..13]);
+                let timestamp = Some(u64::from_be_bytes(timestamp));
+                (PendingKind::Accept { timestamp }, &bytes[13..])
+            }
+            1 | 2 => return Err(malformed("not enough bytes")),
+            _ => return Err(malformed("unknown kind")),
+        };
+        if rest.len() < 36 {
+            return Err(malformed("not enough bytes"));
+        }
+        let mut signer_public_key = [0; 32];
+        signer_public_key.copy_from_slice(&rest[..32]);
+        let mut len = [0; 4];
+        len.copy_from_slice(&rest[32..36]);
+        let len = u32::from_be_bytes(len) as usize;
+        if rest.len() < 36 + len {
+            return Err(malformed("not enough bytes"));
+        }
+        Ok(PendingBlock {
+            chain_id: u32::from_be_bytes(id),
+            kind,
+            signer_public_key,
+            message: rest[36..36 + len].to_vec(),
+        })
+    }
+
+    #[cfg(feature = "std")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.as_bytes())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<PendingBlock> {
+        let bytes = fs::read(path)?;
+        PendingBlock::from_bytes(&bytes)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))
+    }
+}
+
+impl fmt::Display for PendingBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action: String = match self.kind {
+            PendingKind::Accept { .. } => "accept".into(),
+            PendingKind::Move {
+                start_square,
+                end_square,
+            } => match (
+                chess::ALL_SQUARES.get(start_square as usize),
+                chess::ALL_SQUARES.get(end_square as usize),
+            ) {
+                (Some(start), Some(end)) => format!("move {}{}", start, end),
+                _ => "move".into(),
+            },
+        };
+        write!(
+            f,
+            "Game {:08x}: {} signed by {}",
+            self.chain_id,
+            action,
+            crate::crypto::fingerprint(&self.signer_public_key)
+        )
+    }
+}
+
+impl GameChain {
+    /// Prepares an accept for `public_key` to be signed elsewhere. If the
+    /// challenge calls for timestamped accepts, it is stamped with `now`.
+    pub fn pending_accept(&self, public_key: &[u8], now: u64) -> Result<PendingBlock, Error> {
+        let mut signer_public_key = [0; 32];
+        match self.side_of(public_key) {
+            Some(_) => signer_public_key.copy_from_slice(public_key),
+            None => return Err(Error::KeyNotInChallenge),
+        }
+        let challenge = self.challenge();
+        challenge.check_valid_at(now)?;
+        let accept = AcceptBlock::unsigned(challenge, public_key, now);
+        let message = accept.message(&challenge.as_bytes());
+        Ok(PendingBlock {
+            chain_id: self.id(),
+            kind: PendingKind::Accept {
+                timestamp: accept.timestamp(),
+            },
+            signer_public_key,
+            message,
+        })
+    }
+
+    /// Prepares the next move for `public_key` to be signed elsewhere.
+    pub fn pending_move(&self, public_key: &[u8], mv: ChessMove) -> Result<PendingBlock, Error> {
+        if self.accepts().iter().any(Option::is_none) {
+            return Err(Error::VerificationFailed);
+        }
+        let board = self.current_position()?;
+        if self.side_of(public_key) != Some(board.side_to_move()) {
+            return Err(Error::NotYourTurn);
+        }
+        if !board.legal(mv) {
+            return Err(Error::IllegalMove);
+        }
+        let start_square = mv.get_source().to_int();
+        let end_square = mv.get_dest().to_int();
+        adjournment::check_move(self)?;
+        commitment::check_move(self, start_square, end_square)?;
+
+        let mut message = self.signing_prefix();
+        message.push(start_square);
+        message.push(end_square);
+        Ok(PendingBlock {
+            chain_id: self.id(),
+            kind: PendingKind::Move {
+                start_square,
+                end_square,
+            },
+            signer_public_key: *self.signer_for_next_move()?.as_bytes(),
+            message,
+        })
+    }
+
+    /// Appends the block described by `pending` using a signature made
+    /// elsewhere. Fails if the chain has changed since it was exported.
+    pub fn import_signature(
+        &mut self,
+        pending: &PendingBlock,
+        signature: &[u8],
+    ) -> Result<(), Error> {
+        if pending.chain_id != self.id() {
+            return Err(Error::UnknownGame(pending.chain_id));
+        }
+        match pending.kind {
+            PendingKind::Accept { timestamp } => {
+                if signature.len() != 64 {
+                    return Err(Error::Malformed {
+                        block: "accept",
+                        reason: "wrong signature length",
+                    });
+                }
+                let accept = AcceptBlock::unsigned(
+                    self.challenge(),
+                    &pending.signer_public_key,
+                    timestamp.unwrap_or_default(),
+                )
+                .with_signature(signature);
+                if accept.timestamp() != timestamp {
+                    return Err(Error::VerificationFailed);
+                }
+                self.append_accept_block(accept)
+            }
+            PendingKind::Move {
+                start_square,
+                end_square,
+            } => {
+                let mut bytes = Vec::with_capacity(66);
+                bytes.push(start_square);
+                bytes.push(end_square);
+                bytes.extend(signature);
+                self.append_move_block(MoveBlock::from_bytes(&bytes)?)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use chess::Square;
+    use ring::signature::KeyPair;
+
+    #[test]
+    fn sign_offline() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+
+        for key_pair in &[&white, &black] {
+            let pending = chain
+                .pending_accept(key_pair.public_key().as_ref(), 0)
+                .unwrap();
+            let pending = PendingBlock::from_bytes(&pending.as_bytes()).unwrap();
+            let signature = crypto::sign_detached(key_pair, &pending).unwrap();
+            chain.import_signature(&pending, &signature).unwrap();
+        }
+
+        let e4 = ChessMove::new(
+            "e2".parse::<Square>().unwrap(),
+            "e4".parse::<Square>().unwrap(),
+            None,
+        );
+        assert_eq!(
+            chain.pending_move(black.public_key().as_ref(), e4),
+            Err(Error::NotYourTurn)
+        );
+        let pending = chain.pending_move(white.public_key().as_ref(), e4).unwrap();
+        assert!(pending.to_string().contains("move e2e4"));
+        assert_eq!(
+            crypto::sign_detached(&black, &pending),
+            Err(Error::NotYourTurn)
+        );
+        let signature = crypto::sign_detached(&white, &pending).unwrap();
+        chain.import_signature(&pending, &signature).unwrap();
+        assert_eq!(chain.move_count(), 1);
+        assert!(chain.verify());
+
+        // the chain has moved on, so the old signature no longer fits
+        assert!(chain.import_signature(&pending, &signature).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! Open challenges, which anyone can take up. The challenger signs a template:
+//! a challenge with the opponent's key left blank. A player taking it up
+//! signs a binding that fills in their key, giving the bound challenge the
+//! game is played under; both players then accept it as usual.
+//!
+//! A template is the encoded challenge followed by the challenger's signature
+//! over the challenge's canonical id and the tag 0x01. A bound challenge is
+//! the template followed by the taker's key and their signature over the
+//! template challenge's canonical id, the tag 0x02, and their key.
+
+use crate::block::{ChallengeBlock, GameChain};
+use crate::crypto;
+use crate::error::Error;
+use crate::key::PublicKey;
+
+use alloc::vec::Vec;
+use chess::Color;
+use ring::signature::Ed25519KeyPair;
+
+/// The key an open challenge has in place of the opponent's.
+pub const OPEN_KEY: [u8; 32] = [0; 32];
+
+const TEMPLATE_TAG: u8 = 0x01;
+const BINDING_TAG: u8 = 0x02;
+
+fn malformed(block: &'static str, reason: &'static str) -> Error {
+    Error::Malformed { block, reason }
+}
+
+fn template_message(challenge: &ChallengeBlock) -> Vec<u8> {
+    let mut message = challenge.canonical_id().to_vec();
+    message.push(TEMPLATE_TAG);
+    message
+}
+
+fn binding_message(challenge: &ChallengeBlock, public_key: &PublicKey) -> Vec<u8> {
+    let mut message = challenge.canonical_id().to_vec();
+    message.push(BINDING_TAG);
+    message.extend(public_key.as_bytes());
+    message
+}
+
+/// The side left open in a challenge, if exactly one is.
+fn open_color(challenge: &ChallengeBlock) -> Option<Color> {
+    match (
+        challenge.white_public_key() == OPEN_KEY,
+        challenge.black_public_key() == OPEN_KEY,
+    ) {
+        (true, false) => Some(Color::White),
+        (false, true) => Some(Color::Black),
+        _ => None,
+    }
+}
+
+/// A challenge with the opponent's key left blank, signed by the challenger.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ChallengeTemplate {
+    challenge: ChallengeBlock,
+    signature: Vec<u8>,
+}
+
+impl ChallengeTemplate {
+    /// Signs `challenge`, which must have the signer's key on one side and
+    /// OPEN_KEY on the other.
+    pub fn new(
+        challenge: ChallengeBlock,
+        key_pair: &Ed25519KeyPair,
+    ) -> Result<ChallengeTemplate, Error> {
+        let open = open_color(&challenge)
+            .ok_or(Error::InvalidChallenge("exactly one key must be open"))?;
+        let challenger = match open {
+            Color::White => challenge.black_public_key(),
+            Color::Black => challenge.white_public_key(),
+        };
+        if challenger != PublicKey::of(key_pair) {
+            return Err(Error::KeyNotInChallenge);
+        }
+        let signature = crypto::sign(key_pair, &template_message(&challenge));
+        Ok(ChallengeTemplate {
+            challenge,
+            signature,
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ChallengeTemplate, Error> {
+        let challenge = ChallengeBlock::from_bytes(bytes)?;
+        if bytes.len() != challenge.encoded_len() + 64 {
+            return Err(malformed("template", "wrong length"));
+        }
+        let template = ChallengeTemplate {
+            signature: bytes[challenge.encoded_len()..].to_vec(),
+            challenge,
+        };
+        if !template.verify() {
+            return Err(Error::VerificationFailed);
+        }
+        Ok(template)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.challenge.as_bytes();
+        bytes.extend(&self.signature);
+        bytes
+    }
+
+    /// The challenge with its open key.
+    pub fn challenge(&self) -> &ChallengeBlock {
+        &self.challenge
+    }
+
+    /// The side the taker will play.
+    pub fn open_color(&self) -> Color {
+        // checked when the template was made or decoded
+        open_color(&self.challenge).unwrap()
+    }
+
+    pub fn challenger_public_key(&self) -> PublicKey {
+        match self.open_color() {
+            Color::White => self.challenge.black_public_key(),
+            Color::Black => self.challenge.white_public_key(),
+        }
+    }
+
+    pub fn verify(&self) -> bool {
+        open_color(&self.challenge).is_some()
+            && self
+                .challenger_public_key()
+                .verify(&template_message(&self.challenge), &self.signature)
+    }
+
+    /// Takes up the challenge, filling in the signer's key.
+    pub fn bind(&self, key_pair: &Ed25519KeyPair) -> Result<BoundChallenge, Error> {
+        let public_key = PublicKey::of(key_pair);
+        if public_key == self.challenger_public_key() {
+            return Err(Error::InvalidChallenge("white and black keys are the same"));
+        }
+        let signature = crypto::sign(key_pair, &binding_message(&self.challenge, &public_key));
+        Ok(BoundChallenge {
+            template: self.clone(),
+            public_key,
+            signature,
+        })
+    }
+}
+
+/// A template with the taker's key filled in and signed by the taker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoundChallenge {
+    template: ChallengeTemplate,
+    public_key: PublicKey,
+    signature: Vec<u8>,
+}
+
+impl BoundChallenge {
+    pub fn from_bytes(bytes: &[u8]) -> Result<BoundChallenge, Error> {
+        if bytes.len() < 96 {
+            return Err(malformed("bound challenge", "not enough bytes"));
+        }
+        let (template, binding) = bytes.split_at(bytes.len() - 96);
+        let bound = BoundChallenge {
+            template: ChallengeTemplate::from_bytes(template)?,
+            public_key: PublicKey::from_slice(&binding[..32])?,
+            signature: binding[32..].to_vec(),
+        };
+        if !bound.verify() {
+            return Err(Error::VerificationFailed);
+        }
+        Ok(bound)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.template.as_bytes();
+        bytes.extend(self.public_key.as_bytes());
+        bytes.extend(&self.signature);
+        bytes
+    }
+
+    pub fn template(&self) -> &ChallengeTemplate {
+        &self.template
+    }
+
+    /// The taker's key.
+    pub fn public_key(&self) -> PublicKey {
+        self.public_key
+    }
+
+    /// The challenge the game is played under: the template's, with the
+    /// taker's key filled in.
+    pub fn challenge(&self) -> ChallengeBlock {
+        self.template
+            .challenge
+            .with_public_key(self.template.open_color(), self.public_key)
+    }
+
+    /// Checks both signatures and that the taker isn't the challenger.
+    pub fn verify(&self) -> bool {
+        self.template.verify()
+            && self.public_key != self.template.challenger_public_key()
+            && self.public_key != OPEN_KEY
+            && self.public_key.verify(
+                &binding_message(&self.template.challenge, &self.public_key),
+                &self.signature,
+            )
+    }
+
+    /// Checks that `chain` is a valid game played under this bound challenge.
+    pub fn verify_chain(&self, chain: &GameChain) -> bool {
+        self.verify() && chain.challenge() == &self.challenge() && chain.verify()
+    }
+}
+
+impl GameChain {
+    /// Starts a game from a bound open challenge, which both players then
+    /// accept.
+    pub fn from_bound_challenge(bound: &BoundChallenge) -> Result<GameChain, Error> {
+        if !bound.verify() {
+            return Err(Error::VerificationFailed);
+        }
+        Ok(GameChain::new(bound.challenge()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlockBuilder;
+
+    #[test]
+    fn take_up_open_challenge() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge =
+            ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::from(OPEN_KEY))
+                .id(7)
+                .time_control(300, 5)
+                .build()
+                .unwrap();
+        assert_eq!(
+            ChallengeTemplate::new(challenge.clone(), &black),
+            Err(Error::KeyNotInChallenge)
+        );
+        let template = ChallengeTemplate::new(challenge, &white).unwrap();
+        assert_eq!(template.open_color(), Color::Black);
+        assert_eq!(
+            ChallengeTemplate::from_bytes(&template.as_bytes()),
+            Ok(template.clone())
+        );
+        assert!(template.bind(&white).is_err());
+
+        let bound = template.bind(&black).unwrap();
+        assert_eq!(
+            BoundChallenge::from_bytes(&bound.as_bytes()),
+            Ok(bound.clone())
+        );
+        assert_eq!(bound.challenge().black_public_key(), PublicKey::of(&black));
+        let mut chain = GameChain::from_bound_challenge(&bound).unwrap();
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        assert!(bound.verify_chain(&chain));
+
+        // a binding can't be moved onto another key
+        let mut bytes = bound.as_bytes();
+        let key = bytes.len() - 96;
+        bytes[key] ^= 1;
+        assert!(BoundChallenge::from_bytes(&bytes).is_err());
+    }
+}
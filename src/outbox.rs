@@ -0,0 +1,335 @@
+//! A durable outbox for messages a peer couldn't take yet, say because the
+//! opponent is offline. Each message is written to the store's outbox folder
+//! before it's first tried and only removed once it's been handed over, so a
+//! signed move survives restarts until the peer comes back. Failed sends are
+//! retried after a delay that doubles each time, up to a cap, and never
+//! given up on. Peers merge chains they already have, so a message that was
+//! delivered but not recorded as such does no harm when it's sent again.
+//!
+//! Each message is a file named by its sequence number, holding the u32
+//! big-endian number of attempts, the u64 big-endian time it's next due, the
+//! length of the peer's address as a byte, the address, and the encoded
+//! message. A file that no longer decodes, e.g. one queued before a wire
+//! format change, is renamed with a .bad extension when a flush comes to it,
+//! so it can be looked at without holding up the messages behind it.
+
+use crate::error::Error;
+use crate::message::Message;
+use crate::store::GameStore;
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutboxEntry {
+    pub seq: u64,
+    /// The peer's address, e.g. host:port.
+    pub to: String,
+    pub message: Message,
+    /// How many sends have failed so far.
+    pub attempts: u32,
+    /// When the message is next tried, in seconds since the Unix epoch.
+    pub due: u64,
+}
+
+/// What a flush did.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FlushReport {
+    pub delivered: Vec<u64>,
+    /// Messages whose send failed, with the time each is tried next.
+    pub retrying: Vec<(u64, u64)>,
+    /// Messages that couldn't be decoded, set aside as .bad files.
+    pub quarantined: Vec<u64>,
+}
+
+pub struct Outbox {
+    dir: PathBuf,
+    retry_after: u64,
+    max_delay: u64,
+    next_seq: AtomicU64,
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn encode(to: &str, message: &Message, attempts: u32, due: u64) -> io::Result<Vec<u8>> {
+    if to.len() > 255 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "peer address is too long",
+        ));
+    }
+    let mut bytes = Vec::new();
+    bytes.extend(&attempts.to_be_bytes());
+    bytes.extend(&due.to_be_bytes());
+    bytes.push(to.len() as u8);
+    bytes.extend(to.as_bytes());
+    bytes.extend(message.as_bytes());
+    Ok(bytes)
+}
+
+fn decode(seq: u64, bytes: &[u8]) -> io::Result<OutboxEntry> {
+    if bytes.len() < 13 || bytes.len() < 13 + bytes[12] as usize {
+        return Err(invalid_data("truncated outbox entry"));
+    }
+    let mut attempts = [0; 4];
+    attempts.copy_from_slice(&bytes[..4]);
+    let mut due = [0; 8];
+    due.copy_from_slice(&bytes[4..12]);
+    let to_end = 13 + bytes[12] as usize;
+    let to = String::from_utf8(bytes[13..to_end].to_vec())
+        .map_err(|_| invalid_data("peer address is not UTF-8"))?;
+    let message = Message::from_bytes(&bytes[to_end..])
+        .map_err(|error: Error| invalid_data(&error.to_string()))?;
+    Ok(OutboxEntry {
+        seq,
+        to,
+        message,
+        attempts: u32::from_be_bytes(attempts),
+        due: u64::from_be_bytes(due),
+    })
+}
+
+impl Outbox {
+    /// Opens the outbox in `store`, creating it if needed.
+    pub fn open(store: &GameStore) -> io::Result<Outbox> {
+        Outbox::open_dir(store.dir().join("outbox"))
+    }
+
+    /// Opens an outbox in `dir`, creating the directory if needed.
+    pub fn open_dir<P: AsRef<Path>>(dir: P) -> io::Result<Outbox> {
+        fs::create_dir_all(&dir)?;
+        let outbox = Outbox {
+            dir: dir.as_ref().to_path_buf(),
+            retry_after: 10,
+            max_delay: 60 * 60,
+            next_seq: AtomicU64::new(0),
+        };
+        // quarantined entries keep their numbers, so new ones mustn't reuse
+        // them
+        let last = outbox.seqs_with("msg")?.last().cloned();
+        let last_bad = outbox.seqs_with("bad")?.last().cloned();
+        let next_seq = last.max(last_bad).map_or(0, |seq| seq + 1);
+        outbox.next_seq.store(next_seq, Ordering::SeqCst);
+        Ok(outbox)
+    }
+
+    /// How long to wait before the first retry, doubling after each. Ten
+    /// seconds by default.
+    pub fn retry_after(mut self, seconds: u64) -> Outbox {
+        self.retry_after = seconds;
+        self
+    }
+
+    /// The longest wait between retries. An hour by default.
+    pub fn max_delay(mut self, seconds: u64) -> Outbox {
+        self.max_delay = seconds;
+        self
+    }
+
+    fn path(&self, seq: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.msg", seq))
+    }
+
+    fn seqs(&self) -> io::Result<Vec<u64>> {
+        self.seqs_with("msg")
+    }
+
+    /// The sequence numbers of files with the given extension, in order.
+    fn seqs_with(&self, extension: &str) -> io::Result<Vec<u64>> {
+        let mut seqs = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|found| found == extension) {
+                if let Some(seq) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| u64::from_str_radix(stem, 16).ok())
+                {
+                    seqs.push(seq);
+                }
+            }
+        }
+        seqs.sort_unstable();
+        Ok(seqs)
+    }
+
+    /// Writes under a temporary name first, so a crash never leaves a
+    /// partly written entry. The file is synced before the rename and the
+    /// directory after it, so a queued message is on disk once this returns.
+    fn write(&self, seq: u64, bytes: &[u8]) -> io::Result<()> {
+        let path = self.path(seq);
+        let partial = path.with_extension("partial");
+        let mut file = fs::File::create(&partial)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+        fs::rename(&partial, &path)?;
+        self.sync_dir()
+    }
+
+    // Only Unix lets a directory be opened to sync it.
+    #[cfg(unix)]
+    fn sync_dir(&self) -> io::Result<()> {
+        fs::File::open(&self.dir)?.sync_all()
+    }
+
+    #[cfg(not(unix))]
+    fn sync_dir(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Queues `message` for the peer at `to`, due straight away. Returns its
+    /// sequence number.
+    pub fn push(&self, to: &str, message: &Message, now: u64) -> io::Result<u64> {
+        let bytes = encode(to, message, 0, now)?;
+        let seq = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        self.write(seq, &bytes)?;
+        Ok(seq)
+    }
+
+    fn entry(&self, seq: u64) -> io::Result<OutboxEntry> {
+        decode(seq, &fs::read(self.path(seq))?)
+    }
+
+    /// Every queued message, oldest first.
+    pub fn entries(&self) -> io::Result<Vec<OutboxEntry>> {
+        self.seqs()?
+            .into_iter()
+            .map(|seq| self.entry(seq))
+            .collect()
+    }
+
+    /// The number of queued messages.
+    pub fn len(&self) -> io::Result<usize> {
+        Ok(self.seqs()?.len())
+    }
+
+    pub fn is_empty(&self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Removes a message once its peer has it.
+    pub fn delivered(&self, seq: u64) -> io::Result<()> {
+        match fs::remove_file(self.path(seq)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records a failed send of `entry` at `now`, returning when it's due
+    /// next.
+    pub fn failed(&self, entry: &OutboxEntry, now: u64) -> io::Result<u64> {
+        let attempts = entry.attempts.saturating_add(1);
+        let backoff = 1u64.checked_shl(attempts - 1).unwrap_or(u64::MAX);
+        let delay = self.retry_after.saturating_mul(backoff).min(self.max_delay);
+        let due = now.saturating_add(delay);
+        self.write(
+            entry.seq,
+            &encode(&entry.to, &entry.message, attempts, due)?,
+        )?;
+        Ok(due)
+    }
+
+    /// Tries every message due at `now` with `send`, oldest first, removing
+    /// those it hands over and scheduling the rest for a retry. Messages that
+    /// don't decode are set aside rather than failing the flush.
+    pub fn flush<F>(&self, now: u64, mut send: F) -> io::Result<FlushReport>
+    where
+        F: FnMut(&str, &Message) -> io::Result<()>,
+    {
+        let mut report = FlushReport::default();
+        for seq in self.seqs()? {
+            let entry = match self.entry(seq) {
+                Ok(entry) => entry,
+                Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                    fs::rename(self.path(seq), self.path(seq).with_extension("bad"))?;
+                    report.quarantined.push(seq);
+                    continue;
+                }
+                Err(error) => return Err(error),
+            };
+            if entry.due > now {
+                continue;
+            }
+            match send(&entry.to, &entry.message) {
+                Ok(()) => {
+                    self.delivered(entry.seq)?;
+                    report.delivered.push(entry.seq);
+                }
+                Err(_) => {
+                    let due = self.failed(&entry, now)?;
+                    report.retrying.push((entry.seq, due));
+                }
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto;
+
+    #[test]
+    fn retry_until_delivered() {
+        let rng = crypto::new_rng();
+        let dir = std::env::temp_dir().join(format!("lineage-outbox-{}", crypto::random_id(&rng)));
+        let store = GameStore::open(&dir).unwrap();
+        let outbox = Outbox::open(&store).unwrap().retry_after(10).max_delay(30);
+        let ping = Message::Ping { nonce: 7 };
+        assert_eq!(outbox.push("peer:10153", &ping, 100).unwrap(), 0);
+        assert_eq!(outbox.push("other:10153", &ping, 100).unwrap(), 1);
+
+        // the peer is offline: retried after 10, 20, then every 30 seconds
+        let offline = |_: &str, _: &Message| Err(io::Error::from(io::ErrorKind::ConnectionRefused));
+        assert_eq!(
+            outbox.flush(100, offline).unwrap().retrying,
+            vec![(0, 110), (1, 110)]
+        );
+        assert_eq!(outbox.flush(109, offline).unwrap(), FlushReport::default());
+        assert_eq!(outbox.flush(110, offline).unwrap().retrying[0], (0, 130));
+        assert_eq!(outbox.flush(130, offline).unwrap().retrying[0], (0, 160));
+        assert_eq!(outbox.flush(160, offline).unwrap().retrying[0], (0, 190));
+
+        // the queue survives a restart
+        let outbox = Outbox::open(&store).unwrap();
+        let entries = outbox.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].to, "peer:10153");
+        assert_eq!(entries[0].message, ping);
+        assert_eq!(entries[0].attempts, 4);
+        assert_eq!(outbox.push("peer:10153", &ping, 190).unwrap(), 2);
+
+        let mut sent = Vec::new();
+        let report = outbox
+            .flush(190, |to, _| {
+                sent.push(to.to_string());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(report.delivered, vec![0, 1, 2]);
+        assert_eq!(sent, vec!["peer:10153", "other:10153", "peer:10153"]);
+        assert!(outbox.is_empty().unwrap());
+
+        // an entry that no longer decodes is set aside, and the rest still go
+        assert_eq!(outbox.push("peer:10153", &ping, 200).unwrap(), 3);
+        assert_eq!(outbox.push("peer:10153", &ping, 200).unwrap(), 4);
+        fs::write(outbox.path(4), [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 9]).unwrap();
+        assert!(outbox.entries().is_err());
+        let report = outbox.flush(200, |_, _| Ok(())).unwrap();
+        assert_eq!(report.delivered, vec![3]);
+        assert_eq!(report.quarantined, vec![4]);
+        assert!(outbox.is_empty().unwrap());
+        assert!(outbox.path(4).with_extension("bad").exists());
+
+        // after a restart, new entries don't take the quarantined one's number
+        let outbox = Outbox::open(&store).unwrap();
+        assert_eq!(outbox.push("peer:10153", &ping, 210).unwrap(), 5);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
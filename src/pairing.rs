@@ -0,0 +1,356 @@
+//! Pairing the next round of a tournament from the games linked so far.
+//! Round robins follow the circle method, so each round is fixed by its
+//! number. Swiss rounds pair players with the nearest score they haven't
+//! played yet, falling back to rematches only when there's no other way;
+//! arena rounds pair by score and only keep players from meeting the same
+//! opponent twice in a row. With an odd number of players the lowest-ranked
+//! player with the fewest byes sits out. Byes don't score.
+
+use crate::block::{ChallengeBlock, ChallengeBlockBuilder, GameChain};
+use crate::error::Error;
+use crate::key::PublicKey;
+use crate::tournament::{TournamentChain, TournamentFormat};
+
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A game in a round, by participant index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pairing {
+    pub white: usize,
+    pub black: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoundPairings {
+    pub pairings: Vec<Pairing>,
+    /// The participant sitting the round out, if any.
+    pub bye: Option<usize>,
+}
+
+/// Round `round` (from 0) of a round robin between `players` participants.
+/// Colors swap each time the schedule repeats.
+pub fn round_robin(players: usize, round: usize) -> RoundPairings {
+    // an odd field gets a dummy player, and whoever meets it has the bye
+    let seats = players + players % 2;
+    if seats < 2 {
+        return RoundPairings {
+            pairings: Vec::new(),
+            bye: if players == 1 { Some(0) } else { None },
+        };
+    }
+    let cycle = round % (seats - 1);
+    let mut order = vec![0];
+    order.extend((0..seats - 1).map(|i| 1 + (i + cycle) % (seats - 1)));
+    let swap = (round / (seats - 1)) % 2 == 1;
+    let mut pairings = Vec::new();
+    let mut bye = None;
+    for i in 0..seats / 2 {
+        let (mut white, mut black) = (order[i], order[seats - 1 - i]);
+        if (i == 0 && cycle % 2 == 1) || (i > 0 && i % 2 == 1) {
+            core::mem::swap(&mut white, &mut black);
+        }
+        if swap {
+            core::mem::swap(&mut white, &mut black);
+        }
+        if white == players {
+            bye = Some(black);
+        } else if black == players {
+            bye = Some(white);
+        } else {
+            pairings.push(Pairing { white, black });
+        }
+    }
+    RoundPairings { pairings, bye }
+}
+
+/// Pairs `order`, best first, greedily from the top, backtracking when the
+/// players left can't all be paired.
+fn pair_in_order(
+    order: &[usize],
+    can_meet: &dyn Fn(usize, usize) -> bool,
+) -> Option<Vec<(usize, usize)>> {
+    let (&first, rest) = match order.split_first() {
+        Some(split) => split,
+        None => return Some(Vec::new()),
+    };
+    for (i, &opponent) in rest.iter().enumerate() {
+        if !can_meet(first, opponent) {
+            continue;
+        }
+        let mut remaining = rest.to_vec();
+        remaining.remove(i);
+        if let Some(mut pairs) = pair_in_order(&remaining, can_meet) {
+            pairs.insert(0, (first, opponent));
+            return Some(pairs);
+        }
+    }
+    None
+}
+
+/// What the games linked so far say about each participant.
+struct History {
+    half_points: Vec<u32>,
+    /// `games[i][j]` is the number of games between i and j.
+    games: Vec<Vec<u32>>,
+    last_opponent: Vec<Option<usize>>,
+    whites: Vec<u32>,
+    byes: Vec<u32>,
+}
+
+impl History {
+    fn new(tournament: &TournamentChain, games: &[GameChain]) -> Result<History, Error> {
+        let table = tournament.compute_standings(games)?;
+        let participants = tournament.header().participants();
+        let players = participants.len();
+        let mut history = History {
+            half_points: (0..players)
+                .map(|player| table.total_half_points(player))
+                .collect(),
+            games: vec![vec![0; players]; players],
+            last_opponent: vec![None; players],
+            whites: vec![0; players],
+            byes: vec![0; players],
+        };
+        for round in tournament.rounds() {
+            let mut played = vec![false; players];
+            for chain_id in round.chain_ids() {
+                // compute_standings checked that every game is there
+                let chain = games.iter().find(|chain| chain.id() == *chain_id).unwrap();
+                if chain.result().is_none() {
+                    return Err(Error::RoundUnfinished);
+                }
+                let (white, black) = chain.players();
+                let index_of =
+                    |key: PublicKey| participants.iter().position(|player| key == *player);
+                let (white, black) = match (index_of(white), index_of(black)) {
+                    (Some(white), Some(black)) => (white, black),
+                    _ => return Err(Error::NotAParticipant),
+                };
+                history.games[white][black] += 1;
+                history.games[black][white] += 1;
+                history.last_opponent[white] = Some(black);
+                history.last_opponent[black] = Some(white);
+                history.whites[white] += 1;
+                played[white] = true;
+                played[black] = true;
+            }
+            for (player, played) in played.into_iter().enumerate() {
+                if !played {
+                    history.byes[player] += 1;
+                }
+            }
+        }
+        Ok(history)
+    }
+
+    /// Participants by score, best first; ties keep header order.
+    fn ranking(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.half_points.len()).collect();
+        order.sort_by_key(|&player| core::cmp::Reverse(self.half_points[player]));
+        order
+    }
+
+    /// Gives white to whoever has had it less, or else to the higher-ranked
+    /// player.
+    fn colors(&self, higher: usize, lower: usize) -> Pairing {
+        if self.whites[lower] < self.whites[higher] {
+            Pairing {
+                white: lower,
+                black: higher,
+            }
+        } else {
+            Pairing {
+                white: higher,
+                black: lower,
+            }
+        }
+    }
+
+    fn pair(&self, format: TournamentFormat) -> RoundPairings {
+        let mut order = self.ranking();
+        let bye = if order.len() % 2 == 1 {
+            let (position, _) = order
+                .iter()
+                .enumerate()
+                .rev()
+                .min_by_key(|&(_, &player)| self.byes[player])
+                .unwrap();
+            Some(order.remove(position))
+        } else {
+            None
+        };
+        let fresh = |a: usize, b: usize| match format {
+            TournamentFormat::Arena => self.last_opponent[a] != Some(b),
+            _ => self.games[a][b] == 0,
+        };
+        let pairs = pair_in_order(&order, &fresh)
+            .or_else(|| pair_in_order(&order, &|_, _| true))
+            .unwrap_or_default();
+        RoundPairings {
+            pairings: pairs
+                .into_iter()
+                .map(|(higher, lower)| self.colors(higher, lower))
+                .collect(),
+            bye,
+        }
+    }
+}
+
+impl TournamentChain {
+    /// Pairs the next round in the header's format from the games linked so
+    /// far, which must all be in `games`. Swiss and arena rounds can only be
+    /// paired once every linked game has finished.
+    pub fn next_pairings(&self, games: &[GameChain]) -> Result<RoundPairings, Error> {
+        if self.rounds().len() >= self.header().rounds() as usize {
+            return Err(Error::TournamentFull);
+        }
+        match self.header().format() {
+            TournamentFormat::RoundRobin => Ok(round_robin(
+                self.header().participants().len(),
+                self.rounds().len(),
+            )),
+            TournamentFormat::Knockout => Err(Error::UnsupportedAction),
+            format => Ok(History::new(self, games)?.pair(format)),
+        }
+    }
+
+    /// The challenges for the next round, with ids counting up from
+    /// `first_id` and tagged with the round and board. Once they're accepted, the organizer links them with
+    /// add_round.
+    pub fn next_round_challenges(
+        &self,
+        games: &[GameChain],
+        first_id: u32,
+    ) -> Result<Vec<ChallengeBlock>, Error> {
+        let participants = self.header().participants();
+        let round = (self.rounds().len() + 1).to_string();
+        self.next_pairings(games)?
+            .pairings
+            .iter()
+            .enumerate()
+            .map(|(i, pairing)| {
+                ChallengeBlockBuilder::new(
+                    &PublicKey::from(participants[pairing.white]),
+                    &PublicKey::from(participants[pairing.black]),
+                )
+                .network_id(self.header().network_id())
+                .id(first_id.wrapping_add(i as u32))
+                .tag("Round", &round)
+                .tag("Board", &(i + 1).to_string())
+                .build()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto;
+    use crate::notation;
+    use crate::tournament::TournamentHeader;
+    use chess::Action;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    #[test]
+    fn swiss_rounds() {
+        // every pair meets exactly once over a round robin
+        for players in 2..8 {
+            let seats = players + players % 2;
+            let mut met = vec![vec![0; players]; players];
+            for round in 0..seats - 1 {
+                let round = round_robin(players, round);
+                assert_eq!(round.bye.is_some(), players % 2 == 1);
+                for pairing in round.pairings {
+                    met[pairing.white][pairing.black] += 1;
+                    met[pairing.black][pairing.white] += 1;
+                }
+            }
+            for (i, row) in met.iter().enumerate() {
+                for (j, &count) in row.iter().enumerate() {
+                    assert_eq!(count, if i == j { 0 } else { 1 });
+                }
+            }
+        }
+
+        let rng = crypto::new_rng();
+        let organizer = crypto::generate_key(&rng);
+        let keys: Vec<Ed25519KeyPair> = (0..4).map(|_| crypto::generate_key(&rng)).collect();
+        let participants: Vec<[u8; 32]> = keys
+            .iter()
+            .map(|key_pair| {
+                let mut key = [0; 32];
+                key.copy_from_slice(key_pair.public_key().as_ref());
+                key
+            })
+            .collect();
+        let header = TournamentHeader::new(
+            organizer.public_key().as_ref(),
+            1,
+            TournamentFormat::Swiss,
+            3,
+            &participants,
+        );
+        let mut tournament = TournamentChain::new(header, &organizer).unwrap();
+        let key_of = |public_key: PublicKey| {
+            keys.iter()
+                .find(|key_pair| PublicKey::of(key_pair) == public_key)
+                .unwrap()
+        };
+
+        // black mates in every game
+        let mut games = Vec::new();
+        for round in 0..2 {
+            let challenges = tournament
+                .next_round_challenges(&games, 10 * round + 1)
+                .unwrap();
+            assert_eq!(challenges.len(), 2);
+            assert_eq!(challenges[1].tag("Board"), Some("2"));
+            let mut chains = Vec::new();
+            for challenge in challenges {
+                let mut chain = GameChain::new(challenge);
+                let (white, black) = chain.players();
+                let (white, black) = (key_of(white), key_of(black));
+                chain.accept(white).unwrap();
+                chain.accept(black).unwrap();
+                chains.push(chain);
+            }
+            let ids: Vec<u32> = chains.iter().map(GameChain::id).collect();
+
+            // pairing waits for the round to finish
+            let mut pending = tournament.clone();
+            pending.add_round(&organizer, &ids).unwrap();
+            let mut unfinished = games.clone();
+            unfinished.extend(chains.iter().cloned());
+            assert_eq!(
+                pending.next_pairings(&unfinished),
+                Err(Error::RoundUnfinished)
+            );
+
+            for mut chain in chains {
+                let (white, black) = chain.players();
+                let (white, black) = (key_of(white), key_of(black));
+                for (i, uci) in ["f2f3", "e7e5", "g2g4", "d8h4"].iter().enumerate() {
+                    let key_pair = if i % 2 == 0 { white } else { black };
+                    let mv = notation::parse_uci(uci).unwrap();
+                    chain
+                        .make_move_block(key_pair, Action::MakeMove(mv))
+                        .unwrap();
+                }
+                games.push(chain);
+            }
+            tournament.add_round(&organizer, &ids).unwrap();
+        }
+
+        // nobody has a rematch in the third round
+        let table = tournament.compute_standings(&games).unwrap();
+        let third = tournament.next_pairings(&games).unwrap();
+        assert_eq!(third.bye, None);
+        assert_eq!(third.pairings.len(), 2);
+        for pairing in &third.pairings {
+            assert_eq!(table.games[pairing.white][pairing.black], 0);
+        }
+    }
+}
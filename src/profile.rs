@@ -0,0 +1,237 @@
+//! Player profiles. A profile is a record signed by a player's key, so
+//! software can show a name instead of a fingerprint for any chain that key
+//! played. Profiles aren't part of any chain and can be distributed
+//! alongside chains however is convenient.
+
+use crate::block::GameChain;
+use crate::crypto;
+use crate::error::Error;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use chess::Color;
+use core::str;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+// Keeps a profile signature from being mistaken for a signature on anything
+// else.
+const DOMAIN: &[u8] = b"lineage profile";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    public_key: [u8; 32],
+    display_name: String,
+    federation_id: String,
+    avatar_hash: Option<[u8; 32]>,
+    signature: Vec<u8>,
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "profile",
+        reason,
+    }
+}
+
+/// Reads a length-prefixed string at `offset`, returning it and the offset
+/// after it.
+fn read_text(bytes: &[u8], offset: usize) -> Result<(String, usize), Error> {
+    let len = *bytes
+        .get(offset)
+        .ok_or_else(|| malformed("not enough bytes"))? as usize;
+    let text = bytes
+        .get(offset + 1..offset + 1 + len)
+        .ok_or_else(|| malformed("not enough bytes"))?;
+    let text = str::from_utf8(text).map_err(|_| malformed("text is not UTF-8"))?;
+    Ok((text.into(), offset + 1 + len))
+}
+
+impl Profile {
+    /// Signs a profile. The display name and federation id may each be up to
+    /// 255 bytes; the federation id may be empty.
+    pub fn new(
+        key_pair: &Ed25519KeyPair,
+        display_name: &str,
+        federation_id: &str,
+        avatar_hash: Option<[u8; 32]>,
+    ) -> Result<Profile, Error> {
+        if display_name.len() > 255 || federation_id.len() > 255 {
+            return Err(malformed("field too long"));
+        }
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(key_pair.public_key().as_ref());
+        let mut profile = Profile {
+            public_key,
+            display_name: display_name.into(),
+            federation_id: federation_id.into(),
+            avatar_hash,
+            signature: Vec::new(),
+        };
+        profile.signature = crypto::sign(key_pair, &profile.message());
+        Ok(profile)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Profile, Error> {
+        let not_enough = || malformed("not enough bytes");
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(bytes.get(..32).ok_or_else(not_enough)?);
+        let (display_name, offset) = read_text(bytes, 32)?;
+        let (federation_id, mut offset) = read_text(bytes, offset)?;
+        let avatar_hash = match bytes.get(offset).ok_or_else(not_enough)? {
+            0 => None,
+            1 => {
+                let mut hash = [0; 32];
+                hash.copy_from_slice(bytes.get(offset + 1..offset + 33).ok_or_else(not_enough)?);
+                offset += 32;
+                Some(hash)
+            }
+            _ => return Err(malformed("bad avatar flag")),
+        };
+        offset += 1;
+        let signature = bytes.get(offset..offset + 64).ok_or_else(not_enough)?;
+        Ok(Profile {
+            public_key,
+            display_name,
+            federation_id,
+            avatar_hash,
+            signature: signature.to_vec(),
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        self.write_bytes(&mut bytes);
+        bytes
+    }
+
+    /// Encoded as the public key, the display name and federation id each
+    /// preceded by a length byte, an avatar flag byte followed by the hash if
+    /// it is 1, and the signature.
+    pub fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        self.write_unsigned(bytes);
+        bytes.extend(&self.signature);
+    }
+
+    fn write_unsigned(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(&self.public_key);
+        bytes.push(self.display_name.len() as u8);
+        bytes.extend(self.display_name.as_bytes());
+        bytes.push(self.federation_id.len() as u8);
+        bytes.extend(self.federation_id.as_bytes());
+        match &self.avatar_hash {
+            Some(hash) => {
+                bytes.push(1);
+                bytes.extend(hash);
+            }
+            None => bytes.push(0),
+        }
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        32 + 1
+            + self.display_name.len()
+            + 1
+            + self.federation_id.len()
+            + 1
+            + self.avatar_hash.map_or(0, |_| 32)
+            + 64
+    }
+
+    fn message(&self) -> Vec<u8> {
+        let mut message = DOMAIN.to_vec();
+        self.write_unsigned(&mut message);
+        message
+    }
+
+    /// Checks that the profile was signed by the key it describes.
+    pub fn verify(&self) -> bool {
+        crypto::verify(&self.public_key, &self.message(), &self.signature)
+    }
+
+    pub fn public_key(&self) -> &[u8; 32] {
+        &self.public_key
+    }
+
+    pub fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
+    pub fn federation_id(&self) -> &str {
+        &self.federation_id
+    }
+
+    pub fn avatar_hash(&self) -> Option<&[u8; 32]> {
+        self.avatar_hash.as_ref()
+    }
+}
+
+/// The display name from the first valid profile for `public_key`, or the
+/// key's fingerprint if there is none.
+pub fn display_name(public_key: &[u8], profiles: &[Profile]) -> String {
+    profiles
+        .iter()
+        .find(|profile| &profile.public_key[..] == public_key && profile.verify())
+        .map_or_else(
+            || crypto::fingerprint(public_key),
+            |profile| profile.display_name.clone(),
+        )
+}
+
+impl GameChain {
+    /// The side played by the key that signed `profile`, or None if the
+    /// profile doesn't verify or its key isn't in this game.
+    pub fn profile_side(&self, profile: &Profile) -> Option<Color> {
+        if !profile.verify() {
+            return None;
+        }
+        self.side_of(&profile.public_key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::key::PublicKey;
+
+    #[test]
+    fn signed_profiles() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let stranger = crypto::generate_key(&rng);
+        let chain = GameChain::new(ChallengeBlock::new(
+            &PublicKey::of(&white),
+            &PublicKey::of(&black),
+        ));
+
+        let profile = Profile::new(&black, "Magnus", "FIDE 1503014", Some([7; 32])).unwrap();
+        assert!(profile.verify());
+        assert_eq!(
+            Profile::from_bytes(&profile.as_bytes()),
+            Ok(profile.clone())
+        );
+        assert_eq!(profile.as_bytes().len(), profile.encoded_len());
+        assert_eq!(chain.profile_side(&profile), Some(Color::Black));
+        let other = Profile::new(&stranger, "Nobody", "", None).unwrap();
+        assert_eq!(chain.profile_side(&other), None);
+
+        let profiles = vec![profile.clone()];
+        assert_eq!(
+            display_name(black.public_key().as_ref(), &profiles),
+            "Magnus"
+        );
+        assert_eq!(
+            display_name(white.public_key().as_ref(), &profiles),
+            crypto::fingerprint(white.public_key().as_ref())
+        );
+
+        // a profile can't be renamed without the player's key
+        let mut bytes = profile.as_bytes();
+        bytes[33] = b'N';
+        let renamed = Profile::from_bytes(&bytes).unwrap();
+        assert!(!renamed.verify());
+        assert_eq!(chain.profile_side(&renamed), None);
+        assert!(Profile::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use crate::block::{GameChain, MoveBlock};
+use crate::error::Error;
+use crate::key::PublicKey;
+
+use alloc::vec::Vec;
+
+/// Evidence that a player signed two different moves at the same ply. The
+/// proof carries the chain up to (but not including) the disputed ply, so
+/// anyone can check both signatures without any other data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EquivocationProof {
+    prefix: GameChain,
+    first: MoveBlock,
+    second: MoveBlock,
+}
+
+impl EquivocationProof {
+    /// Builds a proof from two copies of the same game whose next move after
+    /// their common prefix differs. Returns None if the chains don't
+    /// demonstrate an equivocation.
+    pub fn from_chains(a: &GameChain, b: &GameChain) -> Option<EquivocationProof> {
+        if a.challenge() != b.challenge() || a.accepts() != b.accepts() {
+            return None;
+        }
+        let ply = a
+            .moves()
+            .iter()
+            .zip(b.moves())
+            .position(|(first, second)| first != second)?;
+        let proof = EquivocationProof {
+            prefix: a.truncated(ply),
+            first: a.moves()[ply].clone(),
+            second: b.moves()[ply].clone(),
+        };
+        if proof.verify() {
+            Some(proof)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<EquivocationProof, Error> {
+        if bytes.len() < 66 * 2 {
+            return Err(Error::Malformed {
+                block: "equivocation proof",
+                reason: "not enough bytes",
+            });
+        }
+        let split = bytes.len() - 66 * 2;
+        Ok(EquivocationProof {
+            prefix: GameChain::from_bytes(&bytes[..split])?,
+            first: MoveBlock::from_bytes(&bytes[split..split + 66])?,
+            second: MoveBlock::from_bytes(&bytes[split + 66..])?,
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.prefix.as_bytes();
+        bytes.extend(self.first.as_bytes());
+        bytes.extend(self.second.as_bytes());
+        bytes
+    }
+
+    /// Checks that the prefix is a valid chain and that both moves are
+    /// different and correctly signed by the player whose turn it was.
+    pub fn verify(&self) -> bool {
+        if !self.prefix.verify() {
+            return false;
+        }
+        if self.first.start_square() == self.second.start_square()
+            && self.first.end_square() == self.second.end_square()
+        {
+            return false;
+        }
+        let signer = match self.prefix.signer_for_next_move() {
+            Ok(signer) => signer,
+            Err(_) => return false,
+        };
+        let prefix_bytes = self.prefix.signing_prefix();
+        [&self.first, &self.second].iter().all(|move_block| {
+            let mut bytes = prefix_bytes.clone();
+            bytes.push(move_block.start_square());
+            bytes.push(move_block.end_square());
+            signer.verify(&bytes, move_block.signature())
+        })
+    }
+
+    /// The public key of the player who equivocated.
+    pub fn offender(&self) -> PublicKey {
+        self.prefix
+            .signer_for_next_move()
+            .unwrap_or_else(|_| self.prefix.signer_public_key(self.prefix.move_count()))
+    }
+
+    pub fn ply(&self) -> usize {
+        self.prefix.move_count()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use chess::{Action, ChessMove, Square};
+
+    fn make_move(from: &str, to: &str) -> Action {
+        Action::MakeMove(ChessMove::new(
+            from.parse::<Square>().unwrap(),
+            to.parse::<Square>().unwrap(),
+            None,
+        ))
+    }
+
+    #[test]
+    fn prove_equivocation() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        assert!(chain.accept(&white).is_ok());
+        assert!(chain.accept(&black).is_ok());
+        assert!(chain.make_move_block(&white, make_move("e2", "e4")).is_ok());
+
+        let mut a = chain.clone();
+        assert!(a.make_move_block(&black, make_move("e7", "e5")).is_ok());
+        let mut b = chain.clone();
+        assert!(b.make_move_block(&black, make_move("c7", "c5")).is_ok());
+
+        let proof = EquivocationProof::from_chains(&a, &b).unwrap();
+        assert!(proof.verify());
+        assert_eq!(proof.ply(), 1);
+        assert_eq!(proof.offender(), PublicKey::of(&black));
+        assert_eq!(
+            proof,
+            EquivocationProof::from_bytes(&proof.as_bytes()).unwrap()
+        );
+
+        assert!(EquivocationProof::from_chains(&a, &a).is_none());
+    }
+}
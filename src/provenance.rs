@@ -0,0 +1,113 @@
+//! Provenance of moves: everything needed to show who vouched for a move and
+//! how, so a dispute can be argued from the signatures alone. Moves carry no
+//! time of their own, so the timestamp is the opponent's receipt for the
+//! move, if they signed one.
+
+use crate::block::GameChain;
+use crate::crypto;
+use crate::key::PublicKey;
+
+use alloc::vec::Vec;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Provenance {
+    pub move_index: usize,
+    /// The key that had to sign the move, after any key rotations.
+    pub signer: PublicKey,
+    /// Exactly the bytes the signature covers.
+    pub signed_bytes: Vec<u8>,
+    pub signature: Vec<u8>,
+    /// When the opponent acknowledged receiving the move, in seconds since
+    /// the Unix epoch.
+    pub timestamp: Option<u64>,
+    /// The SHA-256 of the signing prefix the move extends: the chain before
+    /// it, or its last checkpoint.
+    pub prior_hash: [u8; 32],
+    /// Whether the move redeemed a conditional signed in advance, rather
+    /// than being signed over the chain before it.
+    pub conditional: bool,
+    /// Whether the signature verifies over `signed_bytes`.
+    pub verified: bool,
+}
+
+impl GameChain {
+    /// The provenance of the move block at `move_index`, counting every move
+    /// block in the chain, taken back or not.
+    pub fn provenance(&self, move_index: usize) -> Option<Provenance> {
+        let block = self.moves().get(move_index)?;
+        let before = self.truncated(move_index);
+        let signer = before.signer_for_next_move().ok()?;
+        let squares = [block.start_square(), block.end_square()];
+        let prefix = before.signing_prefix();
+        let redeemed = before.redemptions().redeemed_message(
+            &self.canonical_id(),
+            signer.as_bytes(),
+            squares,
+            block.signature(),
+        );
+        let conditional = redeemed.is_some();
+        let signed_bytes = redeemed.unwrap_or_else(|| {
+            let mut bytes = prefix.clone();
+            bytes.extend(&squares);
+            bytes
+        });
+        let timestamp = self
+            .receipts()
+            .iter()
+            .find(|receipt| receipt.move_index == move_index)
+            .map(|receipt| receipt.timestamp);
+        Some(Provenance {
+            move_index,
+            signer,
+            verified: signer.verify(&signed_bytes, block.signature()),
+            signed_bytes,
+            signature: block.signature().to_vec(),
+            timestamp,
+            prior_hash: crypto::block_hash(&prefix),
+            conditional,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn move_provenance() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        for uci in &["e2e4", "e7e5"] {
+            let mv = notation::parse_uci(uci).unwrap();
+            let key_pair = if *uci == "e2e4" { &white } else { &black };
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        }
+        chain.acknowledge_move(&white, 1_500).unwrap();
+
+        let first = chain.provenance(0).unwrap();
+        assert_eq!(first.signer, PublicKey::of(&white));
+        assert_eq!(first.timestamp, None);
+        assert!(first.verified && !first.conditional);
+
+        let second = chain.provenance(1).unwrap();
+        assert_eq!(second.signer, PublicKey::of(&black));
+        assert_eq!(second.timestamp, Some(1_500));
+        assert!(second.verified);
+        let prefix = chain.truncated(1).signing_prefix();
+        assert_eq!(second.prior_hash, crypto::block_hash(&prefix));
+        assert_eq!(&second.signed_bytes[..prefix.len()], &prefix[..]);
+        assert_eq!(second.signature, chain.moves()[1].signature());
+
+        assert_eq!(chain.provenance(2), None);
+    }
+}
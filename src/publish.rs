@@ -0,0 +1,361 @@
+//! Publishing finished games to archives. A Publisher is configured with
+//! endpoints: an archive running the HTTP API, another node's JSON-RPC
+//! interface, or a folder. Once a game finishes and its chain verifies, it's
+//! queued for every endpoint and pushed on each publish, with failed pushes
+//! retried after a delay that doubles each time. Every delivery leaves a
+//! receipt: the archive's HTTP status, the node's signed witness of the
+//! chain, or the path it was written to.
+
+use crate::block::{GameChain, GameStatus};
+use crate::dropfolder::DropFolder;
+use crate::error::Error;
+use crate::manager::GameManager;
+use crate::notary::{self, Witness};
+
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where finished games are pushed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Endpoint {
+    /// An archive serving the HTTP API at host:port; chains are posted to
+    /// `/games`.
+    Http(String),
+    /// A node's JSON-RPC interface at host:port, which witnesses the chain
+    /// and imports it.
+    Node(String),
+    /// A folder, such as an archive's drop folder.
+    Folder(PathBuf),
+}
+
+/// Proof that an endpoint took a chain.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeliveryReceipt {
+    /// The archive's HTTP status: 200, or 409 if it already had the game.
+    Http(u16),
+    /// The node's signature over exactly the chain delivered.
+    Witness(Witness),
+    /// Where the chain was written.
+    Written(PathBuf),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delivery {
+    pub id: u32,
+    pub endpoint: Endpoint,
+    pub delivered_at: u64,
+    pub receipt: DeliveryReceipt,
+}
+
+/// A chain that was given up on after too many failed pushes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Failure {
+    pub id: u32,
+    pub endpoint: Endpoint,
+    pub attempts: u32,
+    pub error: String,
+}
+
+fn invalid_data(reason: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn connect(addr: &str) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Sends one JSON-RPC request and reads the result.
+fn call(
+    stream: &mut BufReader<TcpStream>,
+    id: u64,
+    method: &str,
+    params: Value,
+) -> io::Result<Result<Value, String>> {
+    let request = json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+    writeln!(stream.get_mut(), "{}", request)?;
+    let mut line = String::new();
+    stream.read_line(&mut line)?;
+    let response: Value =
+        serde_json::from_str(&line).map_err(|error| invalid_data(error.to_string()))?;
+    match response["error"]["message"].as_str() {
+        Some(message) => Ok(Err(message.to_string())),
+        None => Ok(Ok(response["result"].clone())),
+    }
+}
+
+impl Endpoint {
+    /// Reads `http://host:port`, `node://host:port`, or a folder path.
+    pub fn parse(spec: &str) -> Endpoint {
+        if let Some(addr) = spec.strip_prefix("http://") {
+            Endpoint::Http(addr.trim_end_matches('/').to_string())
+        } else if let Some(addr) = spec.strip_prefix("node://") {
+            Endpoint::Node(addr.to_string())
+        } else {
+            Endpoint::Folder(PathBuf::from(spec))
+        }
+    }
+
+    /// Pushes `chain` once.
+    pub fn deliver(&self, chain: &GameChain) -> io::Result<DeliveryReceipt> {
+        let encoded = bs58::encode(chain.as_bytes()).into_string();
+        match self {
+            Endpoint::Http(addr) => {
+                let body = json!({ "chain": encoded }).to_string();
+                let mut stream = connect(addr)?;
+                write!(
+                    stream,
+                    "POST /games HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    addr,
+                    body.len(),
+                    body
+                )?;
+                let mut response = String::new();
+                stream.read_to_string(&mut response)?;
+                let status = response
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|status| status.parse().ok())
+                    .ok_or_else(|| invalid_data("not an HTTP response".to_string()))?;
+                match status {
+                    200 | 409 => Ok(DeliveryReceipt::Http(status)),
+                    _ => Err(io::Error::other(format!("archive answered {}", status))),
+                }
+            }
+            Endpoint::Node(addr) => {
+                let mut stream = BufReader::new(connect(addr)?);
+                let witness = call(&mut stream, 1, "notarize", json!({ "chain": encoded }))?
+                    .map_err(invalid_data)?;
+                let witness = witness["witness"]
+                    .as_str()
+                    .and_then(|witness| bs58::decode(witness).into_vec().ok())
+                    .ok_or_else(|| invalid_data("expected a base58 witness".to_string()))?;
+                let witness = Witness::from_bytes(&witness)
+                    .map_err(|error| invalid_data(error.to_string()))?;
+                if !notary::verify_witness(chain, &witness) {
+                    return Err(invalid_data(Error::VerificationFailed.to_string()));
+                }
+                match call(&mut stream, 2, "import_chain", json!({ "chain": encoded }))? {
+                    Ok(_) => {}
+                    Err(message) if message == Error::DuplicateGame(chain.id()).to_string() => {}
+                    Err(message) => return Err(io::Error::other(message)),
+                }
+                Ok(DeliveryReceipt::Witness(witness))
+            }
+            Endpoint::Folder(dir) => Ok(DeliveryReceipt::Written(
+                DropFolder::open(dir)?.write(chain)?,
+            )),
+        }
+    }
+}
+
+struct Pending {
+    chain: GameChain,
+    endpoint: usize,
+    attempts: u32,
+    due: u64,
+}
+
+/// Pushes finished games to every endpoint, retrying failures. Times are
+/// seconds since the Unix epoch.
+pub struct Publisher {
+    endpoints: Vec<Endpoint>,
+    max_attempts: u32,
+    retry_after: u64,
+    offered: HashSet<u32>,
+    pending: Vec<Pending>,
+    deliveries: Vec<Delivery>,
+    failures: Vec<Failure>,
+}
+
+impl Default for Publisher {
+    fn default() -> Publisher {
+        Publisher {
+            endpoints: Vec::new(),
+            max_attempts: 8,
+            retry_after: 60,
+            offered: HashSet::new(),
+            pending: Vec::new(),
+            deliveries: Vec::new(),
+            failures: Vec::new(),
+        }
+    }
+}
+
+impl Publisher {
+    pub fn new() -> Publisher {
+        Publisher::default()
+    }
+
+    pub fn endpoint(mut self, endpoint: Endpoint) -> Publisher {
+        self.endpoints.push(endpoint);
+        self
+    }
+
+    /// How many times a push is tried before it's given up on. 8 by
+    /// default.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Publisher {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// How long to wait before the first retry, doubling after each. A
+    /// minute by default.
+    pub fn retry_after(mut self, seconds: u64) -> Publisher {
+        self.retry_after = seconds;
+        self
+    }
+
+    /// Queues `chain` for every endpoint if it's finished, verifies, and
+    /// hasn't been offered before. Returns whether it was queued.
+    pub fn offer(&mut self, chain: &GameChain, now: u64) -> bool {
+        match chain.status() {
+            GameStatus::Finished(_) => {}
+            _ => return false,
+        }
+        if !chain.verify() || !self.offered.insert(chain.id()) {
+            return false;
+        }
+        for endpoint in 0..self.endpoints.len() {
+            self.pending.push(Pending {
+                chain: chain.clone(),
+                endpoint,
+                attempts: 0,
+                due: now,
+            });
+        }
+        true
+    }
+
+    /// Offers every game in `manager`, returning how many were queued.
+    pub fn offer_finished(&mut self, manager: &GameManager, now: u64) -> usize {
+        let mut ids = manager.ids();
+        ids.sort_unstable();
+        ids.into_iter()
+            .filter_map(|id| manager.get(id))
+            .filter(|chain| self.offer(chain, now))
+            .count()
+    }
+
+    /// Tries every push that's due at `now`, returning the deliveries made.
+    pub fn publish(&mut self, now: u64) -> Vec<Delivery> {
+        let mut delivered = Vec::new();
+        let mut waiting = Vec::new();
+        for mut pending in self.pending.drain(..) {
+            if pending.due > now {
+                waiting.push(pending);
+                continue;
+            }
+            let endpoint = &self.endpoints[pending.endpoint];
+            pending.attempts += 1;
+            match endpoint.deliver(&pending.chain) {
+                Ok(receipt) => delivered.push(Delivery {
+                    id: pending.chain.id(),
+                    endpoint: endpoint.clone(),
+                    delivered_at: now,
+                    receipt,
+                }),
+                Err(error) if pending.attempts >= self.max_attempts => {
+                    self.failures.push(Failure {
+                        id: pending.chain.id(),
+                        endpoint: endpoint.clone(),
+                        attempts: pending.attempts,
+                        error: error.to_string(),
+                    })
+                }
+                Err(_) => {
+                    let backoff = 1u64.checked_shl(pending.attempts - 1).unwrap_or(u64::MAX);
+                    pending.due = now.saturating_add(self.retry_after.saturating_mul(backoff));
+                    waiting.push(pending);
+                }
+            }
+        }
+        self.pending = waiting;
+        self.deliveries.extend(delivered.iter().cloned());
+        delivered
+    }
+
+    /// Every delivery made so far.
+    pub fn deliveries(&self) -> &[Delivery] {
+        &self.deliveries
+    }
+
+    /// Pushes given up on.
+    pub fn failures(&self) -> &[Failure] {
+        &self.failures
+    }
+
+    /// The number of pushes still to be made.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+    use std::fs;
+
+    #[test]
+    fn publish_finished_games() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("lineage-publish-{}", crypto::random_id(&rng)));
+        let mut publisher = Publisher::new()
+            .endpoint(Endpoint::parse(dir.to_str().unwrap()))
+            // nothing listens on port 1, so every push there fails
+            .endpoint(Endpoint::parse("http://127.0.0.1:1"))
+            .max_attempts(3)
+            .retry_after(10);
+        assert_eq!(publisher.endpoints[1], Endpoint::Http("127.0.0.1:1".into()));
+        assert!(!publisher.offer(&chain, 0));
+
+        for (i, uci) in ["f2f3", "e7e5", "g2g4", "d8h4"].iter().enumerate() {
+            let key_pair = if i % 2 == 0 { &white } else { &black };
+            let mv = notation::parse_uci(uci).unwrap();
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        }
+        assert!(publisher.offer(&chain, 0));
+        assert!(!publisher.offer(&chain, 0));
+
+        let delivered = publisher.publish(0);
+        assert_eq!(delivered.len(), 1);
+        let path = dir.join(format!("{:08x}.chain", chain.id()));
+        assert_eq!(delivered[0].receipt, DeliveryReceipt::Written(path.clone()));
+        assert_eq!(fs::read(&path).unwrap(), chain.as_bytes());
+
+        // the failed push is retried after 10 seconds, then 20
+        assert_eq!(publisher.pending_len(), 1);
+        assert!(publisher.publish(9).is_empty());
+        assert!(publisher.publish(10).is_empty());
+        assert!(publisher.publish(29).is_empty());
+        assert!(publisher.failures().is_empty());
+        assert!(publisher.publish(30).is_empty());
+        assert_eq!(publisher.pending_len(), 0);
+        assert_eq!(publisher.failures()[0].attempts, 3);
+        assert_eq!(publisher.deliveries(), &delivered[..]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+//! Python bindings, built into a `lineage` module with the extension-module
+//! feature, e.g. `cargo rustc --lib --crate-type cdylib --features
+//! extension-module` with the library renamed to lineage.so. Keys,
+//! challenges, and chains are wrapped as KeyPair, ChallengeBlock, and
+//! GameChain classes, moves are given in UCI notation, and errors from the
+//! crate are raised as LineageError.
+
+use crate::block::{ChallengeBlock, ChallengeBlockBuilder, GameChain};
+use crate::crypto;
+use crate::error::Error;
+use crate::key::PublicKey;
+use crate::notation;
+use crate::render::BoardStyle;
+
+use chess::{Action, Color};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+create_exception!(lineage, LineageError, PyException);
+
+impl From<Error> for PyErr {
+    fn from(error: Error) -> PyErr {
+        LineageError::new_err(error.to_string())
+    }
+}
+
+#[pyclass(name = "KeyPair")]
+struct PyKeyPair {
+    pkcs8: Vec<u8>,
+    key_pair: Ed25519KeyPair,
+}
+
+#[pymethods]
+impl PyKeyPair {
+    #[staticmethod]
+    fn generate() -> PyKeyPair {
+        let pkcs8 = crypto::generate_pkcs8(&crypto::new_rng());
+        let key_pair = crypto::key_from_pkcs8(&pkcs8).unwrap();
+        PyKeyPair { pkcs8, key_pair }
+    }
+
+    #[staticmethod]
+    fn from_pkcs8(pkcs8: &[u8]) -> PyResult<PyKeyPair> {
+        Ok(PyKeyPair {
+            pkcs8: pkcs8.to_vec(),
+            key_pair: crypto::key_from_pkcs8(pkcs8)?,
+        })
+    }
+
+    #[getter]
+    fn pkcs8<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.pkcs8)
+    }
+
+    #[getter]
+    fn public_key<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.key_pair.public_key().as_ref())
+    }
+
+    #[getter]
+    fn fingerprint(&self) -> String {
+        crypto::fingerprint(self.key_pair.public_key().as_ref())
+    }
+}
+
+#[pyclass(name = "ChallengeBlock")]
+#[derive(Clone)]
+struct PyChallengeBlock {
+    block: ChallengeBlock,
+}
+
+#[pymethods]
+impl PyChallengeBlock {
+    #[new]
+    #[pyo3(signature = (white_public_key, black_public_key, network_id=0, id=0, timestamp=0))]
+    fn new(
+        white_public_key: &[u8],
+        black_public_key: &[u8],
+        network_id: u8,
+        id: u32,
+        timestamp: u64,
+    ) -> PyResult<PyChallengeBlock> {
+        let key = |bytes| {
+            PublicKey::from_slice(bytes)
+                .map_err(|_| PyValueError::new_err("public keys must be 32 bytes"))
+        };
+        let block = ChallengeBlockBuilder::new(&key(white_public_key)?, &key(black_public_key)?)
+            .network_id(network_id)
+            .id(id)
+            .timestamp(timestamp)
+            .build()?;
+        Ok(PyChallengeBlock { block })
+    }
+
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<PyChallengeBlock> {
+        Ok(PyChallengeBlock {
+            block: ChallengeBlock::from_bytes(bytes)?,
+        })
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.block.as_bytes())
+    }
+
+    fn __str__(&self) -> String {
+        self.block.to_string()
+    }
+
+    #[getter]
+    fn id(&self) -> u32 {
+        self.block.id()
+    }
+
+    #[getter]
+    fn network_id(&self) -> u8 {
+        self.block.network_id()
+    }
+
+    #[getter]
+    fn timestamp(&self) -> u64 {
+        self.block.timestamp()
+    }
+
+    #[getter]
+    fn white_public_key<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.block.white_public_key().as_bytes())
+    }
+
+    #[getter]
+    fn black_public_key<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, self.block.black_public_key().as_bytes())
+    }
+}
+
+#[pyclass(name = "GameChain")]
+struct PyGameChain {
+    chain: GameChain,
+}
+
+#[pymethods]
+impl PyGameChain {
+    #[new]
+    fn new(challenge: &PyChallengeBlock) -> PyGameChain {
+        PyGameChain {
+            chain: GameChain::new(challenge.block.clone()),
+        }
+    }
+
+    #[staticmethod]
+    fn from_bytes(bytes: &[u8]) -> PyResult<PyGameChain> {
+        Ok(PyGameChain {
+            chain: GameChain::from_bytes(bytes)?,
+        })
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> &'py PyBytes {
+        PyBytes::new(py, &self.chain.as_bytes())
+    }
+
+    fn __str__(&self) -> String {
+        self.chain.to_string()
+    }
+
+    fn __len__(&self) -> usize {
+        self.chain.move_count()
+    }
+
+    #[getter]
+    fn challenge(&self) -> PyChallengeBlock {
+        PyChallengeBlock {
+            block: self.chain.challenge().clone(),
+        }
+    }
+
+    fn accept(&mut self, key_pair: &PyKeyPair) -> PyResult<()> {
+        Ok(self.chain.accept(&key_pair.key_pair)?)
+    }
+
+    /// Signs a move given in UCI notation, e.g. "e2e4".
+    fn make_move(&mut self, key_pair: &PyKeyPair, uci: &str) -> PyResult<()> {
+        let mv = notation::parse_uci(uci)
+            .ok_or_else(|| PyValueError::new_err(format!("not a UCI move: {}", uci)))?;
+        Ok(self
+            .chain
+            .make_move_block(&key_pair.key_pair, Action::MakeMove(mv))?)
+    }
+
+    fn verify(&self) -> bool {
+        self.chain.verify()
+    }
+
+    /// The moves played so far, in UCI notation.
+    fn moves(&self) -> Vec<String> {
+        self.chain
+            .iter_positions()
+            .map(|(_, mv, _, _)| mv.to_string())
+            .collect()
+    }
+
+    fn legal_moves(&self) -> PyResult<Vec<String>> {
+        Ok(self
+            .chain
+            .legal_moves()?
+            .iter()
+            .map(|mv| mv.to_string())
+            .collect())
+    }
+
+    fn is_my_turn(&self, public_key: &[u8]) -> bool {
+        self.chain.is_my_turn(public_key)
+    }
+
+    #[pyo3(signature = (unicode=false, flipped=false))]
+    fn render(&self, unicode: bool, flipped: bool) -> PyResult<String> {
+        let style = if unicode {
+            BoardStyle::Unicode
+        } else {
+            BoardStyle::Ascii
+        };
+        let orientation = if flipped { Color::Black } else { Color::White };
+        Ok(self.chain.render_board(style, orientation)?)
+    }
+}
+
+#[pymodule]
+fn lineage(py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyKeyPair>()?;
+    module.add_class::<PyChallengeBlock>()?;
+    module.add_class::<PyGameChain>()?;
+    module.add("LineageError", py.get_type::<LineageError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn play_from_python() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let white = PyKeyPair::generate();
+            let black = PyKeyPair::generate();
+            let challenge = PyChallengeBlock::new(
+                white.public_key(py).as_bytes(),
+                black.public_key(py).as_bytes(),
+                0,
+                5,
+                0,
+            )
+            .unwrap();
+            let mut chain = PyGameChain::new(&challenge);
+            chain.accept(&white).unwrap();
+            chain.accept(&black).unwrap();
+            chain.make_move(&white, "e2e4").unwrap();
+            assert!(chain.make_move(&white, "d2d4").is_err());
+            assert!(chain.make_move(&black, "e7").is_err());
+            assert_eq!(chain.moves(), vec!["e2e4"]);
+            assert_eq!(chain.__len__(), 1);
+            assert!(chain.verify());
+            assert!(chain.is_my_turn(black.public_key(py).as_bytes()));
+
+            let decoded = PyGameChain::from_bytes(chain.__bytes__(py).as_bytes()).unwrap();
+            assert_eq!(decoded.chain, chain.chain);
+            assert_eq!(decoded.challenge().id(), 5);
+        });
+    }
+}
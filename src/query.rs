@@ -0,0 +1,345 @@
+//! Searching the local store. An index file kept in the store directory
+//! holds a small fixed-size record for each chain: its players, when it was
+//! issued, its status, length, and opening. Queries read only the index. A
+//! chain only ever grows, so a record is stale exactly when its chain file's
+//! length has changed; those chains, and any new ones, are decoded and their
+//! records rewritten before the query runs.
+
+use crate::block::{GameChain, GameResult, GameStatus};
+use crate::key::PublicKey;
+use crate::store::{self, GameStore};
+
+use chess::Color;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const INDEX_FILE: &str = "index";
+const RECORD_LEN: usize = 4 + 8 + 32 + 32 + 8 + 1 + 4 + 3;
+
+/// What the index knows about one stored game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexedGame {
+    pub id: u32,
+    pub white: PublicKey,
+    pub black: PublicKey,
+    /// When the challenge was issued, in seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub status: GameStatus,
+    pub move_count: usize,
+    /// The opening's ECO code, e.g. "C20".
+    pub eco: Option<String>,
+    // the length of the chain file the record was made from
+    file_len: u64,
+}
+
+fn status_byte(status: GameStatus) -> u8 {
+    match status {
+        GameStatus::AwaitingAcceptance => 0,
+        GameStatus::InProgress => 1,
+        GameStatus::Adjourned => 2,
+        GameStatus::Disputed => 3,
+        GameStatus::Finished(GameResult::Win(Color::White)) => 4,
+        GameStatus::Finished(GameResult::Win(Color::Black)) => 5,
+        GameStatus::Finished(GameResult::Draw) => 6,
+        GameStatus::Invalid => 7,
+    }
+}
+
+fn status_from_byte(byte: u8) -> Option<GameStatus> {
+    Some(match byte {
+        0 => GameStatus::AwaitingAcceptance,
+        1 => GameStatus::InProgress,
+        2 => GameStatus::Adjourned,
+        3 => GameStatus::Disputed,
+        4 => GameStatus::Finished(GameResult::Win(Color::White)),
+        5 => GameStatus::Finished(GameResult::Win(Color::Black)),
+        6 => GameStatus::Finished(GameResult::Draw),
+        7 => GameStatus::Invalid,
+        _ => return None,
+    })
+}
+
+impl IndexedGame {
+    fn new(chain: &GameChain, file_len: u64) -> IndexedGame {
+        let (white, black) = chain.players();
+        IndexedGame {
+            id: chain.id(),
+            white,
+            black,
+            timestamp: chain.challenge().timestamp(),
+            status: chain.status(),
+            move_count: chain.move_count(),
+            eco: chain.opening().map(|opening| opening.code.to_string()),
+            file_len,
+        }
+    }
+
+    /// Encoded as the id, file length, both keys, the timestamp, a status
+    /// byte, the move count, and the ECO code, or three zero bytes.
+    fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(&self.id.to_be_bytes());
+        bytes.extend(&self.file_len.to_be_bytes());
+        bytes.extend(self.white.as_bytes());
+        bytes.extend(self.black.as_bytes());
+        bytes.extend(&self.timestamp.to_be_bytes());
+        bytes.push(status_byte(self.status));
+        bytes.extend(&(self.move_count as u32).to_be_bytes());
+        match &self.eco {
+            Some(eco) if eco.len() == 3 => bytes.extend(eco.as_bytes()),
+            _ => bytes.extend(&[0; 3]),
+        }
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<IndexedGame> {
+        let mut id = [0; 4];
+        id.copy_from_slice(&bytes[..4]);
+        let mut file_len = [0; 8];
+        file_len.copy_from_slice(&bytes[4..12]);
+        let mut white = [0; 32];
+        white.copy_from_slice(&bytes[12..44]);
+        let mut black = [0; 32];
+        black.copy_from_slice(&bytes[44..76]);
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&bytes[76..84]);
+        let mut move_count = [0; 4];
+        move_count.copy_from_slice(&bytes[85..89]);
+        let eco = match &bytes[89..92] {
+            [0, 0, 0] => None,
+            eco => Some(String::from_utf8(eco.to_vec()).ok()?),
+        };
+        Some(IndexedGame {
+            id: u32::from_be_bytes(id),
+            white: PublicKey::from(white),
+            black: PublicKey::from(black),
+            timestamp: u64::from_be_bytes(timestamp),
+            status: status_from_byte(bytes[84])?,
+            move_count: u32::from_be_bytes(move_count) as usize,
+            eco,
+            file_len: u64::from_be_bytes(file_len),
+        })
+    }
+}
+
+/// Filters for GameStore::query. A new query matches every game.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Query {
+    player: Option<PublicKey>,
+    since: Option<u64>,
+    until: Option<u64>,
+    result: Option<GameResult>,
+    eco: Option<String>,
+    min_moves: usize,
+}
+
+impl Query {
+    pub fn new() -> Query {
+        Query::default()
+    }
+
+    /// Games `public_key` was challenged to play, as white or black.
+    pub fn player(mut self, public_key: &PublicKey) -> Query {
+        self.player = Some(*public_key);
+        self
+    }
+
+    /// Games issued at or after `timestamp`.
+    pub fn since(mut self, timestamp: u64) -> Query {
+        self.since = Some(timestamp);
+        self
+    }
+
+    /// Games issued before `timestamp`.
+    pub fn until(mut self, timestamp: u64) -> Query {
+        self.until = Some(timestamp);
+        self
+    }
+
+    /// Finished games with the given result.
+    pub fn result(mut self, result: GameResult) -> Query {
+        self.result = Some(result);
+        self
+    }
+
+    /// Games whose ECO code starts with `eco`, so "B" matches every
+    /// Sicilian and "C20" only that code.
+    pub fn eco(mut self, eco: &str) -> Query {
+        self.eco = Some(eco.to_string());
+        self
+    }
+
+    /// Games with at least `moves` moves, counting each player's separately.
+    pub fn min_moves(mut self, moves: usize) -> Query {
+        self.min_moves = moves;
+        self
+    }
+
+    pub fn matches(&self, game: &IndexedGame) -> bool {
+        self.player
+            .is_none_or(|key| game.white == key || game.black == key)
+            && self.since.is_none_or(|since| game.timestamp >= since)
+            && self.until.is_none_or(|until| game.timestamp < until)
+            && self
+                .result
+                .is_none_or(|result| game.status == GameStatus::Finished(result))
+            && self.eco.as_ref().is_none_or(|prefix| {
+                game.eco
+                    .as_ref()
+                    .is_some_and(|eco| eco.starts_with(prefix.as_str()))
+            })
+            && game.move_count >= self.min_moves
+    }
+}
+
+impl GameStore {
+    fn index_path(&self) -> PathBuf {
+        self.dir().join(INDEX_FILE)
+    }
+
+    fn read_index(&self) -> io::Result<Vec<IndexedGame>> {
+        let bytes = match fs::read(self.index_path()) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        Ok(bytes
+            .chunks_exact(RECORD_LEN)
+            .filter_map(IndexedGame::from_bytes)
+            .collect())
+    }
+
+    /// Brings the index up to date with the chain files, returning every
+    /// record in id order. Files that don't decode are left out.
+    pub fn reindex(&self) -> io::Result<Vec<IndexedGame>> {
+        let old = self.read_index()?;
+        let mut index = Vec::new();
+        let mut changed = false;
+        for path in self.paths_with_extension("chain")? {
+            let id = match path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| u32::from_str_radix(stem, 16).ok())
+            {
+                Some(id) => id,
+                None => continue,
+            };
+            let file_len = fs::metadata(&path)?.len();
+            match old.iter().find(|game| game.id == id) {
+                Some(game) if game.file_len == file_len => index.push(game.clone()),
+                _ => {
+                    changed = true;
+                    if let Ok(chain) = store::read_chain(&path) {
+                        index.push(IndexedGame::new(&chain, file_len));
+                    }
+                }
+            }
+        }
+        index.sort_by_key(|game| game.id);
+        if changed || index.len() != old.len() {
+            let mut bytes = Vec::with_capacity(index.len() * RECORD_LEN);
+            for game in &index {
+                game.write_bytes(&mut bytes);
+            }
+            let partial = self.index_path().with_extension("partial");
+            fs::write(&partial, bytes)?;
+            fs::rename(partial, self.index_path())?;
+        }
+        Ok(index)
+    }
+
+    /// The stored games matching `query`, in id order, read from the index.
+    pub fn query(&self, query: &Query) -> io::Result<Vec<IndexedGame>> {
+        Ok(self
+            .reindex()?
+            .into_iter()
+            .filter(|game| query.matches(game))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlockBuilder;
+    use crate::crypto;
+    use crate::notation;
+    use chess::Action;
+    use ring::signature::Ed25519KeyPair;
+
+    #[test]
+    fn query_store() {
+        let rng = crypto::new_rng();
+        let dir = std::env::temp_dir().join(format!("lineage-query-{}", std::process::id()));
+        let store = GameStore::open(&dir).unwrap();
+        let me = crypto::generate_key(&rng);
+        let friend = crypto::generate_key(&rng);
+        let stranger = crypto::generate_key(&rng);
+        let game =
+            |id, timestamp, white: &Ed25519KeyPair, black: &Ed25519KeyPair, moves: &[&str]| {
+                let challenge =
+                    ChallengeBlockBuilder::new(&PublicKey::of(white), &PublicKey::of(black))
+                        .id(id)
+                        .timestamp(timestamp)
+                        .build()
+                        .unwrap();
+                let mut chain = GameChain::new(challenge);
+                chain.accept(white).unwrap();
+                chain.accept(black).unwrap();
+                for (i, uci) in moves.iter().enumerate() {
+                    let key_pair = if i % 2 == 0 { white } else { black };
+                    let mv = notation::parse_uci(uci).unwrap();
+                    chain
+                        .make_move_block(key_pair, Action::MakeMove(mv))
+                        .unwrap();
+                }
+                chain
+            };
+        store
+            .save(&game(
+                1,
+                100,
+                &me,
+                &friend,
+                &["f2f3", "e7e5", "g2g4", "d8h4"],
+            ))
+            .unwrap();
+        store
+            .save(&game(2, 200, &friend, &me, &["e2e4", "c7c5"]))
+            .unwrap();
+        let mut growing = game(3, 300, &friend, &stranger, &["e2e4", "e7e5"]);
+        store.save(&growing).unwrap();
+
+        let ids = |query: &Query| -> Vec<u32> {
+            store
+                .query(query)
+                .unwrap()
+                .iter()
+                .map(|game| game.id)
+                .collect()
+        };
+        assert_eq!(ids(&Query::new()), [1, 2, 3]);
+        assert_eq!(ids(&Query::new().player(&PublicKey::of(&me))), [1, 2]);
+        assert_eq!(ids(&Query::new().since(200).until(300)), [2]);
+        assert_eq!(
+            ids(&Query::new().result(GameResult::Win(Color::Black))),
+            [1]
+        );
+        assert_eq!(ids(&Query::new().eco("B")), [2]);
+        assert_eq!(ids(&Query::new().min_moves(3)), [1]);
+
+        // a chain that grows is reindexed, and one that's removed drops out
+        let mv = notation::parse_uci("g1f3").unwrap();
+        growing
+            .make_move_block(&friend, Action::MakeMove(mv))
+            .unwrap();
+        store.save(&growing).unwrap();
+        store.remove(1).unwrap();
+        assert_eq!(ids(&Query::new().min_moves(3)), [3]);
+        let index = store.reindex().unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index[1].eco.as_deref(), Some("C40"));
+        assert_eq!(index[1], IndexedGame::new(&growing, index[1].file_len));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+//! Ratings computed purely from verified games. Chains that don't verify or
+//! haven't finished are skipped.
+
+use crate::block::{GameChain, GameResult};
+
+use chess::Color;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+/// White's score and the two players' keys for every finished, verified game.
+fn results<'a, I>(games: I) -> Vec<([u8; 32], [u8; 32], f64)>
+where
+    I: IntoIterator<Item = &'a GameChain>,
+{
+    games
+        .into_iter()
+        .filter(|chain| chain.verify())
+        .filter_map(|chain| {
+            let score = match chain.result()? {
+                GameResult::Win(Color::White) => 1.0,
+                GameResult::Win(Color::Black) => 0.0,
+                GameResult::Draw => 0.5,
+            };
+            let (white, black) = chain.players();
+            Some((*white.as_bytes(), *black.as_bytes(), score))
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EloConfig {
+    pub initial_rating: f64,
+    pub k_factor: f64,
+}
+
+impl Default for EloConfig {
+    fn default() -> EloConfig {
+        EloConfig {
+            initial_rating: 1500.0,
+            k_factor: 20.0,
+        }
+    }
+}
+
+/// Computes Elo ratings, applying games one at a time in iteration order.
+pub fn elo<'a, I>(games: I, config: EloConfig) -> HashMap<[u8; 32], f64>
+where
+    I: IntoIterator<Item = &'a GameChain>,
+{
+    let mut ratings = HashMap::new();
+    for (white, black, score) in results(games) {
+        let white_rating = *ratings.entry(white).or_insert(config.initial_rating);
+        let black_rating = *ratings.entry(black).or_insert(config.initial_rating);
+        let expected = 1.0 / (1.0 + 10f64.powf((black_rating - white_rating) / 400.0));
+        let change = config.k_factor * (score - expected);
+        ratings.insert(white, white_rating + change);
+        ratings.insert(black, black_rating - change);
+    }
+    ratings
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Glicko2Rating {
+    pub rating: f64,
+    pub deviation: f64,
+    pub volatility: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Glicko2Config {
+    pub initial: Glicko2Rating,
+    /// Constrains how much volatility can change; 0.3 to 1.2 is typical.
+    pub tau: f64,
+}
+
+impl Default for Glicko2Config {
+    fn default() -> Glicko2Config {
+        Glicko2Config {
+            initial: Glicko2Rating {
+                rating: 1500.0,
+                deviation: 350.0,
+                volatility: 0.06,
+            },
+            tau: 0.5,
+        }
+    }
+}
+
+const GLICKO2_SCALE: f64 = 173.7178;
+const CONVERGENCE: f64 = 0.000_001;
+
+fn g(phi: f64) -> f64 {
+    1.0 / (1.0 + 3.0 * phi * phi / (PI * PI)).sqrt()
+}
+
+fn expected(mu: f64, opponent_mu: f64, opponent_phi: f64) -> f64 {
+    1.0 / (1.0 + (-g(opponent_phi) * (mu - opponent_mu)).exp())
+}
+
+/// Computes Glicko-2 ratings, treating all of the games as a single rating
+/// period. `previous` holds ratings from earlier periods; players not in it
+/// start from the configured initial rating.
+pub fn glicko2<'a, I>(
+    games: I,
+    previous: &HashMap<[u8; 32], Glicko2Rating>,
+    config: Glicko2Config,
+) -> HashMap<[u8; 32], Glicko2Rating>
+where
+    I: IntoIterator<Item = &'a GameChain>,
+{
+    // (opponent, score) pairs for each player
+    let mut opponents: HashMap<[u8; 32], Vec<([u8; 32], f64)>> = HashMap::new();
+    for (white, black, score) in results(games) {
+        opponents.entry(white).or_default().push((black, score));
+        opponents
+            .entry(black)
+            .or_default()
+            .push((white, 1.0 - score));
+    }
+    let rating_of = |key: &[u8; 32]| *previous.get(key).unwrap_or(&config.initial);
+
+    let mut ratings = previous.clone();
+    for (player, games) in &opponents {
+        let current = rating_of(player);
+        let mu = (current.rating - 1500.0) / GLICKO2_SCALE;
+        let phi = current.deviation / GLICKO2_SCALE;
+        let sigma = current.volatility;
+
+        let mut v_inverse = 0.0;
+        let mut improvement = 0.0;
+        for (opponent, score) in games {
+            let opponent = rating_of(opponent);
+            let opponent_mu = (opponent.rating - 1500.0) / GLICKO2_SCALE;
+            let opponent_phi = opponent.deviation / GLICKO2_SCALE;
+            let e = expected(mu, opponent_mu, opponent_phi);
+            v_inverse += g(opponent_phi).powi(2) * e * (1.0 - e);
+            improvement += g(opponent_phi) * (score - e);
+        }
+        let v = 1.0 / v_inverse;
+        let delta = v * improvement;
+
+        // new volatility, by the Illinois algorithm
+        let a = (sigma * sigma).ln();
+        let tau = config.tau;
+        let f = |x: f64| {
+            let ex = x.exp();
+            ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2))
+                - (x - a) / (tau * tau)
+        };
+        let mut big_a = a;
+        let mut big_b = if delta * delta > phi * phi + v {
+            (delta * delta - phi * phi - v).ln()
+        } else {
+            let mut k = 1.0;
+            while f(a - k * tau) < 0.0 {
+                k += 1.0;
+            }
+            a - k * tau
+        };
+        let mut f_a = f(big_a);
+        let mut f_b = f(big_b);
+        while (big_b - big_a).abs() > CONVERGENCE {
+            let big_c = big_a + (big_a - big_b) * f_a / (f_b - f_a);
+            let f_c = f(big_c);
+            if f_c * f_b <= 0.0 {
+                big_a = big_b;
+                f_a = f_b;
+            } else {
+                f_a /= 2.0;
+            }
+            big_b = big_c;
+            f_b = f_c;
+        }
+        let new_sigma = (big_a / 2.0).exp();
+
+        let phi_star = (phi * phi + new_sigma * new_sigma).sqrt();
+        let new_phi = 1.0 / (1.0 / (phi_star * phi_star) + 1.0 / v).sqrt();
+        let new_mu = mu + new_phi * new_phi * improvement;
+        ratings.insert(
+            *player,
+            Glicko2Rating {
+                rating: new_mu * GLICKO2_SCALE + 1500.0,
+                deviation: new_phi * GLICKO2_SCALE,
+                volatility: new_sigma,
+            },
+        );
+    }
+
+    // players who sat this period out become less certain
+    for (player, rating) in ratings.iter_mut() {
+        if !opponents.contains_key(player) {
+            let phi = rating.deviation / GLICKO2_SCALE;
+            rating.deviation =
+                (phi * phi + rating.volatility * rating.volatility).sqrt() * GLICKO2_SCALE;
+        }
+    }
+    ratings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    fn fools_mate(white: &Ed25519KeyPair, black: &Ed25519KeyPair) -> GameChain {
+        let challenge = ChallengeBlock::new(&PublicKey::of(white), &PublicKey::of(black));
+        let mut chain = GameChain::new(challenge);
+        assert!(chain.accept(white).is_ok());
+        assert!(chain.accept(black).is_ok());
+        for (i, uci) in ["f2f3", "e7e5", "g2g4", "d8h4"].iter().enumerate() {
+            let key = if i % 2 == 0 { white } else { black };
+            let mv = notation::parse_uci(uci).unwrap();
+            assert!(chain.make_move_block(key, Action::MakeMove(mv)).is_ok());
+        }
+        chain
+    }
+
+    #[test]
+    fn ratings_from_games() {
+        let rng = crypto::new_rng();
+        let alice = crypto::generate_key(&rng);
+        let bob = crypto::generate_key(&rng);
+        let mut alice_key = [0; 32];
+        alice_key.copy_from_slice(alice.public_key().as_ref());
+        let mut bob_key = [0; 32];
+        bob_key.copy_from_slice(bob.public_key().as_ref());
+
+        let games = vec![fools_mate(&alice, &bob)];
+        let ratings = elo(&games, EloConfig::default());
+        assert_eq!(ratings[&alice_key], 1490.0);
+        assert_eq!(ratings[&bob_key], 1510.0);
+
+        let ratings = glicko2(&games, &HashMap::new(), Glicko2Config::default());
+        assert!(ratings[&bob_key].rating > 1500.0);
+        assert!(ratings[&alice_key].rating < 1500.0);
+        assert!(ratings[&bob_key].deviation < 350.0);
+
+        // an unfinished game doesn't count
+        let unfinished = vec![fools_mate(&alice, &bob).truncated(2)];
+        assert!(elo(&unfinished, EloConfig::default()).is_empty());
+    }
+}
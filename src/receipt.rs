@@ -0,0 +1,165 @@
+//! Receipts for moves. The player to move can sign the time they received the
+//! opponent's last move, so in a clock dispute the sender has proof of when
+//! the move was delivered.
+
+use crate::block::GameChain;
+use crate::error::Error;
+use crate::extension::{ExtensionBlock, ExtensionKind};
+
+use alloc::vec::Vec;
+use ring::signature::Ed25519KeyPair;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Receipt {
+    /// The index of the acknowledged move.
+    pub move_index: usize,
+    /// When the move was received, in seconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl GameChain {
+    /// Signs a receipt for the opponent's last move.
+    pub fn acknowledge_move(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        timestamp: u64,
+    ) -> Result<(), Error> {
+        self.sign_extension(
+            key_pair,
+            ExtensionKind::Receipt,
+            timestamp.to_be_bytes().to_vec(),
+        )
+    }
+
+    /// The receipts in the chain, in order.
+    pub fn receipts(&self) -> Vec<Receipt> {
+        self.extensions()
+            .iter()
+            .filter(|(_, block)| block.kind() == ExtensionKind::Receipt)
+            .filter_map(|(ply, block)| receipt(*ply, block))
+            .collect()
+    }
+}
+
+fn receipt(ply: usize, block: &ExtensionBlock) -> Option<Receipt> {
+    if ply == 0 || block.body().len() != 8 {
+        return None;
+    }
+    let mut timestamp = [0; 8];
+    timestamp.copy_from_slice(block.body());
+    Some(Receipt {
+        move_index: ply - 1,
+        timestamp: u64::from_be_bytes(timestamp),
+    })
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "receipt",
+        reason,
+    }
+}
+
+/// Checks a receipt found after `ply` moves against the one before it: there
+/// must be a move to acknowledge, each move is acknowledged at most once, and
+/// timestamps never go backwards.
+fn check_next(
+    previous: Option<Receipt>,
+    challenge_timestamp: u64,
+    ply: usize,
+    block: &ExtensionBlock,
+) -> Result<Receipt, Error> {
+    if ply == 0 {
+        return Err(malformed("no move to acknowledge"));
+    }
+    let receipt = receipt(ply, block).ok_or_else(|| malformed("wrong body length"))?;
+    let earliest = match previous {
+        Some(previous) if previous.move_index == receipt.move_index => {
+            return Err(malformed("move already acknowledged"));
+        }
+        Some(previous) => previous.timestamp,
+        None => challenge_timestamp,
+    };
+    if receipt.timestamp < earliest {
+        return Err(malformed("timestamp goes backwards"));
+    }
+    Ok(receipt)
+}
+
+pub(crate) fn check_extension(chain: &GameChain, block: &ExtensionBlock) -> Result<(), Error> {
+    let previous = chain.receipts().last().cloned();
+    check_next(
+        previous,
+        chain.challenge().timestamp(),
+        chain.move_count(),
+        block,
+    )?;
+    Ok(())
+}
+
+pub(crate) fn check_chain(chain: &GameChain) -> Result<(), Error> {
+    let mut previous = None;
+    for (ply, block) in chain.extensions() {
+        if block.kind() == ExtensionKind::Receipt {
+            previous = Some(check_next(
+                previous,
+                chain.challenge().timestamp(),
+                *ply,
+                block,
+            )?);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlockBuilder;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use chess::{Action, ChessMove, Square};
+
+    #[test]
+    fn acknowledge_moves() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .timestamp(1_000)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        assert!(chain.acknowledge_move(&black, 1_000).is_err());
+
+        chain
+            .make_move_block(
+                &white,
+                Action::MakeMove(ChessMove::new(
+                    "e2".parse::<Square>().unwrap(),
+                    "e4".parse::<Square>().unwrap(),
+                    None,
+                )),
+            )
+            .unwrap();
+        assert_eq!(
+            chain.acknowledge_move(&white, 1_100),
+            Err(Error::NotYourTurn)
+        );
+        assert!(chain.acknowledge_move(&black, 999).is_err());
+        chain.acknowledge_move(&black, 1_100).unwrap();
+        assert!(chain.acknowledge_move(&black, 1_200).is_err());
+        assert_eq!(
+            chain.receipts(),
+            vec![Receipt {
+                move_index: 0,
+                timestamp: 1_100,
+            }]
+        );
+
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::block::GameChain;
+use crate::error::Error;
+use crate::notation::{self, PieceNames};
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use chess::{Board, Color, File, Piece, Rank, Square};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BoardStyle {
+    Ascii,
+    Unicode,
+}
+
+pub(crate) fn piece_char(piece: Piece, color: Color, style: BoardStyle) -> char {
+    match style {
+        BoardStyle::Ascii => {
+            let c = match piece {
+                Piece::Pawn => 'p',
+                Piece::Knight => 'n',
+                Piece::Bishop => 'b',
+                Piece::Rook => 'r',
+                Piece::Queen => 'q',
+                Piece::King => 'k',
+            };
+            match color {
+                Color::White => c.to_ascii_uppercase(),
+                Color::Black => c,
+            }
+        }
+        BoardStyle::Unicode => match (color, piece) {
+            (Color::White, Piece::King) => '♔',
+            (Color::White, Piece::Queen) => '♕',
+            (Color::White, Piece::Rook) => '♖',
+            (Color::White, Piece::Bishop) => '♗',
+            (Color::White, Piece::Knight) => '♘',
+            (Color::White, Piece::Pawn) => '♙',
+            (Color::Black, Piece::King) => '♚',
+            (Color::Black, Piece::Queen) => '♛',
+            (Color::Black, Piece::Rook) => '♜',
+            (Color::Black, Piece::Bishop) => '♝',
+            (Color::Black, Piece::Knight) => '♞',
+            (Color::Black, Piece::Pawn) => '♟',
+        },
+    }
+}
+
+/// How boards and moves are shown. By default, an ASCII board from white's
+/// side, with English piece letters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderOptions {
+    style: BoardStyle,
+    orientation: Color,
+    pieces: PieceNames,
+}
+
+impl Default for RenderOptions {
+    fn default() -> RenderOptions {
+        RenderOptions {
+            style: BoardStyle::Ascii,
+            orientation: Color::White,
+            pieces: PieceNames::ENGLISH,
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn new() -> RenderOptions {
+        RenderOptions::default()
+    }
+
+    pub fn style(mut self, style: BoardStyle) -> RenderOptions {
+        self.style = style;
+        self
+    }
+
+    /// The side shown at the bottom of the board.
+    pub fn orientation(mut self, orientation: Color) -> RenderOptions {
+        self.orientation = orientation;
+        self
+    }
+
+    /// How pieces are named in move text, and on ASCII boards when given as
+    /// letters.
+    pub fn pieces(mut self, pieces: PieceNames) -> RenderOptions {
+        self.pieces = pieces;
+        self
+    }
+}
+
+fn board_char(piece: Piece, color: Color, options: &RenderOptions) -> char {
+    match (options.style, options.pieces) {
+        (BoardStyle::Ascii, PieceNames::Letters(_)) => {
+            let c = options.pieces.name(piece);
+            match color {
+                Color::White => c.to_ascii_uppercase(),
+                Color::Black => c.to_ascii_lowercase(),
+            }
+        }
+        _ => piece_char(piece, color, options.style),
+    }
+}
+
+/// Renders the board as text, one rank per line with rank and file labels.
+/// `orientation` is the side shown at the bottom of the board.
+pub fn render_board(board: &Board, style: BoardStyle, orientation: Color) -> String {
+    render_board_with(
+        board,
+        &RenderOptions::new().style(style).orientation(orientation),
+    )
+}
+
+/// Renders the board as render_board does, with the pieces named as
+/// `options` says.
+pub fn render_board_with(board: &Board, options: &RenderOptions) -> String {
+    let style = options.style;
+    let orientation = options.orientation;
+    let empty = match style {
+        BoardStyle::Ascii => '.',
+        BoardStyle::Unicode => '·',
+    };
+    let (ranks, files): (Vec<usize>, Vec<usize>) = match orientation {
+        Color::White => ((0..8).rev().collect(), (0..8).collect()),
+        Color::Black => ((0..8).collect(), (0..8).rev().collect()),
+    };
+
+    let mut out = String::new();
+    for &rank in &ranks {
+        out.push_str(&(rank + 1).to_string());
+        for &file in &files {
+            let square = Square::make_square(Rank::from_index(rank), File::from_index(file));
+            out.push(' ');
+            out.push(match (board.piece_on(square), board.color_on(square)) {
+                (Some(piece), Some(color)) => board_char(piece, color, options),
+                _ => empty,
+            });
+        }
+        out.push('\n');
+    }
+    out.push(' ');
+    for &file in &files {
+        out.push(' ');
+        out.push((b'a' + file as u8) as char);
+    }
+    out.push('\n');
+    out
+}
+
+impl GameChain {
+    pub fn render_board(&self, style: BoardStyle, orientation: Color) -> Result<String, Error> {
+        Ok(render_board(&self.current_position()?, style, orientation))
+    }
+
+    /// The moves on the game line in standard algebraic notation, numbered,
+    /// e.g. "1. e4 e5 2. Nf3", with pieces named as `options` says.
+    pub fn movetext(&self, options: &RenderOptions) -> String {
+        let mut board = self.challenge().starting_board();
+        let black_first = board.side_to_move() == Color::Black;
+        let mut out = String::new();
+        for (index, mv, after, _) in self.iter_positions() {
+            let ply = index + black_first as usize;
+            if ply.is_multiple_of(2) {
+                out.push_str(&format!("{}. ", ply / 2 + 1));
+            } else if index == 0 {
+                out.push_str("1... ");
+            }
+            out.push_str(&notation::san(&board, mv, options.pieces));
+            out.push(' ');
+            board = after;
+        }
+        out.truncate(out.trim_end().len());
+        out
+    }
+
+    /// The current board followed by the move text, as `options` says.
+    pub fn render(&self, options: &RenderOptions) -> Result<String, Error> {
+        let mut out = render_board_with(&self.current_position()?, options);
+        out.push_str(&self.movetext(options));
+        out.push('\n');
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key::PublicKey;
+
+    #[test]
+    fn render_starting_position() {
+        let board = Board::default();
+        let ascii = render_board(&board, BoardStyle::Ascii, Color::White);
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(lines[0], "8 r n b q k b n r");
+        assert_eq!(lines[4], "4 . . . . . . . .");
+        assert_eq!(lines[7], "1 R N B Q K B N R");
+        assert_eq!(lines[8], "  a b c d e f g h");
+
+        let unicode = render_board(&board, BoardStyle::Unicode, Color::Black);
+        let lines: Vec<&str> = unicode.lines().collect();
+        assert_eq!(lines[0], "1 ♖ ♘ ♗ ♔ ♕ ♗ ♘ ♖");
+        assert_eq!(lines[7], "8 ♜ ♞ ♝ ♚ ♛ ♝ ♞ ♜");
+        assert_eq!(lines[8], "  h g f e d c b a");
+
+        let german = RenderOptions::new().pieces(PieceNames::GERMAN);
+        let lines: Vec<String> = render_board_with(&board, &german)
+            .lines()
+            .map(String::from)
+            .collect();
+        assert_eq!(lines[0], "8 t s l d k l s t");
+        assert_eq!(lines[6], "2 B B B B B B B B");
+    }
+
+    #[test]
+    fn localized_move_text() {
+        use crate::block::ChallengeBlock;
+        use crate::crypto;
+        use crate::notation::parse_uci;
+        use chess::Action;
+
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        for (i, uci) in ["f2f3", "e7e5", "g2g4", "d8h4"].iter().enumerate() {
+            let key_pair = if i % 2 == 0 { &white } else { &black };
+            chain
+                .make_move_block(key_pair, Action::MakeMove(parse_uci(uci).unwrap()))
+                .unwrap();
+        }
+
+        assert_eq!(chain.movetext(&RenderOptions::new()), "1. f3 e5 2. g4 Qh4#");
+        let french = RenderOptions::new().pieces(PieceNames::FRENCH);
+        assert_eq!(chain.movetext(&french), "1. f3 e5 2. g4 Dh4#");
+        let figurines = RenderOptions::new()
+            .style(BoardStyle::Unicode)
+            .pieces(PieceNames::Figurines);
+        assert_eq!(chain.movetext(&figurines), "1. f3 e5 2. g4 ♕h4#");
+        let rendered = chain.render(&figurines).unwrap();
+        assert!(rendered.starts_with("8 ♜ ♞ ♝ · ♚ ♝ ♞ ♜\n"));
+        assert!(rendered.ends_with("1. f3 e5 2. g4 ♕h4#\n"));
+    }
+}
@@ -0,0 +1,227 @@
+//! Revocation lists: a player's signed list of the challenge ids they no
+//! longer intend to honor, like open challenges they've withdrawn. A
+//! challenge only needs its opponent's accept to start, so a player who
+//! changes their mind publishes a list, peers pass it along, and whoever is
+//! about to accept consults the lists they hold first. Each list replaces
+//! the signer's older ones, so it names everything still revoked.
+
+use crate::block::GameChain;
+use crate::crypto;
+use crate::error::Error;
+
+use alloc::vec::Vec;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+// Keeps a revocation signature from being mistaken for a signature on
+// anything else.
+const DOMAIN: &[u8] = b"lineage revocations";
+
+/// The most ids a list may hold.
+pub const MAX_REVOKED: usize = 4096;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RevocationList {
+    public_key: [u8; 32],
+    timestamp: u64,
+    ids: Vec<u32>,
+    signature: Vec<u8>,
+}
+
+fn message(public_key: &[u8; 32], timestamp: u64, ids: &[u32]) -> Vec<u8> {
+    let mut message = DOMAIN.to_vec();
+    message.extend(public_key);
+    message.extend(&timestamp.to_be_bytes());
+    for id in ids {
+        message.extend(&id.to_be_bytes());
+    }
+    message
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "revocation list",
+        reason,
+    }
+}
+
+impl RevocationList {
+    /// Signs a list revoking `ids`, published at `timestamp`.
+    pub fn new(key_pair: &Ed25519KeyPair, timestamp: u64, ids: &[u32]) -> RevocationList {
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(key_pair.public_key().as_ref());
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.truncate(MAX_REVOKED);
+        let signature = crypto::sign(key_pair, &message(&public_key, timestamp, &ids));
+        RevocationList {
+            public_key,
+            timestamp,
+            ids,
+            signature,
+        }
+    }
+
+    pub fn public_key(&self) -> &[u8; 32] {
+        &self.public_key
+    }
+
+    /// When the list was published, in seconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// The revoked challenge ids, in ascending order.
+    pub fn ids(&self) -> &[u32] {
+        &self.ids
+    }
+
+    pub fn verify(&self) -> bool {
+        let message = message(&self.public_key, self.timestamp, &self.ids);
+        crypto::verify(&self.public_key, &message, &self.signature)
+    }
+
+    /// Whether the list is a newer one from the signer of `other`.
+    pub fn supersedes(&self, other: &RevocationList) -> bool {
+        self.public_key == other.public_key && self.timestamp > other.timestamp
+    }
+
+    /// Whether the list withdraws the challenge in `chain`: one of its
+    /// players signed it and it names the chain's id.
+    pub fn revokes(&self, chain: &GameChain) -> bool {
+        chain.side_of(&self.public_key).is_some() && self.ids.binary_search(&chain.id()).is_ok()
+    }
+
+    /// Encoded as the signer's key, the timestamp, the u32 big-endian
+    /// number of ids, the ids, and the signature.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(32 + 8 + 4 + 4 * self.ids.len() + 64);
+        bytes.extend(&self.public_key);
+        bytes.extend(&self.timestamp.to_be_bytes());
+        bytes.extend(&(self.ids.len() as u32).to_be_bytes());
+        for id in &self.ids {
+            bytes.extend(&id.to_be_bytes());
+        }
+        bytes.extend(&self.signature);
+        bytes
+    }
+
+    /// Decodes a list without verifying it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<RevocationList, Error> {
+        if bytes.len() < 44 + 64 {
+            return Err(malformed("not enough bytes"));
+        }
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(&bytes[..32]);
+        let mut timestamp = [0; 8];
+        timestamp.copy_from_slice(&bytes[32..40]);
+        let mut count = [0; 4];
+        count.copy_from_slice(&bytes[40..44]);
+        let count = u32::from_be_bytes(count) as usize;
+        if count > MAX_REVOKED {
+            return Err(Error::TooLarge {
+                what: "revocation list ids",
+                limit: MAX_REVOKED,
+            });
+        }
+        if bytes.len() != 44 + 4 * count + 64 {
+            return Err(malformed("wrong length"));
+        }
+        let ids: Vec<u32> = bytes[44..44 + 4 * count]
+            .chunks(4)
+            .map(|id| u32::from_be_bytes([id[0], id[1], id[2], id[3]]))
+            .collect();
+        if ids.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(malformed("ids out of order"));
+        }
+        Ok(RevocationList {
+            public_key,
+            timestamp: u64::from_be_bytes(timestamp),
+            ids,
+            signature: bytes[44 + 4 * count..].to_vec(),
+        })
+    }
+}
+
+/// Keeps `list` among `lists` if it verifies and no list there from the
+/// same signer is newer, returning whether it was kept.
+pub fn keep(lists: &mut Vec<RevocationList>, list: RevocationList) -> bool {
+    if !list.verify() {
+        return false;
+    }
+    match lists
+        .iter_mut()
+        .find(|kept| kept.public_key == list.public_key)
+    {
+        Some(kept) if list.supersedes(kept) => *kept = list,
+        Some(_) => return false,
+        None => lists.push(list),
+    }
+    true
+}
+
+/// Fails if any list in `lists` that verifies revokes the challenge in
+/// `chain`. Call it before accepting.
+pub fn check(chain: &GameChain, lists: &[RevocationList]) -> Result<(), Error> {
+    if lists
+        .iter()
+        .any(|list| list.revokes(chain) && list.verify())
+    {
+        return Err(Error::ChallengeRevoked);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlockBuilder;
+    use crate::key::PublicKey;
+
+    #[test]
+    fn revoke_challenges() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let stranger = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .id(7)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+
+        let list = RevocationList::new(&white, 1_000, &[9, 7, 7, 3]);
+        assert_eq!(list.ids(), &[3, 7, 9]);
+        assert!(list.verify());
+        assert!(list.revokes(&chain));
+        assert_eq!(
+            RevocationList::from_bytes(&list.as_bytes()),
+            Ok(list.clone())
+        );
+        let bytes = list.as_bytes();
+        assert!(RevocationList::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+
+        // only the players' lists count
+        let theirs = RevocationList::new(&stranger, 1_000, &[7]);
+        assert!(!theirs.revokes(&chain));
+        assert_eq!(check(&chain, core::slice::from_ref(&theirs)), Ok(()));
+
+        let mut lists = Vec::new();
+        assert!(keep(&mut lists, list.clone()));
+        assert!(keep(&mut lists, theirs));
+        assert_eq!(check(&chain, &lists), Err(Error::ChallengeRevoked));
+        let reinstated = RevocationList::new(&white, 2_000, &[3, 9]);
+        assert!(reinstated.supersedes(&list));
+        assert!(keep(&mut lists, reinstated));
+        assert!(!keep(&mut lists, list.clone()));
+        assert_eq!(lists.len(), 2);
+        assert_eq!(check(&chain, &lists), Ok(()));
+
+        let mut forged = RevocationList::from_bytes(&list.as_bytes()).unwrap();
+        forged.timestamp = 3_000;
+        assert!(!forged.verify());
+        assert!(!keep(&mut lists, forged.clone()));
+        assert_eq!(check(&chain, &[forged]), Ok(()));
+    }
+}
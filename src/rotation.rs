@@ -0,0 +1,254 @@
+//! Key rotation. A player moving to a new device can hand the game over to a
+//! new key with a key rotation block, signed by the key it replaces. Its body
+//! is the player's color, the new key, and the new key's signature over the
+//! tag "lineage key rotation", the chain's canonical id, the color and the
+//! new key, so a key can't be handed a game it never agreed to. Every block
+//! the player signs after the rotation must be signed by the new key.
+//! Checkpoints are still signed with the challenge's keys.
+
+use crate::block::{ChallengeBlock, GameChain};
+use crate::crypto;
+use crate::error::Error;
+use crate::extension::ExtensionKind;
+use crate::key::PublicKey;
+
+use alloc::vec::Vec;
+use chess::Color;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+const ROTATION_TAG: &[u8] = b"lineage key rotation";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyRotation {
+    pub color: Color,
+    pub new_public_key: [u8; 32],
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "key rotation",
+        reason,
+    }
+}
+
+fn color_byte(color: Color) -> u8 {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+/// The player who signed a key rotation with the given body.
+pub(crate) fn rotator(body: &[u8]) -> Option<Color> {
+    match body.first() {
+        Some(0) => Some(Color::White),
+        Some(1) => Some(Color::Black),
+        _ => None,
+    }
+}
+
+fn handover_message(canonical_id: &[u8; 32], color: Color, new_public_key: &[u8]) -> Vec<u8> {
+    let mut message = ROTATION_TAG.to_vec();
+    message.extend(canonical_id);
+    message.push(color_byte(color));
+    message.extend(new_public_key);
+    message
+}
+
+/// Reads a key rotation body, checking the new key's signature.
+fn parse(canonical_id: &[u8; 32], body: &[u8]) -> Result<KeyRotation, Error> {
+    if body.len() != 1 + 32 + 64 {
+        return Err(malformed("wrong body length"));
+    }
+    let color = rotator(body).ok_or_else(|| malformed("unknown color"))?;
+    let message = handover_message(canonical_id, color, &body[1..33]);
+    if !crypto::verify(&body[1..33], &message, &body[33..]) {
+        return Err(malformed("not signed by the new key"));
+    }
+    let mut new_public_key = [0; 32];
+    new_public_key.copy_from_slice(&body[1..33]);
+    Ok(KeyRotation {
+        color,
+        new_public_key,
+    })
+}
+
+/// Each player's current key, for checking blocks in encoded order.
+pub(crate) struct Keys {
+    white: PublicKey,
+    black: PublicKey,
+    current: [PublicKey; 2],
+}
+
+impl Keys {
+    pub(crate) fn new(challenge: &ChallengeBlock) -> Keys {
+        let (white, black) = (challenge.white_public_key(), challenge.black_public_key());
+        Keys {
+            white,
+            black,
+            current: [white, black],
+        }
+    }
+
+    /// The key that now signs for the player whose challenge key is
+    /// `original`. Other keys, like the arbiter's, are returned as they are.
+    pub(crate) fn current(&self, original: PublicKey) -> PublicKey {
+        if original == self.white {
+            self.current[0]
+        } else if original == self.black {
+            self.current[1]
+        } else {
+            original
+        }
+    }
+
+    /// Applies a key rotation, returning false if it isn't valid.
+    pub(crate) fn rotate(&mut self, canonical_id: &[u8; 32], body: &[u8]) -> bool {
+        let rotation = match parse(canonical_id, body) {
+            Ok(rotation) => rotation,
+            Err(_) => return false,
+        };
+        let new_key = PublicKey::from(rotation.new_public_key);
+        if self.current.contains(&new_key) {
+            return false;
+        }
+        self.current[color_byte(rotation.color) as usize] = new_key;
+        true
+    }
+}
+
+pub(crate) fn check_extension(chain: &GameChain, body: &[u8]) -> Result<(), Error> {
+    let rotation = parse(&chain.canonical_id(), body)?;
+    if chain.side_of(&rotation.new_public_key).is_some() {
+        return Err(malformed("key already in the game"));
+    }
+    Ok(())
+}
+
+impl GameChain {
+    /// Hands `key_pair`'s side of the game over to `new_key_pair`, which
+    /// signs for that side from then on.
+    pub fn rotate_key(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        new_key_pair: &Ed25519KeyPair,
+    ) -> Result<(), Error> {
+        let color = self
+            .side_of(key_pair.public_key().as_ref())
+            .ok_or(Error::KeyNotInChallenge)?;
+        let new_public_key = new_key_pair.public_key().as_ref();
+        let message = handover_message(&self.canonical_id(), color, new_public_key);
+        let mut body = Vec::with_capacity(1 + 32 + 64);
+        body.push(color_byte(color));
+        body.extend(new_public_key);
+        body.extend(crypto::sign(new_key_pair, &message));
+        self.sign_extension(key_pair, ExtensionKind::KeyRotation, body)
+    }
+
+    /// The key rotations in the chain, in order.
+    pub fn key_rotations(&self) -> Vec<KeyRotation> {
+        let canonical_id = self.canonical_id();
+        self.extensions()
+            .iter()
+            .filter(|(_, block)| block.kind() == ExtensionKind::KeyRotation)
+            .filter_map(|(_, block)| parse(&canonical_id, block.body()).ok())
+            .collect()
+    }
+
+    /// The key `color` signs with: the challenge's, or the last one it was
+    /// handed over to.
+    pub fn current_public_key(&self, color: Color) -> PublicKey {
+        self.extensions()
+            .iter()
+            .rev()
+            .filter(|(_, block)| {
+                block.kind() == ExtensionKind::KeyRotation && rotator(block.body()) == Some(color)
+            })
+            .find_map(|(_, block)| {
+                block
+                    .body()
+                    .get(1..33)
+                    .and_then(|key| PublicKey::from_slice(key).ok())
+            })
+            .unwrap_or_else(|| match color {
+                Color::White => self.challenge().white_public_key(),
+                Color::Black => self.challenge().black_public_key(),
+            })
+    }
+
+    /// The key that now signs for the player whose challenge key is
+    /// `original`. Other keys, like the arbiter's, are returned as they are.
+    pub(crate) fn current_key(&self, original: PublicKey) -> PublicKey {
+        if original == self.challenge().white_public_key() {
+            self.current_public_key(Color::White)
+        } else if original == self.challenge().black_public_key() {
+            self.current_public_key(Color::Black)
+        } else {
+            original
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn rotate_mid_game() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let laptop = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let play = |chain: &mut GameChain, key_pair: &Ed25519KeyPair, uci: &str| {
+            let mv = notation::parse_uci(uci).unwrap();
+            chain.make_move_block(key_pair, Action::MakeMove(mv))
+        };
+        play(&mut chain, &white, "e2e4").unwrap();
+        play(&mut chain, &black, "e7e5").unwrap();
+
+        // the handover must be signed by the new key, and can't take over
+        // the opponent's
+        let mut forged = chain.clone();
+        let mut body = [0].to_vec();
+        body.extend(laptop.public_key().as_ref());
+        body.extend(crypto::sign(&white, b"not a handover"));
+        assert!(forged
+            .sign_extension(&white, ExtensionKind::KeyRotation, body)
+            .is_err());
+        assert!(chain.rotate_key(&white, &black).is_err());
+
+        chain.rotate_key(&white, &laptop).unwrap();
+        assert_eq!(
+            chain.key_rotations(),
+            [KeyRotation {
+                color: Color::White,
+                new_public_key: *PublicKey::of(&laptop).as_bytes(),
+            }]
+        );
+        assert_eq!(
+            chain.current_public_key(Color::White),
+            PublicKey::of(&laptop)
+        );
+        assert_eq!(play(&mut chain, &white, "g1f3"), Err(Error::NotYourTurn));
+        play(&mut chain, &laptop, "g1f3").unwrap();
+        play(&mut chain, &black, "b8c6").unwrap();
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain.clone()));
+
+        // a move signed by the old key after the handover doesn't verify
+        let mut bytes = chain.as_bytes();
+        let move_start = bytes.len() - 2 * 66;
+        let mut message = crate::block::signed_message(&chain.canonical_id(), &bytes[..move_start]);
+        message.extend(&bytes[move_start..move_start + 2]);
+        let signature = crypto::sign(&white, &message);
+        bytes.truncate(move_start + 2);
+        bytes.extend(signature);
+        assert!(GameChain::from_bytes(&bytes).is_err());
+    }
+}
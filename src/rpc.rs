@@ -0,0 +1,904 @@
+//! Line-delimited JSON-RPC 2.0 control interface. Each request is one JSON
+//! object per line and each response is written back as one line. Keys and
+//! chains are passed as base58 strings.
+
+use crate::block::{AcceptBlock, ChallengeBlockBuilder, GameChain, MoveBlock};
+use crate::clock::{Clock, SystemClock};
+use crate::error::Error;
+use crate::event::ChainEvent;
+use crate::extension::ExtensionBlock;
+use crate::heartbeat::Heartbeat;
+use crate::identity::Identity;
+use crate::key::PublicKey;
+use crate::manager::{GameManager, Submission, SubmittedBlock};
+#[cfg(feature = "store")]
+use crate::message::Message;
+use crate::notary::{self, Witness};
+use crate::notation;
+#[cfg(feature = "store")]
+use crate::outbox::Outbox;
+use crate::revocation::RevocationList;
+
+use chess::{Action, Color};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde_json::{json, Value};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const LINEAGE_ERROR: i64 = 1;
+
+/// The longest request line accepted, with room for a full chain in base58.
+const MAX_REQUEST_LEN: usize = 4 << 20;
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: &str) -> RpcError {
+        RpcError {
+            code: INVALID_PARAMS,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(error: Error) -> RpcError {
+        RpcError {
+            code: LINEAGE_ERROR,
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Reads one line of at most `limit` bytes, newline included. Returns an
+/// empty line at end of input and None when the line runs past the limit,
+/// having read at most one byte beyond it.
+pub(crate) fn read_line<R: BufRead>(reader: &mut R, limit: usize) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(limit as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+    if line.len() > limit {
+        return Ok(None);
+    }
+    Ok(Some(line))
+}
+
+/// A chain id, refused rather than truncated when it doesn't fit in 32 bits.
+fn u32_id(value: &Value) -> Option<u32> {
+    value.as_u64().and_then(|id| u32::try_from(id).ok())
+}
+
+fn chain_id(params: &Value) -> Result<u32, RpcError> {
+    u32_id(&params["id"]).ok_or_else(|| RpcError::invalid_params("expected a 32-bit chain id"))
+}
+
+fn chain(value: &Value) -> Result<GameChain, RpcError> {
+    let bytes = value
+        .as_str()
+        .and_then(|chain| bs58::decode(chain).into_vec().ok())
+        .ok_or_else(|| RpcError::invalid_params("expected a base58 chain"))?;
+    Ok(GameChain::from_bytes(&bytes)?)
+}
+
+fn public_key(value: &Value) -> Result<PublicKey, RpcError> {
+    value
+        .as_str()
+        .and_then(|key| PublicKey::from_base58(key).ok())
+        .ok_or_else(|| RpcError::invalid_params("expected a base58 public key"))
+}
+
+pub(crate) fn chain_json(chain: &GameChain) -> Value {
+    json!({
+        "id": chain.id(),
+        "summary": chain.to_string(),
+        "moves": chain.iter_positions().map(|(_, mv, _, _)| mv.to_string()).collect::<Vec<_>>(),
+        "chain": bs58::encode(chain.as_bytes()).into_string(),
+    })
+}
+
+pub(crate) fn event_json(id: u32, event: &ChainEvent) -> Value {
+    match event {
+        ChainEvent::Accepted { public_key } => json!({
+            "id": id,
+            "event": "accepted",
+            "public_key": bs58::encode(public_key).into_string(),
+        }),
+        ChainEvent::MoveAdded {
+            index,
+            start_square,
+            end_square,
+        } => json!({
+            "id": id,
+            "event": "move_added",
+            "index": index,
+            "start_square": start_square,
+            "end_square": end_square,
+        }),
+        ChainEvent::ResultRecorded { winner } => json!({
+            "id": id,
+            "event": "result_recorded",
+            "winner": match winner {
+                Some(Color::White) => "white",
+                Some(Color::Black) => "black",
+                None => "draw",
+            },
+        }),
+        ChainEvent::VerificationFailed => json!({
+            "id": id,
+            "event": "verification_failed",
+        }),
+    }
+}
+
+/// Drives a GameManager over JSON-RPC, signing accepts and moves with the
+/// node's own key. A watch-only server has no key, and its signing methods
+/// fail.
+pub struct RpcServer {
+    manager: Arc<GameManager>,
+    identity: Arc<Identity>,
+    #[cfg(feature = "store")]
+    outbox: Option<Arc<Outbox>>,
+}
+
+impl RpcServer {
+    pub fn new(manager: Arc<GameManager>, key_pair: Ed25519KeyPair) -> RpcServer {
+        RpcServer {
+            manager,
+            identity: Arc::new(Identity::Signing(key_pair)),
+            #[cfg(feature = "store")]
+            outbox: None,
+        }
+    }
+
+    /// A server with no private key, which verifies, stores and serves
+    /// chains but never signs.
+    pub fn watch_only(manager: Arc<GameManager>) -> RpcServer {
+        RpcServer {
+            manager,
+            identity: Arc::new(Identity::WatchOnly),
+            #[cfg(feature = "store")]
+            outbox: None,
+        }
+    }
+
+    fn signer(&self) -> Result<&Ed25519KeyPair, RpcError> {
+        Ok(self.identity.signer()?)
+    }
+
+    /// Queues chains sent with send_chain in `outbox`, for whoever flushes
+    /// it to deliver.
+    #[cfg(feature = "store")]
+    pub fn outbox(mut self, outbox: Arc<Outbox>) -> RpcServer {
+        self.outbox = Some(outbox);
+        self
+    }
+
+    /// Accepts connections forever, serving each on its own thread.
+    pub fn listen<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()> {
+        let listener = TcpListener::bind(addr)?;
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = RpcServer {
+                manager: Arc::clone(&self.manager),
+                identity: Arc::clone(&self.identity),
+                #[cfg(feature = "store")]
+                outbox: self.outbox.clone(),
+            };
+            thread::spawn(move || {
+                let _ = server.serve(stream);
+            });
+        }
+        Ok(())
+    }
+
+    fn serve(&self, stream: TcpStream) -> io::Result<()> {
+        let writer = Arc::new(Mutex::new(stream.try_clone()?));
+        let mut reader = BufReader::new(stream);
+        loop {
+            let line = match read_line(&mut reader, MAX_REQUEST_LEN)? {
+                Some(line) if line.is_empty() => return Ok(()),
+                Some(line) => line,
+                None => {
+                    // the rest of the line can't be skipped without reading
+                    // it, so the connection is dropped after the reply
+                    let response = error_response(
+                        Value::Null,
+                        RpcError {
+                            code: PARSE_ERROR,
+                            message: "request too long".to_string(),
+                        },
+                    );
+                    let mut writer = writer.lock().unwrap();
+                    return writeln!(writer, "{}", response);
+                }
+            };
+            let line = String::from_utf8_lossy(&line);
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = self.handle_line(&line, &writer);
+            let mut writer = writer.lock().unwrap();
+            writeln!(writer, "{}", response)?;
+        }
+    }
+
+    fn handle_line(&self, line: &str, writer: &Arc<Mutex<TcpStream>>) -> Value {
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(error) => {
+                return error_response(
+                    Value::Null,
+                    RpcError {
+                        code: PARSE_ERROR,
+                        message: error.to_string(),
+                    },
+                );
+            }
+        };
+        let id = request["id"].clone();
+        let method = match request["method"].as_str() {
+            Some(method) => method,
+            None => {
+                return error_response(
+                    id,
+                    RpcError {
+                        code: INVALID_REQUEST,
+                        message: "missing method".to_string(),
+                    },
+                );
+            }
+        };
+        match self.call(method, &request["params"], writer) {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(error) => error_response(id, error),
+        }
+    }
+
+    fn call(
+        &self,
+        method: &str,
+        params: &Value,
+        writer: &Arc<Mutex<TcpStream>>,
+    ) -> Result<Value, RpcError> {
+        match method {
+            "create_challenge" => {
+                let opponent = public_key(&params["opponent"])?;
+                let key_pair = self.signer()?;
+                let own_key = PublicKey::of(key_pair);
+                let (white, black) = match params["color"].as_str() {
+                    Some("black") => (opponent, own_key),
+                    _ => (own_key, opponent),
+                };
+                let mut builder = ChallengeBlockBuilder::new(&white, &black);
+                if !params["chain_id"].is_null() {
+                    builder = builder.id(u32_id(&params["chain_id"])
+                        .ok_or_else(|| RpcError::invalid_params("expected a 32-bit chain id"))?);
+                }
+                let network_id = match &params["network_id"] {
+                    Value::Null => self.manager.network().id(),
+                    id => id
+                        .as_u64()
+                        .and_then(|id| u8::try_from(id).ok())
+                        .ok_or_else(|| RpcError::invalid_params("expected a network id"))?,
+                };
+                let challenge = builder.network_id(network_id).build()?;
+                let id = self.manager.create(challenge)?;
+                self.manager.accept(id, key_pair)?;
+                Ok(json!(id))
+            }
+            "import_chain" => {
+                let chain = chain(&params["chain"])?;
+                Ok(json!(self.manager.import(chain)?))
+            }
+            // witnesses a valid chain for anyone, whether or not the node
+            // tracks it
+            "notarize" => {
+                let chain = chain(&params["chain"])?;
+                if !chain.verify() {
+                    return Err(Error::VerificationFailed.into());
+                }
+                let witness = Witness::new(self.signer()?, &chain, SystemClock.now());
+                Ok(json!({
+                    "witness": bs58::encode(witness.as_bytes()).into_string(),
+                    "node": bs58::encode(witness.node_public_key()).into_string(),
+                    "timestamp": witness.timestamp(),
+                }))
+            }
+            "verify_witness" => {
+                let chain = chain(&params["chain"])?;
+                let witness = params["witness"]
+                    .as_str()
+                    .and_then(|witness| bs58::decode(witness).into_vec().ok())
+                    .ok_or_else(|| RpcError::invalid_params("expected a base58 witness"))?;
+                let witness = Witness::from_bytes(&witness)?;
+                Ok(json!(notary::verify_witness(&chain, &witness)))
+            }
+            "accept" => {
+                let id = chain_id(params)?;
+                self.manager.accept(id, self.signer()?)?;
+                Ok(Value::Null)
+            }
+            "make_move" => {
+                let id = chain_id(params)?;
+                let mv = params["move"]
+                    .as_str()
+                    .and_then(notation::parse_uci)
+                    .ok_or_else(|| RpcError::invalid_params("expected a UCI move"))?;
+                self.manager
+                    .make_move(id, self.signer()?, Action::MakeMove(mv))?;
+                Ok(Value::Null)
+            }
+            // appends a block signed elsewhere; resubmitting one that's
+            // already in the chain succeeds with "appended" false
+            "submit_block" => {
+                let id = chain_id(params)?;
+                let bytes = params["block"]
+                    .as_str()
+                    .and_then(|block| bs58::decode(block).into_vec().ok())
+                    .ok_or_else(|| RpcError::invalid_params("expected a base58 block"))?;
+                let block = match params["kind"].as_str() {
+                    Some("accept") => {
+                        SubmittedBlock::Accept(self.manager.with_chain(id, |chain| {
+                            AcceptBlock::from_bytes(&bytes, chain.challenge())
+                        })??)
+                    }
+                    Some("move") => SubmittedBlock::Move(MoveBlock::from_bytes(&bytes)?),
+                    Some("extension") => {
+                        SubmittedBlock::Extension(ExtensionBlock::from_bytes(&bytes)?)
+                    }
+                    _ => {
+                        return Err(RpcError::invalid_params(
+                            "expected kind accept, move or extension",
+                        ))
+                    }
+                };
+                let submission = self.manager.submit(id, block)?;
+                Ok(json!({ "appended": submission == Submission::Appended }))
+            }
+            "take_vacation" => {
+                let id = chain_id(params)?;
+                let (starts_at, ends_at) =
+                    match (params["starts_at"].as_u64(), params["ends_at"].as_u64()) {
+                        (Some(starts_at), Some(ends_at)) => (starts_at, ends_at),
+                        _ => {
+                            return Err(RpcError::invalid_params("expected starts_at and ends_at"))
+                        }
+                    };
+                let key_pair = self.signer()?;
+                self.manager.with_chain(id, |chain| {
+                    let color = chain
+                        .side_of(key_pair.public_key().as_ref())
+                        .ok_or(Error::KeyNotInChallenge)?;
+                    chain.take_vacation(key_pair, color, starts_at, ends_at)
+                })??;
+                Ok(Value::Null)
+            }
+            // signs a heartbeat for one of the node's games, for the
+            // opponent to keep
+            "heartbeat" => {
+                let id = chain_id(params)?;
+                let key_pair = self.signer()?;
+                let heartbeat = self
+                    .manager
+                    .with_chain(id, |chain| chain.heartbeat(key_pair, SystemClock.now()))??;
+                Ok(json!(bs58::encode(heartbeat.as_bytes()).into_string()))
+            }
+            "record_heartbeat" => {
+                let id = chain_id(params)?;
+                let heartbeat = params["heartbeat"]
+                    .as_str()
+                    .and_then(|heartbeat| bs58::decode(heartbeat).into_vec().ok())
+                    .ok_or_else(|| RpcError::invalid_params("expected a base58 heartbeat"))?;
+                self.manager
+                    .record_heartbeat(id, Heartbeat::from_bytes(&heartbeat)?)?;
+                Ok(Value::Null)
+            }
+            // signs the node's revocation list, replacing any before it, and
+            // returns it for gossiping
+            "revoke_challenges" => {
+                let ids = params["ids"]
+                    .as_array()
+                    .and_then(|ids| ids.iter().map(u32_id).collect::<Option<Vec<u32>>>())
+                    .ok_or_else(|| RpcError::invalid_params("expected an array of ids"))?;
+                let list = RevocationList::new(self.signer()?, SystemClock.now(), &ids);
+                self.manager.record_revocations(list.clone())?;
+                Ok(json!(bs58::encode(list.as_bytes()).into_string()))
+            }
+            "record_revocations" => {
+                let list = params["list"]
+                    .as_str()
+                    .and_then(|list| bs58::decode(list).into_vec().ok())
+                    .ok_or_else(|| RpcError::invalid_params("expected a base58 list"))?;
+                let kept = self
+                    .manager
+                    .record_revocations(RevocationList::from_bytes(&list)?)?;
+                Ok(json!({ "kept": kept }))
+            }
+            // queues the chain for the peer at "to" until it can take it
+            #[cfg(feature = "store")]
+            "send_chain" => {
+                let id = chain_id(params)?;
+                let to = params["to"]
+                    .as_str()
+                    .ok_or_else(|| RpcError::invalid_params("expected a peer address"))?;
+                let outbox = self
+                    .outbox
+                    .as_ref()
+                    .ok_or_else(|| RpcError::invalid_params("the node has no outbox"))?;
+                let chain = self.manager.get(id).ok_or(Error::UnknownGame(id))?;
+                let seq = outbox
+                    .push(to, &Message::Chain(Box::new(chain)), SystemClock.now())
+                    .map_err(|error| RpcError {
+                        code: LINEAGE_ERROR,
+                        message: error.to_string(),
+                    })?;
+                Ok(json!({ "queued": seq }))
+            }
+            "get_chain" => {
+                let id = chain_id(params)?;
+                let chain = self.manager.get(id).ok_or(Error::UnknownGame(id))?;
+                Ok(chain_json(&chain))
+            }
+            "list_games" => Ok(json!(self.manager.ids())),
+            "subscribe_events" => {
+                let id = chain_id(params)?;
+                let events = self.manager.with_chain(id, |chain| chain.subscribe())?;
+                let writer = Arc::clone(writer);
+                thread::spawn(move || {
+                    for event in events {
+                        let notification = json!({
+                            "jsonrpc": "2.0",
+                            "method": "event",
+                            "params": event_json(id, &event),
+                        });
+                        let mut writer = writer.lock().unwrap();
+                        if writeln!(writer, "{}", notification).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Ok(Value::Null)
+            }
+            // null for a watch-only node
+            "public_key" => Ok(json!(self
+                .identity
+                .public_key()
+                .map(|public_key| public_key.to_base58()))),
+            "fingerprint" => Ok(json!(self
+                .identity
+                .public_key()
+                .map(|public_key| public_key.fingerprint()))),
+            "watch_only" => Ok(json!(self.identity.is_watch_only())),
+            // peers call this first to check they're on the same network
+            "network" => Ok(json!({
+                "name": self.manager.network().name(),
+                "id": self.manager.network().id(),
+            })),
+            _ => Err(RpcError {
+                code: METHOD_NOT_FOUND,
+                message: format!("unknown method {}", method),
+            }),
+        }
+    }
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {"code": error.code, "message": error.message},
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto;
+
+    fn connection() -> (Arc<Mutex<TcpStream>>, BufReader<TcpStream>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (Arc::new(Mutex::new(server)), BufReader::new(client))
+    }
+
+    fn node() -> (RpcServer, Ed25519KeyPair) {
+        let pkcs8 = crypto::generate_pkcs8(&crypto::new_rng());
+        let server = RpcServer::new(
+            Arc::new(GameManager::new()),
+            crypto::key_from_pkcs8(&pkcs8).unwrap(),
+        );
+        (server, crypto::key_from_pkcs8(&pkcs8).unwrap())
+    }
+
+    fn request(
+        server: &RpcServer,
+        writer: &Arc<Mutex<TcpStream>>,
+        method: &str,
+        params: Value,
+    ) -> Value {
+        let request = json!({"jsonrpc": "2.0", "id": 7, "method": method, "params": params});
+        let response = server.handle_line(&request.to_string(), writer);
+        assert_eq!(response["id"], 7);
+        response
+    }
+
+    fn error_code(response: &Value) -> Option<i64> {
+        response["error"]["code"].as_i64()
+    }
+
+    fn base58(bytes: &[u8]) -> String {
+        bs58::encode(bytes).into_string()
+    }
+
+    #[test]
+    fn bounded_lines() {
+        let mut input = &b"{}\n0123456789\nrest"[..];
+        assert_eq!(read_line(&mut input, 8).unwrap().unwrap(), b"{}\n");
+        assert!(read_line(&mut input, 8).unwrap().is_none());
+        // at most one byte past the limit was consumed
+        assert_eq!(input, b"9\nrest");
+        assert_eq!(read_line(&mut input, 8).unwrap().unwrap(), b"9\n");
+        assert_eq!(read_line(&mut input, 8).unwrap().unwrap(), b"rest");
+        assert!(read_line(&mut input, 8).unwrap().unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_requests() {
+        let (server, _) = node();
+        let (writer, _reader) = connection();
+
+        let response = server.handle_line("{\"method\": ", &writer);
+        assert_eq!(error_code(&response), Some(PARSE_ERROR));
+        assert_eq!(response["id"], Value::Null);
+        let response = server.handle_line("{\"jsonrpc\": \"2.0\", \"id\": 3}", &writer);
+        assert_eq!(error_code(&response), Some(INVALID_REQUEST));
+        assert_eq!(response["id"], 3);
+        let response = request(&server, &writer, "resign", Value::Null);
+        assert_eq!(error_code(&response), Some(METHOD_NOT_FOUND));
+
+        for (method, params) in vec![
+            ("create_challenge", json!({ "opponent": "not a key" })),
+            ("import_chain", json!({ "chain": 12 })),
+            ("notarize", json!({})),
+            ("verify_witness", json!({ "witness": "0OIl" })),
+            ("accept", json!({ "id": "one" })),
+            ("accept", json!({ "id": 4_294_967_301u64 })),
+            (
+                "create_challenge",
+                json!({ "opponent": base58(&[7; 32]), "chain_id": 4_294_967_296u64 }),
+            ),
+            (
+                "create_challenge",
+                json!({ "opponent": base58(&[7; 32]), "network_id": 257 }),
+            ),
+            ("make_move", json!({ "id": 1, "move": "e2e9" })),
+            (
+                "submit_block",
+                json!({ "id": 1, "block": "1", "kind": "resign" }),
+            ),
+            ("take_vacation", json!({ "id": 1, "starts_at": 100 })),
+            ("heartbeat", json!({})),
+            ("record_heartbeat", json!({ "id": 1 })),
+            ("revoke_challenges", json!({ "ids": [1, "two"] })),
+            ("revoke_challenges", json!({ "ids": [4_294_967_297u64] })),
+            ("record_revocations", json!({})),
+            ("get_chain", Value::Null),
+            ("subscribe_events", json!({ "id": -1 })),
+        ] {
+            let response = request(&server, &writer, method, params);
+            assert_eq!(error_code(&response), Some(INVALID_PARAMS), "{}", method);
+        }
+
+        // well-formed, but lineage turns them down
+        let response = request(&server, &writer, "get_chain", json!({ "id": 99 }));
+        assert_eq!(error_code(&response), Some(LINEAGE_ERROR));
+        assert_eq!(
+            response["error"]["message"],
+            Error::UnknownGame(99).to_string()
+        );
+        let response = request(&server, &writer, "import_chain", json!({ "chain": "1" }));
+        assert_eq!(error_code(&response), Some(LINEAGE_ERROR));
+    }
+
+    #[test]
+    fn play_a_game() {
+        let (server, node_key) = node();
+        let (writer, mut reader) = connection();
+        let opponent = crypto::generate_key(&crypto::new_rng());
+
+        let response = request(
+            &server,
+            &writer,
+            "create_challenge",
+            json!({
+                "opponent": PublicKey::of(&opponent).to_base58(),
+                "color": "white",
+                "chain_id": 5,
+            }),
+        );
+        assert_eq!(response["result"], 5);
+        let response = request(&server, &writer, "list_games", Value::Null);
+        assert_eq!(response["result"], json!([5]));
+        let response = request(&server, &writer, "get_chain", json!({ "id": 5 }));
+        let game = &response["result"];
+        assert_eq!(game["id"], 5);
+        assert_eq!(game["moves"], json!([]));
+        let bytes = bs58::decode(game["chain"].as_str().unwrap())
+            .into_vec()
+            .unwrap();
+        let mut theirs = GameChain::from_bytes(&bytes).unwrap();
+        assert_eq!(theirs.players().0, PublicKey::of(&node_key));
+
+        let response = request(&server, &writer, "subscribe_events", json!({ "id": 5 }));
+        assert_eq!(response["result"], Value::Null);
+
+        // the opponent accepts and answers e4 with blocks signed elsewhere
+        theirs.accept(&opponent).unwrap();
+        let challenge = theirs.challenge();
+        let accept_len = challenge.accept_len();
+        let offset = challenge.encoded_len() + accept_len;
+        let accept = base58(&theirs.as_bytes()[offset..offset + accept_len]);
+        let submit = json!({ "id": 5, "kind": "accept", "block": accept });
+        let response = request(&server, &writer, "submit_block", submit.clone());
+        assert_eq!(response["result"], json!({ "appended": true }));
+        let response = request(&server, &writer, "submit_block", submit);
+        assert_eq!(response["result"], json!({ "appended": false }));
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let notification: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(notification["method"], "event");
+        assert_eq!(notification["params"]["event"], "accepted");
+
+        let response = request(
+            &server,
+            &writer,
+            "make_move",
+            json!({ "id": 5, "move": "e2e4" }),
+        );
+        assert_eq!(response["result"], Value::Null);
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let notification: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(notification["params"]["event"], "move_added");
+        assert_eq!(notification["params"]["index"], 0);
+        let response = request(
+            &server,
+            &writer,
+            "make_move",
+            json!({ "id": 5, "move": "d2d4" }),
+        );
+        assert_eq!(response["error"]["message"], Error::NotYourTurn.to_string());
+
+        let mut theirs = server.manager.get(5).unwrap();
+        let e5 = Action::MakeMove(notation::parse_uci("e7e5").unwrap());
+        theirs.make_move_block(&opponent, e5).unwrap();
+        let block = base58(&theirs.moves()[1].as_bytes());
+        let response = request(
+            &server,
+            &writer,
+            "submit_block",
+            json!({ "id": 5, "kind": "move", "block": block }),
+        );
+        assert_eq!(response["result"], json!({ "appended": true }));
+        let response = request(&server, &writer, "get_chain", json!({ "id": 5 }));
+        assert_eq!(response["result"], chain_json(&theirs));
+
+        let response = request(&server, &writer, "accept", json!({ "id": 5 }));
+        assert_eq!(error_code(&response), Some(LINEAGE_ERROR));
+
+        // a challenge from the opponent, imported and then accepted as black
+        let challenge =
+            ChallengeBlockBuilder::new(&PublicKey::of(&opponent), &PublicKey::of(&node_key))
+                .id(6)
+                .build()
+                .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&opponent).unwrap();
+        let response = request(
+            &server,
+            &writer,
+            "import_chain",
+            json!({ "chain": base58(&chain.as_bytes()) }),
+        );
+        assert_eq!(response["result"], 6);
+        let response = request(&server, &writer, "accept", json!({ "id": 6 }));
+        assert_eq!(response["result"], Value::Null);
+        assert!(server.manager.get(6).unwrap().accepts()[1].is_some());
+
+        let response = request(
+            &server,
+            &writer,
+            "take_vacation",
+            json!({ "id": 6, "starts_at": 100, "ends_at": 200 }),
+        );
+        assert_eq!(response["result"], Value::Null);
+        assert!(server
+            .manager
+            .get(6)
+            .unwrap()
+            .on_vacation(Color::Black, 150));
+    }
+
+    #[test]
+    fn signed_records() {
+        let (server, node_key) = node();
+        let (writer, _reader) = connection();
+        let opponent = crypto::generate_key(&crypto::new_rng());
+        let challenge =
+            ChallengeBlockBuilder::new(&PublicKey::of(&node_key), &PublicKey::of(&opponent))
+                .id(3)
+                .build()
+                .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&node_key).unwrap();
+        chain.accept(&opponent).unwrap();
+        server.manager.insert(chain.clone()).unwrap();
+
+        let response = request(
+            &server,
+            &writer,
+            "notarize",
+            json!({ "chain": base58(&chain.as_bytes()) }),
+        );
+        let witness = response["result"]["witness"].clone();
+        assert_eq!(
+            response["result"]["node"],
+            PublicKey::of(&node_key).to_base58()
+        );
+        let response = request(
+            &server,
+            &writer,
+            "verify_witness",
+            json!({ "chain": base58(&chain.as_bytes()), "witness": witness }),
+        );
+        assert_eq!(response["result"], true);
+        chain
+            .make_move_block(
+                &node_key,
+                Action::MakeMove(notation::parse_uci("e2e4").unwrap()),
+            )
+            .unwrap();
+        let response = request(
+            &server,
+            &writer,
+            "verify_witness",
+            json!({ "chain": base58(&chain.as_bytes()), "witness": witness }),
+        );
+        assert_eq!(response["result"], false);
+
+        let response = request(&server, &writer, "heartbeat", json!({ "id": 3 }));
+        let heartbeat = response["result"].clone();
+        assert!(heartbeat.is_string());
+        assert!(server.manager.heartbeats(3).is_empty());
+        let response = request(
+            &server,
+            &writer,
+            "record_heartbeat",
+            json!({ "id": 3, "heartbeat": heartbeat }),
+        );
+        assert_eq!(response["result"], Value::Null);
+        assert_eq!(server.manager.heartbeats(3).len(), 1);
+
+        let response = request(
+            &server,
+            &writer,
+            "revoke_challenges",
+            json!({ "ids": [8, 9] }),
+        );
+        let list = response["result"].as_str().unwrap();
+        let list = RevocationList::from_bytes(&bs58::decode(list).into_vec().unwrap()).unwrap();
+        assert_eq!(list.ids(), &[8, 9]);
+        assert_eq!(server.manager.revocations(), vec![list]);
+        let theirs = RevocationList::new(&opponent, 100, &[4]);
+        let theirs = base58(&theirs.as_bytes());
+        let response = request(
+            &server,
+            &writer,
+            "record_revocations",
+            json!({ "list": theirs }),
+        );
+        assert_eq!(response["result"], json!({ "kept": true }));
+        let response = request(
+            &server,
+            &writer,
+            "record_revocations",
+            json!({ "list": theirs }),
+        );
+        assert_eq!(response["result"], json!({ "kept": false }));
+        assert_eq!(server.manager.revocations().len(), 2);
+    }
+
+    #[test]
+    fn identity() {
+        let (server, node_key) = node();
+        let (writer, _reader) = connection();
+        let public_key = PublicKey::of(&node_key);
+        let response = request(&server, &writer, "public_key", Value::Null);
+        assert_eq!(response["result"], public_key.to_base58());
+        let response = request(&server, &writer, "fingerprint", Value::Null);
+        assert_eq!(response["result"], public_key.fingerprint());
+        let response = request(&server, &writer, "watch_only", Value::Null);
+        assert_eq!(response["result"], false);
+        let response = request(&server, &writer, "network", Value::Null);
+        assert_eq!(
+            response["result"],
+            json!({
+                "name": server.manager.network().name(),
+                "id": server.manager.network().id(),
+            })
+        );
+
+        let server = RpcServer::watch_only(Arc::new(GameManager::new()));
+        let response = request(&server, &writer, "public_key", Value::Null);
+        assert_eq!(response["result"], Value::Null);
+        let response = request(&server, &writer, "fingerprint", Value::Null);
+        assert_eq!(response["result"], Value::Null);
+        let response = request(&server, &writer, "watch_only", Value::Null);
+        assert_eq!(response["result"], true);
+        let opponent = PublicKey::of(&crypto::generate_key(&crypto::new_rng()));
+        let response = request(
+            &server,
+            &writer,
+            "create_challenge",
+            json!({ "opponent": opponent.to_base58() }),
+        );
+        assert_eq!(error_code(&response), Some(LINEAGE_ERROR));
+        assert_eq!(response["error"]["message"], Error::WatchOnly.to_string());
+    }
+
+    #[cfg(feature = "store")]
+    #[test]
+    fn send_chain() {
+        let (server, node_key) = node();
+        let (writer, _reader) = connection();
+        let opponent = crypto::generate_key(&crypto::new_rng());
+        let response = request(
+            &server,
+            &writer,
+            "create_challenge",
+            json!({ "opponent": PublicKey::of(&opponent).to_base58(), "chain_id": 2 }),
+        );
+        assert_eq!(response["result"], 2);
+        let send = json!({ "id": 2, "to": "peer:10153" });
+        let response = request(&server, &writer, "send_chain", send.clone());
+        assert_eq!(error_code(&response), Some(INVALID_PARAMS));
+
+        let rng = crypto::new_rng();
+        let dir = std::env::temp_dir().join(format!("lineage-rpc-{}", crypto::random_id(&rng)));
+        let outbox = Arc::new(Outbox::open_dir(&dir).unwrap());
+        let server = server.outbox(Arc::clone(&outbox));
+        let response = request(&server, &writer, "send_chain", send);
+        assert_eq!(response["result"], json!({ "queued": 0 }));
+        let entries = outbox.entries().unwrap();
+        assert_eq!(entries[0].to, "peer:10153");
+        assert_eq!(
+            entries[0].message,
+            Message::Chain(Box::new(server.manager.get(2).unwrap()))
+        );
+        assert_eq!(
+            server.manager.get(2).unwrap().players().0,
+            PublicKey::of(&node_key)
+        );
+        let response = request(
+            &server,
+            &writer,
+            "send_chain",
+            json!({ "id": 9, "to": "peer:10153" }),
+        );
+        assert_eq!(error_code(&response), Some(LINEAGE_ERROR));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,158 @@
+//! Recovering damaged chain files. GameChain::from_bytes is strict, as it
+//! should be for anything read off the network: bytes left over after the
+//! last block, a block cut off partway, or a block that doesn't verify reject
+//! the whole chain. Salvaging instead keeps the longest run of blocks from the
+//! start that decodes and verifies, and reports what had to be dropped.
+
+use crate::block::{ChallengeBlock, GameChain};
+use crate::error::Error;
+use crate::extension::EXTENSION_TAG_MIN;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The chain recovered from damaged bytes, and what was left out of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Salvaged {
+    pub chain: GameChain,
+    /// The number of leading bytes that make up the recovered chain.
+    pub kept_len: usize,
+    /// The number of bytes after them that were dropped.
+    pub dropped_len: usize,
+    /// How many whole blocks were dropped, not counting a final block that
+    /// was cut off.
+    pub dropped_blocks: usize,
+    /// Why the bytes weren't accepted as they were, if they weren't.
+    pub error: Option<Error>,
+}
+
+impl Salvaged {
+    /// Whether the bytes decoded in full, so nothing was dropped.
+    pub fn is_complete(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// The offset of the end of each whole block, going only by block lengths.
+fn block_ends(bytes: &[u8], challenge: &ChallengeBlock) -> Vec<usize> {
+    let mut offset = challenge.encoded_len();
+    let mut ends = vec![offset];
+    for _ in 0..2 {
+        offset += challenge.accept_len();
+        if offset > bytes.len() {
+            return ends;
+        }
+        ends.push(offset);
+    }
+    while offset < bytes.len() {
+        let rest = &bytes[offset..];
+        let len = if rest[0] < EXTENSION_TAG_MIN {
+            66
+        } else if rest.len() >= 3 {
+            3 + u16::from_be_bytes([rest[1], rest[2]]) as usize + 64
+        } else {
+            break;
+        };
+        if rest.len() < len {
+            break;
+        }
+        offset += len;
+        ends.push(offset);
+    }
+    ends
+}
+
+/// Decodes as much of `bytes` as verifies. Fails only if the challenge
+/// itself can't be read.
+pub fn salvage(bytes: &[u8]) -> Result<Salvaged, Error> {
+    let error = match GameChain::from_bytes(bytes) {
+        Ok(chain) => {
+            return Ok(Salvaged {
+                chain,
+                kept_len: bytes.len(),
+                dropped_len: 0,
+                dropped_blocks: 0,
+                error: None,
+            })
+        }
+        Err(error) => error,
+    };
+    let challenge = ChallengeBlock::from_bytes(bytes)?;
+    let ends = block_ends(bytes, &challenge);
+    let mut chain = GameChain::from_bytes(&bytes[..ends[0]])?;
+    // every prefix of a valid chain that ends on a block boundary is valid
+    // too, so the longest one can be found by bisecting
+    let (mut low, mut high) = (1, ends.len());
+    while low < high {
+        let mid = (low + high) / 2;
+        match GameChain::from_bytes(&bytes[..ends[mid]]) {
+            Ok(longer) => {
+                chain = longer;
+                low = mid + 1;
+            }
+            Err(_) => high = mid,
+        }
+    }
+    let kept_len = ends[low - 1];
+    Ok(Salvaged {
+        chain,
+        kept_len,
+        dropped_len: bytes.len() - kept_len,
+        dropped_blocks: ends.len() - low,
+        error: Some(error),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn salvage_damaged_chain() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        for (i, uci) in ["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "a7a6"]
+            .iter()
+            .enumerate()
+        {
+            let key_pair = if i % 2 == 0 { &white } else { &black };
+            let mv = notation::parse_uci(uci).unwrap();
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        }
+        let bytes = chain.as_bytes();
+        let salvaged = salvage(&bytes).unwrap();
+        assert!(salvaged.is_complete());
+        assert_eq!(salvaged.chain, chain);
+
+        // trailing garbage is rejected strictly, and dropped when salvaging
+        let mut padded = bytes.clone();
+        padded.extend(&[0; 10]);
+        assert!(GameChain::from_bytes(&padded).is_err());
+        let salvaged = salvage(&padded).unwrap();
+        assert_eq!(salvaged.chain, chain);
+        assert_eq!(salvaged.dropped_len, 10);
+        assert_eq!(salvaged.dropped_blocks, 0);
+
+        // a bad signature loses the block and everything after it
+        let mut damaged = bytes.clone();
+        let last = bytes.len() - 66 * 3;
+        damaged[last + 10] ^= 1;
+        let salvaged = salvage(&damaged).unwrap();
+        assert!(!salvaged.is_complete());
+        assert_eq!(salvaged.chain.move_count(), chain.move_count() - 3);
+        assert_eq!(salvaged.kept_len, last);
+        assert_eq!(salvaged.dropped_blocks, 3);
+        assert_eq!(salvaged.chain.as_bytes(), &bytes[..last]);
+    }
+}
@@ -0,0 +1,522 @@
+//! Fair seeding of Chess960 starting positions. Before the challenge is
+//! built, each player picks a random nonce and signs a commitment to it for
+//! the agreed game id. Once both commitments have been exchanged the nonces
+//! are revealed, and the SHA-256 of the id and both nonces picks one of the
+//! 960 positions, so neither player can steer the setup without the other's
+//! nonce. The challenge records the position as its starting FEN; anyone
+//! holding the commitments and nonces can check it with `check_challenge`.
+//!
+//! Colors can be flipped the same way, from the same commitments if the
+//! players like. The challenge's ColorFlip tag records, for each player in
+//! the order of their keys, the revealed nonce followed by the hash and
+//! signature of the commitment to it, all base58 encoded. Both players sign
+//! the tag when they accept, so a chain whose nonces don't open signed
+//! commitments for the game, or that names the wrong colors for its flip,
+//! fails to verify.
+//!
+//! A commitment is the signer's key, the u32 big-endian game id, the
+//! SHA-256 of the tag, the key, and the nonce, and the signature over the
+//! tag followed by the rest.
+
+use crate::block::{ChallengeBlock, ChallengeBlockBuilder, Variant};
+use crate::crypto::{self, Rng};
+use crate::error::Error;
+use crate::key::PublicKey;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use ring::digest;
+use ring::signature::Ed25519KeyPair;
+
+// Keeps a seeding signature from being mistaken for a signature on anything
+// else.
+const DOMAIN: &[u8] = b"lineage seeding";
+
+/// The length of an encoded commitment.
+pub const SEED_COMMITMENT_LEN: usize = 32 + 4 + 32 + 64;
+
+/// The number of Chess960 starting positions.
+pub const CHESS960_POSITIONS: u16 = 960;
+
+/// The challenge tag holding a color flip's nonces and commitments.
+pub const COLOR_FLIP_TAG: &str = "ColorFlip";
+
+/// Each player's share of a ColorFlip tag: the nonce, then the commitment's
+/// hash and signature.
+const FLIP_SHARE_LEN: usize = 32 + 32 + 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeedCommitment {
+    public_key: PublicKey,
+    id: u32,
+    hash: [u8; 32],
+    signature: Vec<u8>,
+}
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut context = digest::Context::new(&digest::SHA256);
+    for part in parts {
+        context.update(part);
+    }
+    let mut hash = [0; 32];
+    hash.copy_from_slice(context.finish().as_ref());
+    hash
+}
+
+fn message(public_key: &PublicKey, id: u32, hash: &[u8; 32]) -> Vec<u8> {
+    let mut message = DOMAIN.to_vec();
+    message.extend(public_key.as_bytes());
+    message.extend(&id.to_be_bytes());
+    message.extend(hash);
+    message
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "seed commitment",
+        reason,
+    }
+}
+
+/// A fresh nonce to commit to.
+pub fn new_nonce(rng: &dyn Rng) -> [u8; 32] {
+    let mut nonce = [0; 32];
+    rng.fill_bytes(&mut nonce);
+    nonce
+}
+
+impl SeedCommitment {
+    /// Signs a commitment to `nonce` for the game `id`.
+    pub fn new(key_pair: &Ed25519KeyPair, id: u32, nonce: &[u8; 32]) -> SeedCommitment {
+        let public_key = PublicKey::of(key_pair);
+        let hash = sha256(&[DOMAIN, public_key.as_bytes(), nonce]);
+        let signature = crypto::sign(key_pair, &message(&public_key, id, &hash));
+        SeedCommitment {
+            public_key,
+            id,
+            hash,
+            signature,
+        }
+    }
+
+    pub fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn verify(&self) -> bool {
+        let message = message(&self.public_key, self.id, &self.hash);
+        self.public_key.verify(&message, &self.signature)
+    }
+
+    /// Whether `nonce` is the one committed to.
+    pub fn opens(&self, nonce: &[u8; 32]) -> bool {
+        sha256(&[DOMAIN, self.public_key.as_bytes(), nonce]) == self.hash
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SEED_COMMITMENT_LEN);
+        bytes.extend(self.public_key.as_bytes());
+        bytes.extend(&self.id.to_be_bytes());
+        bytes.extend(&self.hash);
+        bytes.extend(&self.signature);
+        bytes
+    }
+
+    /// Decodes a commitment without verifying it.
+    pub fn from_bytes(bytes: &[u8]) -> Result<SeedCommitment, Error> {
+        if bytes.len() != SEED_COMMITMENT_LEN {
+            return Err(malformed("wrong length"));
+        }
+        let public_key = PublicKey::from_slice(&bytes[..32])?;
+        let mut id = [0; 4];
+        id.copy_from_slice(&bytes[32..36]);
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&bytes[36..68]);
+        Ok(SeedCommitment {
+            public_key,
+            id: u32::from_be_bytes(id),
+            hash,
+            signature: bytes[68..].to_vec(),
+        })
+    }
+}
+
+/// The Chess960 position the revealed nonces select. Fails unless both
+/// commitments verify, are for the same game by different players, and open
+/// to their nonces.
+pub fn position_index(
+    white: &SeedCommitment,
+    white_nonce: &[u8; 32],
+    black: &SeedCommitment,
+    black_nonce: &[u8; 32],
+) -> Result<u16, Error> {
+    check_reveal(white, white_nonce, black, black_nonce)?;
+    let seed = sha256(&[DOMAIN, &white.id.to_be_bytes(), white_nonce, black_nonce]);
+    let mut value = [0; 8];
+    value.copy_from_slice(&seed[..8]);
+    Ok((u64::from_be_bytes(value) % u64::from(CHESS960_POSITIONS)) as u16)
+}
+
+/// Checks the commitments are for the same game by different players and
+/// open to their nonces.
+fn check_reveal(
+    first: &SeedCommitment,
+    first_nonce: &[u8; 32],
+    second: &SeedCommitment,
+    second_nonce: &[u8; 32],
+) -> Result<(), Error> {
+    if !first.verify() || !second.verify() {
+        return Err(Error::VerificationFailed);
+    }
+    if first.id != second.id {
+        return Err(malformed("commitments are for different games"));
+    }
+    if first.public_key == second.public_key {
+        return Err(malformed("both commitments are from the same player"));
+    }
+    if !first.opens(first_nonce) || !second.opens(second_nonce) {
+        return Err(Error::CommitmentMismatch);
+    }
+    Ok(())
+}
+
+/// Whether the player with the lower key plays white, given the nonces in
+/// the order of the players' keys.
+fn lower_key_plays_white(id: u32, nonces: &[u8; 64]) -> bool {
+    sha256(&[DOMAIN, b"colors", &id.to_be_bytes(), nonces])[0] & 1 == 0
+}
+
+/// Starts a challenge between the two players with colors decided by their
+/// revealed nonces and the flip recorded in its tag. Fails as
+/// position_index does.
+pub fn flip_colors(
+    first: &SeedCommitment,
+    first_nonce: &[u8; 32],
+    second: &SeedCommitment,
+    second_nonce: &[u8; 32],
+) -> Result<ChallengeBlockBuilder, Error> {
+    check_reveal(first, first_nonce, second, second_nonce)?;
+    let (lower, higher) = if first.public_key.as_bytes() < second.public_key.as_bytes() {
+        ((first, first_nonce), (second, second_nonce))
+    } else {
+        ((second, second_nonce), (first, first_nonce))
+    };
+    let mut nonces = [0; 64];
+    nonces[..32].copy_from_slice(lower.1);
+    nonces[32..].copy_from_slice(higher.1);
+    let mut flip = Vec::with_capacity(2 * FLIP_SHARE_LEN);
+    for (commitment, nonce) in &[lower, higher] {
+        flip.extend(&nonce[..]);
+        flip.extend(&commitment.hash);
+        flip.extend(&commitment.signature);
+    }
+    let (white, black) = if lower_key_plays_white(first.id, &nonces) {
+        (lower.0, higher.0)
+    } else {
+        (higher.0, lower.0)
+    };
+    let tag = bs58::encode(&flip).into_string();
+    Ok(
+        ChallengeBlockBuilder::new(&white.public_key, &black.public_key)
+            .id(first.id)
+            .tag(COLOR_FLIP_TAG, &tag),
+    )
+}
+
+/// Checks that the challenge's colors follow from the flip in its
+/// ColorFlip tag, if it has one, and that each nonce in the flip opens its
+/// player's signed commitment for the game.
+pub fn check_color_flip(challenge: &ChallengeBlock) -> Result<(), Error> {
+    let tag = match challenge.tag(COLOR_FLIP_TAG) {
+        Some(tag) => tag,
+        None => return Ok(()),
+    };
+    let decoded = bs58::decode(tag)
+        .into_vec()
+        .map_err(|_| Error::InvalidChallenge("color flip is not base58"))?;
+    if decoded.len() != 2 * FLIP_SHARE_LEN {
+        return Err(Error::InvalidChallenge(
+            "color flip needs two nonces and commitments",
+        ));
+    }
+    let white = challenge.white_public_key();
+    let black = challenge.black_public_key();
+    let mut keys = [*white.as_bytes(), *black.as_bytes()];
+    keys.sort();
+    let mut nonces = [0; 64];
+    for (i, (public_key, share)) in keys.iter().zip(decoded.chunks(FLIP_SHARE_LEN)).enumerate() {
+        let mut nonce = [0; 32];
+        nonce.copy_from_slice(&share[..32]);
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&share[32..64]);
+        let commitment = SeedCommitment {
+            public_key: PublicKey::from(*public_key),
+            id: challenge.id(),
+            hash,
+            signature: share[64..].to_vec(),
+        };
+        if !commitment.verify() || !commitment.opens(&nonce) {
+            return Err(Error::InvalidChallenge(
+                "color flip nonce does not open a signed commitment",
+            ));
+        }
+        nonces[i * 32..(i + 1) * 32].copy_from_slice(&nonce);
+    }
+    if (white.as_bytes() < black.as_bytes()) != lower_key_plays_white(challenge.id(), &nonces) {
+        return Err(Error::InvalidChallenge("colors do not match the flip"));
+    }
+    Ok(())
+}
+
+/// The starting FEN of the Chess960 position with the standard number
+/// `index`, where 518 is the usual setup. The move generator only knows
+/// standard castling, so a right is recorded only for a rook that starts in
+/// its corner with the king on the e-file.
+pub fn chess960_fen(index: u16) -> Option<String> {
+    if index >= CHESS960_POSITIONS {
+        return None;
+    }
+    let mut rank = [None; 8];
+    let mut n = index as usize;
+    rank[n % 4 * 2 + 1] = Some('b');
+    n /= 4;
+    rank[n % 4 * 2] = Some('b');
+    n /= 4;
+    place(&mut rank, n % 6, 'q');
+    n /= 6;
+    let knights = [
+        (0, 0),
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (1, 1),
+        (1, 2),
+        (1, 3),
+        (2, 2),
+        (2, 3),
+        (3, 3),
+    ][n];
+    // the second knight goes among the squares left after the first
+    place(&mut rank, knights.0, 'n');
+    place(&mut rank, knights.1, 'n');
+    for &piece in &['r', 'k', 'r'] {
+        place(&mut rank, 0, piece);
+    }
+    let black: String = rank.iter().map(|piece| piece.unwrap_or('r')).collect();
+    let white = black.to_uppercase();
+    let king = black.find('k').unwrap_or(0);
+    let mut castling = String::new();
+    if king == 4 && black.ends_with('r') {
+        castling.push('K');
+    }
+    if king == 4 && black.starts_with('r') {
+        castling.push('Q');
+    }
+    let black_castling = castling.to_lowercase();
+    castling.push_str(&black_castling);
+    if castling.is_empty() {
+        castling.push('-');
+    }
+    Some(format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w {} - 0 1",
+        black, white, castling
+    ))
+}
+
+/// Puts `piece` on the empty square numbered `nth` from the a-file.
+fn place(rank: &mut [Option<char>; 8], nth: usize, piece: char) {
+    if let Some(square) = rank.iter_mut().filter(|square| square.is_none()).nth(nth) {
+        *square = Some(piece);
+    }
+}
+
+impl ChallengeBlockBuilder {
+    /// Sets up a Chess960 game from the position numbered `index`, taken
+    /// modulo 960.
+    pub fn chess960(self, index: u16) -> ChallengeBlockBuilder {
+        let fen = chess960_fen(index % CHESS960_POSITIONS).unwrap_or_default();
+        self.variant(Variant::Chess960).starting_fen(&fen)
+    }
+}
+
+/// Checks that `challenge` is the Chess960 game its players seeded with
+/// these commitments and nonces.
+pub fn check_challenge(
+    challenge: &ChallengeBlock,
+    white: &SeedCommitment,
+    white_nonce: &[u8; 32],
+    black: &SeedCommitment,
+    black_nonce: &[u8; 32],
+) -> Result<(), Error> {
+    if challenge.white_public_key() != white.public_key
+        || challenge.black_public_key() != black.public_key
+        || white.id != challenge.id()
+    {
+        return Err(malformed("commitments are not for this challenge"));
+    }
+    let index = position_index(white, white_nonce, black, black_nonce)?;
+    if challenge.variant() != Variant::Chess960
+        || challenge.starting_fen() != chess960_fen(index).as_deref()
+    {
+        return Err(Error::InvalidChallenge(
+            "starting position does not match the seeding",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn numbered_positions() {
+        assert_eq!(
+            chess960_fen(518).unwrap(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+        );
+        assert_eq!(
+            chess960_fen(0).unwrap(),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w - - 0 1"
+        );
+        assert_eq!(chess960_fen(960), None);
+        for index in 0..CHESS960_POSITIONS {
+            assert!(chess960_fen(index).unwrap().parse::<chess::Board>().is_ok());
+        }
+    }
+
+    #[test]
+    fn seed_challenge() {
+        let rng = crypto::SeededRng::new(960);
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let (white_nonce, black_nonce) = (new_nonce(&rng), new_nonce(&rng));
+        let white_commitment = SeedCommitment::new(&white, 7, &white_nonce);
+        let black_commitment = SeedCommitment::new(&black, 7, &black_nonce);
+        assert_eq!(
+            SeedCommitment::from_bytes(&white_commitment.as_bytes()),
+            Ok(white_commitment.clone())
+        );
+
+        let index = position_index(
+            &white_commitment,
+            &white_nonce,
+            &black_commitment,
+            &black_nonce,
+        )
+        .unwrap();
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .id(7)
+            .chess960(index)
+            .build()
+            .unwrap();
+        assert_eq!(
+            check_challenge(
+                &challenge,
+                &white_commitment,
+                &white_nonce,
+                &black_commitment,
+                &black_nonce
+            ),
+            Ok(())
+        );
+
+        // a player can't swap in another nonce after seeing the other's
+        assert_eq!(
+            position_index(
+                &white_commitment,
+                &black_nonce,
+                &black_commitment,
+                &black_nonce
+            ),
+            Err(Error::CommitmentMismatch)
+        );
+        let other = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .id(7)
+            .chess960((index + 1) % CHESS960_POSITIONS)
+            .build()
+            .unwrap();
+        assert!(check_challenge(
+            &other,
+            &white_commitment,
+            &white_nonce,
+            &black_commitment,
+            &black_nonce
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn flip_for_colors() {
+        let rng = crypto::SeededRng::new(2);
+        let challenger = crypto::generate_key(&rng);
+        let opponent = crypto::generate_key(&rng);
+        let (challenger_nonce, opponent_nonce) = (new_nonce(&rng), new_nonce(&rng));
+        let ours = SeedCommitment::new(&challenger, 9, &challenger_nonce);
+        let theirs = SeedCommitment::new(&opponent, 9, &opponent_nonce);
+
+        let challenge = flip_colors(&ours, &challenger_nonce, &theirs, &opponent_nonce)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(challenge.id(), 9);
+        assert_eq!(check_color_flip(&challenge), Ok(()));
+        // the flip doesn't depend on who started it
+        let reversed = flip_colors(&theirs, &opponent_nonce, &ours, &challenger_nonce)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(reversed, challenge);
+
+        let mut chain = crate::block::GameChain::new(challenge.clone());
+        chain.accept(&challenger).unwrap();
+        chain.accept(&opponent).unwrap();
+        assert!(chain.verify());
+
+        let tag = challenge.tag(COLOR_FLIP_TAG).unwrap();
+        let swapped = ChallengeBlockBuilder::new(
+            &challenge.black_public_key(),
+            &challenge.white_public_key(),
+        )
+        .id(9)
+        .tag(COLOR_FLIP_TAG, tag)
+        .build()
+        .unwrap();
+        assert_eq!(
+            check_color_flip(&swapped),
+            Err(Error::InvalidChallenge("colors do not match the flip"))
+        );
+        let mut chain = crate::block::GameChain::new(swapped);
+        chain.accept(&challenger).unwrap();
+        chain.accept(&opponent).unwrap();
+        assert!(!chain.verify());
+
+        // nonces picked after the fact don't open the commitments
+        let mut forged = bs58::decode(tag).into_vec().unwrap();
+        forged[0] ^= 1;
+        let forged = ChallengeBlockBuilder::new(
+            &challenge.white_public_key(),
+            &challenge.black_public_key(),
+        )
+        .id(9)
+        .tag(COLOR_FLIP_TAG, &bs58::encode(&forged).into_string())
+        .build()
+        .unwrap();
+        assert_eq!(
+            check_color_flip(&forged),
+            Err(Error::InvalidChallenge(
+                "color flip nonce does not open a signed commitment"
+            ))
+        );
+
+        assert_eq!(
+            flip_colors(&ours, &opponent_nonce, &theirs, &opponent_nonce).err(),
+            Some(Error::CommitmentMismatch)
+        );
+    }
+}
@@ -0,0 +1,680 @@
+//! A game played with a peer. GameSession is a state machine that does no
+//! I/O: it consumes incoming messages and queues outgoing messages and
+//! events, covering the handshake, challenge negotiation, move exchange, and
+//! resyncing. Anything can drive it; ChannelSession drives it over a
+//! blocking channel, so the same logic runs over TCP, serial links, mail, or
+//! a shared folder.
+
+use crate::block::{GameChain, GameStatus};
+use crate::error::Error;
+use crate::heartbeat::Heartbeat;
+#[cfg(feature = "compression")]
+use crate::message::CAN_DEFLATE;
+use crate::message::{capabilities, Message, WIRE_VERSION};
+use crate::metrics;
+use crate::revocation::{self, RevocationList};
+use crate::transport::{Channel, StreamTransport};
+
+use chess::Action;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use std::collections::VecDeque;
+use std::io;
+use std::net::{Shutdown, TcpStream};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionEvent {
+    /// The peer said hello as the opponent.
+    PeerIdentified { public_key: [u8; 32] },
+    /// The peer sent a challenge we haven't accepted yet.
+    ChallengeReceived,
+    /// Both players have now accepted.
+    ChallengeAccepted,
+    /// The peer turned the challenge down. The session is closed.
+    ChallengeDeclined,
+    /// The peer's chain added the move at `index`.
+    MoveReceived { index: usize },
+    /// The peer's chain added blocks other than moves, like receipts.
+    Resynced,
+    /// The peer sent a heartbeat for the game.
+    HeartbeatReceived(Heartbeat),
+    /// The peer passed on a revocation list newer than any we had from its
+    /// signer, for the node to pass on in turn.
+    RevocationsReceived(RevocationList),
+    /// A message from the peer was refused. A bad hello closes the session.
+    Rejected(Error),
+}
+
+pub struct GameSession {
+    public_key: [u8; 32],
+    peer: Option<[u8; 32]>,
+    chain: GameChain,
+    // The wire version both sides speak, and how much of our chain the peer
+    // is known to have, so that moves can be sent on their own.
+    wire_version: u8,
+    peer_len: Option<usize>,
+    peer_capabilities: u8,
+    revocations: Vec<RevocationList>,
+    closed: bool,
+    outgoing: VecDeque<Message>,
+    events: VecDeque<SessionEvent>,
+}
+
+impl GameSession {
+    /// A session for `public_key`, which must play in `chain`. A hello to
+    /// the peer is queued straight away.
+    pub fn new(public_key: &[u8], chain: GameChain) -> Result<GameSession, Error> {
+        chain.side_of(public_key).ok_or(Error::InvalidKey)?;
+        let mut key = [0; 32];
+        key.copy_from_slice(public_key);
+        let mut outgoing = VecDeque::new();
+        outgoing.push_back(Message::Hello {
+            public_key: key,
+            wire_version: WIRE_VERSION,
+        });
+        Ok(GameSession {
+            public_key: key,
+            peer: None,
+            chain,
+            wire_version: 0,
+            peer_len: None,
+            peer_capabilities: 0,
+            revocations: Vec::new(),
+            closed: false,
+            outgoing,
+            events: VecDeque::new(),
+        })
+    }
+
+    pub fn chain(&self) -> &GameChain {
+        &self.chain
+    }
+
+    pub fn into_chain(self) -> GameChain {
+        self.chain
+    }
+
+    /// The opponent's key, once they've said hello.
+    pub fn peer(&self) -> Option<&[u8; 32]> {
+        self.peer.as_ref()
+    }
+
+    pub fn status(&self) -> GameStatus {
+        self.chain.status()
+    }
+
+    /// Whether the challenge was declined or the peer failed the handshake.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    /// The next message to send, if any.
+    pub fn poll_transmit(&mut self) -> Option<Message> {
+        self.outgoing.pop_front()
+    }
+
+    pub fn poll_event(&mut self) -> Option<SessionEvent> {
+        self.events.pop_front()
+    }
+
+    /// The latest revocation list from each signer the peer passed on.
+    pub fn revocations(&self) -> &[RevocationList] {
+        &self.revocations
+    }
+
+    /// The wire version agreed with the peer, 0 until they say hello.
+    pub fn wire_version(&self) -> u8 {
+        self.wire_version
+    }
+
+    /// Sends our whole chain, compressed if the peer can take it that way.
+    fn send_chain(&mut self) {
+        self.peer_len = Some(self.chain.encoded_len());
+        let message = Message::Chain(Box::new(self.chain.clone()));
+        #[cfg(feature = "compression")]
+        let message = if self.peer_capabilities & CAN_DEFLATE != 0 {
+            Message::Compressed(Box::new(message))
+        } else {
+            message
+        };
+        self.outgoing.push_back(message);
+    }
+
+    /// Sends the last move on its own if the peer speaks wire version 1 and
+    /// has everything before it, packed if it speaks version 6, otherwise
+    /// the whole chain.
+    fn send_move(&mut self, len_before: usize) {
+        if self.wire_version >= 1 && self.peer_len == Some(len_before) {
+            let id = self.chain.id();
+            let first = self.chain.move_count() as u32 - 1;
+            let message = if self.wire_version >= 6 {
+                self.chain
+                    .last_compact_move()
+                    .ok()
+                    .map(|mv| Message::CompactMoves {
+                        id,
+                        first,
+                        moves: vec![mv],
+                    })
+            } else {
+                self.chain.moves().last().map(|mv| Message::Moves {
+                    id,
+                    first,
+                    moves: vec![mv.clone()],
+                })
+            };
+            if let Some(message) = message {
+                self.peer_len = Some(self.chain.encoded_len());
+                self.outgoing.push_back(message);
+                return;
+            }
+        }
+        self.send_chain();
+    }
+
+    fn is_accepted(chain: &GameChain) -> bool {
+        chain.status() != GameStatus::AwaitingAcceptance
+    }
+
+    /// Handles a message from the peer. Messages about other games are
+    /// ignored, as is everything once the session is closed.
+    pub fn handle(&mut self, message: Message) {
+        if self.closed {
+            return;
+        }
+        let id = self.chain.id();
+        match message {
+            Message::Hello {
+                public_key,
+                wire_version,
+            } => {
+                let ours = self.chain.side_of(&self.public_key);
+                if self.chain.side_of(&public_key).map(|side| !side) != ours {
+                    self.closed = true;
+                    metrics::handshake_failed();
+                    self.events
+                        .push_back(SessionEvent::Rejected(Error::InvalidKey));
+                } else if self.peer.is_none() {
+                    self.peer = Some(public_key);
+                    self.wire_version = wire_version.min(WIRE_VERSION);
+                    self.events
+                        .push_back(SessionEvent::PeerIdentified { public_key });
+                    if self.wire_version >= 4 {
+                        self.outgoing.push_back(Message::Capabilities {
+                            flags: capabilities(),
+                        });
+                    }
+                    self.send_chain();
+                }
+            }
+            Message::Ping { nonce } => self.outgoing.push_back(Message::Pong { nonce }),
+            Message::Capabilities { flags } => self.peer_capabilities = flags,
+            #[cfg(feature = "compression")]
+            Message::Compressed(message) => self.handle(*message),
+            Message::Heartbeat(heartbeat) => {
+                let ours = self.chain.side_of(&self.public_key);
+                match heartbeat.signer(&self.chain) {
+                    Some(side) if Some(!side) == ours => self
+                        .events
+                        .push_back(SessionEvent::HeartbeatReceived(heartbeat)),
+                    Some(_) => {}
+                    None => self
+                        .events
+                        .push_back(SessionEvent::Rejected(Error::VerificationFailed)),
+                }
+            }
+            Message::Revocations(list) => {
+                if !list.verify() {
+                    self.events
+                        .push_back(SessionEvent::Rejected(Error::VerificationFailed));
+                } else if revocation::keep(&mut self.revocations, list.clone()) {
+                    self.events
+                        .push_back(SessionEvent::RevocationsReceived(list));
+                }
+            }
+            Message::Request { id: requested } if requested == id => self.send_chain(),
+            Message::Decline { id: declined } if declined == id => {
+                self.closed = true;
+                self.events.push_back(SessionEvent::ChallengeDeclined);
+            }
+            Message::Chain(theirs) if theirs.id() == id => self.merge(&theirs),
+            Message::Moves {
+                id: moved,
+                first,
+                moves,
+            } if moved == id => self.append_moves(first as usize, &moves, |chain, mv| {
+                chain.append_move_block(mv.clone())
+            }),
+            Message::CompactMoves {
+                id: moved,
+                first,
+                moves,
+            } if moved == id => {
+                self.append_moves(first as usize, &moves, GameChain::append_compact_move)
+            }
+            _ => {}
+        }
+    }
+
+    /// Appends moves sent on their own. If they don't follow on from our
+    /// copy, we ask for the peer's whole chain instead.
+    fn append_moves<T>(
+        &mut self,
+        first: usize,
+        moves: &[T],
+        append: fn(&mut GameChain, &T) -> Result<(), Error>,
+    ) {
+        if first != self.chain.move_count() {
+            self.outgoing.push_back(Message::Request {
+                id: self.chain.id(),
+            });
+            return;
+        }
+        for mv in moves {
+            if let Err(error) = append(&mut self.chain, mv) {
+                self.events.push_back(SessionEvent::Rejected(error));
+                return;
+            }
+            self.events.push_back(SessionEvent::MoveReceived {
+                index: self.chain.move_count() - 1,
+            });
+        }
+        self.peer_len = Some(self.chain.encoded_len());
+    }
+
+    fn merge(&mut self, theirs: &GameChain) {
+        let before = self.chain.clone();
+        if let Err(error) = self.chain.merge(theirs) {
+            self.events.push_back(SessionEvent::Rejected(error));
+            return;
+        }
+        self.peer_len = Some(theirs.encoded_len());
+        if self.chain.encoded_len() != before.encoded_len() {
+            if !GameSession::is_accepted(&before) {
+                let side = self.chain.side_of(&self.public_key).unwrap();
+                if GameSession::is_accepted(&self.chain) {
+                    self.events.push_back(SessionEvent::ChallengeAccepted);
+                } else if self.chain.accepts()[side.to_index()].is_none() {
+                    self.events.push_back(SessionEvent::ChallengeReceived);
+                }
+            }
+            for index in before.move_count()..self.chain.move_count() {
+                self.events.push_back(SessionEvent::MoveReceived { index });
+            }
+            if GameSession::is_accepted(&before) && self.chain.move_count() == before.move_count() {
+                self.events.push_back(SessionEvent::Resynced);
+            }
+        }
+        // a peer that's behind gets our copy
+        if theirs.encoded_len() < self.chain.encoded_len() {
+            self.send_chain();
+        }
+    }
+
+    /// Accepts the challenge and sends the chain, unless a revocation list
+    /// the peer passed on withdraws it.
+    pub fn accept(&mut self, key_pair: &Ed25519KeyPair) -> Result<(), Error> {
+        revocation::check(&self.chain, &self.revocations)?;
+        self.chain.accept(key_pair)?;
+        self.send_chain();
+        if GameSession::is_accepted(&self.chain) {
+            self.events.push_back(SessionEvent::ChallengeAccepted);
+        }
+        Ok(())
+    }
+
+    /// Turns down the challenge and closes the session.
+    pub fn decline(&mut self) {
+        self.outgoing.push_back(Message::Decline {
+            id: self.chain.id(),
+        });
+        self.closed = true;
+    }
+
+    /// Makes a move and sends the updated chain.
+    pub fn make_move(&mut self, key_pair: &Ed25519KeyPair, action: Action) -> Result<(), Error> {
+        let len_before = self.chain.encoded_len();
+        self.chain.make_move_block(key_pair, action)?;
+        self.send_move(len_before);
+        Ok(())
+    }
+
+    /// Tells the peer we're still here at `now`. Only peers speaking wire
+    /// version 3 are sent heartbeats.
+    pub fn send_heartbeat(&mut self, key_pair: &Ed25519KeyPair, now: u64) -> Result<(), Error> {
+        let heartbeat = self.chain.heartbeat(key_pair, now)?;
+        if self.wire_version >= 3 {
+            self.outgoing.push_back(Message::Heartbeat(heartbeat));
+        }
+        Ok(())
+    }
+
+    /// Passes a revocation list on to the peer, whoever signed it. Only
+    /// peers speaking wire version 5 are sent them.
+    pub fn send_revocations(&mut self, list: RevocationList) {
+        if self.wire_version >= 5 {
+            self.outgoing.push_back(Message::Revocations(list));
+        }
+    }
+
+    /// Asks the peer for their copy and sends ours, e.g. after reconnecting.
+    pub fn resync(&mut self) {
+        self.outgoing.push_back(Message::Request {
+            id: self.chain.id(),
+        });
+        self.send_chain();
+    }
+}
+
+fn invalid_data(error: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+/// Drives a GameSession over a blocking channel.
+pub struct ChannelSession<C> {
+    channel: C,
+    session: GameSession,
+}
+
+impl<C: Channel> ChannelSession<C> {
+    pub fn new(channel: C, session: GameSession) -> ChannelSession<C> {
+        ChannelSession { channel, session }
+    }
+
+    pub fn session(&self) -> &GameSession {
+        &self.session
+    }
+
+    pub fn into_parts(self) -> (C, GameSession) {
+        (self.channel, self.session)
+    }
+
+    /// Sends every queued message.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while let Some(message) = self.session.poll_transmit() {
+            self.channel.send_message(&message)?;
+        }
+        Ok(())
+    }
+
+    /// Sends what's queued, then waits for the next event.
+    pub fn next_event(&mut self) -> io::Result<SessionEvent> {
+        loop {
+            self.flush()?;
+            if let Some(event) = self.session.poll_event() {
+                return Ok(event);
+            }
+            let message = self.channel.recv_message()?;
+            self.session.handle(message);
+        }
+    }
+
+    /// Waits for the peer's hello, returning their key. Channels that only
+    /// carry chains, like mail, can't do a handshake.
+    pub fn handshake(&mut self) -> io::Result<[u8; 32]> {
+        loop {
+            match self.next_event()? {
+                SessionEvent::PeerIdentified { public_key } => return Ok(public_key),
+                SessionEvent::Rejected(error) if self.session.is_closed() => {
+                    return Err(invalid_data(error))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn make_move(&mut self, key_pair: &Ed25519KeyPair, action: Action) -> io::Result<()> {
+        self.session
+            .make_move(key_pair, action)
+            .map_err(invalid_data)?;
+        self.flush()
+    }
+
+    /// Waits for the peer's next move.
+    pub fn wait_for_move(&mut self) -> io::Result<()> {
+        loop {
+            match self.next_event()? {
+                SessionEvent::MoveReceived { .. } => return Ok(()),
+                SessionEvent::ChallengeDeclined => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ConnectionAborted,
+                        "challenge declined",
+                    ))
+                }
+                SessionEvent::Rejected(error) => return Err(invalid_data(error)),
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether `key_pair` is to move.
+    pub fn is_my_turn(&self, key_pair: &Ed25519KeyPair) -> bool {
+        self.session
+            .chain()
+            .is_my_turn(key_pair.public_key().as_ref())
+    }
+}
+
+impl ChannelSession<StreamTransport<TcpStream>> {
+    /// Sends what's queued and closes the connection once the peer has hung
+    /// up too. Whatever else they send is handed to the session, so nothing
+    /// is left unread to reset the connection before they've read our last
+    /// move.
+    pub fn hang_up(mut self) -> io::Result<GameSession> {
+        self.flush()?;
+        let stream = self.channel.into_inner();
+        stream.shutdown(Shutdown::Write)?;
+        let mut transport = StreamTransport::new(stream);
+        loop {
+            match transport.recv_message() {
+                Ok(message) => self.session.handle(message),
+                Err(error) if error.kind() == io::ErrorKind::InvalidData => {}
+                Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Ok(self.session)
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use crate::transport::StreamTransport;
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Delivers queued messages both ways until neither side has any left.
+    fn shuttle(a: &mut GameSession, b: &mut GameSession) {
+        loop {
+            let mut delivered = false;
+            while let Some(message) = a.poll_transmit() {
+                b.handle(message);
+                delivered = true;
+            }
+            while let Some(message) = b.poll_transmit() {
+                a.handle(message);
+                delivered = true;
+            }
+            if !delivered {
+                return;
+            }
+        }
+    }
+
+    fn events(session: &mut GameSession) -> Vec<SessionEvent> {
+        std::iter::from_fn(|| session.poll_event()).collect()
+    }
+
+    #[test]
+    fn negotiate_and_play() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let stranger = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut offered = GameChain::new(challenge.clone());
+        offered.accept(&white).unwrap();
+
+        let mut ours = GameSession::new(white.public_key().as_ref(), offered).unwrap();
+        let mut theirs =
+            GameSession::new(black.public_key().as_ref(), GameChain::new(challenge)).unwrap();
+        shuttle(&mut ours, &mut theirs);
+        assert_eq!(
+            events(&mut theirs),
+            vec![
+                SessionEvent::PeerIdentified {
+                    public_key: *ours.chain().players().0.as_bytes()
+                },
+                SessionEvent::ChallengeReceived,
+            ]
+        );
+        events(&mut ours);
+
+        theirs.accept(&black).unwrap();
+        shuttle(&mut ours, &mut theirs);
+        assert_eq!(events(&mut ours), vec![SessionEvent::ChallengeAccepted]);
+
+        let mv = notation::parse_uci("e2e4").unwrap();
+        ours.make_move(&white, Action::MakeMove(mv)).unwrap();
+        shuttle(&mut ours, &mut theirs);
+        assert_eq!(
+            events(&mut theirs),
+            vec![
+                SessionEvent::ChallengeAccepted,
+                SessionEvent::MoveReceived { index: 0 }
+            ]
+        );
+        assert_eq!(ours.chain(), theirs.chain());
+        assert_eq!(theirs.wire_version(), WIRE_VERSION);
+
+        events(&mut ours);
+        theirs.send_heartbeat(&black, 2_000).unwrap();
+        shuttle(&mut ours, &mut theirs);
+        assert_eq!(
+            events(&mut ours),
+            vec![SessionEvent::HeartbeatReceived(
+                ours.chain().heartbeat(&black, 2_000).unwrap()
+            )]
+        );
+
+        // a peer that lost its copy catches up by resyncing
+        let mut behind =
+            GameSession::new(black.public_key().as_ref(), ours.chain().truncated(0)).unwrap();
+        behind.resync();
+        shuttle(&mut ours, &mut behind);
+        assert_eq!(behind.chain(), ours.chain());
+
+        let mut impostor =
+            GameSession::new(white.public_key().as_ref(), ours.chain().clone()).unwrap();
+        let mut public_key = [0; 32];
+        public_key.copy_from_slice(stranger.public_key().as_ref());
+        impostor.handle(Message::Hello {
+            public_key,
+            wire_version: WIRE_VERSION,
+        });
+        assert!(impostor.is_closed());
+
+        theirs.decline();
+        shuttle(&mut ours, &mut theirs);
+        assert_eq!(events(&mut ours), vec![SessionEvent::ChallengeDeclined]);
+        assert!(ours.is_closed());
+    }
+
+    #[test]
+    fn revoked_challenges() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut offered = GameChain::new(challenge.clone());
+        offered.accept(&white).unwrap();
+        let mut ours = GameSession::new(white.public_key().as_ref(), offered).unwrap();
+        let mut theirs =
+            GameSession::new(black.public_key().as_ref(), GameChain::new(challenge)).unwrap();
+        shuttle(&mut ours, &mut theirs);
+        events(&mut theirs);
+
+        let list = RevocationList::new(&white, 1_000, &[ours.chain().id()]);
+        ours.send_revocations(list.clone());
+        shuttle(&mut ours, &mut theirs);
+        assert_eq!(
+            events(&mut theirs),
+            vec![SessionEvent::RevocationsReceived(list.clone())]
+        );
+        assert_eq!(theirs.revocations(), std::slice::from_ref(&list));
+        assert_eq!(theirs.accept(&black), Err(Error::ChallengeRevoked));
+
+        // a list passed on twice is only reported once
+        ours.send_revocations(list);
+        shuttle(&mut ours, &mut theirs);
+        assert!(events(&mut theirs).is_empty());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compress_chains() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut offered = GameChain::new(challenge.clone());
+        offered.accept(&white).unwrap();
+        let mut ours = GameSession::new(white.public_key().as_ref(), offered).unwrap();
+        let mut theirs =
+            GameSession::new(black.public_key().as_ref(), GameChain::new(challenge)).unwrap();
+        shuttle(&mut ours, &mut theirs);
+
+        // once capabilities are swapped, whole chains go compressed
+        theirs.accept(&black).unwrap();
+        let message = theirs.poll_transmit().unwrap();
+        assert!(matches!(&message, Message::Compressed(inner)
+            if **inner == Message::Chain(Box::new(theirs.chain().clone()))));
+        ours.handle(message);
+        assert_eq!(ours.chain(), theirs.chain());
+    }
+
+    #[test]
+    fn play_over_tcp() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let black_chain = chain.clone();
+        let black_public_key = black.public_key().as_ref().to_vec();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let black_side = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let session = GameSession::new(black.public_key().as_ref(), black_chain).unwrap();
+            let mut session = ChannelSession::new(StreamTransport::new(stream), session);
+            session.handshake().unwrap();
+            session.wait_for_move().unwrap();
+            let mv = notation::parse_uci("e7e5").unwrap();
+            session.make_move(&black, Action::MakeMove(mv)).unwrap();
+            session.hang_up().unwrap().into_chain()
+        });
+
+        let transport = crate::transport::connect_tcp(addr).unwrap();
+        let session = GameSession::new(white.public_key().as_ref(), chain).unwrap();
+        let mut session = ChannelSession::new(transport, session);
+        let peer = session.handshake().unwrap();
+        assert_eq!(&peer[..], &black_public_key[..]);
+        let mv = notation::parse_uci("e2e4").unwrap();
+        session.make_move(&white, Action::MakeMove(mv)).unwrap();
+        session.wait_for_move().unwrap();
+        let session = session.hang_up().unwrap();
+
+        let black_chain = black_side.join().unwrap();
+        assert_eq!(session.chain(), &black_chain);
+        assert_eq!(black_chain.move_count(), 2);
+    }
+}
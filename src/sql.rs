@@ -0,0 +1,180 @@
+//! Export to SQL for analysis. Chains are written as a SQLite script, to be
+//! loaded with e.g. `sqlite3 games.db < games.sql`, over this schema:
+//!
+//! - players: one row per key, by fingerprint
+//! - games: one row per chain, keyed by its base58 canonical id since the
+//!   u32 chain ids of different players' games can collide, with its
+//!   players, status, result, and whether it verified
+//! - moves: one row per move on each game's line, in UCI, with the FEN after
+//!   it and the fingerprint of the key that signed it
+//!
+//! Games that don't verify are still exported, with `verified` set to 0 and
+//! no moves, so that analysts can see what was left out.
+
+use crate::block::{GameChain, GameResult, GameStatus};
+
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use chess::Color;
+
+/// The tables the export fills in.
+pub const SCHEMA: &str = "\
+CREATE TABLE IF NOT EXISTS players (
+    fingerprint TEXT PRIMARY KEY,
+    public_key BLOB NOT NULL
+);
+CREATE TABLE IF NOT EXISTS games (
+    id TEXT PRIMARY KEY,
+    chain_id INTEGER NOT NULL,
+    network INTEGER NOT NULL,
+    white TEXT NOT NULL REFERENCES players(fingerprint),
+    black TEXT NOT NULL REFERENCES players(fingerprint),
+    issued_at INTEGER NOT NULL,
+    starting_fen TEXT,
+    status TEXT NOT NULL,
+    result TEXT,
+    move_count INTEGER NOT NULL,
+    verified INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS moves (
+    game_id TEXT NOT NULL REFERENCES games(id),
+    ply INTEGER NOT NULL,
+    uci TEXT NOT NULL,
+    fen_after TEXT NOT NULL,
+    signer TEXT NOT NULL REFERENCES players(fingerprint),
+    PRIMARY KEY (game_id, ply)
+);
+";
+
+fn text(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn blob(bytes: &[u8]) -> String {
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("X'{}'", hex)
+}
+
+fn status_name(status: GameStatus) -> &'static str {
+    match status {
+        GameStatus::AwaitingAcceptance => "awaiting",
+        GameStatus::InProgress => "in-progress",
+        GameStatus::Adjourned => "adjourned",
+        GameStatus::Disputed => "disputed",
+        GameStatus::Finished(_) => "finished",
+        GameStatus::Invalid => "invalid",
+    }
+}
+
+fn result_name(result: GameResult) -> &'static str {
+    match result {
+        GameResult::Win(Color::White) => "1-0",
+        GameResult::Win(Color::Black) => "0-1",
+        GameResult::Draw => "1/2-1/2",
+    }
+}
+
+/// The statements that insert `chain`, replacing any earlier export of the
+/// same game.
+pub fn insert_statements(chain: &GameChain) -> String {
+    let challenge = chain.challenge();
+    let verified = chain.verify();
+    let (white, black) = chain.players();
+    let id = text(&bs58::encode(chain.canonical_id()).into_string());
+    let mut sql = String::new();
+    for public_key in &[white, black] {
+        sql.push_str(&format!(
+            "INSERT OR IGNORE INTO players VALUES ({}, {});\n",
+            text(&public_key.fingerprint()),
+            blob(public_key.as_bytes()),
+        ));
+    }
+    let positions: Vec<_> = if verified {
+        chain.iter_positions().collect()
+    } else {
+        Vec::new()
+    };
+    sql.push_str(&format!("DELETE FROM moves WHERE game_id = {};\n", id));
+    sql.push_str(&format!(
+        "INSERT OR REPLACE INTO games VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {});\n",
+        id,
+        chain.id(),
+        challenge.network_id(),
+        text(&white.fingerprint()),
+        text(&black.fingerprint()),
+        challenge.timestamp(),
+        challenge.starting_fen().map_or_else(|| "NULL".into(), text),
+        text(status_name(chain.status())),
+        chain
+            .result()
+            .filter(|_| verified)
+            .map_or_else(|| "NULL".into(), |result| text(result_name(result))),
+        positions.len(),
+        verified as u8,
+    ));
+    for (index, mv, board, signer) in positions {
+        sql.push_str(&format!(
+            "INSERT INTO moves VALUES ({}, {}, {}, {}, {});\n",
+            id,
+            index + 1,
+            text(&format!("{}", mv)),
+            text(&format!("{}", board)),
+            text(&signer),
+        ));
+    }
+    sql
+}
+
+/// A script that creates the schema and inserts every chain in one
+/// transaction.
+pub fn export<'a, I: IntoIterator<Item = &'a GameChain>>(chains: I) -> String {
+    let mut sql = String::from(SCHEMA);
+    sql.push_str("BEGIN;\n");
+    for chain in chains {
+        sql.push_str(&insert_statements(chain));
+    }
+    sql.push_str("COMMIT;\n");
+    sql
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn export_games() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        for (uci, key_pair) in &[
+            ("f2f3", &white),
+            ("e7e5", &black),
+            ("g2g4", &white),
+            ("d8h4", &black),
+        ] {
+            let mv = notation::parse_uci(uci).unwrap();
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        }
+
+        let id = bs58::encode(chain.canonical_id()).into_string();
+        let sql = export(&[chain]);
+        assert!(sql.starts_with("CREATE TABLE IF NOT EXISTS players"));
+        assert!(sql.ends_with("COMMIT;\n"));
+        assert!(sql.contains("'finished', '0-1', 4, 1);"));
+        assert!(sql.contains(&format!("DELETE FROM moves WHERE game_id = '{}';", id)));
+        assert!(sql.contains(&format!("INSERT INTO moves VALUES ('{}', 4, 'd8h4', ", id)));
+        assert_eq!(text("it's"), "'it''s'");
+    }
+}
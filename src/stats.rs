@@ -0,0 +1,176 @@
+//! Post-game statistics, computed by replaying the game line.
+
+use crate::block::GameChain;
+use crate::error::Error;
+
+use alloc::vec::Vec;
+use chess::{Board, ChessMove, Color, Piece, ALL_SQUARES};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameStats {
+    /// Captures made by (white, black).
+    pub captures: (u32, u32),
+    /// Checks given by (white, black).
+    pub checks: (u32, u32),
+    /// Castling moves made by (white, black).
+    pub castles: (u32, u32),
+    /// White's material minus Black's, in pawns, after each move.
+    pub material_balance: Vec<i32>,
+    /// The average seconds (white, black) took per move, measured between
+    /// consecutive receipts and leaving out adjournments. None for a side
+    /// with no timed moves.
+    pub average_think_seconds: (Option<f64>, Option<f64>),
+}
+
+fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 1,
+        Piece::Knight | Piece::Bishop => 3,
+        Piece::Rook => 5,
+        Piece::Queen => 9,
+        Piece::King => 0,
+    }
+}
+
+fn material_balance(board: &Board) -> i32 {
+    ALL_SQUARES
+        .iter()
+        .filter_map(|&square| {
+            let value = piece_value(board.piece_on(square)?);
+            match board.color_on(square)? {
+                Color::White => Some(value),
+                Color::Black => Some(-value),
+            }
+        })
+        .sum()
+}
+
+fn is_capture(board: &Board, mv: ChessMove) -> bool {
+    // en passant is the only capture onto an empty square
+    board.piece_on(mv.get_dest()).is_some()
+        || (board.piece_on(mv.get_source()) == Some(Piece::Pawn)
+            && mv.get_source().get_file() != mv.get_dest().get_file())
+}
+
+fn is_castle(board: &Board, mv: ChessMove) -> bool {
+    board.piece_on(mv.get_source()) == Some(Piece::King)
+        && (mv.get_source().get_file().to_index() as i32
+            - mv.get_dest().get_file().to_index() as i32)
+            .abs()
+            == 2
+}
+
+fn count(totals: &mut (u32, u32), color: Color) {
+    match color {
+        Color::White => totals.0 += 1,
+        Color::Black => totals.1 += 1,
+    }
+}
+
+impl GameChain {
+    /// Statistics for the game line. Fails if the moves aren't legal. Think
+    /// times are only measured for chains without takebacks, where move
+    /// indices and the side to move line up.
+    pub fn stats(&self) -> Result<GameStats, Error> {
+        self.get_game()?;
+        let mut stats = GameStats {
+            captures: (0, 0),
+            checks: (0, 0),
+            castles: (0, 0),
+            material_balance: Vec::new(),
+            average_think_seconds: (None, None),
+        };
+        let mut before = self.challenge().starting_board();
+        for (_, mv, after, _) in self.iter_positions() {
+            let color = before.side_to_move();
+            if is_capture(&before, mv) {
+                count(&mut stats.captures, color);
+            }
+            if is_castle(&before, mv) {
+                count(&mut stats.castles, color);
+            }
+            if after.checkers().popcnt() > 0 {
+                count(&mut stats.checks, color);
+            }
+            stats.material_balance.push(material_balance(&after));
+            before = after;
+        }
+
+        if stats.material_balance.len() == self.move_count() {
+            let first = self.challenge().starting_board().side_to_move();
+            let mut totals = [(0u64, 0u32); 2];
+            for pair in self.receipts().windows(2) {
+                if pair[1].move_index != pair[0].move_index + 1 {
+                    continue;
+                }
+                // the move after the one acknowledged first took this long
+                let color = if pair[1].move_index % 2 == 0 {
+                    first
+                } else {
+                    !first
+                };
+                let seconds = self.active_seconds(pair[0].timestamp, pair[1].timestamp);
+                let total = &mut totals[color.to_index()];
+                total.0 += seconds;
+                total.1 += 1;
+            }
+            let average = |(seconds, moves): (u64, u32)| {
+                if moves == 0 {
+                    None
+                } else {
+                    Some(seconds as f64 / moves as f64)
+                }
+            };
+            stats.average_think_seconds = (
+                average(totals[Color::White.to_index()]),
+                average(totals[Color::Black.to_index()]),
+            );
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlockBuilder;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn game_stats() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .timestamp(1_000)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let moves = ["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5e5"];
+        for (i, mv) in moves.iter().enumerate() {
+            let (mover, receiver) = if i % 2 == 0 {
+                (&white, &black)
+            } else {
+                (&black, &white)
+            };
+            let mv = notation::parse_uci(mv).unwrap();
+            chain.make_move_block(mover, Action::MakeMove(mv)).unwrap();
+            chain
+                .acknowledge_move(receiver, 1_000 + 10 * (i as u64 + 1) * (i as u64 + 1))
+                .unwrap();
+        }
+
+        let stats = chain.stats().unwrap();
+        assert_eq!(stats.captures, (1, 1));
+        assert_eq!(stats.checks, (0, 1));
+        assert_eq!(stats.castles, (0, 0));
+        assert_eq!(stats.material_balance, vec![0, 0, 1, 0, 0, 0]);
+        // receipts at 1010, 1040, 1090, 1160, 1250, 1360
+        assert_eq!(stats.average_think_seconds, (Some(70.0), Some(70.0)));
+    }
+}
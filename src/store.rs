@@ -0,0 +1,486 @@
+//! A local store of games: a directory holding one file per chain, named by
+//! chain id, next to the latest heartbeats for it, alongside any player
+//! profiles. Long-running nodes can collect
+//! old games with a retention policy.
+
+use crate::block::{GameChain, GameStatus};
+use crate::contacts::AddressBook;
+use crate::crypto;
+use crate::error::Error;
+use crate::heartbeat::{Heartbeat, HEARTBEAT_LEN};
+use crate::profile::{self, Profile};
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// One game as seen by a local player.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameSummary {
+    pub id: u32,
+    /// The opponent's petname from the address book, their display name from
+    /// a stored profile, or their fingerprint.
+    pub opponent: String,
+    pub status: GameStatus,
+    pub my_turn: bool,
+    /// When the game last changed, in seconds since the Unix epoch: the
+    /// latest receipt if there is one, or else when the file was written.
+    pub last_activity: Option<u64>,
+}
+
+/// Which stored games garbage collection removes. By default it keeps
+/// everything, and it never removes games with a dispute unless told to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetentionPolicy {
+    keep_finished_for: Option<u64>,
+    only_involving: Option<Vec<u8>>,
+    keep_disputed: bool,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> RetentionPolicy {
+        RetentionPolicy {
+            keep_finished_for: None,
+            only_involving: None,
+            keep_disputed: true,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    pub fn new() -> RetentionPolicy {
+        RetentionPolicy::default()
+    }
+
+    /// Removes finished games once they've been idle for `seconds`.
+    pub fn keep_finished_for(mut self, seconds: u64) -> RetentionPolicy {
+        self.keep_finished_for = Some(seconds);
+        self
+    }
+
+    /// Removes games `public_key` doesn't play in.
+    pub fn only_involving(mut self, public_key: &[u8]) -> RetentionPolicy {
+        self.only_involving = Some(public_key.to_vec());
+        self
+    }
+
+    /// Whether games with a dispute, resolved or not, are always kept.
+    pub fn keep_disputed(mut self, keep_disputed: bool) -> RetentionPolicy {
+        self.keep_disputed = keep_disputed;
+        self
+    }
+
+    /// Whether the policy keeps `chain`, last active at `last_activity`,
+    /// at `now`.
+    pub fn keeps(&self, chain: &GameChain, last_activity: Option<u64>, now: u64) -> bool {
+        if self.keep_disputed && chain.dispute().is_some() {
+            return true;
+        }
+        if let Some(public_key) = &self.only_involving {
+            let (white, black) = chain.players();
+            if white != public_key[..]
+                && black != public_key[..]
+                && chain.side_of(public_key).is_none()
+            {
+                return false;
+            }
+        }
+        match (self.keep_finished_for, chain.status()) {
+            (Some(seconds), GameStatus::Finished(_)) => {
+                last_activity.is_none_or(|last| now.saturating_sub(last) < seconds)
+            }
+            _ => true,
+        }
+    }
+}
+
+pub struct GameStore {
+    dir: PathBuf,
+}
+
+impl GameStore {
+    /// Opens the store in `dir`, creating the directory if needed.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<GameStore> {
+        fs::create_dir_all(&dir)?;
+        Ok(GameStore {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn chain_path(&self, id: u32) -> PathBuf {
+        self.dir.join(format!("{:08x}.chain", id))
+    }
+
+    fn heartbeats_path(&self, id: u32) -> PathBuf {
+        self.dir.join(format!("{:08x}.heartbeats", id))
+    }
+
+    pub fn save(&self, chain: &GameChain) -> io::Result<()> {
+        fs::write(self.chain_path(chain.id()), chain.as_bytes())
+    }
+
+    pub fn save_profile(&self, profile: &Profile) -> io::Result<()> {
+        let name = format!("{}.profile", crypto::fingerprint(profile.public_key()));
+        fs::write(self.dir.join(name), profile.as_bytes())
+    }
+
+    pub fn load(&self, id: u32) -> io::Result<GameChain> {
+        read_chain(&self.chain_path(id))
+    }
+
+    /// Merges `chain` into the stored copy of the same game, or saves it if
+    /// the game is new. Returns whether the store changed. Chains that fork
+    /// from the stored copy are rejected.
+    pub fn merge(&self, chain: &GameChain) -> io::Result<bool> {
+        let path = self.chain_path(chain.id());
+        let mut stored = match read_chain(&path) {
+            Ok(stored) => stored,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                self.save(chain)?;
+                return Ok(true);
+            }
+            Err(error) => return Err(error),
+        };
+        let before = stored.as_bytes();
+        stored.merge(chain).map_err(invalid_data)?;
+        let after = stored.as_bytes();
+        if after == before {
+            return Ok(false);
+        }
+        fs::write(path, after)?;
+        Ok(true)
+    }
+
+    pub(crate) fn paths_with_extension(&self, extension: &str) -> io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|found| found == extension) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Every stored chain that decodes and verifies, in id order. Files that
+    /// don't are skipped.
+    pub fn load_all(&self) -> io::Result<Vec<GameChain>> {
+        Ok(self
+            .paths_with_extension("chain")?
+            .iter()
+            .filter_map(|path| read_chain(path).ok())
+            .collect())
+    }
+
+    /// Every stored profile that verifies.
+    pub fn profiles(&self) -> io::Result<Vec<Profile>> {
+        Ok(self
+            .paths_with_extension("profile")?
+            .iter()
+            .filter_map(|path| Profile::from_bytes(&fs::read(path).ok()?).ok())
+            .filter(Profile::verify)
+            .collect())
+    }
+
+    /// Summaries of the stored games that `public_key` plays in, naming
+    /// opponents from `contacts` where possible.
+    pub fn summaries(
+        &self,
+        public_key: &[u8],
+        contacts: &AddressBook,
+    ) -> io::Result<Vec<GameSummary>> {
+        let profiles = self.profiles()?;
+        let mut summaries = Vec::new();
+        for path in self.paths_with_extension("chain")? {
+            let chain = match read_chain(&path) {
+                Ok(chain) => chain,
+                Err(_) => continue,
+            };
+            let side = match chain.side_of(public_key) {
+                Some(side) => side,
+                None => continue,
+            };
+            let (white, black) = chain.players();
+            let opponent = match side {
+                chess::Color::White => black,
+                chess::Color::Black => white,
+            };
+            let status = chain.status();
+            let last_activity = last_activity(&chain, &path)?;
+            summaries.push(GameSummary {
+                id: chain.id(),
+                opponent: contacts.name(opponent.as_ref()).map_or_else(
+                    || profile::display_name(opponent.as_ref(), &profiles),
+                    String::from,
+                ),
+                status,
+                my_turn: status == GameStatus::InProgress && chain.is_my_turn(public_key),
+                last_activity,
+            });
+        }
+        Ok(summaries)
+    }
+
+    /// The ids of the stored games `policy` would remove at `now`. Files that
+    /// don't decode are left alone.
+    pub fn collectable(&self, policy: &RetentionPolicy, now: u64) -> io::Result<Vec<u32>> {
+        let mut ids = Vec::new();
+        for path in self.paths_with_extension("chain")? {
+            let chain = match read_chain(&path) {
+                Ok(chain) => chain,
+                Err(_) => continue,
+            };
+            if !policy.keeps(&chain, last_activity(&chain, &path)?, now) {
+                ids.push(chain.id());
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Removes the stored games `policy` doesn't keep at `now`, returning
+    /// their ids.
+    pub fn gc(&self, policy: &RetentionPolicy, now: u64) -> io::Result<Vec<u32>> {
+        let ids = self.collectable(policy, now)?;
+        for &id in &ids {
+            self.remove(id)?;
+        }
+        Ok(ids)
+    }
+
+    pub fn remove(&self, id: u32) -> io::Result<()> {
+        match fs::remove_file(self.heartbeats_path(id)) {
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+            _ => {}
+        }
+        fs::remove_file(self.chain_path(id))
+    }
+
+    /// Keeps `heartbeat` next to the stored game with the given id, replacing
+    /// any older heartbeat from the same player. Heartbeats that aren't
+    /// signed by one of the game's players are refused.
+    pub fn save_heartbeat(&self, id: u32, heartbeat: &Heartbeat) -> io::Result<()> {
+        let chain = self.load(id)?;
+        if heartbeat.signer(&chain).is_none() {
+            return Err(invalid_data(Error::VerificationFailed));
+        }
+        let mut heartbeats = self.heartbeats(id)?;
+        match heartbeats
+            .iter_mut()
+            .find(|kept| kept.public_key() == heartbeat.public_key())
+        {
+            Some(kept) if kept.timestamp() < heartbeat.timestamp() => *kept = heartbeat.clone(),
+            Some(_) => return Ok(()),
+            None => heartbeats.push(heartbeat.clone()),
+        }
+        let bytes: Vec<u8> = heartbeats.iter().flat_map(Heartbeat::as_bytes).collect();
+        fs::write(self.heartbeats_path(id), bytes)
+    }
+
+    /// The heartbeats kept for the game with the given id.
+    pub fn heartbeats(&self, id: u32) -> io::Result<Vec<Heartbeat>> {
+        let bytes = match fs::read(self.heartbeats_path(id)) {
+            Ok(bytes) => bytes,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error),
+        };
+        Ok(bytes
+            .chunks(HEARTBEAT_LEN)
+            .filter_map(|bytes| Heartbeat::from_bytes(bytes).ok())
+            .collect())
+    }
+}
+
+/// When the game last changed, in seconds since the Unix epoch: the latest
+/// receipt if there is one, or else when its file was written.
+fn last_activity(chain: &GameChain, path: &Path) -> io::Result<Option<u64>> {
+    let written = fs::metadata(path)?
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs());
+    Ok(chain
+        .receipts()
+        .last()
+        .map(|receipt| receipt.timestamp)
+        .or(written))
+}
+
+fn invalid_data(error: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error.to_string())
+}
+
+pub(crate) fn read_chain(path: &Path) -> io::Result<GameChain> {
+    GameChain::from_bytes(&fs::read(path)?).map_err(invalid_data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlockBuilder;
+    use crate::key::PublicKey;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    #[test]
+    fn summarize_stored_games() {
+        let dir = std::env::temp_dir().join(format!("lineage-store-{}", std::process::id()));
+        let store = GameStore::open(&dir).unwrap();
+        let rng = crypto::new_rng();
+        let me = crypto::generate_key(&rng);
+        let friend = crypto::generate_key(&rng);
+        let stranger = crypto::generate_key(&rng);
+
+        for (id, opponent) in [(1, &friend), (2, &stranger)].iter() {
+            let challenge =
+                ChallengeBlockBuilder::new(&PublicKey::of(&me), &PublicKey::of(opponent))
+                    .id(*id)
+                    .build()
+                    .unwrap();
+            let mut chain = GameChain::new(challenge);
+            chain.accept(&me).unwrap();
+            chain.accept(opponent).unwrap();
+            store.save(&chain).unwrap();
+        }
+        store
+            .save_profile(&Profile::new(&friend, "Friend", "", None).unwrap())
+            .unwrap();
+        fs::write(dir.join("junk.chain"), b"junk").unwrap();
+
+        let mut contacts = AddressBook::new();
+        let summaries = store
+            .summaries(me.public_key().as_ref(), &contacts)
+            .unwrap();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].id, 1);
+        assert_eq!(summaries[0].opponent, "Friend");
+        assert_eq!(
+            summaries[1].opponent,
+            crypto::fingerprint(stranger.public_key().as_ref())
+        );
+        assert!(summaries.iter().all(|summary| summary.my_turn));
+
+        // petnames win over profile names
+        contacts.add("pal", friend.public_key().as_ref()).unwrap();
+        let summaries = store
+            .summaries(me.public_key().as_ref(), &contacts)
+            .unwrap();
+        assert_eq!(summaries[0].opponent, "pal");
+        assert!(store
+            .summaries(stranger.public_key().as_ref(), &contacts)
+            .unwrap()
+            .iter()
+            .all(|summary| !summary.my_turn));
+        assert_eq!(store.load(1).unwrap().id(), 1);
+
+        let mut longer = store.load(1).unwrap();
+        let mv = crate::notation::parse_uci("e2e4").unwrap();
+        longer
+            .make_move_block(&me, chess::Action::MakeMove(mv))
+            .unwrap();
+        assert!(store.merge(&longer).unwrap());
+        assert_eq!(store.load(1).unwrap().move_count(), 1);
+        assert!(!store.merge(&longer).unwrap());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn collect_old_games() {
+        let dir = std::env::temp_dir().join(format!("lineage-gc-{}", std::process::id()));
+        let store = GameStore::open(&dir).unwrap();
+        let rng = crypto::new_rng();
+        let me = crypto::generate_key(&rng);
+        let friend = crypto::generate_key(&rng);
+        let stranger = crypto::generate_key(&rng);
+        let arbiter = crypto::generate_key(&rng);
+        let play = |chain: &mut GameChain,
+                    white: &Ed25519KeyPair,
+                    black: &Ed25519KeyPair,
+                    moves: &[&str]| {
+            chain.accept(white).unwrap();
+            chain.accept(black).unwrap();
+            for (i, uci) in moves.iter().enumerate() {
+                let key_pair = if i % 2 == 0 { white } else { black };
+                let mv = crate::notation::parse_uci(uci).unwrap();
+                chain
+                    .make_move_block(key_pair, chess::Action::MakeMove(mv))
+                    .unwrap();
+            }
+        };
+        let new_chain = |id, white: &Ed25519KeyPair, black: &Ed25519KeyPair| {
+            GameChain::new(
+                ChallengeBlockBuilder::new(&PublicKey::of(white), &PublicKey::of(black))
+                    .id(id)
+                    .timestamp(1_000)
+                    .time_control(60, 0)
+                    .arbiter(&PublicKey::of(&arbiter))
+                    .build()
+                    .unwrap(),
+            )
+        };
+
+        let mut finished = new_chain(1, &me, &friend);
+        play(
+            &mut finished,
+            &me,
+            &friend,
+            &["f2f3", "e7e5", "g2g4", "d8h4"],
+        );
+        let mut playing = new_chain(2, &me, &friend);
+        play(&mut playing, &me, &friend, &["e2e4"]);
+        let mut others = new_chain(3, &friend, &stranger);
+        play(&mut others, &friend, &stranger, &[]);
+        let mut disputed = new_chain(4, &me, &friend);
+        play(&mut disputed, &me, &friend, &["e2e4"]);
+        disputed.acknowledge_move(&friend, 1_000).unwrap();
+        disputed.claim_time_forfeit(&me, 1_100).unwrap();
+        disputed
+            .dispute_result(&friend, "my clock was wrong")
+            .unwrap();
+        for chain in &[&finished, &playing, &others, &disputed] {
+            store.save(chain).unwrap();
+        }
+        store
+            .save_heartbeat(1, &finished.heartbeat(&me, 5).unwrap())
+            .unwrap();
+        store
+            .save_heartbeat(1, &finished.heartbeat(&me, 3).unwrap())
+            .unwrap();
+        assert!(store
+            .save_heartbeat(1, &others.heartbeat(&friend, 5).unwrap())
+            .is_err());
+        let heartbeats = store.heartbeats(1).unwrap();
+        assert_eq!(heartbeats.len(), 1);
+        assert_eq!(heartbeats[0].timestamp(), 5);
+
+        let now = std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let day = 24 * 60 * 60;
+        let policy = RetentionPolicy::new()
+            .keep_finished_for(day)
+            .only_involving(me.public_key().as_ref());
+        assert_eq!(store.collectable(&policy, now).unwrap(), [3]);
+        assert_eq!(store.gc(&policy, now + 2 * day).unwrap(), [1, 3]);
+        let kept: Vec<u32> = store
+            .load_all()
+            .unwrap()
+            .iter()
+            .map(GameChain::id)
+            .collect();
+        assert_eq!(kept, [2, 4]);
+        assert!(store.heartbeats(1).unwrap().is_empty());
+        assert!(store
+            .collectable(&RetentionPolicy::new(), now)
+            .unwrap()
+            .is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,201 @@
+//! Takebacks. Either player may sign a request to take back the last few
+//! moves; once the opponent signs a matching accept, the game continues from
+//! the earlier position. The taken-back move blocks stay in the chain, so the
+//! full history can still be audited.
+
+use crate::block::GameChain;
+use crate::checkpoint::Checkpoint;
+use crate::error::Error;
+use crate::extension::ExtensionKind;
+
+use alloc::vec;
+use chess::Color;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+fn color_from_byte(byte: u8) -> Option<Color> {
+    match byte {
+        0 => Some(Color::White),
+        1 => Some(Color::Black),
+        _ => None,
+    }
+}
+
+fn color_to_byte(color: Color) -> u8 {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+/// The player who signed a takeback request or accept, given its body: the
+/// requester's color followed by the number of moves to take back.
+pub(crate) fn takeback_signer(kind: ExtensionKind, body: &[u8]) -> Option<Color> {
+    if body.len() != 2 {
+        return None;
+    }
+    let requester = color_from_byte(body[0])?;
+    match kind {
+        ExtensionKind::TakebackRequest => Some(requester),
+        ExtensionKind::TakebackAccept => Some(!requester),
+        _ => None,
+    }
+}
+
+/// Follows the length of the game line as blocks are read in encoded order:
+/// moves extend it and accepted takebacks shorten it, though never past the
+/// last checkpoint.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Line {
+    len: usize,
+    floor: usize,
+    open_request: Option<[u8; 2]>,
+}
+
+impl Line {
+    /// A line starting after `len` moves, for chains pruned at a checkpoint.
+    pub(crate) fn at(len: usize) -> Line {
+        Line {
+            len,
+            floor: len,
+            open_request: None,
+        }
+    }
+
+    /// The number of moves in the line, which decides whose turn it is.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Extends the line. A move also withdraws any open takeback request.
+    pub(crate) fn push_move(&mut self) {
+        self.len += 1;
+        self.open_request = None;
+    }
+
+    /// Applies an extension block, failing if it is a takeback that doesn't
+    /// fit the line so far.
+    pub(crate) fn push_extension(&mut self, kind: ExtensionKind, body: &[u8]) -> Result<(), Error> {
+        let malformed = |reason| Error::Malformed {
+            block: "takeback",
+            reason,
+        };
+        match kind {
+            ExtensionKind::TakebackRequest => {
+                if takeback_signer(kind, body).is_none() {
+                    return Err(malformed("wrong body"));
+                }
+                if body[1] == 0 || body[1] as usize > self.len - self.floor {
+                    return Err(Error::InvalidTakeback);
+                }
+                self.open_request = Some([body[0], body[1]]);
+            }
+            ExtensionKind::TakebackAccept => {
+                if self.open_request.as_ref().map(|request| &request[..]) != Some(body) {
+                    return Err(Error::InvalidTakeback);
+                }
+                self.len -= body[1] as usize;
+                self.open_request = None;
+            }
+            ExtensionKind::Checkpoint => {
+                let checkpoint = Checkpoint::from_body(body)?;
+                if checkpoint.move_count != self.len || self.open_request.is_some() {
+                    return Err(Error::Malformed {
+                        block: "checkpoint",
+                        reason: "doesn't fit the game line",
+                    });
+                }
+                self.floor = self.len;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl GameChain {
+    /// Asks the opponent to take back the last `count` moves.
+    pub fn request_takeback(&mut self, key_pair: &Ed25519KeyPair, count: u8) -> Result<(), Error> {
+        let color = self
+            .side_of(key_pair.public_key().as_ref())
+            .ok_or(Error::KeyNotInChallenge)?;
+        self.sign_extension(
+            key_pair,
+            ExtensionKind::TakebackRequest,
+            vec![color_to_byte(color), count],
+        )
+    }
+
+    /// Agrees to the opponent's open takeback request.
+    pub fn accept_takeback(&mut self, key_pair: &Ed25519KeyPair) -> Result<(), Error> {
+        let request = self
+            .pending_extensions()
+            .filter(|block| {
+                block.kind() == ExtensionKind::TakebackRequest
+                    || block.kind() == ExtensionKind::TakebackAccept
+            })
+            .last()
+            .filter(|block| block.kind() == ExtensionKind::TakebackRequest)
+            .ok_or(Error::InvalidTakeback)?
+            .body()
+            .to_vec();
+        self.sign_extension(key_pair, ExtensionKind::TakebackAccept, request)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlock;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use chess::{Action, ChessMove, Square};
+
+    fn uci(mv: &str) -> Action {
+        Action::MakeMove(ChessMove::new(
+            mv[..2].parse::<Square>().unwrap(),
+            mv[2..4].parse::<Square>().unwrap(),
+            None,
+        ))
+    }
+
+    #[test]
+    fn take_back_moves() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        chain.make_move_block(&white, uci("e2e4")).unwrap();
+        chain.make_move_block(&black, uci("e7e5")).unwrap();
+
+        assert_eq!(chain.accept_takeback(&white), Err(Error::InvalidTakeback));
+        assert_eq!(
+            chain.request_takeback(&black, 3),
+            Err(Error::InvalidTakeback)
+        );
+        chain.request_takeback(&black, 1).unwrap();
+        assert_eq!(chain.accept_takeback(&black), Err(Error::NotYourTurn));
+        chain.accept_takeback(&white).unwrap();
+
+        // black is to move again, from the position after 1. e4
+        assert!(chain.is_my_turn(black.public_key().as_ref()));
+        chain.make_move_block(&black, uci("c7c5")).unwrap();
+        chain.make_move_block(&white, uci("g1f3")).unwrap();
+        assert_eq!(chain.move_count(), 4);
+        let line: Vec<_> = chain
+            .iter_positions()
+            .map(|(_, mv, _, _)| mv.to_string())
+            .collect();
+        assert_eq!(line, vec!["e2e4", "c7c5", "g1f3"]);
+
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain.clone()));
+
+        // a request is withdrawn once a move is made
+        chain.request_takeback(&black, 1).unwrap();
+        chain.make_move_block(&black, uci("b8c6")).unwrap();
+        assert_eq!(chain.accept_takeback(&white), Err(Error::InvalidTakeback));
+    }
+}
@@ -0,0 +1,184 @@
+//! Interop test vectors. Each vector is an encoded chain built from fixed
+//! keys, given in hex so other implementations can embed it as is, along
+//! with the field values it should decode to. Ed25519 signatures are
+//! deterministic, so an implementation that signs the same blocks with the
+//! same seeds must reproduce the bytes exactly; check_vectors does that for
+//! this crate.
+//!
+//! The keys are Ed25519 seeds of 32 0x01 bytes for white and 32 0x02 bytes
+//! for black. The challenge is version 2 on the main network, with id
+//! 0x01020304, issued at 1600000000, five minutes plus five seconds a move.
+//! The full game is the fool's mate, 1. f3 e5 2. g4 Qh4#.
+
+use crate::block::{ChallengeBlock, ChallengeBlockBuilder, GameChain, GameResult};
+use crate::error::Error;
+use crate::key::PublicKey;
+use crate::notation;
+
+use alloc::vec::Vec;
+use chess::{Action, Color};
+use ring::signature::Ed25519KeyPair;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TestVector {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub hex: &'static str,
+}
+
+impl TestVector {
+    pub fn bytes(&self) -> Vec<u8> {
+        from_hex(self.hex)
+    }
+}
+
+pub const WHITE_SEED: [u8; 32] = [0x01; 32];
+pub const BLACK_SEED: [u8; 32] = [0x02; 32];
+
+pub const WHITE_PUBLIC_KEY_HEX: &str =
+    "8a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf3748801b40f6f5c";
+pub const BLACK_PUBLIC_KEY_HEX: &str =
+    "8139770ea87d175f56a35466c34c7ecccb8d8a91b4ee37a25df60f5b8fc9b394";
+
+pub const CHALLENGE_ID: u32 = 0x0102_0304;
+pub const CHALLENGE_TIMESTAMP: u64 = 1_600_000_000;
+pub const BASE_SECONDS: u32 = 300;
+pub const INCREMENT_SECONDS: u32 = 5;
+
+/// The SHA-256 of the encoded challenge, which every signature covers.
+pub const CANONICAL_ID_HEX: &str =
+    "c61ec3d115c0740affb7dc213496943a14d34729ff9ce4d1084b651858fe41ee";
+
+/// The full game's moves, in UCI, and its result.
+pub const MOVES: [&str; 4] = ["f2f3", "e7e5", "g2g4", "d8h4"];
+pub const RESULT: GameResult = GameResult::Win(Color::Black);
+
+pub const CHALLENGE: TestVector = TestVector {
+    name: "challenge",
+    description: "the challenge on its own",
+    hex: concat!(
+        "0200010203048a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf374",
+        "8801b40f6f5c8139770ea87d175f56a35466c34c7ecccb8d8a91b4ee37a25df6",
+        "0f5b8fc9b39400000000000000005f5e1000000000012c000000050000",
+    ),
+};
+
+pub const ACCEPTED_CHAIN: TestVector = TestVector {
+    name: "accepted chain",
+    description: "the challenge accepted by white, then black",
+    hex: concat!(
+        "0200010203048a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf374",
+        "8801b40f6f5c8139770ea87d175f56a35466c34c7ecccb8d8a91b4ee37a25df6",
+        "0f5b8fc9b39400000000000000005f5e1000000000012c00000005000086e116",
+        "f067fbb15307560702e58af72a41e5aba27b3708d564c3d1e6f69834dd2766c9",
+        "eaa091b5ec8cecb91ae1d899b04af292773aac59fb2fb147a19f11e20438dae0",
+        "80751151f7021cfcbdedcdac5f7c308cad129ebcd5ba8f78cb90adf9671bca4d",
+        "bd4efcecc23d289cf32ef34c61538331c715331d0aa119c3893deb6e06",
+    ),
+};
+
+pub const FULL_GAME: TestVector = TestVector {
+    name: "full game",
+    description: "the accepted chain followed by the four moves",
+    hex: concat!(
+        "0200010203048a88e3dd7409f195fd52db2d3cba5d72ca6709bf1d94121bf374",
+        "8801b40f6f5c8139770ea87d175f56a35466c34c7ecccb8d8a91b4ee37a25df6",
+        "0f5b8fc9b39400000000000000005f5e1000000000012c00000005000086e116",
+        "f067fbb15307560702e58af72a41e5aba27b3708d564c3d1e6f69834dd2766c9",
+        "eaa091b5ec8cecb91ae1d899b04af292773aac59fb2fb147a19f11e20438dae0",
+        "80751151f7021cfcbdedcdac5f7c308cad129ebcd5ba8f78cb90adf9671bca4d",
+        "bd4efcecc23d289cf32ef34c61538331c715331d0aa119c3893deb6e060d1589",
+        "0b8da878ccf58dd0dd7a6248c2328264de8b00e76271aebdd470faddb4b54cc3",
+        "806697e5e02adfe4ee1ee5b47198db2b6727f50ba77852ac1990113673f20834",
+        "2400d4d6e25e2dbec418877b428d3aaf354677c7b0bdff1a1d3f22118fb10f73",
+        "380c6decdf526062dc17dad66a65540414b06a88409fb837fe185528b2421ac6",
+        "080e1ede549de426b0b115b3650c09499174bc6ed7e65a9398cdc6a351be6b8d",
+        "3d41496ea5e5bb62ce3db39c6ee83032d2e4de62813c1e0191c277aaa834e355",
+        "80c80b3b1f2ee4da5ae3185cbd12e7321bf24cd05d8abae9c69265710c1c4229",
+        "ac073dcfd40dfed09536724bc7e147d6a9a51f2a01d266a1d9fa674519a60a25",
+        "a6de50450c",
+    ),
+};
+
+pub const VECTORS: [TestVector; 3] = [CHALLENGE, ACCEPTED_CHAIN, FULL_GAME];
+
+/// Decodes a hex string, as used by the vectors. Panics on invalid hex.
+pub fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("invalid hex"))
+        .collect()
+}
+
+/// The key pair for one of the fixed seeds.
+pub fn key_pair(seed: &[u8; 32]) -> Ed25519KeyPair {
+    Ed25519KeyPair::from_seed_unchecked(seed).unwrap()
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "test vector",
+        reason,
+    }
+}
+
+/// Builds every vector from the fixed keys, failing if this crate doesn't
+/// reproduce the embedded bytes or decode them to the expected fields.
+pub fn check_vectors() -> Result<(), Error> {
+    let white = key_pair(&WHITE_SEED);
+    let black = key_pair(&BLACK_SEED);
+    let (white_public_key, black_public_key) = (
+        PublicKey::from_slice(&from_hex(WHITE_PUBLIC_KEY_HEX))?,
+        PublicKey::from_slice(&from_hex(BLACK_PUBLIC_KEY_HEX))?,
+    );
+    let challenge = ChallengeBlockBuilder::new(&white_public_key, &black_public_key)
+        .id(CHALLENGE_ID)
+        .timestamp(CHALLENGE_TIMESTAMP)
+        .time_control(BASE_SECONDS, INCREMENT_SECONDS)
+        .build()?;
+    if challenge.as_bytes() != CHALLENGE.bytes() {
+        return Err(malformed("challenge bytes differ"));
+    }
+    if challenge.canonical_id()[..] != from_hex(CANONICAL_ID_HEX)[..] {
+        return Err(malformed("canonical id differs"));
+    }
+    let decoded = ChallengeBlock::from_bytes(&CHALLENGE.bytes())?;
+    if decoded != challenge {
+        return Err(malformed("challenge fields differ"));
+    }
+
+    let mut chain = GameChain::new(challenge);
+    chain.accept(&white)?;
+    chain.accept(&black)?;
+    if chain.as_bytes() != ACCEPTED_CHAIN.bytes() {
+        return Err(malformed("accepted chain bytes differ"));
+    }
+    for (i, uci) in MOVES.iter().enumerate() {
+        let key_pair = if i % 2 == 0 { &white } else { &black };
+        let mv = notation::parse_uci(uci).ok_or_else(|| malformed("bad move"))?;
+        chain.make_move_block(key_pair, Action::MakeMove(mv))?;
+    }
+    if chain.as_bytes() != FULL_GAME.bytes() {
+        return Err(malformed("full game bytes differ"));
+    }
+    let decoded = GameChain::from_bytes(&FULL_GAME.bytes())?;
+    if !decoded.verify() || decoded.result() != Some(RESULT) {
+        return Err(malformed("full game doesn't decode to its result"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn vectors_reproduce() {
+        assert_eq!(check_vectors(), Ok(()));
+        for vector in VECTORS.iter() {
+            assert_eq!(vector.hex.len() % 2, 0, "{}", vector.name);
+        }
+        assert_eq!(ACCEPTED_CHAIN.bytes().len(), 93 + 2 * 64);
+        assert_eq!(FULL_GAME.bytes().len(), 93 + 2 * 64 + 4 * 66);
+    }
+}
@@ -0,0 +1,205 @@
+//! Random chains for tests and fuzzers. Each generated chain uses fresh keys
+//! and a random legal game, and can be corrupted in ways a verifier must
+//! catch.
+
+use crate::block::{ChallengeBlock, GameChain};
+use crate::crypto::{self, Rng, SeededRng};
+use crate::key::PublicKey;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use chess::{Action, BoardStatus, MoveGen};
+use ring::signature::Ed25519KeyPair;
+
+/// Ways to break a chain's bytes so that GameChain::from_bytes rejects them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Corruption {
+    /// One bit of the last move's signature is flipped.
+    FlippedSignature,
+    /// The last move is signed over the right bytes, but by a key outside
+    /// the challenge.
+    WrongSigner,
+    /// A correctly signed move that isn't legal is appended.
+    IllegalMove,
+}
+
+pub struct ChainGenerator {
+    rng: Box<dyn Rng>,
+    plies: usize,
+}
+
+impl Default for ChainGenerator {
+    fn default() -> ChainGenerator {
+        ChainGenerator::new()
+    }
+}
+
+impl ChainGenerator {
+    /// A generator for accepted games of 40 plies.
+    pub fn new() -> ChainGenerator {
+        ChainGenerator {
+            rng: Box::new(crypto::new_rng()),
+            plies: 40,
+        }
+    }
+
+    /// A generator whose keys and games are fixed by `seed`, for reproducible
+    /// runs.
+    pub fn seeded(seed: u64) -> ChainGenerator {
+        ChainGenerator {
+            rng: Box::new(SeededRng::new(seed)),
+            plies: 40,
+        }
+    }
+
+    /// Sets the number of moves to play. Games that end in checkmate or
+    /// stalemate first are shorter.
+    pub fn plies(mut self, plies: usize) -> ChainGenerator {
+        self.plies = plies;
+        self
+    }
+
+    fn random_index(&self, len: usize) -> usize {
+        let mut bytes = [0; 4];
+        self.rng.fill_bytes(&mut bytes);
+        u32::from_be_bytes(bytes) as usize % len
+    }
+
+    fn generate_with_keys(&self, plies: usize) -> (GameChain, [Ed25519KeyPair; 2]) {
+        let white = crypto::generate_key(&*self.rng);
+        let black = crypto::generate_key(&*self.rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        for ply in 0..plies {
+            let board = chain.current_position().unwrap();
+            if board.status() != BoardStatus::Ongoing {
+                break;
+            }
+            let moves: Vec<_> = MoveGen::new_legal(&board).collect();
+            let mv = moves[self.random_index(moves.len())];
+            let key_pair = if ply % 2 == 0 { &white } else { &black };
+            chain
+                .make_move_block(key_pair, Action::MakeMove(mv))
+                .unwrap();
+        }
+        (chain, [white, black])
+    }
+
+    /// A random valid chain.
+    pub fn generate(&self) -> GameChain {
+        self.generate_with_keys(self.plies).0
+    }
+
+    /// The bytes of a random chain with at least one move, broken as
+    /// described by `corruption`.
+    pub fn corrupted(&self, corruption: Corruption) -> Vec<u8> {
+        let (chain, keys) = self.generate_with_keys(self.plies.max(1));
+        let mut bytes = chain.as_bytes();
+        match corruption {
+            Corruption::FlippedSignature => {
+                let last = bytes.len() - 1;
+                bytes[last] ^= 1 << self.random_index(8);
+            }
+            Corruption::WrongSigner => {
+                let stranger = crypto::generate_key(&*self.rng);
+                let shorter = chain.truncated(chain.move_count() - 1);
+                let start = bytes.len() - 66;
+                let mut message = shorter.signing_prefix();
+                message.extend(&bytes[start..start + 2]);
+                let signature = crypto::sign(&stranger, &message);
+                bytes[start + 2..].copy_from_slice(&signature);
+            }
+            Corruption::IllegalMove => {
+                let signer = chain.signer_for_next_move().unwrap();
+                let key_pair = keys
+                    .iter()
+                    .find(|key_pair| PublicKey::of(key_pair) == signer)
+                    .unwrap();
+                // a move from a square to itself is never legal
+                let mut message = chain.signing_prefix();
+                message.extend(&[0, 0]);
+                bytes.extend(&[0, 0]);
+                bytes.extend(crypto::sign(key_pair, &message));
+            }
+        }
+        bytes
+    }
+}
+
+/// Returns `chain_bytes` with one bit of the byte at `index` flipped,
+/// panicking if the result still decodes to a verified chain. Every byte of a
+/// chain without commentary is covered by a signature or by the encoding, so
+/// this holds for any index.
+pub fn corrupt_at(chain_bytes: &[u8], index: usize) -> Vec<u8> {
+    let mut bytes = chain_bytes.to_vec();
+    bytes[index] ^= 1;
+    if let Ok(chain) = GameChain::from_bytes(&bytes) {
+        assert!(
+            !chain.verify(),
+            "flipping byte {} left a valid chain",
+            index
+        );
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    #[test]
+    fn generate_chains() {
+        let generator = ChainGenerator::new().plies(30);
+        let chain = generator.generate();
+        assert!(chain.move_count() <= 30);
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain));
+
+        for &corruption in &[
+            Corruption::FlippedSignature,
+            Corruption::WrongSigner,
+            Corruption::IllegalMove,
+        ] {
+            let bytes = generator.corrupted(corruption);
+            assert!(GameChain::from_bytes(&bytes).is_err(), "{:?}", corruption);
+        }
+    }
+
+    #[test]
+    fn seeded_generators_agree() {
+        let chain = ChainGenerator::seeded(3).plies(20).generate();
+        assert_eq!(chain, ChainGenerator::seeded(3).plies(20).generate());
+        assert_ne!(chain, ChainGenerator::seeded(4).plies(20).generate());
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn round_trip(plies in 0usize..40) {
+            let chain = ChainGenerator::new().plies(plies).generate();
+            prop_assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain));
+        }
+
+        // a prefix either ends on a block boundary and decodes to exactly
+        // those bytes, or is rejected
+        #[test]
+        fn prefixes_are_exact(plies in 0usize..20, cut in any::<Index>()) {
+            let bytes = ChainGenerator::new().plies(plies).generate().as_bytes();
+            let prefix = &bytes[..cut.index(bytes.len())];
+            if let Ok(chain) = GameChain::from_bytes(prefix) {
+                prop_assert_eq!(chain.as_bytes(), prefix);
+            }
+        }
+
+        #[test]
+        fn flipped_bytes_fail(plies in 1usize..20, index in any::<Index>()) {
+            let bytes = ChainGenerator::new().plies(plies).generate().as_bytes();
+            corrupt_at(&bytes, index.index(bytes.len()));
+        }
+    }
+}
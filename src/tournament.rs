@@ -0,0 +1,522 @@
+use crate::block::{GameChain, GameResult};
+use crate::crypto;
+use crate::error::Error;
+use crate::key::PublicKey;
+use crate::network::Network;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use chess::Color;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TournamentFormat {
+    RoundRobin,
+    Swiss,
+    Knockout,
+    Arena,
+}
+
+impl TournamentFormat {
+    fn from_byte(byte: u8) -> Option<TournamentFormat> {
+        match byte {
+            0 => Some(TournamentFormat::RoundRobin),
+            1 => Some(TournamentFormat::Swiss),
+            2 => Some(TournamentFormat::Knockout),
+            3 => Some(TournamentFormat::Arena),
+            _ => None,
+        }
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            TournamentFormat::RoundRobin => 0,
+            TournamentFormat::Swiss => 1,
+            TournamentFormat::Knockout => 2,
+            TournamentFormat::Arena => 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TournamentHeader {
+    version: u8,
+    network_id: u8,
+    id: u32,
+    organizer_public_key: [u8; 32],
+    format: TournamentFormat,
+    rounds: u8,
+    participants: Vec<[u8; 32]>,
+}
+
+impl TournamentHeader {
+    pub fn new(
+        organizer_public_key: &[u8],
+        id: u32,
+        format: TournamentFormat,
+        rounds: u8,
+        participants: &[[u8; 32]],
+    ) -> TournamentHeader {
+        let mut organizer_bytes = [0; 32];
+        organizer_bytes.copy_from_slice(organizer_public_key);
+        TournamentHeader {
+            version: 0,
+            network_id: 0,
+            id,
+            organizer_public_key: organizer_bytes,
+            format,
+            rounds,
+            participants: participants.to_vec(),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<TournamentHeader, Error> {
+        let malformed = |reason| Error::Malformed {
+            block: "tournament header",
+            reason,
+        };
+        if bytes.len() < 41 {
+            return Err(malformed("not enough bytes"));
+        }
+        let count = bytes[40] as usize;
+        if bytes.len() < 41 + count * 32 {
+            return Err(malformed("not enough bytes"));
+        }
+        let mut id_bytes = [0; 4];
+        id_bytes.copy_from_slice(&bytes[2..6]);
+        let mut organizer_public_key = [0; 32];
+        organizer_public_key.copy_from_slice(&bytes[6..38]);
+        let format = TournamentFormat::from_byte(bytes[38]).ok_or(malformed("unknown format"))?;
+        if Network::from_id(bytes[1]).is_none() {
+            return Err(malformed("unknown network"));
+        }
+        let participants = bytes[41..41 + count * 32]
+            .chunks(32)
+            .map(|chunk| {
+                let mut key = [0; 32];
+                key.copy_from_slice(chunk);
+                key
+            })
+            .collect();
+        Ok(TournamentHeader {
+            version: bytes[0],
+            network_id: bytes[1],
+            id: u32::from_be_bytes(id_bytes),
+            organizer_public_key,
+            format,
+            rounds: bytes[39],
+            participants,
+        })
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.encoded_len());
+        self.write_bytes(&mut bytes);
+        bytes
+    }
+
+    pub fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.push(self.version);
+        bytes.push(self.network_id);
+        bytes.extend(&self.id.to_be_bytes());
+        bytes.extend(&self.organizer_public_key);
+        bytes.push(self.format.to_byte());
+        bytes.push(self.rounds);
+        bytes.push(self.participants.len() as u8);
+        for participant in &self.participants {
+            bytes.extend(participant);
+        }
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        41 + self.participants.len() * 32
+    }
+
+    /// Moves the tournament to another network; its games must be on the
+    /// same one.
+    pub fn network(mut self, network: Network) -> TournamentHeader {
+        self.network_id = network.id();
+        self
+    }
+
+    pub fn network_id(&self) -> u8 {
+        self.network_id
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn organizer_public_key(&self) -> &[u8; 32] {
+        &self.organizer_public_key
+    }
+
+    pub fn format(&self) -> TournamentFormat {
+        self.format
+    }
+
+    pub fn rounds(&self) -> u8 {
+        self.rounds
+    }
+
+    pub fn participants(&self) -> &[[u8; 32]] {
+        &self.participants
+    }
+}
+
+/// Links one round's games to the tournament by chain id. The organizer signs
+/// the whole tournament chain up to and including the round's chain ids.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoundBlock {
+    chain_ids: Vec<u32>,
+    signature: Vec<u8>,
+}
+
+impl RoundBlock {
+    pub fn from_bytes(bytes: &[u8]) -> Result<RoundBlock, Error> {
+        let malformed = Error::Malformed {
+            block: "round",
+            reason: "not enough bytes",
+        };
+        if bytes.len() < 2 {
+            return Err(malformed);
+        }
+        let count = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+        let len = 2 + count * 4 + 64;
+        if bytes.len() < len {
+            return Err(malformed);
+        }
+        let chain_ids = bytes[2..2 + count * 4]
+            .chunks(4)
+            .map(|chunk| u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        Ok(RoundBlock {
+            chain_ids,
+            signature: bytes[len - 64..len].to_vec(),
+        })
+    }
+
+    fn write_unsigned_bytes(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(&(self.chain_ids.len() as u16).to_be_bytes());
+        for chain_id in &self.chain_ids {
+            bytes.extend(&chain_id.to_be_bytes());
+        }
+    }
+
+    pub fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        self.write_unsigned_bytes(bytes);
+        bytes.extend(&self.signature);
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        2 + self.chain_ids.len() * 4 + 64
+    }
+
+    pub fn chain_ids(&self) -> &[u32] {
+        &self.chain_ids
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TournamentChain {
+    header: TournamentHeader,
+    signature: Vec<u8>,
+    rounds: Vec<RoundBlock>,
+}
+
+/// Results between every pair of participants, in half-points so draws stay
+/// exact.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrossTable {
+    /// Participant public keys, in the order they appear in the header.
+    pub players: Vec<[u8; 32]>,
+    /// `half_points[i][j]` is what player i scored against player j.
+    pub half_points: Vec<Vec<u32>>,
+    /// `games[i][j]` is the number of finished games between i and j.
+    pub games: Vec<Vec<u32>>,
+}
+
+impl CrossTable {
+    pub fn total_half_points(&self, player: usize) -> u32 {
+        self.half_points[player].iter().sum()
+    }
+
+    /// Participant indices sorted by score, best first.
+    pub fn standings(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.players.len()).collect();
+        order.sort_by_key(|&player| core::cmp::Reverse(self.total_half_points(player)));
+        order
+    }
+}
+
+impl TournamentChain {
+    /// Starts a tournament. The key must be the organizer's key in the header.
+    pub fn new(
+        header: TournamentHeader,
+        key_pair: &Ed25519KeyPair,
+    ) -> Result<TournamentChain, Error> {
+        if key_pair.public_key().as_ref() != header.organizer_public_key {
+            return Err(Error::NotOrganizer);
+        }
+        let signature = crypto::sign(key_pair, &header.as_bytes());
+        Ok(TournamentChain {
+            header,
+            signature,
+            rounds: Vec::new(),
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<TournamentChain, Error> {
+        let header = TournamentHeader::from_bytes(bytes)?;
+        let mut offset = header.encoded_len();
+        if bytes.len() < offset + 64 {
+            return Err(Error::Malformed {
+                block: "tournament header",
+                reason: "not enough bytes",
+            });
+        }
+        let signature = bytes[offset..offset + 64].to_vec();
+        offset += 64;
+        let mut chain = TournamentChain {
+            header,
+            signature,
+            rounds: Vec::new(),
+        };
+        while offset < bytes.len() {
+            let round = RoundBlock::from_bytes(&bytes[offset..])?;
+            offset += round.encoded_len();
+            chain.rounds.push(round);
+        }
+        if !chain.verify() {
+            return Err(Error::VerificationFailed);
+        }
+        Ok(chain)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.write_bytes(&mut bytes);
+        bytes
+    }
+
+    pub fn write_bytes(&self, bytes: &mut Vec<u8>) {
+        self.header.write_bytes(bytes);
+        bytes.extend(&self.signature);
+        for round in &self.rounds {
+            round.write_bytes(bytes);
+        }
+    }
+
+    pub fn header(&self) -> &TournamentHeader {
+        &self.header
+    }
+
+    pub fn rounds(&self) -> &[RoundBlock] {
+        &self.rounds
+    }
+
+    /// Links the next round's games, signed by the organizer.
+    pub fn add_round(&mut self, key_pair: &Ed25519KeyPair, chain_ids: &[u32]) -> Result<(), Error> {
+        if key_pair.public_key().as_ref() != self.header.organizer_public_key {
+            return Err(Error::NotOrganizer);
+        }
+        if self.rounds.len() >= self.header.rounds as usize {
+            return Err(Error::TournamentFull);
+        }
+        let mut round = RoundBlock {
+            chain_ids: chain_ids.to_vec(),
+            signature: Vec::new(),
+        };
+        let mut bytes = self.as_bytes();
+        round.write_unsigned_bytes(&mut bytes);
+        round.signature = crypto::sign(key_pair, &bytes);
+        self.rounds.push(round);
+        Ok(())
+    }
+
+    pub fn verify(&self) -> bool {
+        let organizer = &self.header.organizer_public_key;
+        let mut bytes = self.header.as_bytes();
+        if !crypto::verify(organizer, &bytes, &self.signature) {
+            return false;
+        }
+        bytes.extend(&self.signature);
+        if self.rounds.len() > self.header.rounds as usize {
+            return false;
+        }
+        for round in &self.rounds {
+            round.write_unsigned_bytes(&mut bytes);
+            if !crypto::verify(organizer, &bytes, &round.signature) {
+                return false;
+            }
+            bytes.extend(&round.signature);
+        }
+        true
+    }
+
+    /// Verifies every linked game and tallies the results into a cross table.
+    /// `games` must contain every linked chain; unfinished games don't score
+    /// unless the arbiter has adjudicated them.
+    pub fn compute_standings(&self, games: &[GameChain]) -> Result<CrossTable, Error> {
+        if !self.verify() {
+            return Err(Error::VerificationFailed);
+        }
+        let players = self.header.participants.clone();
+        let mut half_points = vec![vec![0; players.len()]; players.len()];
+        let mut played = vec![vec![0; players.len()]; players.len()];
+        let index_of = |key: PublicKey| {
+            players
+                .iter()
+                .position(|player| key == *player)
+                .ok_or(Error::NotAParticipant)
+        };
+
+        for chain_id in self.rounds.iter().flat_map(|round| &round.chain_ids) {
+            let chain = games
+                .iter()
+                .find(|chain| chain.id() == *chain_id)
+                .ok_or(Error::UnknownGame(*chain_id))?;
+            if chain.challenge().network_id() != self.header.network_id {
+                return Err(Error::WrongNetwork {
+                    expected: self.header.network_id,
+                    found: chain.challenge().network_id(),
+                });
+            }
+            if !chain.verify() {
+                return Err(Error::VerificationFailed);
+            }
+            chain.get_game()?;
+            let (white, black) = chain.players();
+            let (white, black) = (index_of(white)?, index_of(black)?);
+            let (white_score, black_score) = match chain.result() {
+                Some(GameResult::Win(Color::White)) => (2, 0),
+                Some(GameResult::Win(Color::Black)) => (0, 2),
+                Some(GameResult::Draw) => (1, 1),
+                None => continue,
+            };
+            half_points[white][black] += white_score;
+            half_points[black][white] += black_score;
+            played[white][black] += 1;
+            played[black][white] += 1;
+        }
+
+        Ok(CrossTable {
+            players,
+            half_points,
+            games: played,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlockBuilder;
+    use crate::notation;
+    use chess::Action;
+
+    fn fools_mate(white: &Ed25519KeyPair, black: &Ed25519KeyPair, id: u32) -> GameChain {
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(white), &PublicKey::of(black))
+            .id(id)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        assert!(chain.accept(white).is_ok());
+        assert!(chain.accept(black).is_ok());
+        for (i, uci) in ["f2f3", "e7e5", "g2g4", "d8h4"].iter().enumerate() {
+            let key = if i % 2 == 0 { white } else { black };
+            let mv = notation::parse_uci(uci).unwrap();
+            assert!(chain.make_move_block(key, Action::MakeMove(mv)).is_ok());
+        }
+        chain
+    }
+
+    #[test]
+    fn tournament_standings() {
+        let rng = crypto::new_rng();
+        let organizer = crypto::generate_key(&rng);
+        let alice = crypto::generate_key(&rng);
+        let bob = crypto::generate_key(&rng);
+        let key = |key_pair: &Ed25519KeyPair| {
+            let mut bytes = [0; 32];
+            bytes.copy_from_slice(key_pair.public_key().as_ref());
+            bytes
+        };
+        let header = TournamentHeader::new(
+            organizer.public_key().as_ref(),
+            1,
+            TournamentFormat::RoundRobin,
+            2,
+            &[key(&alice), key(&bob)],
+        );
+        let mut tournament = TournamentChain::new(header, &organizer).unwrap();
+        assert_eq!(
+            tournament.add_round(&alice, &[10]),
+            Err(Error::NotOrganizer)
+        );
+        assert!(tournament.add_round(&organizer, &[10]).is_ok());
+        assert!(tournament.add_round(&organizer, &[11]).is_ok());
+        assert_eq!(
+            tournament.add_round(&organizer, &[12]),
+            Err(Error::TournamentFull)
+        );
+        assert_eq!(
+            tournament,
+            TournamentChain::from_bytes(&tournament.as_bytes()).unwrap()
+        );
+
+        // bob wins with black twice
+        let games = vec![fools_mate(&alice, &bob, 10), fools_mate(&alice, &bob, 11)];
+        let table = tournament.compute_standings(&games).unwrap();
+        assert_eq!(table.half_points[1][0], 4);
+        assert_eq!(table.games[0][1], 2);
+        assert_eq!(table.standings(), vec![1, 0]);
+
+        assert_eq!(
+            tournament.compute_standings(&games[..1]),
+            Err(Error::UnknownGame(11))
+        );
+    }
+
+    #[test]
+    fn adjudicated_games_score() {
+        let rng = crypto::new_rng();
+        let organizer = crypto::generate_key(&rng);
+        let alice = crypto::generate_key(&rng);
+        let bob = crypto::generate_key(&rng);
+        let mut players = [[0; 32]; 2];
+        players[0].copy_from_slice(alice.public_key().as_ref());
+        players[1].copy_from_slice(bob.public_key().as_ref());
+        let header = TournamentHeader::new(
+            organizer.public_key().as_ref(),
+            2,
+            TournamentFormat::RoundRobin,
+            1,
+            &players,
+        );
+        let mut tournament = TournamentChain::new(header, &organizer).unwrap();
+        tournament.add_round(&organizer, &[20]).unwrap();
+
+        // the organizer arbitrates the game left unfinished at the deadline
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&alice), &PublicKey::of(&bob))
+            .id(20)
+            .arbiter(&PublicKey::of(&organizer))
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&alice).unwrap();
+        chain.accept(&bob).unwrap();
+        let mv = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&alice, Action::MakeMove(mv)).unwrap();
+        let table = tournament.compute_standings(&[chain.clone()]).unwrap();
+        assert_eq!(table.games[0][1], 0);
+
+        chain
+            .adjudicate(&organizer, GameResult::Win(Color::White), Some("+3.1"))
+            .unwrap();
+        let table = tournament.compute_standings(&[chain]).unwrap();
+        assert_eq!(table.games[0][1], 1);
+        assert_eq!(table.half_points[0][1], 2);
+        assert_eq!(table.standings(), vec![0, 1]);
+    }
+}
@@ -0,0 +1,183 @@
+//! Byte-stream transports for exchanging signed blocks. Every transport uses
+//! the same framing: a u32 big-endian payload length followed by the
+//! payload. Anything that reads and writes bytes can carry frames, whether a
+//! TCP connection, a serial cable, or a Bluetooth SPP link.
+//!
+//! Sessions talk to peers through the Channel trait instead, which every
+//! transport implements, framed or not.
+
+use crate::message::Message;
+use crate::metrics;
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// The largest frame payload accepted: the largest message.
+pub use crate::message::MAX_FRAME_LEN;
+
+/// Carries messages to and from a peer.
+pub trait Channel {
+    fn send_message(&mut self, message: &Message) -> io::Result<()>;
+
+    /// Blocks until a message arrives.
+    fn recv_message(&mut self) -> io::Result<Message>;
+}
+
+pub trait Transport {
+    fn send_frame(&mut self, payload: &[u8]) -> io::Result<()>;
+
+    /// Blocks until a whole frame has arrived.
+    fn recv_frame(&mut self) -> io::Result<Vec<u8>>;
+}
+
+/// Frames over any byte stream.
+pub struct StreamTransport<S> {
+    stream: S,
+}
+
+impl<S: Read + Write> StreamTransport<S> {
+    pub fn new(stream: S) -> StreamTransport<S> {
+        StreamTransport { stream }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: Read + Write> Transport for StreamTransport<S> {
+    fn send_frame(&mut self, payload: &[u8]) -> io::Result<()> {
+        if payload.len() > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame too large",
+            ));
+        }
+        self.stream
+            .write_all(&(payload.len() as u32).to_be_bytes())?;
+        self.stream.write_all(payload)?;
+        self.stream.flush()?;
+        metrics::bytes_sent(4 + payload.len());
+        Ok(())
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Vec<u8>> {
+        let mut len = [0; 4];
+        self.stream.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "frame too large",
+            ));
+        }
+        let mut payload = vec![0; len];
+        self.stream.read_exact(&mut payload)?;
+        metrics::bytes_received(4 + len);
+        Ok(payload)
+    }
+}
+
+/// Each frame holds one message.
+impl<T: Transport> Channel for T {
+    fn send_message(&mut self, message: &Message) -> io::Result<()> {
+        self.send_frame(&message.as_bytes())
+    }
+
+    fn recv_message(&mut self) -> io::Result<Message> {
+        Message::from_bytes(&self.recv_frame()?)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))
+    }
+}
+
+pub fn connect_tcp<A: ToSocketAddrs>(addr: A) -> io::Result<StreamTransport<TcpStream>> {
+    Ok(StreamTransport::new(TcpStream::connect(addr)?))
+}
+
+/// Opens a serial device such as /dev/ttyUSB0, or /dev/rfcomm0 for a bound
+/// Bluetooth SPP link. Line settings like the baud rate are left as the
+/// system has them configured, e.g. with stty.
+pub fn open_serial<P: AsRef<Path>>(device: P) -> io::Result<StreamTransport<File>> {
+    let file = OpenOptions::new().read(true).write(true).open(device)?;
+    Ok(StreamTransport::new(file))
+}
+
+/// A child process's standard input and output as one stream. The process
+/// is killed when the stream is dropped.
+pub struct ChildStream {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+impl Read for ChildStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stdout.read(buf)
+    }
+}
+
+impl Write for ChildStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stdin.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdin.flush()
+    }
+}
+
+impl Drop for ChildStream {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Runs `command`, exchanging frames over its standard input and output.
+pub fn spawn(command: &mut Command) -> io::Result<StreamTransport<ChildStream>> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let stdin = child.stdin.take().expect("stdin is piped");
+    let stdout = child.stdout.take().expect("stdout is piped");
+    Ok(StreamTransport::new(ChildStream {
+        child,
+        stdin,
+        stdout,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn frames_round_trip() {
+        let mut transport = StreamTransport::new(Cursor::new(Vec::new()));
+        transport.send_frame(b"challenge").unwrap();
+        transport.send_frame(b"").unwrap();
+        assert!(transport.send_frame(&vec![0; MAX_FRAME_LEN + 1]).is_err());
+
+        let mut cursor = transport.into_inner();
+        assert_eq!(&cursor.get_ref()[..4], &[0, 0, 0, 9]);
+        cursor.set_position(0);
+        let mut transport = StreamTransport::new(cursor);
+        assert_eq!(transport.recv_frame().unwrap(), b"challenge");
+        assert_eq!(transport.recv_frame().unwrap(), b"");
+        assert_eq!(
+            transport.recv_frame().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+
+        let mut transport = StreamTransport::new(Cursor::new(vec![0xff; 8]));
+        assert_eq!(
+            transport.recv_frame().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
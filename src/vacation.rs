@@ -0,0 +1,272 @@
+//! Vacations for correspondence play. Either player can sign a vacation with
+//! their color and the time it starts and ends, whether or not it's their
+//! move; their clock doesn't run while it lasts. A vacation can't start
+//! before the last timestamp already in the chain, and each player has
+//! VACATION_ALLOWANCE seconds of vacation per game.
+
+use crate::block::{ChainEntry, GameChain};
+use crate::error::Error;
+use crate::extension::ExtensionKind;
+
+use alloc::vec::Vec;
+use chess::Color;
+use ring::signature::Ed25519KeyPair;
+
+/// The vacation each player may take in a game: thirty days.
+pub const VACATION_ALLOWANCE: u64 = 30 * 24 * 60 * 60;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vacation {
+    pub color: Color,
+    /// When the vacation starts, in seconds since the Unix epoch.
+    pub starts_at: u64,
+    /// When it ends, in seconds since the Unix epoch.
+    pub ends_at: u64,
+}
+
+impl Vacation {
+    pub fn seconds(&self) -> u64 {
+        self.ends_at - self.starts_at
+    }
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::Malformed {
+        block: "vacation",
+        reason,
+    }
+}
+
+fn index(color: Color) -> usize {
+    match color {
+        Color::White => 0,
+        Color::Black => 1,
+    }
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(bytes);
+    u64::from_be_bytes(value)
+}
+
+/// The player who signed a vacation with the given body.
+pub(crate) fn vacationer(body: &[u8]) -> Option<Color> {
+    match body.first() {
+        Some(0) => Some(Color::White),
+        Some(1) => Some(Color::Black),
+        _ => None,
+    }
+}
+
+/// Reads a vacation body: the color byte, then the start and end as
+/// eight-byte big-endian timestamps.
+fn parse(body: &[u8]) -> Result<Vacation, Error> {
+    if body.len() != 17 {
+        return Err(malformed("wrong body length"));
+    }
+    let color = vacationer(body).ok_or_else(|| malformed("unknown color"))?;
+    let vacation = Vacation {
+        color,
+        starts_at: read_u64(&body[1..9]),
+        ends_at: read_u64(&body[9..17]),
+    };
+    if vacation.ends_at <= vacation.starts_at {
+        return Err(malformed("ends before it starts"));
+    }
+    Ok(vacation)
+}
+
+/// Checks each vacation in `blocks` against the blocks before it, with the
+/// receipts, pauses, resumes and claims among them setting the earliest a
+/// vacation can start.
+fn check_sequence<'a, I>(challenge_timestamp: u64, blocks: I) -> Result<(), Error>
+where
+    I: IntoIterator<Item = (ExtensionKind, &'a [u8])>,
+{
+    let mut latest = challenge_timestamp;
+    let mut taken = [0; 2];
+    for (kind, body) in blocks {
+        match kind {
+            ExtensionKind::Vacation => {
+                let vacation = parse(body)?;
+                if vacation.starts_at < latest {
+                    return Err(malformed("starts in the past"));
+                }
+                let taken = &mut taken[index(vacation.color)];
+                *taken += vacation.seconds();
+                if *taken > VACATION_ALLOWANCE {
+                    return Err(malformed("allowance used up"));
+                }
+                latest = vacation.starts_at;
+            }
+            ExtensionKind::Receipt
+            | ExtensionKind::Pause
+            | ExtensionKind::Resume
+            | ExtensionKind::ClaimTimeForfeit
+                if body.len() == 8 =>
+            {
+                latest = latest.max(read_u64(body));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn extension_blocks(chain: &GameChain) -> Vec<(ExtensionKind, Vec<u8>)> {
+    chain
+        .entries()
+        .into_iter()
+        .filter_map(|entry| match entry {
+            ChainEntry::Extension(block) => Some((block.kind(), block.body().to_vec())),
+            _ => None,
+        })
+        .collect()
+}
+
+pub(crate) fn check_extension(chain: &GameChain, body: &[u8]) -> Result<(), Error> {
+    let blocks = extension_blocks(chain);
+    let blocks = blocks
+        .iter()
+        .map(|(kind, body)| (*kind, &body[..]))
+        .chain(Some((ExtensionKind::Vacation, body)));
+    check_sequence(chain.challenge().timestamp(), blocks)
+}
+
+pub(crate) fn check_chain(chain: &GameChain) -> Result<(), Error> {
+    let blocks = extension_blocks(chain);
+    check_sequence(
+        chain.challenge().timestamp(),
+        blocks.iter().map(|(kind, body)| (*kind, &body[..])),
+    )
+}
+
+impl GameChain {
+    /// Takes a vacation from `starts_at` to `ends_at` as `color`.
+    pub fn take_vacation(
+        &mut self,
+        key_pair: &Ed25519KeyPair,
+        color: Color,
+        starts_at: u64,
+        ends_at: u64,
+    ) -> Result<(), Error> {
+        let mut body = Vec::with_capacity(17);
+        body.push(index(color) as u8);
+        body.extend(&starts_at.to_be_bytes());
+        body.extend(&ends_at.to_be_bytes());
+        self.sign_extension(key_pair, ExtensionKind::Vacation, body)
+    }
+
+    /// The vacations in the chain, in order.
+    pub fn vacations(&self) -> Vec<Vacation> {
+        self.extensions()
+            .iter()
+            .filter(|(_, block)| block.kind() == ExtensionKind::Vacation)
+            .filter_map(|(_, block)| parse(block.body()).ok())
+            .collect()
+    }
+
+    /// Whether `color` is on vacation at `now`.
+    pub fn on_vacation(&self, color: Color, now: u64) -> bool {
+        self.vacations().iter().any(|vacation| {
+            vacation.color == color && vacation.starts_at <= now && now < vacation.ends_at
+        })
+    }
+
+    /// The seconds between `from` and `to` that count against `color`'s
+    /// clock: those when the game wasn't adjourned and they weren't on
+    /// vacation.
+    pub fn clock_seconds(&self, color: Color, from: u64, to: u64) -> u64 {
+        let mut stopped: Vec<(u64, u64)> = self
+            .adjournments()
+            .iter()
+            .map(|adjournment| {
+                (
+                    adjournment.paused_at,
+                    adjournment.resumed_at.unwrap_or(u64::MAX),
+                )
+            })
+            .chain(
+                self.vacations()
+                    .iter()
+                    .filter(|vacation| vacation.color == color)
+                    .map(|vacation| (vacation.starts_at, vacation.ends_at)),
+            )
+            .map(|(start, end)| (start.max(from), end.min(to)))
+            .filter(|(start, end)| start < end)
+            .collect();
+        stopped.sort_unstable();
+        let mut running = to.saturating_sub(from);
+        let mut counted = from;
+        for (start, end) in stopped {
+            let start = start.max(counted);
+            if start < end {
+                running -= end - start;
+                counted = end;
+            }
+        }
+        running
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::block::ChallengeBlockBuilder;
+    use crate::crypto;
+    use crate::key::PublicKey;
+    use crate::notation;
+    use chess::Action;
+
+    #[test]
+    fn vacation_stops_clock() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlockBuilder::new(&PublicKey::of(&white), &PublicKey::of(&black))
+            .timestamp(1_000)
+            .time_control(3 * 24 * 60 * 60, 0)
+            .build()
+            .unwrap();
+        let mut chain = GameChain::new(challenge);
+        chain.accept(&white).unwrap();
+        chain.accept(&black).unwrap();
+        let e4 = notation::parse_uci("e2e4").unwrap();
+        chain.make_move_block(&white, Action::MakeMove(e4)).unwrap();
+        chain.acknowledge_move(&black, 2_000).unwrap();
+
+        // black can go away for a week off turn, but not on white's key or
+        // from before the last receipt
+        let week = 7 * 24 * 60 * 60;
+        assert_eq!(
+            chain.take_vacation(&white, Color::Black, 3_000, 3_000 + week),
+            Err(Error::NotYourTurn)
+        );
+        assert!(chain
+            .take_vacation(&black, Color::Black, 1_500, 1_500 + week)
+            .is_err());
+        chain
+            .take_vacation(&black, Color::Black, 3_000, 3_000 + week)
+            .unwrap();
+        assert!(chain.on_vacation(Color::Black, 4_000));
+        assert!(!chain.on_vacation(Color::White, 4_000));
+        assert_eq!(
+            chain.clock_seconds(Color::Black, 2_000, 4_000 + week),
+            2_000
+        );
+        assert_eq!(
+            chain.clock_seconds(Color::White, 2_000, 4_000 + week),
+            2_000 + week
+        );
+
+        // the week doesn't count towards a forfeit
+        let reading = chain.clock_at(4_000 + week).unwrap();
+        assert_eq!(reading.used_seconds, 2_000);
+        assert!(chain
+            .take_vacation(&black, Color::Black, 5_000 + week, 5_000 + 5 * week)
+            .is_err());
+        assert!(chain.verify());
+        assert_eq!(GameChain::from_bytes(&chain.as_bytes()), Ok(chain));
+    }
+}
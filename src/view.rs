@@ -0,0 +1,657 @@
+use crate::block::{self, AcceptBlock, ChallengeBlock, GameChain, MoveBlock};
+use crate::checkpoint::{self, Checkpoint};
+use crate::conditional::{ConditionalMove, Redemptions};
+use crate::crypto;
+use crate::error::Error;
+use crate::extension::{ExtensionBlock, ExtensionKind, EXTENSION_TAG_MIN};
+use crate::rotation::Keys;
+use crate::takeback::Line;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use chess::Color;
+use core::fmt;
+use core::ops::Range;
+
+/// A parsed view over an encoded chain that borrows the block data rather than
+/// copying it. Each block signs the chain's canonical id followed by
+/// everything before it, so every signed message is a prefix of one buffer and
+/// verification copies the chain only once, and again after each checkpoint.
+pub struct GameChainView<'a> {
+    bytes: &'a [u8],
+    challenge: ChallengeBlock,
+    challenge_len: usize,
+    accept_count: usize,
+    // Offsets of the move blocks, and of the extension blocks along with the
+    // number of moves before each.
+    moves: Vec<usize>,
+    extensions: Vec<(usize, usize)>,
+    // Offsets of the commentary blocks, which come after every game block.
+    commentary: Vec<usize>,
+    game_end: usize,
+    end: usize,
+}
+
+fn after_commentary() -> Error {
+    Error::Malformed {
+        block: "commentary",
+        reason: "followed by a game block",
+    }
+}
+
+impl<'a> GameChainView<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<GameChainView<'a>, Error> {
+        let challenge = ChallengeBlock::from_bytes(bytes)?;
+        let challenge_len = challenge.encoded_len();
+        let accept_len = challenge.accept_len();
+        let rest = bytes.len() - challenge_len;
+        let accept_count = (rest / accept_len).min(2);
+        let mut offset = challenge_len + accept_count * accept_len;
+        let mut moves = Vec::new();
+        let mut extensions = Vec::new();
+        let mut commentary = Vec::new();
+        let mut game_end = offset;
+        while accept_count == 2 && offset < bytes.len() {
+            let rest = &bytes[offset..];
+            if rest[0] < EXTENSION_TAG_MIN {
+                if rest.len() < 66 {
+                    break;
+                }
+                if !commentary.is_empty() {
+                    return Err(after_commentary());
+                }
+                if moves.len() == block::MAX_MOVES {
+                    return Err(Error::TooLarge {
+                        what: "moves in a chain",
+                        limit: block::MAX_MOVES,
+                    });
+                }
+                moves.push(offset);
+                offset += 66;
+            } else {
+                if rest.len() < 3 {
+                    break;
+                }
+                let len = 3 + u16::from_be_bytes([rest[1], rest[2]]) as usize + 64;
+                if rest.len() < len {
+                    break;
+                }
+                if extensions.len() + commentary.len() == block::MAX_EXTENSIONS {
+                    return Err(Error::TooLarge {
+                        what: "extension blocks in a chain",
+                        limit: block::MAX_EXTENSIONS,
+                    });
+                }
+                match ExtensionKind::from_byte(rest[0]) {
+                    None => {
+                        return Err(Error::Malformed {
+                            block: "extension",
+                            reason: "unknown kind",
+                        });
+                    }
+                    Some(ExtensionKind::Commentary) | Some(ExtensionKind::Analysis) => {
+                        commentary.push(offset)
+                    }
+                    Some(_) if !commentary.is_empty() => return Err(after_commentary()),
+                    Some(_) => extensions.push((moves.len(), offset)),
+                }
+                offset += len;
+            }
+            if commentary.is_empty() {
+                game_end = offset;
+            }
+        }
+        Ok(GameChainView {
+            bytes,
+            challenge,
+            challenge_len,
+            accept_count,
+            moves,
+            extensions,
+            commentary,
+            game_end,
+            end: offset,
+        })
+    }
+
+    pub fn challenge(&self) -> &ChallengeBlock {
+        &self.challenge
+    }
+
+    pub fn challenge_bytes(&self) -> &'a [u8] {
+        &self.bytes[..self.challenge_len]
+    }
+
+    pub fn accept_count(&self) -> usize {
+        self.accept_count
+    }
+
+    /// Returns the encoded accept block: the signature, preceded by the
+    /// signer's color and a timestamp when the challenge calls for them.
+    pub fn accept_bytes(&self, index: usize) -> Option<&'a [u8]> {
+        if index >= self.accept_count {
+            return None;
+        }
+        let accept_len = self.challenge.accept_len();
+        let offset = self.challenge_len + index * accept_len;
+        Some(&self.bytes[offset..offset + accept_len])
+    }
+
+    pub fn accept_signature(&self, index: usize) -> Option<&'a [u8]> {
+        let bytes = self.accept_bytes(index)?;
+        Some(&bytes[bytes.len() - 64..])
+    }
+
+    pub fn move_count(&self) -> usize {
+        self.moves.len()
+    }
+
+    /// Returns the start square, end square, and signature of a move block.
+    pub fn move_block(&self, index: usize) -> Option<(u8, u8, &'a [u8])> {
+        let offset = *self.moves.get(index)?;
+        Some((
+            self.bytes[offset],
+            self.bytes[offset + 1],
+            &self.bytes[offset + 2..offset + 66],
+        ))
+    }
+
+    pub fn extension_count(&self) -> usize {
+        self.extensions.len()
+    }
+
+    /// Returns the number of moves before an extension block, its kind, its
+    /// body, and its signature.
+    pub fn extension_block(
+        &self,
+        index: usize,
+    ) -> Option<(usize, ExtensionKind, &'a [u8], &'a [u8])> {
+        let (ply, offset) = *self.extensions.get(index)?;
+        let (body_end, end) = self.extension_bounds(offset);
+        Some((
+            ply,
+            ExtensionKind::from_byte(self.bytes[offset])?,
+            &self.bytes[offset + 3..body_end],
+            &self.bytes[body_end..end],
+        ))
+    }
+
+    fn extension_bounds(&self, offset: usize) -> (usize, usize) {
+        let body_len = u16::from_be_bytes([self.bytes[offset + 1], self.bytes[offset + 2]]);
+        let body_end = offset + 3 + body_len as usize;
+        (body_end, body_end + 64)
+    }
+
+    /// The checkpoint, if the first block after the accepts is one.
+    pub fn root_checkpoint(&self) -> Option<Checkpoint> {
+        let &(_, offset) = self.extensions.first()?;
+        if offset != self.accepts_end() || self.bytes[offset] != ExtensionKind::Checkpoint.to_byte()
+        {
+            return None;
+        }
+        let (body_end, _) = self.extension_bounds(offset);
+        Checkpoint::from_body(&self.bytes[offset + 3..body_end]).ok()
+    }
+
+    /// Whether the blocks before the first checkpoint were pruned.
+    pub fn is_pruned(&self) -> bool {
+        self.root_checkpoint()
+            .is_some_and(|checkpoint| checkpoint.move_count > 0)
+    }
+
+    /// The byte range of the last checkpoint block, if there is one.
+    pub(crate) fn last_checkpoint(&self) -> Option<Range<usize>> {
+        self.extensions
+            .iter()
+            .rev()
+            .map(|&(_, offset)| offset)
+            .find(|&offset| self.bytes[offset] == ExtensionKind::Checkpoint.to_byte())
+            .map(|offset| offset..self.extension_bounds(offset).1)
+    }
+
+    /// The chain without the game blocks before its last checkpoint, or its
+    /// commentary.
+    pub(crate) fn pruned_bytes(&self) -> Option<Vec<u8>> {
+        let checkpoint = self.last_checkpoint()?;
+        let mut bytes = self.bytes[..self.accepts_end()].to_vec();
+        bytes.extend(&self.bytes[checkpoint.start..self.game_end]);
+        Some(bytes)
+    }
+
+    fn accepts_end(&self) -> usize {
+        self.challenge_len + self.accept_count * self.challenge.accept_len()
+    }
+
+    pub fn commentary_count(&self) -> usize {
+        self.commentary.len()
+    }
+
+    /// The number of bytes the parsed blocks cover. Anything after this in
+    /// the original slice was ignored.
+    pub fn encoded_len(&self) -> usize {
+        self.end
+    }
+
+    pub fn verify(&self) -> bool {
+        let (first, second) = match (self.accept_bytes(0), self.accept_bytes(1)) {
+            (Some(first), Some(second)) => (
+                AcceptBlock::from_bytes(first, &self.challenge),
+                AcceptBlock::from_bytes(second, &self.challenge),
+            ),
+            _ => return false,
+        };
+        let (first, second) = match (first, second) {
+            (Ok(first), Ok(second)) => (first, second),
+            _ => return false,
+        };
+        if first.check_time(&self.challenge).is_err() || second.check_time(&self.challenge).is_err()
+        {
+            return false;
+        }
+        let challenge = self.challenge_bytes();
+        match (
+            first.signer(&self.challenge, challenge),
+            second.signer(&self.challenge, challenge),
+        ) {
+            (Some(first), Some(second)) if first != second => {}
+            _ => return false,
+        }
+
+        self.check_game_blocks(|_, valid| valid)
+    }
+
+    /// Checks the signature of each move and extension block in encoded
+    /// order, passing its offset and whether it is valid to `visit`, and
+    /// stopping early if `visit` returns false. Returns false if it stopped.
+    fn check_game_blocks<F: FnMut(usize, bool) -> bool>(&self, mut visit: F) -> bool {
+        let canonical_id = block::canonical_id(self.challenge_bytes());
+        let mut message = block::signed_message(&canonical_id, &self.bytes[..self.game_end]);
+        // the message holds `head` bytes before the chain bytes from `base`:
+        // the canonical id, followed by a checkpoint digest once there is one
+        let (mut head, mut base) = (32, 0);
+        // signers follow the game line, which takebacks can shorten, so the
+        // blocks are checked in encoded order
+        let pruned_at = self
+            .root_checkpoint()
+            .map(|checkpoint| checkpoint.move_count)
+            .filter(|&move_count| move_count > 0);
+        let mut line = pruned_at.map_or_else(Line::default, Line::at);
+        let mut redemptions = Redemptions::default();
+        let mut keys = Keys::new(&self.challenge);
+        let mut moves = self.moves.iter().peekable();
+        let mut extensions = self.extensions.iter().peekable();
+        loop {
+            let next_extension = extensions.peek().map(|&&(_, offset)| offset);
+            match (moves.peek(), next_extension) {
+                (Some(&&offset), next) if next.is_none_or(|next| offset < next) => {
+                    moves.next();
+                    let squares = [self.bytes[offset], self.bytes[offset + 1]];
+                    let public_key = keys.current(self.challenge.signer_public_key(line.len()));
+                    let signature = &self.bytes[offset + 2..offset + 66];
+                    let valid = public_key.verify(&message[..head + offset - base + 2], signature)
+                        || redemptions.redeems(
+                            &canonical_id,
+                            public_key.as_bytes(),
+                            squares,
+                            signature,
+                        );
+                    if !visit(offset, valid) {
+                        return false;
+                    }
+                    line.push_move();
+                    redemptions.push_move(squares);
+                }
+                (_, Some(offset)) => {
+                    extensions.next();
+                    let kind = match ExtensionKind::from_byte(self.bytes[offset]) {
+                        Some(kind) => kind,
+                        None => return false,
+                    };
+                    let (body_end, end) = self.extension_bounds(offset);
+                    let body = &self.bytes[offset + 3..body_end];
+                    let verified = if kind == ExtensionKind::Checkpoint {
+                        let prefix = match pruned_at {
+                            Some(_) if offset == self.accepts_end() => None,
+                            _ => Some(&message[..head + offset - base]),
+                        };
+                        checkpoint::verify_block(
+                            &self.challenge,
+                            &canonical_id,
+                            prefix,
+                            &self.bytes[offset..end],
+                        )
+                    } else {
+                        let prefix = &message[..head + offset - base];
+                        let follows = kind != ExtensionKind::Conditional
+                            || ConditionalMove::from_body(body)
+                                .is_ok_and(|conditional| conditional.follows(prefix));
+                        let public_key = kind
+                            .signer_public_key(&self.challenge, line.len(), body)
+                            .map(|public_key| keys.current(public_key));
+                        match public_key {
+                            Some(public_key) => {
+                                follows
+                                    && public_key.verify(
+                                        &message[..head + body_end - base],
+                                        &self.bytes[body_end..end],
+                                    )
+                            }
+                            None => false,
+                        }
+                    };
+                    let valid = verified
+                        && line.push_extension(kind, body).is_ok()
+                        && (kind != ExtensionKind::KeyRotation || keys.rotate(&canonical_id, body));
+                    redemptions.push_extension(kind, body);
+                    if !visit(offset, valid) {
+                        return false;
+                    }
+                    if valid && kind == ExtensionKind::Checkpoint {
+                        message = checkpoint::rooted_message(
+                            &canonical_id,
+                            &self.bytes[..self.game_end],
+                            offset..end,
+                        );
+                        head = 64;
+                        base = end;
+                    }
+                }
+                (_, None) => return true,
+            }
+        }
+    }
+
+    /// Copies the viewed blocks into an owned chain, without verifying them.
+    pub fn to_chain(&self) -> Result<GameChain, Error> {
+        let mut accepts = [None, None];
+        for (i, accept) in accepts.iter_mut().enumerate() {
+            if let Some(bytes) = self.accept_bytes(i) {
+                *accept = Some(AcceptBlock::from_bytes(bytes, &self.challenge)?);
+            }
+        }
+        let mut moves = Vec::with_capacity(self.moves.len());
+        for &offset in &self.moves {
+            moves.push(MoveBlock::from_bytes(&self.bytes[offset..offset + 66])?);
+        }
+        let mut extensions = Vec::with_capacity(self.extensions.len());
+        for &(ply, offset) in &self.extensions {
+            extensions.push((ply, ExtensionBlock::from_bytes(&self.bytes[offset..])?));
+        }
+        let mut commentary = Vec::with_capacity(self.commentary.len());
+        for &offset in &self.commentary {
+            commentary.push(ExtensionBlock::from_bytes(&self.bytes[offset..])?);
+        }
+        Ok(GameChain::from_parts(
+            self.challenge.clone(),
+            accepts,
+            moves,
+            extensions,
+            commentary,
+        ))
+    }
+}
+
+/// One block of an encoded chain, as found by GameChainView::describe.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockDescription {
+    pub offset: usize,
+    pub len: usize,
+    pub label: String,
+    /// Field names and values, in encoded order.
+    pub fields: Vec<(&'static str, String)>,
+    /// Whether the block's signature verifies, or None for blocks without
+    /// one.
+    pub signature_valid: Option<bool>,
+}
+
+impl fmt::Display for BlockDescription {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:06x} {:>5}  {:<16}", self.offset, self.len, self.label)?;
+        for (name, value) in &self.fields {
+            write!(f, " {}={}", name, value)?;
+        }
+        match self.signature_valid {
+            Some(true) => write!(f, "  signature ok"),
+            Some(false) => write!(f, "  signature BAD"),
+            None => Ok(()),
+        }
+    }
+}
+
+fn square_name(square: u8) -> String {
+    match chess::ALL_SQUARES.get(square as usize) {
+        Some(square) => square.to_string(),
+        None => format!("#{}", square),
+    }
+}
+
+impl<'a> GameChainView<'a> {
+    /// Annotates the encoded chain block by block, for debugging encodings
+    /// produced by other implementations. Bytes past the last whole block
+    /// are described as trailing.
+    pub fn describe(&self) -> Vec<BlockDescription> {
+        let challenge = &self.challenge;
+        let mut fields = vec![
+            ("version", challenge.version().to_string()),
+            ("network", challenge.network_id().to_string()),
+            ("id", format!("{:08x}", challenge.id())),
+            ("white", challenge.white_public_key().fingerprint()),
+            ("black", challenge.black_public_key().fingerprint()),
+            ("timestamp", challenge.timestamp().to_string()),
+        ];
+        if let Some(fen) = challenge.starting_fen() {
+            fields.push(("fen", fen.into()));
+        }
+        if let Some(valid_until) = challenge.valid_until() {
+            fields.push(("valid_until", valid_until.to_string()));
+        }
+        if let Some(arbiter) = challenge.arbiter_public_key() {
+            fields.push(("arbiter", arbiter.fingerprint()));
+        }
+        for (name, value) in challenge.tags() {
+            fields.push(("tag", format!("{}={}", name, value)));
+        }
+        let mut blocks = vec![BlockDescription {
+            offset: 0,
+            len: self.challenge_len,
+            label: "challenge".into(),
+            fields,
+            signature_valid: None,
+        }];
+
+        for index in 0..self.accept_count {
+            let bytes = self.accept_bytes(index).unwrap_or_default();
+            let mut fields = Vec::new();
+            let mut signature_valid = false;
+            if let Ok(accept) = AcceptBlock::from_bytes(bytes, challenge) {
+                if let Some(timestamp) = accept.timestamp() {
+                    fields.push(("timestamp", timestamp.to_string()));
+                }
+                if let Some(color) = accept.signer(challenge, self.challenge_bytes()) {
+                    let name = match color {
+                        Color::White => "white",
+                        Color::Black => "black",
+                    };
+                    fields.push(("signer", String::from(name)));
+                    signature_valid = true;
+                }
+            }
+            blocks.push(BlockDescription {
+                offset: self.challenge_len + index * challenge.accept_len(),
+                len: bytes.len(),
+                label: "accept".into(),
+                fields,
+                signature_valid: Some(signature_valid),
+            });
+        }
+
+        let mut valid = Vec::new();
+        self.check_game_blocks(|offset, is_valid| {
+            valid.push((offset, is_valid));
+            true
+        });
+        for (offset, signature_valid) in valid {
+            let block = if self.bytes[offset] < EXTENSION_TAG_MIN {
+                BlockDescription {
+                    offset,
+                    len: 66,
+                    label: "move".into(),
+                    fields: vec![
+                        ("from", square_name(self.bytes[offset])),
+                        ("to", square_name(self.bytes[offset + 1])),
+                    ],
+                    signature_valid: Some(signature_valid),
+                }
+            } else {
+                let (body_end, end) = self.extension_bounds(offset);
+                BlockDescription {
+                    offset,
+                    len: end - offset,
+                    label: ExtensionKind::from_byte(self.bytes[offset])
+                        .map_or_else(|| "extension".into(), |kind| format!("{:?}", kind)),
+                    fields: vec![("body_len", (body_end - offset - 3).to_string())],
+                    signature_valid: Some(signature_valid),
+                }
+            };
+            blocks.push(block);
+        }
+
+        let canonical_id = block::canonical_id(self.challenge_bytes());
+        for &offset in &self.commentary {
+            let (_, end) = self.extension_bounds(offset);
+            let mut fields = Vec::new();
+            let mut signature_valid = false;
+            let label = match ExtensionKind::from_byte(self.bytes[offset]) {
+                Some(ExtensionKind::Analysis) => "analysis",
+                _ => "commentary",
+            };
+            if let Ok(comment) = ExtensionBlock::from_bytes(&self.bytes[offset..]) {
+                if let Some(annotator) = comment.body().get(..32) {
+                    fields.push(("annotator", crypto::fingerprint(annotator)));
+                    signature_valid = comment.verify(&canonical_id, annotator);
+                }
+            }
+            blocks.push(BlockDescription {
+                offset,
+                len: end - offset,
+                label: label.into(),
+                fields,
+                signature_valid: Some(signature_valid),
+            });
+        }
+
+        if self.end < self.bytes.len() {
+            blocks.push(BlockDescription {
+                offset: self.end,
+                len: self.bytes.len() - self.end,
+                label: "trailing".into(),
+                fields: Vec::new(),
+                signature_valid: None,
+            });
+        }
+        blocks
+    }
+}
+
+impl GameChain {
+    /// Annotates this chain's encoding block by block.
+    pub fn describe_bytes(&self) -> Vec<BlockDescription> {
+        let bytes = self.as_bytes();
+        match GameChainView::new(&bytes) {
+            Ok(view) => view.describe(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key::PublicKey;
+    use chess::{Action, ChessMove, Square};
+
+    #[test]
+    fn view_matches_chain() {
+        let rng = crypto::new_rng();
+        let white = crypto::generate_key(&rng);
+        let black = crypto::generate_key(&rng);
+        let challenge = ChallengeBlock::new(&PublicKey::of(&white), &PublicKey::of(&black));
+        let mut chain = GameChain::new(challenge);
+        assert!(chain.accept(&white).is_ok());
+        assert!(chain.accept(&black).is_ok());
+        assert!(chain
+            .make_move_block(
+                &white,
+                Action::MakeMove(ChessMove::new(
+                    "e2".parse::<Square>().unwrap(),
+                    "e4".parse::<Square>().unwrap(),
+                    None,
+                )),
+            )
+            .is_ok());
+
+        let mut bytes = Vec::new();
+        chain.write_bytes(&mut bytes);
+        assert_eq!(bytes.len(), chain.encoded_len());
+        // trailing bytes that don't make up a whole block are ignored
+        bytes.extend(&[0; 10]);
+
+        let view = GameChainView::new(&bytes).unwrap();
+        assert_eq!(view.encoded_len(), chain.encoded_len());
+        assert_eq!(view.move_count(), 1);
+        let (start_square, end_square, signature) = view.move_block(0).unwrap();
+        assert_eq!(start_square, chain.moves()[0].start_square());
+        assert_eq!(end_square, chain.moves()[0].end_square());
+        assert_eq!(signature, chain.moves()[0].signature());
+        assert!(view.verify());
+        assert_eq!(view.to_chain().unwrap(), chain);
+
+        let labels: Vec<_> = view
+            .describe()
+            .into_iter()
+            .map(|block| (block.label, block.signature_valid))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("challenge".to_string(), None),
+                ("accept".to_string(), Some(true)),
+                ("accept".to_string(), Some(true)),
+                ("move".to_string(), Some(true)),
+                ("trailing".to_string(), None),
+            ]
+        );
+        assert!(view.describe()[3].to_string().contains("from=e2 to=e4"));
+
+        let len = view.encoded_len();
+        drop(view);
+        bytes[len - 1] ^= 1;
+        let view = GameChainView::new(&bytes).unwrap();
+        assert!(!view.verify());
+        assert_eq!(view.describe()[3].signature_valid, Some(false));
+    }
+
+    #[test]
+    fn bounded_block_counts() {
+        let rng = crypto::new_rng();
+        let challenge = ChallengeBlock::new(
+            &PublicKey::of(&crypto::generate_key(&rng)),
+            &PublicKey::of(&crypto::generate_key(&rng)),
+        );
+        // blocks are counted before any of them are checked
+        let mut bytes = challenge.as_bytes();
+        bytes.extend(vec![0; 2 * challenge.accept_len()]);
+        bytes.extend(vec![0; 66 * block::MAX_MOVES]);
+        assert!(GameChainView::new(&bytes).is_ok());
+        bytes.extend(vec![0; 66]);
+        assert_eq!(
+            GameChainView::new(&bytes).err(),
+            Some(Error::TooLarge {
+                what: "moves in a chain",
+                limit: block::MAX_MOVES,
+            })
+        );
+    }
+}
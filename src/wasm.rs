@@ -0,0 +1,114 @@
+use crate::block::{ChallengeBlock, GameChain};
+use crate::crypto;
+use crate::key::PublicKey;
+use crate::notation;
+use crate::render::BoardStyle;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use chess::{Action, Color};
+use ring::signature::KeyPair;
+use wasm_bindgen::prelude::*;
+
+fn to_js(error: crate::Error) -> JsValue {
+    JsValue::from_str(&error.to_string())
+}
+
+/// Generates a new key and returns it as a PKCS#8 document. The browser keeps
+/// this and passes it back in to sign.
+#[wasm_bindgen(js_name = generateKey)]
+pub fn generate_key() -> Vec<u8> {
+    crypto::generate_pkcs8(&crypto::new_rng())
+}
+
+#[wasm_bindgen(js_name = publicKey)]
+pub fn public_key(pkcs8: &[u8]) -> Result<Vec<u8>, JsValue> {
+    let key_pair = crypto::key_from_pkcs8(pkcs8).map_err(to_js)?;
+    Ok(key_pair.public_key().as_ref().to_vec())
+}
+
+#[wasm_bindgen(js_name = GameChain)]
+pub struct WasmGameChain {
+    chain: GameChain,
+}
+
+#[wasm_bindgen(js_class = GameChain)]
+impl WasmGameChain {
+    /// Creates a new game between the two public keys.
+    #[wasm_bindgen(constructor)]
+    pub fn new(white_public_key: &[u8], black_public_key: &[u8]) -> Result<WasmGameChain, JsValue> {
+        let white = PublicKey::from_slice(white_public_key).map_err(to_js)?;
+        let black = PublicKey::from_slice(black_public_key).map_err(to_js)?;
+        Ok(WasmGameChain {
+            chain: GameChain::new(ChallengeBlock::new(&white, &black)),
+        })
+    }
+
+    #[wasm_bindgen(js_name = fromBytes)]
+    pub fn from_bytes(bytes: &[u8]) -> Result<WasmGameChain, JsValue> {
+        Ok(WasmGameChain {
+            chain: GameChain::from_bytes(bytes).map_err(to_js)?,
+        })
+    }
+
+    #[wasm_bindgen(js_name = toBytes)]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.chain.as_bytes()
+    }
+
+    pub fn accept(&mut self, pkcs8: &[u8]) -> Result<(), JsValue> {
+        let key_pair = crypto::key_from_pkcs8(pkcs8).map_err(to_js)?;
+        self.chain.accept(&key_pair).map_err(to_js)
+    }
+
+    /// Signs a move given in UCI notation, e.g. "e2e4".
+    #[wasm_bindgen(js_name = makeMove)]
+    pub fn make_move(&mut self, pkcs8: &[u8], uci: &str) -> Result<(), JsValue> {
+        let key_pair = crypto::key_from_pkcs8(pkcs8).map_err(to_js)?;
+        let mv = notation::parse_uci(uci).ok_or_else(|| to_js(crate::Error::IllegalMove))?;
+        self.chain
+            .make_move_block(&key_pair, Action::MakeMove(mv))
+            .map_err(to_js)
+    }
+
+    pub fn verify(&self) -> bool {
+        self.chain.verify()
+    }
+
+    /// The legal moves in the current position, in UCI notation.
+    #[wasm_bindgen(js_name = legalMoves)]
+    pub fn legal_moves(&self) -> Result<Vec<JsValue>, JsValue> {
+        Ok(self
+            .chain
+            .legal_moves()
+            .map_err(to_js)?
+            .iter()
+            .map(|mv| JsValue::from_str(&mv.to_string()))
+            .collect())
+    }
+
+    #[wasm_bindgen(js_name = isMyTurn)]
+    pub fn is_my_turn(&self, public_key: &[u8]) -> bool {
+        self.chain.is_my_turn(public_key)
+    }
+
+    #[wasm_bindgen(js_name = renderBoard)]
+    pub fn render_board(&self, unicode: bool, black_at_bottom: bool) -> Result<String, JsValue> {
+        let style = if unicode {
+            BoardStyle::Unicode
+        } else {
+            BoardStyle::Ascii
+        };
+        let orientation = if black_at_bottom {
+            Color::Black
+        } else {
+            Color::White
+        };
+        self.chain.render_board(style, orientation).map_err(to_js)
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn summary(&self) -> String {
+        self.chain.to_string()
+    }
+}